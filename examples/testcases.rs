@@ -1,13 +1,12 @@
-#![cfg(feature = "serde-error")]
+#![cfg(feature = "samples")]
 #![allow(unused_must_use)]
 extern crate biscuit_auth as biscuit;
 
-use biscuit::error;
+use biscuit::samples::{self, BlockContent, TestCase, TestCases, Validation};
 use biscuit::KeyPair;
 use biscuit::{builder::*, Biscuit};
 use prost::Message;
 use rand::prelude::*;
-use serde::Serialize;
 use std::{
     collections::{BTreeMap, BTreeSet},
     convert::TryInto,
@@ -120,102 +119,59 @@ fn main() {
 
         for result in results {
             println!("\n------------------------------\n");
-            println!("{}", result.print());
+            println!("{}", print_case(&result));
         }
     }
 }
 
-#[derive(Debug, Serialize)]
-struct TestCases {
-    pub root_private_key: String,
-    pub root_public_key: String,
-    pub testcases: Vec<TestResult>,
-}
-
-#[derive(Debug, Serialize)]
-struct TestResult {
-    pub title: String,
-    pub filename: String,
-    pub token: Vec<BlockContent>,
-    pub validations: BTreeMap<String, Validation>,
-}
-
-#[derive(Debug, Serialize)]
-struct BlockContent {
-    pub symbols: Vec<String>,
-    pub code: String,
-}
-
-#[derive(Debug, Serialize)]
-struct Validation {
-    world: Option<AuthorizerWorld>,
-    result: AuthorizerResult,
-    authorizer_code: String,
-    revocation_ids: Vec<String>,
-}
-
-impl TestResult {
-    fn print(&self) -> String {
-        use std::fmt::Write;
-        let mut s = String::new();
+fn print_case(case: &TestCase) -> String {
+    use std::fmt::Write;
+    let mut s = String::new();
 
-        writeln!(&mut s, "## {}: {}", self.title, self.filename);
+    writeln!(&mut s, "## {}: {}", case.title, case.filename);
 
-        writeln!(&mut s, "### token\n");
-        for (i, block) in self.token.iter().enumerate() {
-            if i == 0 {
-                writeln!(&mut s, "authority:");
-            } else {
-                writeln!(&mut s, "{}:", i);
-            }
-
-            writeln!(&mut s, "symbols: {:?}\n", block.symbols);
-            writeln!(&mut s, "```\n{}```\n", block.code);
+    writeln!(&mut s, "### token\n");
+    for (i, block) in case.token.iter().enumerate() {
+        if i == 0 {
+            writeln!(&mut s, "authority:");
+        } else {
+            writeln!(&mut s, "{}:", i);
         }
 
-        for (name, validation) in &self.validations {
-            if name.is_empty() {
-                writeln!(&mut s, "### validation\n")
-            } else {
-                writeln!(&mut s, "### validation for \"{}\"\n", name)
-            };
-
-            if let Some(world) = &validation.world {
-                writeln!(
-                    &mut s,
-                    "authorizer code:\n```\n{}```\n",
-                    validation.authorizer_code
-                );
-
-                writeln!(&mut s, "revocation ids:");
-                for id in &validation.revocation_ids {
-                    writeln!(&mut s, "- `{}`", id);
-                }
-                writeln!(&mut s, "\nauthorizer world:\n```\nWorld {{\n  facts: {:#?}\n  rules: {:#?}\n  checks: {:#?}\n  policies: {:#?}\n}}\n```\n",
-                         world.facts, world.rules, world.checks, world.policies);
-            }
+        writeln!(&mut s, "symbols: {:?}\n", block.symbols);
+        writeln!(&mut s, "```\n{}```\n", block.code);
+    }
 
-            writeln!(&mut s, "result: `{:?}`", validation.result);
+    for (name, validation) in &case.validations {
+        if name.is_empty() {
+            writeln!(&mut s, "### validation\n")
+        } else {
+            writeln!(&mut s, "### validation for \"{}\"\n", name)
+        };
+
+        if let Some(world) = &validation.world {
+            writeln!(
+                &mut s,
+                "authorizer code:\n```\n{}```\n",
+                validation.authorizer_code
+            );
+
+            writeln!(&mut s, "revocation ids:");
+            for id in &validation.revocation_ids {
+                writeln!(&mut s, "- `{}`", id);
+            }
+            writeln!(&mut s, "\nauthorizer world:\n```\nWorld {{\n  facts: {:#?}\n  rules: {:#?}\n  checks: {:#?}\n  policies: {:#?}\n}}\n```\n",
+                     world.facts, world.rules, world.checks, world.policies);
         }
 
-        s
+        writeln!(&mut s, "result: `{:?}`", validation.result);
     }
-}
-
-#[derive(Debug, Serialize)]
-struct AuthorizerWorld {
-    pub facts: BTreeSet<String>,
-    pub rules: BTreeSet<String>,
-    pub checks: BTreeSet<String>,
-    pub policies: BTreeSet<String>,
-}
 
-#[derive(Debug, Serialize)]
-enum AuthorizerResult {
-    Ok(usize),
-    Err(error::Token),
+    s
 }
 
+/// the results are computed by the `samples` module, which also checks
+/// test vector directories against this implementation
 fn validate_token(
     root: &KeyPair,
     data: &[u8],
@@ -223,40 +179,9 @@ fn validate_token(
     ambient_rules: Vec<Rule>,
     checks: Vec<Vec<Rule>>,
 ) -> Validation {
-    let token = match Biscuit::from(&data[..], |_| root.public()) {
-        Ok(t) => t,
-        Err(e) => {
-            return Validation {
-                world: None,
-                authorizer_code: String::new(),
-                result: AuthorizerResult::Err(e),
-                revocation_ids: vec![],
-            }
-        }
-    };
-
-    let mut revocation_ids = vec![];
-
-    for bytes in token.revocation_identifiers() {
-        revocation_ids.push(hex::encode(&bytes));
-    }
-
-    let mut authorizer = match token.authorizer() {
-        Ok(v) => v,
-        Err(e) => {
-            return Validation {
-                world: None,
-                authorizer_code: String::new(),
-                result: AuthorizerResult::Err(e),
-                revocation_ids,
-            }
-        }
-    };
-
     let mut authorizer_code = String::new();
     for fact in ambient_facts {
         authorizer_code += &format!("{};\n", fact);
-        authorizer.add_fact(fact).unwrap();
     }
 
     if !ambient_rules.is_empty() {
@@ -265,7 +190,6 @@ fn validate_token(
 
     for rule in ambient_rules {
         authorizer_code += &format!("{};\n", rule);
-        authorizer.add_rule(rule).unwrap();
     }
 
     if !checks.is_empty() {
@@ -273,31 +197,11 @@ fn validate_token(
     }
 
     for check in checks {
-        authorizer.add_check(&check[..]).unwrap();
         let c: Check = (&check[..]).try_into().unwrap();
         authorizer_code += &format!("{};\n", c);
     }
 
-    authorizer.allow().unwrap();
-
-    let res = authorizer.authorize();
-    //println!("authorizer world:\n{}", authorizer.print_world());
-    let (mut facts, mut rules, mut checks, mut policies) = authorizer.dump();
-
-    Validation {
-        world: Some(AuthorizerWorld {
-            facts: facts.drain(..).map(|f| f.to_string()).collect(),
-            rules: rules.drain(..).map(|r| r.to_string()).collect(),
-            checks: checks.drain(..).map(|c| c.to_string()).collect(),
-            policies: policies.drain(..).map(|p| p.to_string()).collect(),
-        }),
-        result: match res {
-            Ok(i) => AuthorizerResult::Ok(i),
-            Err(e) => AuthorizerResult::Err(e),
-        },
-        authorizer_code,
-        revocation_ids,
-    }
+    samples::validate(&root.public(), data, &authorizer_code)
 }
 
 fn write_testcase(target: &str, name: &str, data: &[u8]) {
@@ -323,7 +227,7 @@ fn basic_token<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "basic token".to_string();
     let filename = "test1_basic.bc".to_string();
     let token;
@@ -387,7 +291,7 @@ fn basic_token<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -400,7 +304,7 @@ fn different_root_key<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "different root key".to_string();
     let filename = "test2_different_root_key.bc".to_string();
     let token;
@@ -454,7 +358,7 @@ fn different_root_key<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -467,7 +371,7 @@ fn invalid_signature_format<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "invalid signature format".to_string();
     let filename = "test3_invalid_signature_format.bc".to_string();
     let token;
@@ -529,7 +433,7 @@ fn invalid_signature_format<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -542,7 +446,7 @@ fn random_block<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "random block".to_string();
     let filename = "test4_random_block.bc".to_string();
     let token;
@@ -607,7 +511,7 @@ fn random_block<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -620,7 +524,7 @@ fn invalid_signature<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "invalid signature".to_string();
     let filename = "test5_invalid_signature.bc".to_string();
     let token;
@@ -684,7 +588,7 @@ fn invalid_signature<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -697,7 +601,7 @@ fn reordered_blocks<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "reordered blocks".to_string();
     let filename = "test6_reordered_blocks.bc".to_string();
     let token;
@@ -774,7 +678,7 @@ fn reordered_blocks<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -787,7 +691,7 @@ fn scoped_rules<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "scoped rules".to_string();
     let filename = "test7_scoped_rules.bc".to_string();
     let token;
@@ -868,7 +772,7 @@ fn scoped_rules<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -881,7 +785,7 @@ fn scoped_checks<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "scoped checks".to_string();
     let filename = "test8_scoped_checks.bc".to_string();
     let token;
@@ -948,7 +852,7 @@ fn scoped_checks<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -961,7 +865,7 @@ fn expired_token<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "expired token".to_string();
     let filename = "test9_expired_token.bc".to_string();
     let token;
@@ -1024,7 +928,7 @@ fn expired_token<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1037,7 +941,7 @@ fn authorizer_scope<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "authorizer scope".to_string();
     let filename = "test10_authorizer_scope.bc".to_string();
     let token;
@@ -1095,7 +999,7 @@ fn authorizer_scope<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1107,7 +1011,7 @@ fn authorizer_authority_checks<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "authorizer authority checks".to_string();
     let filename = "test11_authorizer_authority_caveats.bc".to_string();
     let token;
@@ -1155,7 +1059,7 @@ fn authorizer_authority_checks<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1168,7 +1072,7 @@ fn authority_checks<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "authority checks".to_string();
     let filename = "test12_authority_caveats.bc".to_string();
     let token;
@@ -1226,7 +1130,7 @@ fn authority_checks<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1239,7 +1143,7 @@ fn block_rules<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "block rules".to_string();
     let filename = "test13_block_rules.bc".to_string();
     let token;
@@ -1383,7 +1287,7 @@ fn block_rules<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1396,7 +1300,7 @@ fn regex_constraint<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "regex_constraint".to_string();
     let filename = "test14_regex_constraint.bc".to_string();
     let token;
@@ -1454,7 +1358,7 @@ fn regex_constraint<T: Rng + CryptoRng>(
             vec![],
         ),
     );
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1467,7 +1371,7 @@ fn multi_queries_checks<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "multi queries checks".to_string();
     let filename = "test15_multi_queries_caveats.bc".to_string();
     let token;
@@ -1519,7 +1423,7 @@ fn multi_queries_checks<T: Rng + CryptoRng>(
             ]],
         ),
     );
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1532,7 +1436,7 @@ fn check_head_name<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "check head name should be independent from fact names".to_string();
     let filename = "test16_caveat_head_name.bc".to_string();
     let token;
@@ -1574,7 +1478,7 @@ fn check_head_name<T: Rng + CryptoRng>(
         "".to_string(),
         validate_token(root, &data[..], vec![], vec![], vec![]),
     );
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1587,7 +1491,7 @@ fn expressions<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "test expression syntax and all available operations".to_string();
     let filename = "test17_expressions.bc".to_string();
     let token;
@@ -1716,7 +1620,7 @@ fn expressions<T: Rng + CryptoRng>(
         validate_token(root, &data[..], vec![], vec![], vec![]),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1729,7 +1633,7 @@ fn unbound_variables_in_rule<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "invalid block rule with unbound_variables".to_string();
     let filename = "test18_unbound_variables_in_rule.bc".to_string();
     let token;
@@ -1781,7 +1685,7 @@ fn unbound_variables_in_rule<T: Rng + CryptoRng>(
             vec![],
         ),
     );
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1794,7 +1698,7 @@ fn generating_ambient_from_variables<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "invalid block rule generating an #authority or #ambient symbol with a variable"
         .to_string();
     let filename = "test19_generating_ambient_from_variables.bc".to_string();
@@ -1851,7 +1755,7 @@ fn generating_ambient_from_variables<T: Rng + CryptoRng>(
             vec![],
         ),
     );
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1864,7 +1768,7 @@ fn sealed_token<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "sealed token".to_string();
     let filename = "test20_sealed.bc".to_string();
     let token;
@@ -1931,7 +1835,7 @@ fn sealed_token<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1939,12 +1843,7 @@ fn sealed_token<T: Rng + CryptoRng>(
     }
 }
 
-fn parsing<T: Rng + CryptoRng>(
-    rng: &mut T,
-    target: &str,
-    root: &KeyPair,
-    test: bool,
-) -> TestResult {
+fn parsing<T: Rng + CryptoRng>(rng: &mut T, target: &str, root: &KeyPair, test: bool) -> TestCase {
     let title = "parsing".to_string();
     let filename = "test21_parsing.bc".to_string();
     let token;
@@ -1985,7 +1884,7 @@ fn parsing<T: Rng + CryptoRng>(
         ),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -1998,7 +1897,7 @@ fn default_symbols<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "default_symbols".to_string();
     let filename = "test22_default_symbols.bc".to_string();
     let token;
@@ -2042,7 +1941,7 @@ fn default_symbols<T: Rng + CryptoRng>(
         validate_token(root, &data[..], vec![], vec![], vec![check.queries]),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -2055,7 +1954,7 @@ fn execution_scope<T: Rng + CryptoRng>(
     target: &str,
     root: &KeyPair,
     test: bool,
-) -> TestResult {
+) -> TestCase {
     let title = "execution scope".to_string();
     let filename = "test23_execution_scope.bc".to_string();
     let token;
@@ -2100,7 +1999,7 @@ fn execution_scope<T: Rng + CryptoRng>(
         validate_token(root, &data[..], vec![], vec![], vec![]),
     );
 
-    TestResult {
+    TestCase {
         title,
         filename,
         token,
@@ -0,0 +1,216 @@
+//! attribute sources, to load the facts used by attribute based policies
+//!
+//! an [AttributeSource] returns the attributes of a subject or resource, as
+//! facts, from its identifier. Registered with
+//! [Authorizer::add_attribute_source](crate::Authorizer::add_attribute_source),
+//! it is called before each authorization with the identifiers found in a
+//! predicate, like `user($id)` or `resource($id)`.
+//!
+//! Sources that have to wait on a database or a remote service implement
+//! [AsyncAttributeSource] and are loaded with
+//! [Authorizer::load_attributes_async](crate::Authorizer::load_attributes_async)
+//! before the authorization. Both can be wrapped in [Cached] to avoid a lookup
+//! for each request
+//!
+//! ```rust
+//! # use biscuit_auth::{Authorizer, error};
+//! # use biscuit_auth::builder::{fact, string, Fact};
+//! let mut authorizer = Authorizer::new().unwrap();
+//! authorizer.add_attribute_source("user", |user: &str| -> Result<Vec<Fact>, error::Cause> {
+//!     Ok(match user {
+//!         "alice" => vec![fact("department", &[string("alice"), string("engineering")])],
+//!         _ => vec![],
+//!     })
+//! });
+//! authorizer.add_code(r#"
+//!     user("alice");
+//!     allow if user($user), department($user, "engineering");
+//! "#).unwrap();
+//! assert_eq!(authorizer.authorize(), Ok(0));
+//! ```
+use crate::builder::Fact;
+use crate::error;
+#[cfg(feature = "std")]
+use crate::sync::lock;
+#[cfg(feature = "std")]
+use crate::time::Instant;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// returns the attribute facts of an identifier
+pub trait AttributeSource: Send + Sync {
+    fn attributes(&self, id: &str) -> Result<Vec<Fact>, error::Cause>;
+}
+
+impl<F> AttributeSource for F
+where
+    F: Fn(&str) -> Result<Vec<Fact>, error::Cause> + Send + Sync,
+{
+    fn attributes(&self, id: &str) -> Result<Vec<Fact>, error::Cause> {
+        self(id)
+    }
+}
+
+/// future returned by [AsyncAttributeSource::attributes]
+pub type AttributeFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<Fact>, error::Cause>> + Send + 'a>>;
+
+/// returns the attribute facts of an identifier, from an async lookup
+pub trait AsyncAttributeSource: Send + Sync {
+    fn attributes<'a>(&'a self, id: &'a str) -> AttributeFuture<'a>;
+}
+
+/// caches the attributes returned by another source for a duration
+///
+/// changes in the underlying source can take up to `ttl` to be seen
+#[cfg(feature = "std")]
+pub struct Cached<S> {
+    source: S,
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, (Vec<Fact>, Instant)>>,
+}
+
+#[cfg(feature = "std")]
+impl<S> Cached<S> {
+    /// the cache keeps up to 10000 identifiers, see [Cached::with_max_entries]
+    pub fn new(source: S, ttl: Duration) -> Self {
+        Cached {
+            source,
+            ttl,
+            max_entries: 10_000,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// sets the maximum number of cached identifiers. When it is reached,
+    /// the expired entries are removed, then the whole cache if it is still full
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// the underlying source
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// removes all cached attributes
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    fn get(&self, id: &str) -> Option<Vec<Fact>> {
+        match self.entries().get(id) {
+            Some((facts, time)) if time.elapsed() < self.ttl => Some(facts.clone()),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, id: &str, facts: Vec<Fact>) {
+        let mut entries = self.entries();
+        if entries.len() >= self.max_entries {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, time)| time.elapsed() < ttl);
+            if entries.len() >= self.max_entries {
+                entries.clear();
+            }
+        }
+        entries.insert(id.to_string(), (facts, Instant::now()));
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Vec<Fact>, Instant)>> {
+        lock(&self.entries)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: AttributeSource> AttributeSource for Cached<S> {
+    fn attributes(&self, id: &str) -> Result<Vec<Fact>, error::Cause> {
+        if let Some(facts) = self.get(id) {
+            return Ok(facts);
+        }
+
+        let facts = self.source.attributes(id)?;
+        self.insert(id, facts.clone());
+        Ok(facts)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: AsyncAttributeSource> AsyncAttributeSource for Cached<S> {
+    fn attributes<'a>(&'a self, id: &'a str) -> AttributeFuture<'a> {
+        Box::pin(async move {
+            if let Some(facts) = self.get(id) {
+                return Ok(facts);
+            }
+
+            let facts = self.source.attributes(id).await?;
+            self.insert(id, facts.clone());
+            Ok(facts)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{fact, string};
+    use crate::{Authorizer, Biscuit, KeyPair};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn cached_source() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counter = lookups.clone();
+        let source = Cached::new(
+            move |user: &str| -> Result<Vec<Fact>, error::Cause> {
+                counter.fetch_add(1, Ordering::Relaxed);
+                match user {
+                    "alice" => Ok(vec![fact(
+                        "clearance",
+                        &[string("alice"), string("secret")],
+                    )]),
+                    "bob" => Ok(vec![]),
+                    _ => Err("unknown user".into()),
+                }
+            },
+            Duration::from_secs(60),
+        );
+
+        let mut template = Authorizer::new().unwrap();
+        template.add_attribute_source("user", source);
+        template
+            .add_code("allow if user($user), clearance($user, \"secret\")")
+            .unwrap();
+
+        let root = KeyPair::new();
+        let token = |user: &str| {
+            let mut builder = Biscuit::builder(&root);
+            builder
+                .add_authority_fact(fact("user", &[string(user)]))
+                .unwrap();
+            builder.build().unwrap()
+        };
+
+        for _ in 0..2 {
+            assert_eq!(template.authorize_token(&token("alice")), Ok(0));
+        }
+        assert_eq!(lookups.load(Ordering::Relaxed), 1);
+
+        assert!(template.authorize_token(&token("bob")).is_err());
+        assert_eq!(
+            template.authorize_token(&token("carol")),
+            Err(error::Token::AttributeLookup("unknown user".into()))
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! interactive Datalog session, see [biscuit_auth::repl]
+//!
+//! usage: biscuit-repl [root public key in hex] [token file]
+use biscuit_auth::repl::Repl;
+use std::io;
+
+fn main() -> io::Result<()> {
+    let mut repl = Repl::new();
+
+    let mut args = std::env::args().skip(1);
+    if let Some(key) = args.next() {
+        println!(
+            "{}",
+            repl.eval(&format!(":key {}", key)).unwrap_or_default()
+        );
+    }
+    if let Some(token) = args.next() {
+        println!(
+            "{}",
+            repl.eval(&format!(":token {}", token)).unwrap_or_default()
+        );
+    }
+    println!("type :help for the list of commands");
+
+    let stdin = io::stdin();
+    repl.run(stdin.lock(), io::stdout())
+}
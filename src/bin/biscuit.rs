@@ -0,0 +1,360 @@
+//! command line tool to create, attenuate, inspect and verify tokens
+//!
+//! tokens are read from a file (binary or base64) or from a base64 argument,
+//! and written as base64 on the standard output, or in binary with `--output`.
+//! Keys are hex strings, or `@path` to read them from a file
+use biscuit_auth::builder::BlockBuilder;
+use biscuit_auth::error;
+use biscuit_auth::{Authorizer, Biscuit, KeyPair, PrivateKey, PublicKey, UnverifiedBiscuit};
+use std::collections::HashMap;
+
+const USAGE: &str = "usage:
+  biscuit keygen
+  biscuit create --private-key <key> [--root-key-id <id>] [--output <file>] <datalog file>
+  biscuit attenuate [--output <file>] <token> <datalog file>
+  biscuit inspect [--public-key <key>] <token>
+  biscuit seal [--output <file>] <token>
+  biscuit verify --public-key <key> [--time] <token> <authorizer datalog file>
+
+keys are hex strings, or @<file> to read them from a file
+tokens are files, binary or base64, or base64 strings";
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    match run(&args) {
+        Ok(output) => {
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// runs a command, returning the text to print
+fn run(args: &[String]) -> Result<String, String> {
+    let args = Args::parse(args)?;
+
+    match args.command.as_str() {
+        "keygen" => keygen(),
+        "create" => create(&args),
+        "attenuate" => attenuate(&args),
+        "inspect" => inspect(&args),
+        "seal" => seal(&args),
+        "verify" => verify(&args),
+        "" | "help" | "--help" => Ok(USAGE.to_string()),
+        command => Err(format!("unknown command {}\n{}", command, USAGE)),
+    }
+}
+
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args {
+            command: args.first().cloned().unwrap_or_default(),
+            positional: Vec::new(),
+            options: HashMap::new(),
+            flags: Vec::new(),
+        };
+
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--time" => parsed.flags.push(arg.clone()),
+                option if option.starts_with("--") => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("missing value for {}", option))?;
+                    parsed
+                        .options
+                        .insert(option[2..].to_string(), value.clone());
+                }
+                _ => parsed.positional.push(arg.clone()),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(|s| s.as_str())
+            .ok_or_else(|| format!("missing {} argument\n{}", name, USAGE))
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|s| s.as_str())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn public_key(&self) -> Result<Option<PublicKey>, String> {
+        match self.option("public-key") {
+            None => Ok(None),
+            Some(key) => {
+                let bytes = read_key(key)?;
+                PublicKey::from_bytes(&bytes)
+                    .map(Some)
+                    .map_err(|e| format!("invalid public key: {}", e))
+            }
+        }
+    }
+}
+
+fn read_key(key: &str) -> Result<Vec<u8>, String> {
+    let hex_key = match key.strip_prefix('@') {
+        Some(path) => read_file(path)?,
+        None => key.to_string(),
+    };
+
+    hex::decode(hex_key.trim()).map_err(|e| format!("invalid hex key: {}", e))
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))
+}
+
+/// reads a token from a file, binary or base64, or from a base64 argument
+fn read_token(source: &str) -> Result<UnverifiedBiscuit, String> {
+    let token = match std::fs::read(source) {
+        Ok(data) => UnverifiedBiscuit::from(&data).or_else(|_| {
+            std::str::from_utf8(&data)
+                .map_err(|_| error::Token::InternalError)
+                .and_then(|text| UnverifiedBiscuit::from_base64(text.trim()))
+        }),
+        Err(_) => UnverifiedBiscuit::from_base64(source),
+    };
+
+    token.map_err(|e| format!("invalid token: {}", print_error(&e)))
+}
+
+/// writes the token to the `--output` file, or returns it in base64
+fn write_token(args: &Args, data: Vec<u8>) -> Result<String, String> {
+    match args.option("output") {
+        Some(path) => std::fs::write(path, data)
+            .map(|_| String::new())
+            .map_err(|e| format!("cannot write {}: {}", path, e)),
+        None => Ok(base64::encode_config(data, base64::URL_SAFE_NO_PAD)),
+    }
+}
+
+fn keygen() -> Result<String, String> {
+    let keypair = KeyPair::new();
+    Ok(format!(
+        "private key: {}\npublic key: {}",
+        hex::encode(keypair.private().to_bytes()),
+        hex::encode(keypair.public().to_bytes())
+    ))
+}
+
+fn create(args: &Args) -> Result<String, String> {
+    let private_key = args
+        .option("private-key")
+        .ok_or_else(|| format!("missing --private-key\n{}", USAGE))?;
+    let private_key = PrivateKey::from_bytes(&read_key(private_key)?)
+        .map_err(|e| format!("invalid private key: {}", e))?;
+    let root = KeyPair::from(private_key);
+    let code = read_file(args.positional(0, "datalog file")?)?;
+
+    let mut builder = Biscuit::builder(&root);
+    builder.add_code(&code).map_err(|e| print_error(&e))?;
+    if let Some(id) = args.option("root-key-id") {
+        let id = id
+            .parse()
+            .map_err(|_| format!("invalid root key id: {}", id))?;
+        builder.set_root_key_id(id);
+    }
+
+    let token = builder.build().map_err(|e| print_error(&e))?;
+    write_token(args, token.to_vec().map_err(|e| print_error(&e))?)
+}
+
+fn attenuate(args: &Args) -> Result<String, String> {
+    let token = read_token(args.positional(0, "token")?)?;
+    let code = read_file(args.positional(1, "datalog file")?)?;
+
+    let mut block = BlockBuilder::new();
+    block.add_code(&code).map_err(|e| print_error(&e))?;
+
+    let token = token.append(block).map_err(|e| print_error(&e))?;
+    write_token(args, token.to_vec().map_err(|e| print_error(&e))?)
+}
+
+fn seal(args: &Args) -> Result<String, String> {
+    let token = read_token(args.positional(0, "token")?)?;
+    let token = token.seal().map_err(|e| print_error(&e))?;
+    write_token(args, token.to_vec().map_err(|e| print_error(&e))?)
+}
+
+fn inspect(args: &Args) -> Result<String, String> {
+    let token = read_token(args.positional(0, "token")?)?;
+    let mut output = Vec::new();
+
+    if let Some(id) = token.root_key_id() {
+        output.push(format!("root key id: {}", id));
+    }
+
+    let contexts = token.context();
+    let revocation_ids = token.revocation_identifiers();
+    for i in 0..token.block_count() {
+        output.push(format!("block {}:", i));
+        if let Some(Some(context)) = contexts.get(i) {
+            let metadata = biscuit_auth::builder::context_metadata(context);
+            if metadata.is_empty() {
+                output.push(format!("  context: {}", context));
+            } else {
+                output.push("  context:".to_string());
+                for (key, value) in metadata {
+                    output.push(format!("    {}: {:?}", key, value));
+                }
+            }
+        }
+        if let Some(id) = revocation_ids.get(i) {
+            output.push(format!("  revocation id: {}", hex::encode(id)));
+        }
+        for line in token.print_block_source(i).unwrap_or_default().lines() {
+            output.push(format!("  {}", line));
+        }
+    }
+
+    if let Some(root) = args.public_key()? {
+        match token.check_signature(|_| root) {
+            Ok(_) => output.push("signature: valid".to_string()),
+            Err(e) => output.push(format!("signature: invalid ({})", e)),
+        }
+    }
+
+    Ok(output.join("\n"))
+}
+
+fn verify(args: &Args) -> Result<String, String> {
+    let root = args
+        .public_key()?
+        .ok_or_else(|| format!("missing --public-key\n{}", USAGE))?;
+    let token = read_token(args.positional(0, "token")?)?
+        .check_signature(|_| root)
+        .map_err(|e| format!("invalid signature: {}", e))?;
+    let code = read_file(args.positional(1, "authorizer datalog file")?)?;
+
+    let mut authorizer: Authorizer = token.authorizer().map_err(|e| print_error(&e))?;
+    authorizer.add_code(&code).map_err(|e| print_error(&e))?;
+    if args.flag("--time") {
+        authorizer.set_time();
+    }
+
+    match authorizer.authorize() {
+        Ok(index) => Ok(format!("allowed by policy {}", index)),
+        Err(error::Token::FailedLogic(logic)) => Err(print_failure(&logic)),
+        Err(e) => Err(print_error(&e)),
+    }
+}
+
+fn print_failure(logic: &error::Logic) -> String {
+    let (mut output, checks) = match logic {
+        error::Logic::Unauthorized {
+            policy: error::MatchedPolicy::Allow(i),
+            checks,
+        } => (
+            format!("denied: policy {} matched, but checks failed", i),
+            checks,
+        ),
+        error::Logic::Unauthorized {
+            policy: error::MatchedPolicy::Deny(i),
+            checks,
+        } => (format!("denied by policy {}", i), checks),
+        error::Logic::NoMatchingPolicy { checks } => {
+            ("denied: no matching policy".to_string(), checks)
+        }
+        e => return format!("denied: {}", e),
+    };
+
+    for check in checks {
+        match check {
+            error::FailedCheck::Block(c) => output.push_str(&format!(
+                "\n  block {}, check {}: {}",
+                c.block_id, c.check_id, c.rule
+            )),
+            error::FailedCheck::Authorizer(c) => {
+                output.push_str(&format!("\n  authorizer check {}: {}", c.check_id, c.rule))
+            }
+        }
+    }
+
+    output
+}
+
+fn print_error(e: &error::Token) -> String {
+    format!("error: {}: {}", e.code(), e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_args(args: &[&str]) -> Result<String, String> {
+        run(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn token_lifecycle() {
+        let dir = std::env::temp_dir().join(format!("biscuit-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let root = KeyPair::new();
+        std::fs::write(path("root.key"), hex::encode(root.private().to_bytes())).unwrap();
+        std::fs::write(path("authority.datalog"), "right(\"file1\", \"read\");").unwrap();
+        std::fs::write(path("block.datalog"), "check if operation(\"read\");").unwrap();
+        std::fs::write(
+            path("authorizer.datalog"),
+            "operation(\"write\"); allow if right(\"file1\", $op);",
+        )
+        .unwrap();
+
+        let private_key = format!("@{}", path("root.key"));
+        let public_key = hex::encode(root.public().to_bytes());
+
+        let token = run_args(&[
+            "create",
+            "--private-key",
+            &private_key,
+            &path("authority.datalog"),
+        ])
+        .unwrap();
+        let token = run_args(&["attenuate", &token, &path("block.datalog")]).unwrap();
+
+        let inspected = run_args(&["inspect", "--public-key", &public_key, &token]).unwrap();
+        assert!(inspected.contains("block 1:\n  revocation id: "));
+        assert!(inspected.contains("  check if operation(\"read\");"));
+        assert!(inspected.ends_with("signature: valid"));
+
+        assert_eq!(
+            run_args(&[
+                "verify",
+                "--public-key",
+                &public_key,
+                &token,
+                &path("authorizer.datalog")
+            ]),
+            Err("denied: policy 0 matched, but checks failed\n  block 1, check 0: check if operation(\"read\")".to_string())
+        );
+
+        let sealed = run_args(&["seal", &token]).unwrap();
+        assert!(run_args(&["attenuate", &sealed, &path("block.datalog")]).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
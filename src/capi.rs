@@ -81,6 +81,22 @@ pub enum ErrorKind {
     FormatInvalidKey,
     FormatSignatureInvalidSignatureGeneration,
     AlreadySealed,
+    FormatDeserializationLimit,
+    TooMuchMemory,
+    Revoked,
+    RevocationLookup,
+    AttributeLookup,
+    QueryNoResult,
+    QueryTooManyResults,
+    WrongAudience,
+    NonceReused,
+    ReplayLookup,
+    InvalidPackVersion,
+    IncompatiblePolicyPack,
+    TooManyPredicateFacts,
+    PolicySource,
+    LogicNoPolicies,
+    FormatUnsupportedVersion,
 }
 
 #[no_mangle]
@@ -117,11 +133,23 @@ pub extern "C" fn error_kind() -> ErrorKind {
                         ErrorKind::FormatBlockSerializationError
                     }
                     Token::Format(Format::Version { .. }) => ErrorKind::FormatVersion,
+                    Token::Format(Format::UnsupportedVersion { .. }) => {
+                        ErrorKind::FormatUnsupportedVersion
+                    }
                     Token::Format(Format::InvalidKeySize(_)) => ErrorKind::FormatInvalidKeySize,
                     Token::Format(Format::InvalidSignatureSize(_)) => {
                         ErrorKind::FormatInvalidSignatureSize
                     }
                     Token::Format(Format::InvalidKey(_)) => ErrorKind::FormatInvalidKey,
+                    Token::Format(Format::Limit(_)) => ErrorKind::FormatDeserializationLimit,
+                    Token::Format(Format::KeyEncoding(_)) => ErrorKind::FormatInvalidKey,
+                    Token::Format(Format::UnsupportedAlgorithm(_)) => {
+                        ErrorKind::FormatDeserializationError
+                    }
+                    Token::Format(Format::UnknownSymbol(_)) => {
+                        ErrorKind::FormatBlockDeserializationError
+                    }
+                    Token::Format(Format::Base64(_)) => ErrorKind::FormatDeserializationError,
                     Token::SymbolTableOverlap => ErrorKind::SymbolTableOverlap,
                     Token::AppendOnSealed => ErrorKind::AppendOnSealed,
                     Token::AlreadySealed => ErrorKind::AlreadySealed,
@@ -136,11 +164,32 @@ pub extern "C" fn error_kind() -> ErrorKind {
                     Token::FailedLogic(Logic::NoMatchingPolicy { .. }) => {
                         ErrorKind::LogicNoMatchingPolicy
                     }
+                    Token::FailedLogic(Logic::NoPolicies) => ErrorKind::LogicNoPolicies,
                     Token::RunLimit(RunLimit::TooManyFacts) => ErrorKind::TooManyFacts,
                     Token::RunLimit(RunLimit::TooManyIterations) => ErrorKind::TooManyIterations,
                     Token::RunLimit(RunLimit::Timeout) => ErrorKind::Timeout,
+                    Token::RunLimit(RunLimit::TooMuchMemory) => ErrorKind::TooMuchMemory,
+                    Token::RunLimit(RunLimit::TooManyPredicateFacts { .. }) => {
+                        ErrorKind::TooManyPredicateFacts
+                    }
                     Token::ConversionError(_) => ErrorKind::ConversionError,
                     Token::Base64(_) => ErrorKind::FormatDeserializationError,
+                    Token::AuthorizationHeader(_) => ErrorKind::FormatDeserializationError,
+                    Token::Revocation(Revocation::Revoked(_)) => ErrorKind::Revoked,
+                    Token::Revocation(Revocation::Lookup(_)) => ErrorKind::RevocationLookup,
+                    Token::AttributeLookup(_) => ErrorKind::AttributeLookup,
+                    Token::Query(Query::NoResult { .. }) => ErrorKind::QueryNoResult,
+                    Token::Query(Query::TooManyResults { .. }) => ErrorKind::QueryTooManyResults,
+                    Token::WrongAudience(_) => ErrorKind::WrongAudience,
+                    Token::Replay(Replay::Reused) => ErrorKind::NonceReused,
+                    Token::Replay(Replay::Lookup(_)) => ErrorKind::ReplayLookup,
+                    Token::PolicyPack(PolicyPack::InvalidVersion(_)) => {
+                        ErrorKind::InvalidPackVersion
+                    }
+                    Token::PolicyPack(PolicyPack::Incompatible { .. }) => {
+                        ErrorKind::IncompatiblePolicyPack
+                    }
+                    Token::PolicySource(_) => ErrorKind::PolicySource,
                 }
             }
         },
@@ -301,7 +350,7 @@ pub unsafe extern "C" fn key_pair_serialize(kp: Option<&KeyPair>, buffer_ptr: *m
 
     let output_slice = std::slice::from_raw_parts_mut(buffer_ptr, 32);
 
-    output_slice.copy_from_slice(&kp.0.private().to_bytes()[..]);
+    output_slice.copy_from_slice(&kp.0.private_bytes()[..]);
     32
 }
 
@@ -477,6 +526,33 @@ pub unsafe extern "C" fn biscuit_builder_add_authority_check<'a>(
         .is_ok()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn biscuit_builder_add_authority_code<'a>(
+    builder: Option<&mut BiscuitBuilder<'a>>,
+    code: *const c_char,
+) -> bool {
+    if builder.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return false;
+    }
+    let builder = builder.unwrap();
+
+    let code = CStr::from_ptr(code);
+    let s = code.to_str();
+    if s.is_err() {
+        update_last_error(Error::InvalidArgument);
+        return false;
+    }
+
+    builder
+        .0
+        .add_code(s.unwrap())
+        .map_err(|e| {
+            update_last_error(Error::Biscuit(e));
+        })
+        .is_ok()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn biscuit_builder_build<'a>(
     builder: Option<&BiscuitBuilder<'a>>,
@@ -1049,9 +1125,48 @@ pub unsafe extern "C" fn block_builder_add_check(
         .is_ok()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn block_builder_add_code(
+    builder: Option<&mut BlockBuilder>,
+    code: *const c_char,
+) -> bool {
+    if builder.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return false;
+    }
+    let builder = builder.unwrap();
+
+    let code = CStr::from_ptr(code);
+    let s = code.to_str();
+    if s.is_err() {
+        update_last_error(Error::InvalidArgument);
+        return false;
+    }
+
+    builder
+        .0
+        .add_code(s.unwrap())
+        .map_err(|e| {
+            update_last_error(Error::Biscuit(e));
+        })
+        .is_ok()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn block_builder_free(_builder: Option<Box<BlockBuilder>>) {}
 
+/// creates an authorizer without a token
+#[no_mangle]
+pub unsafe extern "C" fn authorizer_new() -> Option<Box<Authorizer<'static>>> {
+    match crate::token::authorizer::Authorizer::new() {
+        Ok(authorizer) => Some(Box::new(Authorizer(authorizer))),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e.into()));
+            None
+        }
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn authorizer_add_fact(
     authorizer: Option<&mut Authorizer>,
@@ -1160,6 +1275,33 @@ pub unsafe extern "C" fn authorizer_add_policy(
         .is_ok()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn authorizer_add_code(
+    authorizer: Option<&mut Authorizer>,
+    code: *const c_char,
+) -> bool {
+    if authorizer.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return false;
+    }
+    let authorizer = authorizer.unwrap();
+
+    let code = CStr::from_ptr(code);
+    let s = code.to_str();
+    if s.is_err() {
+        update_last_error(Error::InvalidArgument);
+        return false;
+    }
+
+    authorizer
+        .0
+        .add_code(s.unwrap())
+        .map_err(|e| {
+            update_last_error(Error::Biscuit(e));
+        })
+        .is_ok()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn authorizer_authorize(authorizer: Option<&mut Authorizer>) -> bool {
     if authorizer.is_none() {
@@ -0,0 +1,19 @@
+//! hash maps and sets that are available without the standard library
+//!
+//! with the `std` feature, they are the standard library's collections.
+//! Without it, they come from `hashbrown`, with SipHash keyed by constants
+//! since there is no random source to key it: they are not resistant to
+//! collision attacks, so the inputs of an authorizer should come from signed
+//! tokens or trusted code
+#[cfg(feature = "std")]
+pub use std::collections::{HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+#[allow(deprecated)]
+type Hasher = core::hash::BuildHasherDefault<core::hash::SipHasher>;
+
+#[cfg(not(feature = "std"))]
+pub type HashMap<K, V> = hashbrown::HashMap<K, V, Hasher>;
+
+#[cfg(not(feature = "std"))]
+pub type HashSet<T> = hashbrown::HashSet<T, Hasher>;
@@ -0,0 +1,206 @@
+//! JSON Web Key representation of Ed25519 keys (RFC 8037)
+//!
+//! this is the format used by WebCrypto's `importKey` and `exportKey`
+//! for Ed25519 keys, along with the raw format (`to_bytes`/`from_bytes`).
+//! P-256 public keys use the `EC` key type (RFC 7518)
+use super::{Algorithm, Key, KeyPair, PrivateKey, PublicKey};
+use crate::error::{Format, KeyEncoding};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use zeroize::{Zeroize, Zeroizing};
+
+/// an Ed25519 or P-256 key in the JSON Web Key format
+///
+/// with the `serde` feature, it can be serialized to and from JSON.
+/// The private key is wiped from memory when the structure is dropped
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Jwk {
+    /// key type, `OKP` for Ed25519 and `EC` for P-256
+    pub kty: String,
+    /// curve, `Ed25519` or `P-256`
+    pub crv: String,
+    /// public key, in base64url without padding. For P-256, the x coordinate
+    pub x: String,
+    /// y coordinate of a P-256 public key, in base64url without padding
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub y: Option<String>,
+    /// private key, in base64url without padding
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub d: Option<String>,
+}
+
+impl Drop for Jwk {
+    fn drop(&mut self) {
+        if let Some(d) = self.d.as_mut() {
+            d.zeroize();
+        }
+    }
+}
+
+impl Jwk {
+    fn algorithm(&self) -> Result<Algorithm, Format> {
+        match (self.kty.as_str(), self.crv.as_str()) {
+            ("OKP", "Ed25519") => Ok(Algorithm::Ed25519),
+            #[cfg(feature = "p256")]
+            ("EC", "P-256") => Ok(Algorithm::P256),
+            _ => Err(KeyEncoding::UnsupportedAlgorithm.into()),
+        }
+    }
+}
+
+fn decode(s: &str) -> Result<Vec<u8>, Format> {
+    base64::decode_config(s, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| KeyEncoding::InvalidText(e.to_string()).into())
+}
+
+impl PublicKey {
+    /// exports to a JSON Web Key
+    pub fn to_jwk(&self) -> Jwk {
+        match &self.0 {
+            Key::Ed25519(key) => Jwk {
+                kty: "OKP".to_string(),
+                crv: "Ed25519".to_string(),
+                x: base64::encode_config(key.to_bytes(), base64::URL_SAFE_NO_PAD),
+                y: None,
+                d: None,
+            },
+            #[cfg(feature = "p256")]
+            Key::P256(key) => {
+                let point = key.to_encoded_point(false);
+                // uncompressed points always have both coordinates
+                let (x, y) = (point.x().unwrap(), point.y().unwrap());
+
+                Jwk {
+                    kty: "EC".to_string(),
+                    crv: "P-256".to_string(),
+                    x: base64::encode_config(x, base64::URL_SAFE_NO_PAD),
+                    y: Some(base64::encode_config(y, base64::URL_SAFE_NO_PAD)),
+                    d: None,
+                }
+            }
+        }
+    }
+
+    /// imports from a JSON Web Key. The private key, if present, is ignored
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, Format> {
+        match jwk.algorithm()? {
+            Algorithm::Ed25519 => PublicKey::from_bytes(&decode(&jwk.x)?),
+            #[cfg(feature = "p256")]
+            Algorithm::P256 => {
+                let y = jwk
+                    .y
+                    .as_ref()
+                    .ok_or_else(|| KeyEncoding::InvalidText("missing y coordinate".to_string()))?;
+
+                // uncompressed SEC1 point
+                let mut bytes = vec![0x04];
+                bytes.extend(decode(&jwk.x)?);
+                bytes.extend(decode(y)?);
+                PublicKey::from_bytes_with_algorithm(&bytes, Algorithm::P256)
+            }
+        }
+    }
+}
+
+impl KeyPair {
+    /// exports to a JSON Web Key, including the private key
+    pub fn to_jwk(&self) -> Jwk {
+        let mut jwk = self.public().to_jwk();
+        let private = self.private_bytes();
+        jwk.d = Some(base64::encode_config(&private[..], base64::URL_SAFE_NO_PAD));
+        jwk
+    }
+
+    /// imports from a JSON Web Key containing an Ed25519 private key
+    ///
+    /// the public key must match the private key
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, Format> {
+        if jwk.algorithm()? != Algorithm::Ed25519 {
+            return Err(KeyEncoding::UnsupportedAlgorithm.into());
+        }
+        let public = PublicKey::from_jwk(jwk)?;
+
+        let d = jwk
+            .d
+            .as_ref()
+            .ok_or_else(|| KeyEncoding::InvalidText("missing private key".to_string()))?;
+        let bytes = Zeroizing::new(
+            base64::decode_config(d, base64::URL_SAFE_NO_PAD)
+                .map_err(|e| KeyEncoding::InvalidText(e.to_string()))?,
+        );
+        let keypair = KeyPair::from(PrivateKey::from_bytes(&bytes)?);
+
+        if keypair.public() != public {
+            return Err(Format::InvalidKey(
+                "the public key does not match the private key".into(),
+            ));
+        }
+
+        Ok(keypair)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jwk() {
+        // RFC 8037, appendix A.1
+        let jwk = Jwk {
+            kty: "OKP".to_string(),
+            crv: "Ed25519".to_string(),
+            x: "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo".to_string(),
+            y: None,
+            d: Some("nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A".to_string()),
+        };
+
+        let keypair = KeyPair::from_jwk(&jwk).unwrap();
+        assert_eq!(
+            hex::encode(keypair.public().to_bytes()),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        assert_eq!(keypair.to_jwk(), jwk);
+
+        let public = PublicKey::from_jwk(&jwk).unwrap();
+        assert_eq!(public, keypair.public());
+        assert_eq!(public.to_jwk().d, None);
+        assert!(KeyPair::from_jwk(&public.to_jwk()).is_err());
+
+        let mut other = KeyPair::new().to_jwk();
+        other.x = jwk.x.clone();
+        assert!(KeyPair::from_jwk(&other).is_err());
+
+        let mut x25519 = jwk.clone();
+        x25519.crv = "X25519".to_string();
+        assert_eq!(
+            PublicKey::from_jwk(&x25519),
+            Err(Format::KeyEncoding(KeyEncoding::UnsupportedAlgorithm))
+        );
+    }
+    #[cfg(feature = "p256")]
+    #[test]
+    fn p256_jwk() {
+        // RFC 7517, appendix A.1
+        let jwk = Jwk {
+            kty: "EC".to_string(),
+            crv: "P-256".to_string(),
+            x: "MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4".to_string(),
+            y: Some("4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM".to_string()),
+            d: None,
+        };
+
+        let public = PublicKey::from_jwk(&jwk).unwrap();
+        assert_eq!(public.algorithm(), Algorithm::P256);
+        assert_eq!(public.to_jwk(), jwk);
+        assert_eq!(
+            KeyPair::from_jwk(&jwk).unwrap_err(),
+            Format::KeyEncoding(KeyEncoding::UnsupportedAlgorithm)
+        );
+
+        let mut missing_y = jwk.clone();
+        missing_y.y = None;
+        assert!(PublicKey::from_jwk(&missing_y).is_err());
+    }
+}
@@ -5,15 +5,33 @@
 //! and a valid signature, it is possible to add a new message and produce a valid
 //! signature for the whole.
 //!
-//! The implementation is based on [ed25519_dalek](https://github.com/dalek-cryptography/ed25519-dalek).
+//! The default implementation is based on [ed25519_dalek](https://github.com/dalek-cryptography/ed25519-dalek).
+//! Other implementations of Ed25519 (platform crypto, HSM, FIPS validated modules)
+//! can be used through the [Signer] and [Verifier] traits.
+//!
+//! With the `p256` feature, the root key and the keys of third party blocks can
+//! also be ECDSA P-256 keys, see [P256KeyPair]. The keys of the following blocks
+//! are always Ed25519 keys.
 #![allow(non_snake_case)]
 use crate::error::Format;
+use alloc::vec::Vec;
 
 use super::error;
-use ed25519_dalek::*;
+use core::{convert::TryInto, ops::Drop};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ed25519_dalek::SecretKey;
+use ed25519_dalek::Signer as _;
 use rand_core::{CryptoRng, RngCore};
-use std::{convert::TryInto, ops::Drop};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
+
+mod encoding;
+mod jwk;
+#[cfg(feature = "p256")]
+mod p256;
+
+#[cfg(feature = "p256")]
+pub use self::p256::P256KeyPair;
+pub use jwk::Jwk;
 
 /// pair of cryptographic keys used to sign a token's block
 #[derive(Debug)]
@@ -22,6 +40,7 @@ pub struct KeyPair {
 }
 
 impl KeyPair {
+    #[cfg(feature = "std")]
     pub fn new() -> Self {
         Self::new_with_rng(&mut rand::rngs::OsRng)
     }
@@ -32,8 +51,34 @@ impl KeyPair {
         KeyPair { kp }
     }
 
+    /// creates a key pair from a 32 bytes seed
+    ///
+    /// the seed is the Ed25519 private key, so it must be kept as secret as a private key
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let secret = SecretKey::from_bytes(seed).unwrap();
+        let public = (&secret).into();
+
+        KeyPair {
+            kp: ed25519_dalek::Keypair { secret, public },
+        }
+    }
+
+    /// derives a new key pair from this one and a context, with HKDF-SHA256
+    ///
+    /// this can be used to generate per tenant or per environment root keys
+    /// from a single master key. The same context always gives the same key pair
+    pub fn derive_key(&self, context: &[u8]) -> KeyPair {
+        let hkdf = hkdf::Hkdf::<sha2::Sha256>::new(None, &*self.private_bytes());
+
+        let mut seed = Zeroizing::new([0u8; 32]);
+        hkdf.expand(context, &mut *seed)
+            .expect("32 bytes is a valid output length for HKDF-SHA256");
+
+        KeyPair::from_seed(&seed)
+    }
+
     pub fn from(key: PrivateKey) -> Self {
-        let secret = SecretKey::from_bytes(&key.0.to_bytes()).unwrap();
+        let secret = SecretKey::from_bytes(&*key.private_bytes()).unwrap();
 
         let public = (&key.0).into();
 
@@ -43,16 +88,22 @@ impl KeyPair {
     }
 
     pub fn private(&self) -> PrivateKey {
-        let secret = SecretKey::from_bytes(&self.kp.secret.to_bytes()).unwrap();
+        let secret = SecretKey::from_bytes(&*self.private_bytes()).unwrap();
         PrivateKey(secret)
     }
 
+    /// returns the private key's bytes, wiped from memory when dropped
+    pub fn private_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.kp.secret.to_bytes())
+    }
+
     pub fn public(&self) -> PublicKey {
-        PublicKey(self.kp.public)
+        PublicKey(Key::Ed25519(self.kp.public))
     }
 }
 
-impl std::default::Default for KeyPair {
+#[cfg(feature = "std")]
+impl core::default::Default for KeyPair {
     fn default() -> Self {
         Self::new()
     }
@@ -70,30 +121,40 @@ pub struct PrivateKey(pub(crate) ed25519_dalek::SecretKey);
 
 impl PrivateKey {
     /// serializes to a byte array
+    ///
+    /// the caller is responsible for wiping the array, [PrivateKey::private_bytes]
+    /// does it automatically
     pub fn to_bytes(&self) -> [u8; 32] {
         self.0.to_bytes()
     }
 
+    /// serializes to a byte array, wiped from memory when dropped
+    pub fn private_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.0.to_bytes())
+    }
+
     /// deserializes from a byte array
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, error::Format> {
-        let bytes: [u8; 32] = bytes
-            .try_into()
-            .map_err(|_| Format::InvalidKeySize(bytes.len()))?;
-        SecretKey::from_bytes(&bytes)
+        let bytes: Zeroizing<[u8; 32]> = Zeroizing::new(
+            bytes
+                .try_into()
+                .map_err(|_| Format::InvalidKeySize(bytes.len()))?,
+        );
+        SecretKey::from_bytes(&*bytes)
             .map(PrivateKey)
-            .map_err(|s| s.to_string())
+            .map_err(error::Cause::from_dependency)
             .map_err(Format::InvalidKey)
     }
 
     /// returns the matching public key
     pub fn public(&self) -> PublicKey {
-        PublicKey((&self.0).into())
+        PublicKey(Key::Ed25519((&self.0).into()))
     }
 }
 
-impl std::clone::Clone for PrivateKey {
+impl core::clone::Clone for PrivateKey {
     fn clone(&self) -> Self {
-        PrivateKey::from_bytes(&self.to_bytes()).unwrap()
+        PrivateKey::from_bytes(&*self.private_bytes()).unwrap()
     }
 }
 
@@ -103,30 +164,299 @@ impl Drop for PrivateKey {
     }
 }
 
-/// the public part of a [KeyPair]
+/// signature algorithms
+///
+/// the discriminant is the identifier used in the serialization format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum Algorithm {
+    Ed25519 = 0,
+    /// ECDSA on the P-256 curve (secp256r1), with SHA-256
+    #[cfg(feature = "p256")]
+    P256 = 1,
+}
+
+impl Algorithm {
+    /// converts from the serialization format's identifier
+    pub fn from_i32(id: i32) -> Option<Self> {
+        match id {
+            0 => Some(Algorithm::Ed25519),
+            #[cfg(feature = "p256")]
+            1 => Some(Algorithm::P256),
+            _ => None,
+        }
+    }
+
+    /// name of the algorithm in the textual representation of public keys
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Ed25519 => "ed25519",
+            #[cfg(feature = "p256")]
+            Algorithm::P256 => "secp256r1",
+        }
+    }
+
+    /// the algorithms supported with the enabled features
+    pub fn supported() -> Vec<Self> {
+        #[allow(unused_mut)]
+        let mut algorithms = vec![Algorithm::Ed25519];
+        #[cfg(feature = "p256")]
+        algorithms.push(Algorithm::P256);
+
+        algorithms
+    }
+
+    /// converts from the name used in the textual representation of public keys
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ed25519" => Some(Algorithm::Ed25519),
+            #[cfg(feature = "p256")]
+            "secp256r1" => Some(Algorithm::P256),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for KeyPair {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(KeyPair::from_seed(&u.arbitrary()?))
+    }
+}
+
+/// the public part of a [KeyPair], or of a key using another [Algorithm]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct PublicKey(pub(crate) ed25519_dalek::PublicKey);
+pub struct PublicKey(pub(crate) Key);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Key {
+    Ed25519(ed25519_dalek::PublicKey),
+    #[cfg(feature = "p256")]
+    P256(::p256::ecdsa::VerifyingKey),
+}
 
 impl PublicKey {
+    /// the signature algorithm used with this key
+    pub fn algorithm(&self) -> Algorithm {
+        match self.0 {
+            Key::Ed25519(_) => Algorithm::Ed25519,
+            #[cfg(feature = "p256")]
+            Key::P256(_) => Algorithm::P256,
+        }
+    }
+
     /// serializes to a byte array
-    pub fn to_bytes(&self) -> [u8; 32] {
-        self.0.to_bytes()
+    ///
+    /// Ed25519 keys are 32 bytes long, P-256 keys are serialized as
+    /// compressed SEC1 points of 33 bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match &self.0 {
+            Key::Ed25519(key) => key.to_bytes().to_vec(),
+            #[cfg(feature = "p256")]
+            Key::P256(key) => key.to_encoded_point(true).as_bytes().to_vec(),
+        }
     }
 
-    /// deserializes from a byte array
+    /// deserializes an Ed25519 key from a byte array
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, error::Format> {
         ed25519_dalek::PublicKey::from_bytes(bytes)
-            .map(PublicKey)
-            .map_err(|s| s.to_string())
+            .map(|key| PublicKey(Key::Ed25519(key)))
+            .map_err(error::Cause::from_dependency)
             .map_err(Format::InvalidKey)
     }
+
+    /// deserializes a key of the given algorithm from a byte array
+    ///
+    /// P-256 keys can be compressed or uncompressed SEC1 points
+    pub fn from_bytes_with_algorithm(
+        bytes: &[u8],
+        algorithm: Algorithm,
+    ) -> Result<Self, error::Format> {
+        match algorithm {
+            Algorithm::Ed25519 => PublicKey::from_bytes(bytes),
+            #[cfg(feature = "p256")]
+            Algorithm::P256 => ::p256::ecdsa::VerifyingKey::from_sec1_bytes(bytes)
+                .map(|key| PublicKey(Key::P256(key)))
+                .map_err(error::Cause::from_dependency)
+                .map_err(Format::InvalidKey),
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for PublicKey {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(KeyPair::arbitrary(u)?.public())
+    }
+}
+
+impl core::fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            self.algorithm().name(),
+            hex::encode(self.to_bytes())
+        )
+    }
+}
+
+/// serialized in the `Display` format, `<algorithm>/<hex>`
+#[cfg(feature = "serde")]
+impl serde::Serialize for PublicKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// also accepts an hex string without algorithm, for the Ed25519 keys
+/// serialized by previous versions
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PublicKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use alloc::string::String;
+        use serde::de::Error;
+
+        let s = String::deserialize(deserializer)?;
+        if s.contains('/') {
+            return s.parse().map_err(D::Error::custom);
+        }
+
+        let bytes = hex::decode(&s).map_err(D::Error::custom)?;
+        PublicKey::from_bytes(&bytes).map_err(D::Error::custom)
+    }
+}
+
+/// serialized as an hex string
+#[cfg(feature = "serde")]
+impl serde::Serialize for PrivateKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = Zeroizing::new(hex::encode(self.private_bytes().as_ref()));
+        serializer.serialize_str(&encoded)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PrivateKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use alloc::string::String;
+        use serde::de::Error;
+
+        let s = Zeroizing::new(String::deserialize(deserializer)?);
+        let bytes = Zeroizing::new(hex::decode(&*s).map_err(D::Error::custom)?);
+        PrivateKey::from_bytes(&bytes).map_err(D::Error::custom)
+    }
+}
+
+/// signature of a block
+///
+/// Ed25519 signatures and ECDSA P-256 signatures, in their fixed size
+/// `r || s` encoding, are both 64 bytes long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature(pub(crate) [u8; 64]);
+
+impl Signature {
+    /// serializes to a byte array
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.0
+    }
+
+    /// deserializes from a byte array
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, error::Format> {
+        bytes
+            .try_into()
+            .map(Signature)
+            .map_err(|_| Format::InvalidSignatureSize(bytes.len()))
+    }
+}
+
+impl From<[u8; 64]> for Signature {
+    fn from(bytes: [u8; 64]) -> Self {
+        Signature(bytes)
+    }
+}
+
+impl From<ed25519_dalek::Signature> for Signature {
+    fn from(signature: ed25519_dalek::Signature) -> Self {
+        Signature(signature.to_bytes())
+    }
+}
+
+/// signs the data of a token's blocks
+pub trait Signer {
+    /// the public key matching the signing key
+    fn public_key(&self) -> PublicKey;
+
+    /// signs a message with the algorithm of [Signer::public_key]
+    fn sign(&self, message: &[u8]) -> Result<Signature, error::Format>;
+}
+
+impl Signer for KeyPair {
+    fn public_key(&self) -> PublicKey {
+        self.public()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature, error::Format> {
+        self.kp
+            .try_sign(message)
+            .map(Signature::from)
+            .map_err(error::Cause::from_dependency)
+            .map_err(error::Signature::InvalidSignatureGeneration)
+            .map_err(error::Format::Signature)
+    }
+}
+
+/// verifies the signatures of a token's blocks
+pub trait Verifier {
+    /// verifies a signature with the algorithm of `public_key`
+    fn verify(
+        &self,
+        public_key: &PublicKey,
+        message: &[u8],
+        signature: &Signature,
+    ) -> Result<(), error::Format>;
+}
+
+/// default [Verifier], using ed25519-dalek's strict verification
+///
+/// with the `p256` feature, P-256 signatures are verified with the `p256`
+/// crate, and rejected if `s` is not normalized to the lower half of the
+/// curve order, so that a signature has only one valid encoding
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DalekVerifier;
+
+impl Verifier for DalekVerifier {
+    fn verify(
+        &self,
+        public_key: &PublicKey,
+        message: &[u8],
+        signature: &Signature,
+    ) -> Result<(), error::Format> {
+        match &public_key.0 {
+            Key::Ed25519(key) => key
+                .verify_strict(message, &ed25519_dalek::Signature::from(signature.0))
+                .map_err(error::Cause::from_dependency)
+                .map_err(error::Signature::InvalidSignature)
+                .map_err(error::Format::Signature),
+            #[cfg(feature = "p256")]
+            Key::P256(key) => self::p256::verify(key, message, signature),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Block {
     pub(crate) data: Vec<u8>,
     pub(crate) next_key: PublicKey,
-    pub signature: ed25519_dalek::Signature,
+    pub signature: Signature,
+    pub external_signature: Option<ExternalSignature>,
+}
+
+/// signature of a third party block, made by a key that is not part of the token's chain
+#[derive(Clone, Debug)]
+pub struct ExternalSignature {
+    pub(crate) public_key: PublicKey,
+    pub(crate) signature: Signature,
 }
 
 #[derive(Clone, Debug)]
@@ -139,43 +469,172 @@ pub struct Token {
 #[derive(Clone, Debug)]
 pub enum TokenNext {
     Secret(PrivateKey),
-    Seal(ed25519_dalek::Signature),
+    Seal(Signature),
 }
 
 pub fn sign(
-    keypair: &KeyPair,
+    signer: &dyn Signer,
     next_key: &KeyPair,
     message: &[u8],
+    external_signature: Option<&ExternalSignature>,
 ) -> Result<Signature, error::Token> {
-    //FIXME: replace with SHA512 hashing
-    let mut to_sign = message.to_vec();
-    to_sign.extend(&(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes());
-    to_sign.extend(&next_key.public().to_bytes());
+    let to_sign = signature_message(message, external_signature, &next_key.public());
 
-    let signature = keypair
-        .kp
-        .try_sign(&to_sign)
-        .map_err(|s| s.to_string())
-        .map_err(error::Signature::InvalidSignatureGeneration)
-        .map_err(error::Format::Signature)?;
+    let signature = signer.sign(&to_sign)?;
 
     Ok(signature)
 }
 
-pub fn verify_block_signature(block: &Block, public_key: &PublicKey) -> Result<(), error::Format> {
+/// data to sign for a new block
+pub(crate) fn signature_message(
+    message: &[u8],
+    external_signature: Option<&ExternalSignature>,
+    next_key: &PublicKey,
+) -> Vec<u8> {
     //FIXME: replace with SHA512 hashing
-    let mut to_verify = block.data.to_vec();
-    to_verify.extend(&(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes());
-    to_verify.extend(&block.next_key.to_bytes());
-
-    public_key
-        .0
-        .verify_strict(&to_verify, &block.signature)
-        .map_err(|s| s.to_string())
+    let mut to_sign = message.to_vec();
+    if let Some(signature) = external_signature {
+        to_sign.extend(&signature.signature.to_bytes());
+    }
+    to_sign.extend(&(next_key.algorithm() as i32).to_le_bytes());
+    to_sign.extend(&next_key.to_bytes());
+
+    to_sign
+}
+
+/// data covered by a block's signature
+pub(crate) fn block_signature_message(block: &Block) -> Vec<u8> {
+    signature_message(
+        &block.data,
+        block.external_signature.as_ref(),
+        &block.next_key,
+    )
+}
+
+pub fn verify_block_signature(
+    block: &Block,
+    public_key: &PublicKey,
+    verifier: &dyn Verifier,
+) -> Result<(), error::Format> {
+    let to_verify = block_signature_message(block);
+
+    verifier.verify(public_key, &to_verify, &block.signature)
+}
+
+/// verifies multiple signatures at once
+///
+/// this is faster than verifying them one by one, but it does not
+/// indicate which signature failed. Like [DalekVerifier], it rejects
+/// small order public keys and signature points. All the keys must be
+/// Ed25519 keys
+///
+/// without the `std` feature, the signatures are verified one by one, as
+/// batch verification draws random scalars from the operating system's RNG
+pub fn verify_batch(
+    messages: &[&[u8]],
+    signatures: &[Signature],
+    public_keys: &[PublicKey],
+) -> Result<(), error::Format> {
+    let public_keys = public_keys
+        .iter()
+        .map(|k| match k.0 {
+            Key::Ed25519(key) => Ok(key),
+            #[cfg(feature = "p256")]
+            _ => Err(error::Format::Signature(
+                error::Signature::InvalidSignature(
+                    "batch verification only supports Ed25519 keys".into(),
+                ),
+            )),
+        })
+        .collect::<Result<Vec<_>, error::Format>>()?;
+
+    let points = public_keys
+        .iter()
+        .map(|k| k.to_bytes())
+        .chain(signatures.iter().map(|s| {
+            let mut r = [0u8; 32];
+            r.copy_from_slice(&s.to_bytes()[..32]);
+            r
+        }));
+    for point in points {
+        let small_order = match CompressedEdwardsY(point).decompress() {
+            Some(point) => point.is_small_order(),
+            None => true,
+        };
+        if small_order {
+            return Err(error::Format::Signature(
+                error::Signature::InvalidSignature("small order point in batch".into()),
+            ));
+        }
+    }
+
+    let signatures = signatures
+        .iter()
+        .map(|s| ed25519_dalek::Signature::from(s.0))
+        .collect::<Vec<_>>();
+
+    #[cfg(feature = "std")]
+    let result = ed25519_dalek::verify_batch(messages, &signatures, &public_keys);
+    #[cfg(not(feature = "std"))]
+    let result = if messages.len() != signatures.len() || messages.len() != public_keys.len() {
+        Err(ed25519_dalek::SignatureError::new())
+    } else {
+        messages
+            .iter()
+            .zip(signatures.iter())
+            .zip(public_keys.iter())
+            .try_for_each(|((message, signature), key)| key.verify_strict(message, signature))
+    };
+
+    result
+        .map_err(error::Cause::from_dependency)
         .map_err(error::Signature::InvalidSignature)
-        .map_err(error::Format::Signature)?;
+        .map_err(error::Format::Signature)
+}
 
-    Ok(())
+/// signs a third party block
+///
+/// the signature covers the serialized block and the signature of the
+/// previous block, so it cannot be moved to another token
+pub fn sign_external(
+    signer: &dyn Signer,
+    payload: &[u8],
+    previous_signature: &Signature,
+) -> Result<ExternalSignature, error::Token> {
+    let to_sign = external_signature_message(payload, previous_signature);
+
+    let signature = signer.sign(&to_sign)?;
+
+    Ok(ExternalSignature {
+        public_key: signer.public_key(),
+        signature,
+    })
+}
+
+/// data covered by the signature of a third party block
+pub(crate) fn external_signature_message(
+    payload: &[u8],
+    previous_signature: &Signature,
+) -> Vec<u8> {
+    let mut to_sign = payload.to_vec();
+    to_sign.extend(&previous_signature.to_bytes());
+
+    to_sign
+}
+
+pub fn verify_external_signature(
+    payload: &[u8],
+    previous_signature: &Signature,
+    external_signature: &ExternalSignature,
+    verifier: &dyn Verifier,
+) -> Result<(), error::Format> {
+    let to_verify = external_signature_message(payload, previous_signature);
+
+    verifier.verify(
+        &external_signature.public_key,
+        &to_verify,
+        &external_signature.signature,
+    )
 }
 
 impl Token {
@@ -185,12 +644,13 @@ impl Token {
         next_key: &KeyPair,
         message: &[u8],
     ) -> Result<Self, error::Token> {
-        let signature = sign(keypair, next_key, message)?;
+        let signature = sign(keypair, next_key, message, None)?;
 
         let block = Block {
             data: message.to_vec(),
             next_key: next_key.public(),
             signature,
+            external_signature: None,
         };
 
         Ok(Token {
@@ -211,12 +671,13 @@ impl Token {
             other => other,
         }?;
 
-        let signature = sign(&keypair, next_key, message)?;
+        let signature = sign(&keypair, next_key, message, None)?;
 
         let block = Block {
             data: message.to_vec(),
             next_key: next_key.public(),
             signature,
+            external_signature: None,
         };
 
         let mut t = Token {
@@ -236,7 +697,7 @@ impl Token {
         let mut current_pub = root;
 
         for block in &self.blocks {
-            verify_block_signature(block, &current_pub)?;
+            verify_block_signature(block, &current_pub, &DalekVerifier)?;
             current_pub = block.next_key;
         }
 
@@ -244,7 +705,7 @@ impl Token {
             TokenNext::Secret(private) => {
                 if current_pub != private.public() {
                     return Err(error::Format::Signature(error::Signature::InvalidSignature(
-                        "the last public key does not match the private key".to_string(),
+                        "the last public key does not match the private key".into(),
                     ))
                     .into());
                 }
@@ -257,12 +718,7 @@ impl Token {
                     to_verify.extend(&block.next_key.to_bytes());
                 }
 
-                current_pub
-                    .0
-                    .verify_strict(&to_verify, signature)
-                    .map_err(|s| s.to_string())
-                    .map_err(error::Signature::InvalidSignature)
-                    .map_err(error::Format::Signature)?;
+                DalekVerifier.verify(&current_pub, &to_verify, signature)?;
             }
         }
 
@@ -281,6 +737,50 @@ impl TokenNext {
 
 #[cfg(test)]
 mod tests {
+    use super::KeyPair;
+
+    #[test]
+    fn key_derivation() {
+        // RFC 8032, section 7.1, test 1
+        let seed = hex::decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+            .unwrap();
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&seed);
+        let master = KeyPair::from_seed(&bytes);
+        assert_eq!(
+            hex::encode(master.public().to_bytes()),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+
+        let tenant1 = master.derive_key(b"tenant-1");
+        let tenant2 = master.derive_key(b"tenant-2");
+        assert_eq!(tenant1.public(), master.derive_key(b"tenant-1").public());
+        assert_ne!(tenant1.public(), tenant2.public());
+        assert_ne!(tenant1.public(), master.public());
+    }
+
+    #[test]
+    fn batch_rejects_small_order_points() {
+        use super::{verify_batch, DalekVerifier, PublicKey, Signature, Signer, Verifier};
+
+        // with the identity as public key and signature point, and a zero
+        // scalar, the verification equation holds for any message
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let key = PublicKey::from_bytes(&identity).unwrap();
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&identity);
+        let signature = Signature::from_bytes(&bytes).unwrap();
+        let message = &b"hello"[..];
+
+        assert!(DalekVerifier.verify(&key, message, &signature).is_err());
+        assert!(verify_batch(&[message], &[signature], &[key]).is_err());
+
+        let keypair = KeyPair::new();
+        let signature = keypair.sign(message).unwrap();
+        assert!(verify_batch(&[message], &[signature], &[keypair.public()]).is_ok());
+    }
+
     /*
     use super::*;
     use rand::prelude::*;
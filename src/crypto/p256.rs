@@ -0,0 +1,165 @@
+//! ECDSA signatures on the P-256 curve, with the `p256` feature
+//!
+//! some HSMs and platform key stores can only produce P-256 signatures.
+//! A [P256KeyPair], or any [Signer] returning a P-256 public key, can sign the
+//! authority block of a token or a third party block. Verifiers accept those
+//! signatures once [Algorithm::P256](crate::Algorithm::P256) is in
+//! [DeserializationLimits::algorithms](crate::DeserializationLimits::algorithms)
+use super::{Key, PublicKey, Signature, Signer};
+use crate::error::{self, Format};
+use p256::ecdsa::signature::{Signer as _, Verifier as _};
+use p256::ecdsa::{SigningKey, VerifyingKey};
+use rand_core::{CryptoRng, RngCore};
+use zeroize::Zeroizing;
+
+/// ECDSA P-256 key pair
+///
+/// the private key is wiped from memory when the key pair is dropped
+#[derive(Clone, Debug)]
+pub struct P256KeyPair {
+    kp: SigningKey,
+}
+
+impl P256KeyPair {
+    #[cfg(feature = "std")]
+    pub fn new() -> Self {
+        Self::new_with_rng(&mut rand::rngs::OsRng)
+    }
+
+    pub fn new_with_rng<T: RngCore + CryptoRng>(rng: &mut T) -> Self {
+        // a random 32 bytes string is a valid scalar, except with a
+        // probability of about 2^-32
+        loop {
+            let mut bytes = Zeroizing::new([0u8; 32]);
+            rng.fill_bytes(&mut *bytes);
+
+            if let Ok(keypair) = P256KeyPair::from_bytes(&*bytes) {
+                return keypair;
+            }
+        }
+    }
+
+    /// deserializes the private key from a 32 bytes big endian scalar
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Format> {
+        SigningKey::from_slice(bytes)
+            .map(|kp| P256KeyPair { kp })
+            .map_err(error::Cause::from_dependency)
+            .map_err(Format::InvalidKey)
+    }
+
+    /// returns the private key's bytes, wiped from memory when dropped
+    pub fn private_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.kp.to_bytes().into())
+    }
+
+    pub fn public(&self) -> PublicKey {
+        PublicKey(Key::P256(*self.kp.verifying_key()))
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::default::Default for P256KeyPair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signer for P256KeyPair {
+    fn public_key(&self) -> PublicKey {
+        self.public()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature, Format> {
+        let signature: p256::ecdsa::Signature = self
+            .kp
+            .try_sign(message)
+            .map_err(error::Cause::from_dependency)
+            .map_err(error::Signature::InvalidSignatureGeneration)
+            .map_err(Format::Signature)?;
+        let signature = signature.normalize_s().unwrap_or(signature);
+
+        Signature::from_bytes(&signature.to_bytes())
+    }
+}
+
+/// verifies a signature, rejecting the ones where `s` is not normalized
+pub(super) fn verify(
+    key: &VerifyingKey,
+    message: &[u8],
+    signature: &Signature,
+) -> Result<(), Format> {
+    let signature = p256::ecdsa::Signature::from_slice(&signature.0)
+        .map_err(error::Cause::from_dependency)
+        .map_err(error::Signature::InvalidSignature)
+        .map_err(Format::Signature)?;
+
+    if signature.normalize_s().is_some() {
+        return Err(Format::Signature(error::Signature::InvalidSignature(
+            "the P-256 signature is not normalized".into(),
+        )));
+    }
+
+    key.verify(message, &signature)
+        .map_err(error::Cause::from_dependency)
+        .map_err(error::Signature::InvalidSignature)
+        .map_err(Format::Signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{Algorithm, DalekVerifier, Verifier};
+
+    #[test]
+    fn sign_and_verify() {
+        let keypair = P256KeyPair::new();
+        let public = keypair.public();
+        assert_eq!(public.algorithm(), Algorithm::P256);
+
+        let message = &b"hello"[..];
+        let signature = keypair.sign(message).unwrap();
+        DalekVerifier.verify(&public, message, &signature).unwrap();
+        assert!(DalekVerifier.verify(&public, b"world", &signature).is_err());
+        assert!(DalekVerifier
+            .verify(&P256KeyPair::new().public(), message, &signature)
+            .is_err());
+
+        // the same signature with s replaced by n - s is valid ECDSA, but
+        // would give another revocation id for the same block
+        let high_s = p256::ecdsa::Signature::from_slice(&signature.0).unwrap();
+        let (r, s) = high_s.split_scalars();
+        let high_s = p256::ecdsa::Signature::from_scalars(r, -*s).unwrap();
+        assert!(key_verify(&public, message, &high_s));
+        let high_s = Signature::from_bytes(&high_s.to_bytes()).unwrap();
+        assert!(DalekVerifier.verify(&public, message, &high_s).is_err());
+
+        let bytes = public.to_bytes();
+        assert_eq!(bytes.len(), 33);
+        assert_eq!(
+            PublicKey::from_bytes_with_algorithm(&bytes, Algorithm::P256).unwrap(),
+            public
+        );
+        assert!(PublicKey::from_bytes(&bytes).is_err());
+
+        let restored = P256KeyPair::from_bytes(&*keypair.private_bytes()).unwrap();
+        assert_eq!(restored.public(), public);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let public = P256KeyPair::new().public();
+
+        let json = serde_json::to_string(&public).unwrap();
+        assert_eq!(json, format!("\"{}\"", public));
+        assert!(json.starts_with("\"secp256r1/"));
+        assert_eq!(serde_json::from_str::<PublicKey>(&json).unwrap(), public);
+    }
+
+    fn key_verify(key: &PublicKey, message: &[u8], signature: &p256::ecdsa::Signature) -> bool {
+        match &key.0 {
+            Key::P256(key) => key.verify(message, signature).is_ok(),
+            _ => false,
+        }
+    }
+}
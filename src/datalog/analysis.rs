@@ -0,0 +1,294 @@
+//! static analysis of the rules of a world
+use super::{ExternalPredicates, SymbolIndex, World};
+use crate::collections::{HashMap, HashSet};
+use alloc::vec::Vec;
+
+/// problems found in the rules of a world, see [World::analyze_rules]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RuleDiagnostics {
+    /// groups of rules that depend on each other without a base case, by
+    /// index in the world's rules
+    ///
+    /// recursive rules are common in Datalog, like `ancestor($a, $c) <-
+    /// parent($a, $b), ancestor($b, $c)`, and are only reported when no
+    /// facts and no other rule can start the recursion, so it can never
+    /// generate anything
+    pub cycles: Vec<Vec<usize>>,
+    /// rules that can never generate facts
+    pub unproductive: Vec<UnproductiveRule>,
+}
+
+impl RuleDiagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty() && self.unproductive.is_empty()
+    }
+}
+
+/// rule with a predicate in its body that has no facts and is
+/// not generated by any other rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnproductiveRule {
+    /// index of the rule in the world's rules
+    pub rule: usize,
+    /// name of the predicate that cannot match
+    pub missing: SymbolIndex,
+}
+
+impl World {
+    /// returns, for each rule, the indexes of the rules generating
+    /// the facts used in its body
+    pub fn rule_dependencies(&self) -> Vec<Vec<usize>> {
+        let mut producers: HashMap<SymbolIndex, Vec<usize>> = HashMap::default();
+        for (i, rule) in self.rules.iter().enumerate() {
+            producers.entry(rule.head.name).or_default().push(i);
+        }
+
+        self.rules
+            .iter()
+            .map(|rule| {
+                let mut dependencies = rule
+                    .body
+                    .iter()
+                    .flat_map(|p| producers.get(&p.name).into_iter().flatten().copied())
+                    .collect::<Vec<_>>();
+                dependencies.sort_unstable();
+                dependencies.dedup();
+                dependencies
+            })
+            .collect()
+    }
+
+    /// looks for recursive and unproductive rules, without running them
+    pub fn analyze_rules(&self) -> RuleDiagnostics {
+        self.analyze_rules_with_externals(&ExternalPredicates::new())
+    }
+
+    /// looks for recursive and unproductive rules, without running them
+    ///
+    /// the external predicates are considered as having facts
+    pub fn analyze_rules_with_externals(&self, externals: &ExternalPredicates) -> RuleDiagnostics {
+        // a predicate is productive if it has facts, if it is external, or if
+        // a rule using only productive predicates generates it
+        let mut productive: HashSet<SymbolIndex> = self.facts.predicates().collect();
+        productive.extend(externals.names());
+        let mut productive_rules = vec![false; self.rules.len()];
+        loop {
+            let mut changed = false;
+            for (i, rule) in self.rules.iter().enumerate() {
+                if !productive_rules[i] && rule.body.iter().all(|p| productive.contains(&p.name)) {
+                    productive_rules[i] = true;
+                    productive.insert(rule.head.name);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let unproductive = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !productive_rules[*i])
+            .filter_map(|(i, rule)| {
+                rule.body
+                    .iter()
+                    .find(|p| !productive.contains(&p.name))
+                    .map(|p| UnproductiveRule {
+                        rule: i,
+                        missing: p.name,
+                    })
+            })
+            .collect();
+
+        let dependencies = self.rule_dependencies();
+        let cycles = strongly_connected_components(&dependencies)
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || dependencies[component[0]].contains(&component[0])
+            })
+            .filter(|component| component.iter().all(|i| !productive_rules[*i]))
+            .collect();
+
+        RuleDiagnostics {
+            cycles,
+            unproductive,
+        }
+    }
+}
+
+/// Tarjan's algorithm, each component is sorted
+fn strongly_connected_components(graph: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        graph: &'a [Vec<usize>],
+        index: usize,
+        indexes: Vec<Option<usize>>,
+        lowlinks: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn visit(state: &mut State, v: usize) {
+        state.indexes[v] = Some(state.index);
+        state.lowlinks[v] = state.index;
+        state.index += 1;
+        state.stack.push(v);
+        state.on_stack[v] = true;
+
+        let graph = state.graph;
+        for &w in graph[v].iter() {
+            match state.indexes[w] {
+                None => {
+                    visit(state, w);
+                    state.lowlinks[v] = state.lowlinks[v].min(state.lowlinks[w]);
+                }
+                Some(index) if state.on_stack[w] => {
+                    state.lowlinks[v] = state.lowlinks[v].min(index);
+                }
+                _ => {}
+            }
+        }
+
+        if state.indexes[v] == Some(state.lowlinks[v]) {
+            let mut component = Vec::new();
+            while let Some(w) = state.stack.pop() {
+                state.on_stack[w] = false;
+                component.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            component.sort_unstable();
+            state.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        graph,
+        index: 0,
+        indexes: vec![None; graph.len()],
+        lowlinks: vec![0; graph.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; graph.len()],
+        components: Vec::new(),
+    };
+
+    for v in 0..graph.len() {
+        if state.indexes[v].is_none() {
+            visit(&mut state, v);
+        }
+    }
+
+    state.components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalog::{fact, int, pred, rule, var, SymbolTable};
+
+    #[test]
+    fn analyze() {
+        let mut syms = SymbolTable::new();
+        let parent = syms.insert("parent");
+        let ancestor = syms.insert("ancestor");
+        let even = syms.insert("even");
+        let odd = syms.insert("odd");
+        let number = syms.insert("number");
+        let missing = syms.insert("missing");
+        let unused = syms.insert("unused");
+
+        let mut w = World::new();
+        w.add_fact(fact(parent, &[int(0), int(1)]));
+        w.add_fact(fact(number, &[int(0)]));
+
+        // 0: ancestor($a, $b) <- parent($a, $b)
+        w.add_rule(rule(
+            ancestor,
+            &[var(&mut syms, "a"), var(&mut syms, "b")],
+            &[pred(parent, &[var(&mut syms, "a"), var(&mut syms, "b")])],
+        ));
+        // 1: ancestor($a, $c) <- parent($a, $b), ancestor($b, $c)
+        w.add_rule(rule(
+            ancestor,
+            &[var(&mut syms, "a"), var(&mut syms, "c")],
+            &[
+                pred(parent, &[var(&mut syms, "a"), var(&mut syms, "b")]),
+                pred(ancestor, &[var(&mut syms, "b"), var(&mut syms, "c")]),
+            ],
+        ));
+        // 2: even($a) <- number($a), odd($a)
+        w.add_rule(rule(
+            even,
+            &[var(&mut syms, "a")],
+            &[
+                pred(number, &[var(&mut syms, "a")]),
+                pred(odd, &[var(&mut syms, "a")]),
+            ],
+        ));
+        // 3: odd($a) <- even($a)
+        w.add_rule(rule(
+            odd,
+            &[var(&mut syms, "a")],
+            &[pred(even, &[var(&mut syms, "a")])],
+        ));
+        // 4: unused($a) <- missing($a)
+        w.add_rule(rule(
+            unused,
+            &[var(&mut syms, "a")],
+            &[pred(missing, &[var(&mut syms, "a")])],
+        ));
+
+        assert_eq!(
+            w.rule_dependencies(),
+            vec![vec![], vec![0, 1], vec![3], vec![2], vec![]]
+        );
+
+        let diagnostics = w.analyze_rules();
+        let mut cycles = diagnostics.cycles.clone();
+        cycles.sort();
+        // the recursion of `ancestor` starts from rule 0
+        assert_eq!(cycles, vec![vec![2, 3]]);
+        assert_eq!(
+            diagnostics.unproductive,
+            vec![
+                UnproductiveRule {
+                    rule: 2,
+                    missing: odd
+                },
+                UnproductiveRule {
+                    rule: 3,
+                    missing: even
+                },
+                UnproductiveRule { rule: 4, missing },
+            ]
+        );
+
+        let mut externals = ExternalPredicates::new();
+        externals.insert(missing, std::sync::Arc::new(|_, _| vec![]));
+        // 5: used($a) <- unused($a)
+        let used = syms.insert("used");
+        w.add_rule(rule(
+            used,
+            &[var(&mut syms, "a")],
+            &[pred(unused, &[var(&mut syms, "a")])],
+        ));
+        let diagnostics = w.analyze_rules_with_externals(&externals);
+        assert_eq!(
+            diagnostics.unproductive,
+            vec![
+                UnproductiveRule {
+                    rule: 2,
+                    missing: odd
+                },
+                UnproductiveRule {
+                    rule: 3,
+                    missing: even
+                },
+            ]
+        );
+    }
+}
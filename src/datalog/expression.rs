@@ -1,13 +1,21 @@
 use super::Term;
 use super::{SymbolTable, TemporarySymbolTable};
+use crate::collections::HashMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::net::IpAddr;
+#[cfg(feature = "std")]
 use regex::Regex;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Expression {
     pub ops: Vec<Op>,
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Op {
     Value(Term),
@@ -15,15 +23,25 @@ pub enum Op {
     Binary(Binary),
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unary {
     Negate,
     Parens,
     Length,
+    /// SHA-256 hash of a byte array, or of the UTF-8 bytes of a string
+    Sha256,
+    /// conversions between byte arrays and hex or base64 strings
+    ToHex,
+    FromHex,
+    ToBase64,
+    FromBase64,
 }
 
 impl Unary {
-    fn evaluate(&self, value: Term, symbols: &TemporarySymbolTable) -> Option<Term> {
+    fn evaluate(&self, value: Term, symbols: &mut TemporarySymbolTable) -> Option<Term> {
         match (self, value) {
             (Unary::Negate, Term::Bool(b)) => Some(Term::Bool(!b)),
             (Unary::Parens, i) => Some(i),
@@ -32,6 +50,20 @@ impl Unary {
             }
             (Unary::Length, Term::Bytes(s)) => Some(Term::Integer(s.len() as i64)),
             (Unary::Length, Term::Set(s)) => Some(Term::Integer(s.len() as i64)),
+            (Unary::Sha256, Term::Bytes(b)) => Some(Term::Bytes(Sha256::digest(&b).to_vec())),
+            (Unary::Sha256, Term::Str(i)) => symbols
+                .get_symbol(i)
+                .map(|s| Term::Bytes(Sha256::digest(s.as_bytes()).to_vec())),
+            (Unary::ToHex, Term::Bytes(b)) => Some(Term::Str(symbols.insert(&hex::encode(b)))),
+            (Unary::FromHex, Term::Str(i)) => {
+                hex::decode(symbols.get_symbol(i)?).ok().map(Term::Bytes)
+            }
+            (Unary::ToBase64, Term::Bytes(b)) => {
+                Some(Term::Str(symbols.insert(&base64::encode(b))))
+            }
+            (Unary::FromBase64, Term::Str(i)) => {
+                base64::decode(symbols.get_symbol(i)?).ok().map(Term::Bytes)
+            }
             _ => {
                 //println!("unexpected value type on the stack");
                 None
@@ -44,10 +76,18 @@ impl Unary {
             Unary::Negate => format!("!{}", value),
             Unary::Parens => format!("({})", value),
             Unary::Length => format!("{}.length()", value),
+            Unary::Sha256 => format!("{}.sha256()", value),
+            Unary::ToHex => format!("{}.to_hex()", value),
+            Unary::FromHex => format!("{}.from_hex()", value),
+            Unary::ToBase64 => format!("{}.to_base64()", value),
+            Unary::FromBase64 => format!("{}.from_base64()", value),
         }
     }
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Binary {
     LessThan,
@@ -67,6 +107,15 @@ pub enum Binary {
     Or,
     Intersection,
     Union,
+    /// a CIDR range, or a set of ranges, contains an IP address
+    ContainsIp,
+    /// semantic version comparisons
+    SemverGe,
+    SemverLt,
+    /// first bytes of a byte array
+    Take,
+    /// byte array without its first bytes
+    Skip,
 }
 
 impl Binary {
@@ -111,6 +160,8 @@ impl Binary {
                     _ => None,
                 }
             }
+            // regex requires the standard library, without it the evaluation fails
+            #[cfg(feature = "std")]
             (Binary::Regex, Term::Str(s), Term::Str(r)) => {
                 match (symbols.get_symbol(s), symbols.get_symbol(r)) {
                     (Some(s), Some(r)) => Some(Term::Bool(
@@ -148,6 +199,23 @@ impl Binary {
 
             // byte array
             (Binary::Equal, Term::Bytes(i), Term::Bytes(j)) => Some(Term::Bool(i == j)),
+            (Binary::Prefix, Term::Bytes(b), Term::Bytes(pref)) => {
+                Some(Term::Bool(b.starts_with(&pref)))
+            }
+            (Binary::Suffix, Term::Bytes(b), Term::Bytes(suff)) => {
+                Some(Term::Bool(b.ends_with(&suff)))
+            }
+            (Binary::Contains, Term::Bytes(b), Term::Bytes(pattern)) => Some(Term::Bool(
+                pattern.is_empty() || b.windows(pattern.len()).any(|w| w == &pattern[..]),
+            )),
+            (Binary::Take, Term::Bytes(b), Term::Integer(n)) if n >= 0 => {
+                let n = core::cmp::min(n as usize, b.len());
+                Some(Term::Bytes(b[..n].to_vec()))
+            }
+            (Binary::Skip, Term::Bytes(b), Term::Integer(n)) if n >= 0 => {
+                let n = core::cmp::min(n as usize, b.len());
+                Some(Term::Bytes(b[n..].to_vec()))
+            }
 
             // set
             (Binary::Equal, Term::Set(set), Term::Set(s)) => Some(Term::Bool(set == s)),
@@ -176,6 +244,41 @@ impl Binary {
                 Some(Term::Bool(set.contains(&Term::Bytes(i))))
             }
 
+            // IP address, as strings
+            (Binary::ContainsIp, Term::Str(range), Term::Str(ip)) => {
+                match (symbols.get_symbol(range), symbols.get_symbol(ip)) {
+                    (Some(range), Some(ip)) => cidr_contains(range, ip).map(Term::Bool),
+                    _ => None,
+                }
+            }
+            (Binary::ContainsIp, Term::Set(set), Term::Str(ip)) => {
+                let ip = symbols.get_symbol(ip)?.to_string();
+                let mut contained = false;
+                for term in set.iter() {
+                    match term {
+                        Term::Str(range) => {
+                            contained |= cidr_contains(symbols.get_symbol(*range)?, &ip)?
+                        }
+                        _ => return None,
+                    }
+                }
+                Some(Term::Bool(contained))
+            }
+
+            // semantic version, as strings
+            (Binary::SemverGe, Term::Str(i), Term::Str(j)) => {
+                match (symbols.get_symbol(i), symbols.get_symbol(j)) {
+                    (Some(i), Some(j)) => semver_cmp(i, j).map(|o| Term::Bool(o != Ordering::Less)),
+                    _ => None,
+                }
+            }
+            (Binary::SemverLt, Term::Str(i), Term::Str(j)) => {
+                match (symbols.get_symbol(i), symbols.get_symbol(j)) {
+                    (Some(i), Some(j)) => semver_cmp(i, j).map(|o| Term::Bool(o == Ordering::Less)),
+                    _ => None,
+                }
+            }
+
             // boolean
             (Binary::And, Term::Bool(i), Term::Bool(j)) => Some(Term::Bool(i & j)),
             (Binary::Or, Term::Bool(i), Term::Bool(j)) => Some(Term::Bool(i | j)),
@@ -205,10 +308,106 @@ impl Binary {
             Binary::Or => format!("{} || {}", left, right),
             Binary::Intersection => format!("{}.intersection({})", left, right),
             Binary::Union => format!("{}.union({})", left, right),
+            Binary::ContainsIp => format!("{}.contains_ip({})", left, right),
+            Binary::SemverGe => format!("{}.semver_ge({})", left, right),
+            Binary::SemverLt => format!("{}.semver_lt({})", left, right),
+            Binary::Take => format!("{}.take({})", left, right),
+            Binary::Skip => format!("{}.skip({})", left, right),
         }
     }
 }
 
+/// compares two versions following the precedence rules of semantic versioning
+///
+/// versions have the form `major.minor.patch`, with an optional `v` prefix,
+/// pre-release (`-rc.1`) and build metadata (`+abc`), which is ignored.
+/// Missing minor and patch numbers are 0. Returns `None` for invalid versions
+pub fn semver_cmp(left: &str, right: &str) -> Option<Ordering> {
+    fn parse(version: &str) -> Option<([u64; 3], Vec<&str>)> {
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let version = version.split('+').next()?;
+        let (core, pre) = match version.split_once('-') {
+            Some((core, pre)) => (core, pre.split('.').collect::<Vec<_>>()),
+            None => (version, Vec::new()),
+        };
+
+        let mut numbers = [0u64; 3];
+        let mut parts = core.split('.');
+        for (i, part) in parts.by_ref().take(3).enumerate() {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            numbers[i] = part.parse().ok()?;
+        }
+        if parts.next().is_some() || pre.iter().any(|p| p.is_empty()) {
+            return None;
+        }
+
+        Some((numbers, pre))
+    }
+
+    let (left_numbers, left_pre) = parse(left)?;
+    let (right_numbers, right_pre) = parse(right)?;
+
+    let ordering = left_numbers.cmp(&right_numbers).then_with(|| {
+        match (left_pre.is_empty(), right_pre.is_empty()) {
+            (true, true) => Ordering::Equal,
+            // a pre-release has a lower precedence than the release
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => {
+                for (l, r) in left_pre.iter().zip(right_pre.iter()) {
+                    let ordering = match (l.parse::<u64>(), r.parse::<u64>()) {
+                        (Ok(l), Ok(r)) => l.cmp(&r),
+                        (Ok(_), Err(_)) => Ordering::Less,
+                        (Err(_), Ok(_)) => Ordering::Greater,
+                        (Err(_), Err(_)) => l.cmp(r),
+                    };
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                left_pre.len().cmp(&right_pre.len())
+            }
+        }
+    });
+
+    Some(ordering)
+}
+
+/// checks if an IP address is in a CIDR range, like `10.0.0.0/8` or
+/// `2001:db8::/32`. A range without prefix length contains only its address
+///
+/// returns `None` if the address or the range are invalid. IPv4 addresses
+/// are not in IPv6 ranges, and conversely
+pub fn cidr_contains(range: &str, ip: &str) -> Option<bool> {
+    let ip: IpAddr = ip.parse().ok()?;
+    let (network, length) = match range.split_once('/') {
+        Some((network, length)) => (network.parse::<IpAddr>().ok()?, Some(length.parse().ok()?)),
+        None => (range.parse::<IpAddr>().ok()?, None),
+    };
+
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let length: u32 = length.unwrap_or(32);
+            if length > 32 {
+                return None;
+            }
+            let mask = u32::MAX.checked_shl(32 - length).unwrap_or(0);
+            Some(u32::from(network) & mask == u32::from(ip) & mask)
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let length: u32 = length.unwrap_or(128);
+            if length > 128 {
+                return None;
+            }
+            let mask = u128::MAX.checked_shl(128 - length).unwrap_or(0);
+            Some(u128::from(network) & mask == u128::from(ip) & mask)
+        }
+        _ => Some(false),
+    }
+}
+
 impl Expression {
     pub fn evaluate(
         &self,
@@ -260,6 +459,114 @@ impl Expression {
         }
     }
 
+    /// evaluates constant sub-expressions and removes redundant parens
+    ///
+    /// operations that fail (overflow, division by zero, type mismatch), or
+    /// that would create a string absent from the symbol table, are kept as is
+    /// so they still fail at evaluation time
+    pub fn fold(&self, symbols: &SymbolTable) -> Expression {
+        // each element holds the opcodes of a sub-expression, and its value
+        // if it is constant
+        let mut stack: Vec<(Vec<Op>, Option<Term>)> = Vec::new();
+        let mut temporary_symbols = TemporarySymbolTable::new(symbols);
+
+        for op in self.ops.iter() {
+            match op {
+                Op::Value(Term::Variable(_)) => stack.push((vec![op.clone()], None)),
+                Op::Value(term) => stack.push((vec![op.clone()], Some(term.clone()))),
+                Op::Unary(unary) => match stack.pop() {
+                    None => return self.clone(),
+                    Some((ops, Some(value))) => {
+                        match unary
+                            .evaluate(value, &mut temporary_symbols)
+                            .filter(|res| is_known_term(res, symbols))
+                        {
+                            Some(res) => stack.push((vec![Op::Value(res.clone())], Some(res))),
+                            None => {
+                                let mut ops = ops;
+                                ops.push(op.clone());
+                                stack.push((ops, None));
+                            }
+                        }
+                    }
+                    Some((mut ops, None)) => {
+                        // parens around a single variable are not needed
+                        if !(*unary == Unary::Parens && ops.len() == 1) {
+                            ops.push(op.clone());
+                        }
+                        stack.push((ops, None));
+                    }
+                },
+                Op::Binary(binary) => match (stack.pop(), stack.pop()) {
+                    (Some((right_ops, right)), Some((left_ops, left))) => {
+                        let folded = match (left, right) {
+                            (Some(left), Some(right)) => binary
+                                .evaluate(left, right, &mut temporary_symbols)
+                                .filter(|res| is_known_term(res, symbols)),
+                            _ => None,
+                        };
+
+                        match folded {
+                            Some(res) => stack.push((vec![Op::Value(res.clone())], Some(res))),
+                            None => {
+                                let mut ops = left_ops;
+                                ops.extend(right_ops);
+                                ops.push(op.clone());
+                                stack.push((ops, None));
+                            }
+                        }
+                    }
+                    _ => return self.clone(),
+                },
+            }
+        }
+
+        if stack.len() == 1 {
+            Expression {
+                ops: stack.remove(0).0,
+            }
+        } else {
+            self.clone()
+        }
+    }
+
+    /// prepares the expression for repeated evaluation
+    ///
+    /// this is done once per rule application, instead of interpreting the
+    /// opcodes again for each set of matched variables
+    #[cfg_attr(
+        not(feature = "std"),
+        allow(unused_variables, clippy::while_let_on_iterator)
+    )]
+    pub fn compile(&self, symbols: &SymbolTable) -> CompiledExpression {
+        let mut ops = Vec::with_capacity(self.ops.len());
+        let mut iter = self.ops.iter().peekable();
+
+        while let Some(op) = iter.next() {
+            match op {
+                Op::Value(Term::Variable(i)) => ops.push(CompiledOp::Variable(*i)),
+                #[cfg(feature = "std")]
+                Op::Value(Term::Str(pattern))
+                    if iter.peek() == Some(&&Op::Binary(Binary::Regex)) =>
+                {
+                    match symbols.get_symbol(*pattern) {
+                        Some(pattern) => {
+                            iter.next();
+                            ops.push(CompiledOp::Regex(Regex::new(pattern).ok()));
+                        }
+                        None => ops.push(CompiledOp::Value(Term::Str(*pattern))),
+                    }
+                }
+                Op::Value(term) => ops.push(CompiledOp::Value(term.clone())),
+                Op::Unary(Unary::Parens) => {}
+                Op::Unary(unary) => ops.push(CompiledOp::Unary(unary.clone())),
+                Op::Binary(binary) => ops.push(CompiledOp::Binary(binary.clone())),
+            }
+        }
+
+        CompiledExpression { ops }
+    }
+
     pub fn print(&self, symbols: &SymbolTable) -> Option<String> {
         let mut stack: Vec<String> = Vec::new();
 
@@ -286,6 +593,78 @@ impl Expression {
     }
 }
 
+/// expression prepared for evaluation by [Expression::compile]
+///
+/// parens are removed, variables are separated from constant values,
+/// and regular expressions with a constant pattern are compiled once
+#[derive(Debug, Clone)]
+pub struct CompiledExpression {
+    ops: Vec<CompiledOp>,
+}
+
+#[derive(Debug, Clone)]
+enum CompiledOp {
+    Value(Term),
+    Variable(u32),
+    Unary(Unary),
+    Binary(Binary),
+    /// `.matches()` with a constant pattern, `None` if the pattern is invalid
+    #[cfg(feature = "std")]
+    Regex(Option<Regex>),
+}
+
+impl CompiledExpression {
+    pub fn evaluate(
+        &self,
+        values: &HashMap<u32, Term>,
+        symbols: &mut TemporarySymbolTable,
+    ) -> Option<Term> {
+        let mut stack: Vec<Term> = Vec::with_capacity(self.ops.len());
+
+        for op in self.ops.iter() {
+            match op {
+                CompiledOp::Variable(i) => stack.push(values.get(i)?.clone()),
+                CompiledOp::Value(term) => stack.push(term.clone()),
+                CompiledOp::Unary(unary) => {
+                    let term = stack.pop()?;
+                    stack.push(unary.evaluate(term, symbols)?);
+                }
+                CompiledOp::Binary(binary) => {
+                    let right = stack.pop()?;
+                    let left = stack.pop()?;
+                    stack.push(binary.evaluate(left, right, symbols)?);
+                }
+                #[cfg(feature = "std")]
+                CompiledOp::Regex(regex) => match stack.pop()? {
+                    Term::Str(s) => {
+                        let s = symbols.get_symbol(s)?;
+                        stack.push(Term::Bool(
+                            regex.as_ref().map(|re| re.is_match(s)).unwrap_or(false),
+                        ));
+                    }
+                    _ => return None,
+                },
+            }
+        }
+
+        if stack.len() == 1 {
+            stack.pop()
+        } else {
+            None
+        }
+    }
+}
+
+/// a folded value must not reference symbols created during evaluation,
+/// since they would not be part of the block's symbol table
+fn is_known_term(term: &Term, symbols: &SymbolTable) -> bool {
+    match term {
+        Term::Str(i) => symbols.get_symbol(*i).is_some(),
+        Term::Set(set) => set.iter().all(|t| is_known_term(t, symbols)),
+        _ => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -410,4 +789,283 @@ mod tests {
         assert_eq!(e3.print(&symbols).unwrap(), "1 + 2 < 3");
         //panic!();
     }
+
+    #[test]
+    fn contains_ip() {
+        assert_eq!(cidr_contains("10.0.0.0/8", "10.1.2.3"), Some(true));
+        assert_eq!(cidr_contains("10.0.0.0/8", "11.1.2.3"), Some(false));
+        assert_eq!(cidr_contains("192.168.1.1", "192.168.1.1"), Some(true));
+        assert_eq!(cidr_contains("0.0.0.0/0", "8.8.8.8"), Some(true));
+        assert_eq!(cidr_contains("2001:db8::/32", "2001:db8:1::1"), Some(true));
+        assert_eq!(cidr_contains("2001:db8::/32", "10.1.2.3"), Some(false));
+        assert_eq!(cidr_contains("10.0.0.0/33", "10.1.2.3"), None);
+        assert_eq!(cidr_contains("10.0.0.0/8", "localhost"), None);
+
+        let mut symbols = SymbolTable::new();
+        let ranges = ["10.0.0.0/8", "192.168.0.0/16"]
+            .iter()
+            .map(|r| Term::Str(symbols.insert(r)))
+            .collect();
+        let ip = symbols.insert("192.168.1.1");
+        let mut tmp_symbols = TemporarySymbolTable::new(&symbols);
+
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Set(ranges)),
+                Op::Value(Term::Str(ip)),
+                Op::Binary(Binary::ContainsIp),
+            ],
+        };
+        assert_eq!(
+            e.evaluate(&HashMap::new(), &mut tmp_symbols),
+            Some(Term::Bool(true))
+        );
+        assert_eq!(
+            e.print(&symbols).unwrap(),
+            "[\"10.0.0.0/8\", \"192.168.0.0/16\"].contains_ip(\"192.168.1.1\")"
+        );
+    }
+
+    #[test]
+    fn semver() {
+        assert_eq!(semver_cmp("2.3.0", "2.3.0"), Some(Ordering::Equal));
+        assert_eq!(semver_cmp("v2.10.0", "2.9.1"), Some(Ordering::Greater));
+        assert_eq!(semver_cmp("2.3", "2.3.0+build.5"), Some(Ordering::Equal));
+        assert_eq!(semver_cmp("2.3.0-rc.1", "2.3.0"), Some(Ordering::Less));
+        assert_eq!(
+            semver_cmp("2.3.0-rc.2", "2.3.0-rc.10"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            semver_cmp("2.3.0-alpha", "2.3.0-alpha.1"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(semver_cmp("2.3.0-1", "2.3.0-alpha"), Some(Ordering::Less));
+        assert_eq!(semver_cmp("2.3.x", "2.3.0"), None);
+        assert_eq!(semver_cmp("1.2.3.4", "2.3.0"), None);
+
+        let mut symbols = SymbolTable::new();
+        let version = symbols.insert("2.4.1");
+        let minimum = symbols.insert("2.3.0");
+        let mut tmp_symbols = TemporarySymbolTable::new(&symbols);
+
+        for (op, expected) in &[(Binary::SemverGe, true), (Binary::SemverLt, false)] {
+            let e = Expression {
+                ops: vec![
+                    Op::Value(Term::Str(version)),
+                    Op::Value(Term::Str(minimum)),
+                    Op::Binary(op.clone()),
+                ],
+            };
+            assert_eq!(
+                e.evaluate(&HashMap::new(), &mut tmp_symbols),
+                Some(Term::Bool(*expected))
+            );
+        }
+    }
+
+    #[test]
+    fn bytes() {
+        let mut symbols = SymbolTable::new();
+        let digest =
+            symbols.insert("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+        let base64 = symbols.insert("aGVsbG8=");
+        let hello = b"hello".to_vec();
+
+        let cases = vec![
+            (
+                vec![
+                    Op::Value(Term::Bytes(hello.clone())),
+                    Op::Unary(Unary::Sha256),
+                    Op::Unary(Unary::ToHex),
+                ],
+                Term::Str(digest),
+            ),
+            (
+                vec![
+                    Op::Value(Term::Str(digest)),
+                    Op::Unary(Unary::FromHex),
+                    Op::Unary(Unary::Length),
+                ],
+                Term::Integer(32),
+            ),
+            (
+                vec![
+                    Op::Value(Term::Bytes(hello.clone())),
+                    Op::Unary(Unary::ToBase64),
+                ],
+                Term::Str(base64),
+            ),
+            (
+                vec![Op::Value(Term::Str(base64)), Op::Unary(Unary::FromBase64)],
+                Term::Bytes(hello.clone()),
+            ),
+            (
+                vec![
+                    Op::Value(Term::Bytes(hello.clone())),
+                    Op::Value(Term::Integer(1)),
+                    Op::Binary(Binary::Skip),
+                    Op::Value(Term::Integer(3)),
+                    Op::Binary(Binary::Take),
+                ],
+                Term::Bytes(b"ell".to_vec()),
+            ),
+            (
+                vec![
+                    Op::Value(Term::Bytes(hello.clone())),
+                    Op::Value(Term::Integer(10)),
+                    Op::Binary(Binary::Take),
+                ],
+                Term::Bytes(hello.clone()),
+            ),
+            (
+                vec![
+                    Op::Value(Term::Bytes(hello.clone())),
+                    Op::Value(Term::Bytes(b"he".to_vec())),
+                    Op::Binary(Binary::Prefix),
+                ],
+                Term::Bool(true),
+            ),
+            (
+                vec![
+                    Op::Value(Term::Bytes(hello.clone())),
+                    Op::Value(Term::Bytes(b"ll".to_vec())),
+                    Op::Binary(Binary::Contains),
+                ],
+                Term::Bool(true),
+            ),
+        ];
+
+        let mut tmp_symbols = TemporarySymbolTable::new(&symbols);
+        for (ops, expected) in cases {
+            let e = Expression { ops };
+            assert_eq!(
+                e.evaluate(&HashMap::new(), &mut tmp_symbols),
+                Some(expected),
+                "{}",
+                e.print(&symbols).unwrap()
+            );
+        }
+
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Bytes(hello)),
+                Op::Value(Term::Integer(-1)),
+                Op::Binary(Binary::Take),
+            ],
+        };
+        assert_eq!(e.evaluate(&HashMap::new(), &mut tmp_symbols), None);
+    }
+
+    #[test]
+    fn compile() {
+        let mut symbols = SymbolTable::new();
+        let var = symbols.insert("var1") as u32;
+        let pattern = symbols.insert("^file[0-9]+$");
+        let file1 = symbols.insert("file1");
+        let other = symbols.insert("other");
+
+        // ($var1.matches("^file[0-9]+$"))
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Variable(var)),
+                Op::Value(Term::Str(pattern)),
+                Op::Binary(Binary::Regex),
+                Op::Unary(Unary::Parens),
+            ],
+        };
+        let compiled = e.compile(&symbols);
+        assert_eq!(compiled.ops.len(), 2);
+
+        let mut tmp_symbols = TemporarySymbolTable::new(&symbols);
+        for value in &[
+            Term::Str(file1),
+            Term::Str(other),
+            Term::Integer(1),
+            Term::Str(12345),
+        ] {
+            let values: HashMap<u32, Term> = [(var, value.clone())].iter().cloned().collect();
+            assert_eq!(
+                compiled.evaluate(&values, &mut tmp_symbols),
+                e.evaluate(&values, &mut tmp_symbols)
+            );
+        }
+
+        let values: HashMap<u32, Term> = [(var, Term::Str(file1))].iter().cloned().collect();
+        assert_eq!(
+            compiled.evaluate(&values, &mut tmp_symbols),
+            Some(Term::Bool(true))
+        );
+        assert_eq!(compiled.evaluate(&HashMap::new(), &mut tmp_symbols), None);
+    }
+
+    #[test]
+    fn fold() {
+        let mut symbols = SymbolTable::new();
+        let var = symbols.insert("var1") as u32;
+        let abc = symbols.insert("abc");
+        let def = symbols.insert("def");
+
+        // $var1 < 100 * 60
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Variable(var)),
+                Op::Value(Term::Integer(100)),
+                Op::Value(Term::Integer(60)),
+                Op::Binary(Binary::Mul),
+                Op::Binary(Binary::LessThan),
+            ],
+        };
+        let folded = e.fold(&symbols);
+        assert_eq!(folded.print(&symbols).unwrap(), "$var1 < 6000");
+
+        // ($var1 + 1) * (2 + 3)
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Variable(var)),
+                Op::Value(Term::Integer(1)),
+                Op::Binary(Binary::Add),
+                Op::Unary(Unary::Parens),
+                Op::Value(Term::Integer(2)),
+                Op::Value(Term::Integer(3)),
+                Op::Binary(Binary::Add),
+                Op::Unary(Unary::Parens),
+                Op::Binary(Binary::Mul),
+            ],
+        };
+        let folded = e.fold(&symbols);
+        assert_eq!(folded.print(&symbols).unwrap(), "($var1 + 1) * 5");
+
+        // failing operations are kept for evaluation
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Integer(1)),
+                Op::Value(Term::Integer(0)),
+                Op::Binary(Binary::Div),
+            ],
+        };
+        assert_eq!(e.fold(&symbols), e);
+
+        // string concatenation would need a new symbol
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Str(abc)),
+                Op::Value(Term::Str(def)),
+                Op::Binary(Binary::Add),
+                Op::Value(Term::Variable(var)),
+                Op::Binary(Binary::Equal),
+            ],
+        };
+        assert_eq!(e.fold(&symbols), e);
+
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Str(abc)),
+                Op::Value(Term::Str(abc)),
+                Op::Binary(Binary::Equal),
+                Op::Unary(Unary::Negate),
+            ],
+        };
+        assert_eq!(e.fold(&symbols).ops, vec![Op::Value(Term::Bool(false))]);
+    }
 }
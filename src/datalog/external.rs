@@ -0,0 +1,284 @@
+//! external predicates, whose facts are computed by callbacks during evaluation
+use super::{Fact, FactSet, Predicate, Rule, RunLimits, SymbolIndex, SymbolTable, Term, World};
+use crate::collections::{HashMap, HashSet};
+use crate::error::RunLimit;
+use crate::time::Instant;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// callback generating the facts of an external predicate
+///
+/// it receives the arguments of the predicate, with `None` for the
+/// variables that are not bound yet, and returns the matching rows of terms
+pub type ExternalPredicate =
+    Arc<dyn Fn(&[Option<Term>], &mut SymbolTable) -> Vec<Vec<Term>> + Send + Sync>;
+
+/// external predicates, indexed by name
+#[derive(Clone, Default)]
+pub struct ExternalPredicates {
+    predicates: HashMap<SymbolIndex, ExternalPredicate>,
+}
+
+impl ExternalPredicates {
+    pub fn new() -> Self {
+        ExternalPredicates::default()
+    }
+
+    pub fn insert(&mut self, name: SymbolIndex, predicate: ExternalPredicate) {
+        self.predicates.insert(name, predicate);
+    }
+
+    pub fn contains(&self, name: SymbolIndex) -> bool {
+        self.predicates.contains_key(&name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.predicates.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = SymbolIndex> + '_ {
+        self.predicates.keys().copied()
+    }
+}
+
+impl fmt::Debug for ExternalPredicates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.predicates.keys()).finish()
+    }
+}
+
+impl World {
+    /// runs the rules until no new facts are generated, calling the external
+    /// predicates used by the rules and by `queries`
+    ///
+    /// the results of the callbacks are stored as facts, so they count against
+    /// the limits, and a callback is called only once for a list of arguments
+    pub fn run_with_externals(
+        &mut self,
+        symbols: &mut SymbolTable,
+        limits: RunLimits,
+        externals: &ExternalPredicates,
+        queries: &[Rule],
+    ) -> Result<(), RunLimit> {
+        let time_limit = Instant::now() + limits.max_time;
+        let mut calls = HashSet::default();
+        let mut first = true;
+
+        loop {
+            let mut delta = FactSet::new();
+            for fact in self.call_externals(symbols, externals, queries, &mut calls) {
+                if !self.facts.contains(&fact) {
+                    delta.insert(fact);
+                }
+            }
+
+            if delta.is_empty() && !first {
+                break;
+            }
+
+            self.facts.extend(delta.iter().cloned());
+            if self.facts.len() >= limits.max_facts as usize {
+                return Err(RunLimit::TooManyFacts);
+            }
+
+            let now = Instant::now();
+            if now >= time_limit {
+                return Err(RunLimit::Timeout);
+            }
+
+            // the rules were already applied to the previous facts
+            let new_rules = if first { 0 } else { self.rules.len() };
+            self.run_from(
+                delta,
+                new_rules,
+                symbols,
+                RunLimits {
+                    max_time: time_limit.duration_since(now),
+                    ..limits.clone()
+                },
+            )?;
+            first = false;
+        }
+
+        Ok(())
+    }
+
+    fn call_externals(
+        &self,
+        symbols: &mut SymbolTable,
+        externals: &ExternalPredicates,
+        queries: &[Rule],
+        calls: &mut HashSet<(SymbolIndex, Vec<Option<Term>>)>,
+    ) -> Vec<Fact> {
+        let mut facts = Vec::new();
+
+        for rule in self.rules.iter().chain(queries.iter()) {
+            for predicate in rule.body.iter() {
+                let callback = match externals.predicates.get(&predicate.name) {
+                    Some(callback) => callback,
+                    None => continue,
+                };
+
+                for arguments in self.external_arguments(rule, predicate, externals, symbols) {
+                    if !calls.insert((predicate.name, arguments.clone())) {
+                        continue;
+                    }
+
+                    for row in callback(&arguments, symbols) {
+                        let matches = row.len() == arguments.len()
+                            && row.iter().zip(arguments.iter()).all(|(term, arg)| {
+                                !matches!(term, Term::Variable(_))
+                                    && arg.as_ref().map(|a| a == term).unwrap_or(true)
+                            });
+
+                        if matches {
+                            facts.push(Fact::new(predicate.name, &row));
+                        }
+                    }
+                }
+            }
+        }
+
+        facts
+    }
+
+    /// lists the arguments of `predicate` for each match of the rule's other
+    /// predicates. Variables that are only bound by external predicates are
+    /// passed as `None`
+    fn external_arguments(
+        &self,
+        rule: &Rule,
+        predicate: &Predicate,
+        externals: &ExternalPredicates,
+        symbols: &SymbolTable,
+    ) -> Vec<Vec<Option<Term>>> {
+        let body = rule
+            .body
+            .iter()
+            .filter(|p| !externals.contains(p.name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let bound = predicate
+            .terms
+            .iter()
+            .filter(|term| {
+                matches!(term, Term::Variable(_)) && body.iter().any(|p| p.terms.contains(*term))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if body.is_empty() {
+            return vec![predicate
+                .terms
+                .iter()
+                .map(|term| match term {
+                    Term::Variable(_) => None,
+                    term => Some(term.clone()),
+                })
+                .collect()];
+        }
+
+        let bindings = Rule {
+            head: Predicate::new(predicate.name, &bound),
+            body,
+            expressions: Vec::new(),
+            trusted_keys: Vec::new(),
+        };
+
+        bindings
+            .apply(&self.facts, symbols)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|fact| {
+                predicate
+                    .terms
+                    .iter()
+                    .map(|term| match term {
+                        Term::Variable(_) => bound
+                            .iter()
+                            .position(|b| b == term)
+                            .map(|i| fact.predicate.terms[i].clone()),
+                        term => Some(term.clone()),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalog::{fact, pred, rule, var};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn external_predicate() {
+        let mut syms = SymbolTable::new();
+        let employee = syms.insert("employee");
+        let group = syms.insert("group");
+        let admin = syms.insert("admin");
+        let alice = syms.add("alice");
+        let bob = syms.add("bob");
+        let admins = syms.add("admins");
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let alice_ = alice.clone();
+        let admins_ = admins.clone();
+        let mut externals = ExternalPredicates::new();
+        externals.insert(
+            group,
+            Arc::new(move |arguments: &[Option<Term>], _: &mut SymbolTable| {
+                counter.fetch_add(1, Ordering::Relaxed);
+                match &arguments[0] {
+                    Some(user) if *user == alice_ => {
+                        vec![vec![alice_.clone(), admins_.clone()]]
+                    }
+                    _ => vec![],
+                }
+            }),
+        );
+
+        let mut w = World::new();
+        w.add_fact(fact(employee, &[&alice]));
+        w.add_fact(fact(employee, &[&bob]));
+        w.add_rule(rule(
+            admin,
+            &[var(&mut syms, "user")],
+            &[
+                pred(employee, &[var(&mut syms, "user")]),
+                pred(group, &[var(&mut syms, "user"), admins.clone()]),
+            ],
+        ));
+
+        let query = rule(
+            admin,
+            &[var(&mut syms, "user")],
+            &[pred(
+                group,
+                &[var(&mut syms, "user"), var(&mut syms, "group")],
+            )],
+        );
+
+        w.run_with_externals(
+            &mut syms,
+            RunLimits {
+                max_time: Duration::from_secs(10),
+                ..Default::default()
+            },
+            &externals,
+            &[query],
+        )
+        .unwrap();
+
+        assert!(w.facts.contains(&fact(admin, &[&alice])));
+        assert!(!w.facts.contains(&fact(admin, &[&bob])));
+        assert!(w.facts.contains(&fact(group, &[&alice, &admins])));
+        // once for each employee in the rule, once with no bound argument for the query
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,213 @@
+//! fact storage indexed by predicate name
+use super::{Fact, SymbolIndex};
+use crate::collections::HashMap;
+use alloc::sync::Arc;
+
+/// where a fact comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Origin {
+    /// added by the authorizer
+    Authorizer,
+    /// from the authority block
+    Authority,
+    /// from the block at this index, starting at 1 after the authority block
+    Block(usize),
+    /// generated by the rule at this index in the world's rules
+    Rule(usize),
+}
+
+impl core::fmt::Display for Origin {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Origin::Authorizer => write!(f, "authorizer"),
+            Origin::Authority => write!(f, "authority"),
+            Origin::Block(i) => write!(f, "block {}", i),
+            Origin::Rule(i) => write!(f, "rule {}", i),
+        }
+    }
+}
+
+/// set of facts, indexed by predicate name
+///
+/// rules only look at the facts with the same predicate name as the
+/// predicates in their body, so they do not scan the whole set
+///
+/// the set keeps the origin of each fact. Equality only compares the facts
+///
+/// the facts of each predicate are shared between clones of the set, and
+/// copied when a clone adds a fact with this predicate name
+#[derive(Debug, Clone, Default)]
+pub struct FactSet {
+    inner: HashMap<SymbolIndex, Arc<HashMap<Fact, Origin>>>,
+    len: usize,
+}
+
+impl FactSet {
+    pub fn new() -> Self {
+        FactSet::default()
+    }
+
+    /// adds a fact coming from the authorizer, returns false if it was already present
+    pub fn insert(&mut self, fact: Fact) -> bool {
+        self.insert_with_origin(fact, Origin::Authorizer)
+    }
+
+    /// adds a fact, returns false if it was already present
+    ///
+    /// the origin of an existing fact is not modified
+    pub fn insert_with_origin(&mut self, fact: Fact, origin: Origin) -> bool {
+        if self.contains(&fact) {
+            return false;
+        }
+
+        let facts = self.inner.entry(fact.predicate.name).or_default();
+        Arc::make_mut(facts).insert(fact, origin);
+        self.len += 1;
+        true
+    }
+
+    pub fn contains(&self, fact: &Fact) -> bool {
+        self.origin(fact).is_some()
+    }
+
+    /// returns where this fact comes from
+    pub fn origin(&self, fact: &Fact) -> Option<Origin> {
+        self.inner
+            .get(&fact.predicate.name)
+            .and_then(|facts| facts.get(fact))
+            .copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// iterates over all the facts
+    pub fn iter(&self) -> impl Iterator<Item = &Fact> {
+        self.inner.values().flat_map(|facts| facts.keys())
+    }
+
+    /// iterates over all the facts and their origin
+    pub fn iter_with_origin(&self) -> impl Iterator<Item = (&Fact, Origin)> {
+        self.inner
+            .values()
+            .flat_map(|facts| facts.iter().map(|(fact, origin)| (fact, *origin)))
+    }
+
+    /// number of facts with this predicate name
+    pub fn count_predicate(&self, name: SymbolIndex) -> usize {
+        self.inner.get(&name).map(|facts| facts.len()).unwrap_or(0)
+    }
+
+    /// iterates over the names of the predicates with at least one fact
+    pub fn predicates(&self) -> impl Iterator<Item = SymbolIndex> + '_ {
+        self.inner
+            .iter()
+            .filter(|(_, facts)| !facts.is_empty())
+            .map(|(name, _)| *name)
+    }
+
+    /// iterates over the facts with this predicate name
+    pub fn iter_predicate(&self, name: SymbolIndex) -> impl Iterator<Item = &Fact> {
+        self.inner
+            .get(&name)
+            .into_iter()
+            .flat_map(|facts| facts.keys())
+    }
+}
+
+impl PartialEq for FactSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|fact| other.contains(fact))
+    }
+}
+
+impl Extend<Fact> for FactSet {
+    fn extend<T: IntoIterator<Item = Fact>>(&mut self, iter: T) {
+        for fact in iter {
+            self.insert(fact);
+        }
+    }
+}
+
+impl Extend<(Fact, Origin)> for FactSet {
+    fn extend<T: IntoIterator<Item = (Fact, Origin)>>(&mut self, iter: T) {
+        for (fact, origin) in iter {
+            self.insert_with_origin(fact, origin);
+        }
+    }
+}
+
+impl core::iter::FromIterator<Fact> for FactSet {
+    fn from_iter<T: IntoIterator<Item = Fact>>(iter: T) -> Self {
+        let mut set = FactSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for FactSet {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        u.arbitrary_iter::<Fact>()?.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalog::{Predicate, Term};
+
+    #[test]
+    fn index() {
+        let mut facts = FactSet::new();
+        let fact = |name, value| Fact {
+            predicate: Predicate::new(name, &[Term::Integer(value)]),
+        };
+
+        assert!(facts.insert(fact(1, 0)));
+        assert!(facts.insert(fact(1, 1)));
+        assert!(facts.insert(fact(2, 0)));
+        assert!(!facts.insert(fact(1, 0)));
+
+        assert_eq!(facts.len(), 3);
+        assert!(facts.contains(&fact(2, 0)));
+        assert!(!facts.contains(&fact(2, 1)));
+        assert_eq!(facts.iter().count(), 3);
+        assert_eq!(facts.iter_predicate(1).count(), 2);
+        assert_eq!(facts.iter_predicate(3).count(), 0);
+    }
+
+    #[test]
+    fn origin() {
+        let mut facts = FactSet::new();
+        let fact = |value| Fact {
+            predicate: Predicate::new(1, &[Term::Integer(value)]),
+        };
+
+        assert!(facts.insert_with_origin(fact(0), Origin::Block(1)));
+        assert!(facts.insert(fact(1)));
+        // the first origin is kept
+        assert!(!facts.insert_with_origin(fact(0), Origin::Rule(0)));
+
+        assert_eq!(facts.origin(&fact(0)), Some(Origin::Block(1)));
+        assert_eq!(facts.origin(&fact(1)), Some(Origin::Authorizer));
+        assert_eq!(facts.origin(&fact(2)), None);
+
+        let mut other = FactSet::new();
+        other.insert_with_origin(fact(1), Origin::Authority);
+        other.insert_with_origin(fact(0), Origin::Authority);
+        assert_eq!(facts, other);
+
+        // clones share the facts until they are modified
+        let mut copy = facts.clone();
+        assert!(copy.insert(fact(2)));
+        assert_eq!(copy.len(), 3);
+        assert_eq!(facts.len(), 2);
+        assert_eq!(facts.origin(&fact(2)), None);
+    }
+}
@@ -1,15 +1,30 @@
 //! Logic language implementation for checks
-use crate::time::Instant;
-use std::collections::{BTreeSet, HashMap, HashSet};
-use std::convert::AsRef;
-use std::fmt;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-
+use crate::collections::{HashMap, HashSet};
+use crate::crypto::PublicKey;
+use crate::time::{Duration, Instant};
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::AsRef;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod analysis;
 mod expression;
+mod external;
+mod fact_set;
+mod persist;
 mod symbol;
+pub use analysis::*;
 pub use expression::*;
+pub use external::*;
+pub use fact_set::*;
 pub use symbol::*;
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq, Hash, Eq, PartialOrd, Ord)]
 pub enum Term {
     Variable(u32),
@@ -35,12 +50,25 @@ impl From<&Term> for Term {
     }
 }
 
+impl Term {
+    /// approximate memory used by this term, including heap allocations
+    pub fn estimated_memory(&self) -> usize {
+        core::mem::size_of::<Term>()
+            + match self {
+                Term::Bytes(b) => b.len(),
+                Term::Set(s) => s.iter().map(|t| t.estimated_memory()).sum(),
+                _ => 0,
+            }
+    }
+}
+
 impl AsRef<Term> for Term {
     fn as_ref(&self) -> &Term {
         self
     }
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct Predicate {
     pub name: SymbolIndex,
@@ -56,12 +84,25 @@ impl Predicate {
     }
 }
 
+impl Predicate {
+    /// approximate memory used by this predicate, including heap allocations
+    pub fn estimated_memory(&self) -> usize {
+        core::mem::size_of::<Predicate>()
+            + self
+                .terms
+                .iter()
+                .map(|t| t.estimated_memory())
+                .sum::<usize>()
+    }
+}
+
 impl AsRef<Predicate> for Predicate {
     fn as_ref(&self) -> &Predicate {
         self
     }
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct Fact {
     pub predicate: Predicate,
@@ -73,13 +114,22 @@ impl Fact {
             predicate: Predicate::new(name, terms),
         }
     }
+
+    /// approximate memory used by this fact, including heap allocations
+    pub fn estimated_memory(&self) -> usize {
+        self.predicate.estimated_memory()
+    }
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
     pub head: Predicate,
     pub body: Vec<Predicate>,
     pub expressions: Vec<Expression>,
+    /// when used as a check or policy query, facts from third party
+    /// blocks signed by those keys are visible to the query
+    pub trusted_keys: Vec<PublicKey>,
 }
 
 impl AsRef<Expression> for Expression {
@@ -88,6 +138,7 @@ impl AsRef<Expression> for Expression {
     }
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Check {
     pub queries: Vec<Rule>,
@@ -100,6 +151,16 @@ impl fmt::Display for Fact {
 }
 
 impl Rule {
+    /// checks that all the variables of the head appear in the body
+    pub fn head_variables_bound(&self) -> bool {
+        let body = self.variables_set();
+
+        self.head.terms.iter().all(|term| match term {
+            Term::Variable(i) => body.contains(i),
+            _ => true,
+        })
+    }
+
     /// gather all of the variables used in that rule
     fn variables_set(&self) -> HashSet<u32> {
         self.body
@@ -115,32 +176,114 @@ impl Rule {
 
     pub fn apply<'a>(
         &'a self,
-        facts: &'a HashSet<Fact>,
+        facts: &'a FactSet,
+        symbols: &'a SymbolTable,
+    ) -> impl Iterator<Item = Fact> + 'a {
+        self.apply_delta(facts, None, symbols)
+    }
+
+    /// applies the rule, with the body predicate at `delta.0` only matching
+    /// the facts of `delta.1`
+    fn apply_delta<'a>(
+        &'a self,
+        facts: &'a FactSet,
+        delta: Option<(usize, &'a FactSet)>,
         symbols: &'a SymbolTable,
     ) -> impl Iterator<Item = Fact> + 'a {
         let head = self.head.clone();
         let variables = MatchedVariables::new(self.variables_set());
+        let body = Rc::from(self.plan(facts, delta.map(|(position, _)| position)));
+        // the planned body starts with the predicate matching the delta
+        let delta = delta.map(|(_, delta_facts)| (0, delta_facts));
+        let expressions = Rc::new(
+            self.expressions
+                .iter()
+                .map(|e| e.compile(symbols))
+                .collect::<Vec<_>>(),
+        );
 
-        CombineIt::new(variables, &self.body, &self.expressions, facts, symbols).filter_map(move |h| {
-            let mut p = head.clone();
-            for index in 0..p.terms.len() {
-                match &p.terms[index] {
-                    Term::Variable(i) => match h.get(i) {
-                      Some(val) => p.terms[index] = val.clone(),
-                      None => {
-                        println!("error: variables that appear in the head should appear in the body and constraints as well");
-                        return None;
-                      }
-                    },
-                    _ => continue,
-                };
+        CombineIt::new(variables, body, 0, expressions, facts, delta, symbols).filter_map(
+            move |h| {
+                let mut p = head.clone();
+                for index in 0..p.terms.len() {
+                    match &p.terms[index] {
+                        Term::Variable(i) => match h.get(i) {
+                            Some(val) => p.terms[index] = val.clone(),
+                            // rules are validated when they are added, this
+                            // variable does not appear in the body
+                            None => {
+                                #[cfg(feature = "tracing")]
+                                tracing::warn!(
+                                    variable = *i,
+                                    "rule head variable not bound by its body"
+                                );
+                                return None;
+                            }
+                        },
+                        _ => continue,
+                    };
+                }
+
+                Some(Fact { predicate: p })
+            },
+        )
+    }
+
+    /// orders the body predicates to reduce the size of intermediate results
+    ///
+    /// the predicate matching the delta comes first, since it has the fewest
+    /// facts. Then at each step, the next predicate is the one with the least
+    /// facts, or the least unbound variables if there is a tie. Predicates
+    /// that share no variable with the ones already matched come last, to
+    /// avoid cross products
+    fn plan(&self, facts: &FactSet, delta: Option<usize>) -> Vec<Predicate> {
+        let mut remaining: Vec<usize> = (0..self.body.len()).collect();
+        let mut order = Vec::with_capacity(self.body.len());
+        let mut bound: HashSet<_> = HashSet::default();
+
+        if let Some(position) = delta {
+            remaining.retain(|i| *i != position);
+            order.push(position);
+        }
+
+        loop {
+            if let Some(last) = order.last() {
+                bound.extend(self.body[*last].terms.iter().filter_map(|t| match t {
+                    Term::Variable(v) => Some(*v),
+                    _ => None,
+                }));
             }
 
-            Some(Fact { predicate: p })
-        })
+            let next = remaining
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, i)| {
+                    let p = &self.body[**i];
+                    let variables = p
+                        .terms
+                        .iter()
+                        .filter(|t| matches!(t, Term::Variable(_)))
+                        .count();
+                    let unbound = p
+                        .terms
+                        .iter()
+                        .filter(|t| matches!(t, Term::Variable(v) if !bound.contains(v)))
+                        .count();
+                    let disconnected = !bound.is_empty() && variables > 0 && unbound == variables;
+                    (disconnected, facts.count_predicate(p.name), unbound)
+                })
+                .map(|(position, _)| position);
+
+            match next {
+                Some(position) => order.push(remaining.remove(position)),
+                None => break,
+            }
+        }
+
+        order.into_iter().map(|i| self.body[i].clone()).collect()
     }
 
-    pub fn find_match(&self, facts: &HashSet<Fact>, symbols: &SymbolTable) -> bool {
+    pub fn find_match(&self, facts: &FactSet, symbols: &SymbolTable) -> bool {
         let mut it = self.apply(facts, symbols);
 
         let next = it.next();
@@ -151,9 +294,12 @@ impl Rule {
 /// recursive iterator for rule application
 pub struct CombineIt<'a> {
     variables: MatchedVariables,
-    predicates: &'a [Predicate],
-    expressions: &'a [Expression],
-    all_facts: &'a HashSet<Fact>,
+    /// the predicates starting at index `start` remain to be matched
+    predicates: Rc<[Predicate]>,
+    start: usize,
+    expressions: Rc<Vec<CompiledExpression>>,
+    all_facts: &'a FactSet,
+    delta: Option<(usize, &'a FactSet)>,
     symbols: &'a SymbolTable,
     current_facts: Box<dyn Iterator<Item = &'a Fact> + 'a>,
     current_it: Option<Box<CombineIt<'a>>>,
@@ -162,27 +308,36 @@ pub struct CombineIt<'a> {
 impl<'a> CombineIt<'a> {
     pub fn new(
         variables: MatchedVariables,
-        predicates: &'a [Predicate],
-        expressions: &'a [Expression],
-        facts: &'a HashSet<Fact>,
+        predicates: Rc<[Predicate]>,
+        start: usize,
+        expressions: Rc<Vec<CompiledExpression>>,
+        facts: &'a FactSet,
+        delta: Option<(usize, &'a FactSet)>,
         symbols: &'a SymbolTable,
     ) -> Self {
-        let current_facts: Box<dyn Iterator<Item = &'a Fact> + 'a> = if predicates.is_empty() {
+        let current_facts: Box<dyn Iterator<Item = &'a Fact> + 'a> = if start == predicates.len() {
             Box::new(facts.iter())
         } else {
-            let p = predicates[0].clone();
+            let source = match delta {
+                Some((0, delta_facts)) => delta_facts,
+                _ => facts,
+            };
+            let name = predicates[start].name;
+            let filter = predicates.clone();
             Box::new(
-                facts
-                    .iter()
-                    .filter(move |fact| match_preds(&p, &fact.predicate)),
+                source
+                    .iter_predicate(name)
+                    .filter(move |fact| match_preds(&filter[start], &fact.predicate)),
             )
         };
 
         CombineIt {
             variables,
             predicates,
+            start,
             expressions,
             all_facts: facts,
+            delta,
             symbols,
             current_facts,
             current_it: None,
@@ -195,7 +350,7 @@ impl<'a> Iterator for CombineIt<'a> {
 
     fn next(&mut self) -> Option<HashMap<u32, Term>> {
         // if we're the last iterator in the recursive chain, stop here
-        if self.predicates.is_empty() {
+        if self.start == self.predicates.len() {
             //return None;
             //return self.variables.complete();
             match self.variables.complete() {
@@ -228,7 +383,7 @@ impl<'a> Iterator for CombineIt<'a> {
         loop {
             if self.current_it.is_none() {
                 //fix the first predicate
-                let pred = &self.predicates[0];
+                let pred = &self.predicates[self.start];
 
                 loop {
                     if let Some(current_fact) = self.current_facts.next() {
@@ -252,7 +407,7 @@ impl<'a> Iterator for CombineIt<'a> {
                             continue;
                         }
 
-                        if self.predicates.len() == 1 {
+                        if self.predicates.len() - self.start == 1 {
                             match vars.complete() {
                                 None => {
                                     //println!("variables not complete, continue");
@@ -289,9 +444,16 @@ impl<'a> Iterator for CombineIt<'a> {
                             // and all of the facts
                             self.current_it = Some(Box::new(CombineIt::new(
                                 vars,
-                                &self.predicates[1..],
-                                self.expressions,
+                                self.predicates.clone(),
+                                self.start + 1,
+                                self.expressions.clone(),
                                 self.all_facts,
+                                match self.delta {
+                                    Some((index, delta_facts)) if index > 0 => {
+                                        Some((index - 1, delta_facts))
+                                    }
+                                    _ => None,
+                                },
                                 self.symbols,
                             )));
                         }
@@ -339,7 +501,7 @@ impl MatchedVariables {
     }
 
     pub fn complete(&self) -> Option<HashMap<u32, Term>> {
-        let mut result = HashMap::new();
+        let mut result = HashMap::default();
         for (k, v) in self.0.iter() {
             match v {
                 Some(value) => result.insert(*k, value.clone()),
@@ -375,6 +537,7 @@ pub fn rule<I: AsRef<Term>, P: AsRef<Predicate>>(
         head: pred(head_name, head_terms),
         body: predicates.iter().map(|p| p.as_ref().clone()).collect(),
         expressions: Vec::new(),
+        trusted_keys: Vec::new(),
     }
 }
 
@@ -388,6 +551,7 @@ pub fn expressed_rule<I: AsRef<Term>, P: AsRef<Predicate>, C: AsRef<Expression>>
         head: pred(head_name, head_terms),
         body: predicates.iter().map(|p| p.as_ref().clone()).collect(),
         expressions: expressions.iter().map(|c| c.as_ref().clone()).collect(),
+        trusted_keys: Vec::new(),
     }
 }
 
@@ -399,6 +563,7 @@ pub fn int(i: i64) -> Term {
     Term::Str(s.to_string())
 }*/
 
+#[cfg(feature = "std")]
 pub fn date(t: &SystemTime) -> Term {
     let dur = t.duration_since(UNIX_EPOCH).unwrap();
     Term::Date(dur.as_secs())
@@ -430,9 +595,10 @@ pub fn match_preds(rule_pred: &Predicate, fact_pred: &Predicate) -> bool {
             })
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct World {
-    pub facts: HashSet<Fact>,
+    pub facts: FactSet,
     pub rules: Vec<Rule>,
 }
 
@@ -449,33 +615,128 @@ impl World {
         self.rules.push(rule);
     }
 
+    /// approximate memory used by the facts and rules of this world
+    ///
+    /// this only accounts for the size of the terms, not for the overhead
+    /// of the fact set's hash tables
+    pub fn estimated_memory(&self) -> usize {
+        let facts: usize = self.facts.iter().map(|f| f.estimated_memory()).sum();
+        let rules: usize = self
+            .rules
+            .iter()
+            .map(|r| {
+                r.head.estimated_memory()
+                    + r.body.iter().map(|p| p.estimated_memory()).sum::<usize>()
+            })
+            .sum();
+
+        facts + rules
+    }
+
     pub fn run(&mut self, symbols: &SymbolTable) -> Result<(), crate::error::RunLimit> {
         self.run_with_limits(symbols, RunLimits::default())
     }
 
+    /// runs the rules until no new facts are generated
+    ///
+    /// this uses semi-naive evaluation: after the first iteration, a rule
+    /// only generates facts if one of its body predicates matches a fact
+    /// generated in the previous iteration
     pub fn run_with_limits(
         &mut self,
         symbols: &SymbolTable,
         limits: RunLimits,
+    ) -> Result<(), crate::error::RunLimit> {
+        self.run_from(FactSet::new(), 0, symbols, limits)
+    }
+
+    /// adds facts and rules to a world that was already run, and only
+    /// derives the consequences of the new facts and rules
+    ///
+    /// the existing rules are only applied to combinations including
+    /// at least one new fact, while the new rules are applied to all facts.
+    /// If the world was not run before, this will miss some facts
+    ///
+    /// the new facts are recorded with the `origin` provided
+    pub fn run_incremental(
+        &mut self,
+        facts: Vec<Fact>,
+        origin: Origin,
+        rules: Vec<Rule>,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+    ) -> Result<(), crate::error::RunLimit> {
+        let mut delta = FactSet::new();
+        for fact in facts {
+            if !self.facts.contains(&fact) {
+                delta.insert_with_origin(fact, origin);
+            }
+        }
+        self.facts
+            .extend(delta.iter_with_origin().map(|(f, o)| (f.clone(), o)));
+
+        let new_rules = self.rules.len();
+        self.rules.extend(rules);
+
+        self.run_from(delta, new_rules, symbols, limits)
+    }
+
+    /// in the first iteration, the rules starting at index `new_rules` are applied
+    /// to all facts, while the previous rules only match with the facts from `delta`
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "world_run",
+            skip_all,
+            fields(rules = self.rules.len(), facts = self.facts.len())
+        )
+    )]
+    fn run_from(
+        &mut self,
+        mut delta: FactSet,
+        new_rules: usize,
+        symbols: &SymbolTable,
+        limits: RunLimits,
     ) -> Result<(), crate::error::RunLimit> {
         let start = Instant::now();
         let time_limit = start + limits.max_time;
         let mut index = 0;
+        let mut memory = self.estimated_memory() as u64;
+        self.check_predicate_limits(symbols, &limits)?;
 
         loop {
-            let mut new_facts: Vec<Fact> = Vec::new();
+            let mut new_facts = if index == 0 {
+                self.derive_facts(&delta, new_rules, symbols)
+            } else {
+                self.derive_facts(&delta, self.rules.len(), symbols)
+            };
 
-            for rule in self.rules.iter() {
-                new_facts.extend(rule.apply(&self.facts, symbols));
-                //println!("new_facts after applying {:?}:\n{:#?}", rule, new_facts);
+            let mut next_delta = FactSet::new();
+            for (fact, origin) in new_facts.drain(..) {
+                if !self.facts.contains(&fact) {
+                    next_delta.insert_with_origin(fact, origin);
+                }
             }
 
-            let len = self.facts.len();
-            self.facts.extend(new_facts.drain(..));
-            if self.facts.len() == len {
+            if next_delta.is_empty() {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    iterations = index + 1,
+                    facts = self.facts.len(),
+                    "world run complete"
+                );
                 break;
             }
 
+            memory += next_delta
+                .iter()
+                .map(|f| f.estimated_memory() as u64)
+                .sum::<u64>();
+            self.facts
+                .extend(next_delta.iter_with_origin().map(|(f, o)| (f.clone(), o)));
+            delta = next_delta;
+
             index += 1;
             if index == limits.max_iterations {
                 return Err(crate::error::RunLimit::TooManyIterations);
@@ -485,6 +746,12 @@ impl World {
                 return Err(crate::error::RunLimit::TooManyFacts);
             }
 
+            if memory >= limits.max_memory_bytes {
+                return Err(crate::error::RunLimit::TooMuchMemory);
+            }
+
+            self.check_predicate_limits(symbols, &limits)?;
+
             let now = Instant::now();
             if now >= time_limit {
                 return Err(crate::error::RunLimit::Timeout);
@@ -494,9 +761,92 @@ impl World {
         Ok(())
     }
 
+    fn check_predicate_limits(
+        &self,
+        symbols: &SymbolTable,
+        limits: &RunLimits,
+    ) -> Result<(), crate::error::RunLimit> {
+        for (predicate, max) in limits.max_facts_per_predicate.iter() {
+            let count = symbols
+                .get(predicate)
+                .map(|name| self.facts.count_predicate(name))
+                .unwrap_or(0);
+
+            if count > *max as usize {
+                return Err(crate::error::RunLimit::TooManyPredicateFacts {
+                    predicate: predicate.clone(),
+                    max: *max,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// applies all the rules once, in parallel with the `rayon` feature
+    ///
+    /// the rules before `new_rules` only generate facts if one of their
+    /// body predicates matches a fact from the delta
+    fn derive_facts(
+        &self,
+        delta: &FactSet,
+        new_rules: usize,
+        symbols: &SymbolTable,
+    ) -> Vec<(Fact, Origin)> {
+        let apply = |(index, rule): (usize, &Rule)| -> Vec<(Fact, Origin)> {
+            let origin = Origin::Rule(index);
+            if index >= new_rules {
+                rule.apply(&self.facts, symbols)
+                    .map(|fact| (fact, origin))
+                    .collect()
+            } else {
+                (0..rule.body.len())
+                    .flat_map(|position| {
+                        rule.apply_delta(&self.facts, Some((position, delta)), symbols)
+                    })
+                    .map(|fact| (fact, origin))
+                    .collect()
+            }
+        };
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            self.rules
+                .par_iter()
+                .enumerate()
+                .flat_map_iter(apply)
+                .collect()
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        {
+            self.rules.iter().enumerate().flat_map(apply).collect()
+        }
+    }
+
+    /// lists the facts that are in `other` but not in this world, and
+    /// the facts that are in this world but not in `other`
+    pub fn diff(&self, other: &World) -> WorldDiff {
+        WorldDiff {
+            added: other
+                .facts
+                .iter()
+                .filter(|f| !self.facts.contains(f))
+                .cloned()
+                .collect(),
+            removed: self
+                .facts
+                .iter()
+                .filter(|f| !other.facts.contains(f))
+                .cloned()
+                .collect(),
+        }
+    }
+
     pub fn query(&self, pred: Predicate) -> Vec<&Fact> {
         self.facts
-            .iter()
+            .iter_predicate(pred.name)
             .filter(|f| {
                 f.predicate.name == pred.name
                     && f.predicate.terms.iter().zip(&pred.terms).all(|(fid, pid)| {
@@ -519,28 +869,123 @@ impl World {
     }
 
     pub fn query_rule(&self, rule: Rule, symbols: &SymbolTable) -> Vec<Fact> {
-        let mut new_facts: Vec<Fact> = Vec::new();
-        new_facts.extend(rule.apply(&self.facts, symbols));
-        new_facts
+        self.query_rule_iter(&rule, symbols).collect()
+    }
+
+    /// returns the results of a query as they are found, without collecting them
+    ///
+    /// the same fact can be returned multiple times, if it is generated
+    /// by different sets of variables
+    pub fn query_rule_iter<'a>(
+        &'a self,
+        rule: &'a Rule,
+        symbols: &'a SymbolTable,
+    ) -> impl Iterator<Item = Fact> + 'a {
+        rule.apply(&self.facts, symbols)
     }
 
     pub fn query_match(&self, rule: Rule, symbols: &SymbolTable) -> bool {
         rule.find_match(&self.facts, symbols)
     }
+
+    /// runs a query without materializing the whole world
+    ///
+    /// only the rules that can generate facts used by the query are run,
+    /// on a copy of the facts whose predicates they depend on
+    pub fn query_rule_goal_directed(
+        &self,
+        rule: Rule,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+    ) -> Result<Vec<Fact>, crate::error::RunLimit> {
+        let mut world = self.relevant_to(&rule);
+        world.run_with_limits(symbols, limits)?;
+        Ok(world.query_rule(rule, symbols))
+    }
+
+    /// checks if a query matches, without materializing the whole world
+    ///
+    /// see [World::query_rule_goal_directed]
+    pub fn query_match_goal_directed(
+        &self,
+        rule: Rule,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+    ) -> Result<bool, crate::error::RunLimit> {
+        let mut world = self.relevant_to(&rule);
+        world.run_with_limits(symbols, limits)?;
+        Ok(world.query_match(rule, symbols))
+    }
+
+    /// extracts the facts and rules that can contribute to the query's body
+    fn relevant_to(&self, query: &Rule) -> World {
+        let mut predicates: HashSet<SymbolIndex> = query.body.iter().map(|p| p.name).collect();
+        let mut rules = vec![false; self.rules.len()];
+
+        loop {
+            let mut changed = false;
+            for (selected, rule) in rules.iter_mut().zip(self.rules.iter()) {
+                if !*selected && predicates.contains(&rule.head.name) {
+                    *selected = true;
+                    changed = true;
+                    predicates.extend(rule.body.iter().map(|p| p.name));
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let mut world = World::new();
+        for name in predicates.iter() {
+            world
+                .facts
+                .extend(self.facts.iter_predicate(*name).cloned());
+        }
+        world.rules = rules
+            .iter()
+            .zip(self.rules.iter())
+            .filter(|(selected, _)| **selected)
+            .map(|(_, rule)| rule.clone())
+            .collect();
+
+        world
+    }
 }
 
+/// difference between two worlds, see [World::diff]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WorldDiff {
+    pub added: Vec<Fact>,
+    pub removed: Vec<Fact>,
+}
+
+impl WorldDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct RunLimits {
     pub max_facts: u32,
     pub max_iterations: u32,
     pub max_time: Duration,
+    /// maximum memory used by the facts, as estimated by [World::estimated_memory]
+    pub max_memory_bytes: u64,
+    /// maximum number of facts with a predicate name, like `time` or `operation`
+    pub max_facts_per_predicate: HashMap<String, u32>,
 }
 
-impl std::default::Default for RunLimits {
+impl core::default::Default for RunLimits {
     fn default() -> Self {
         RunLimits {
             max_facts: 1000,
             max_iterations: 100,
             max_time: Duration::from_millis(1),
+            max_memory_bytes: 10 * 1024 * 1024,
+            max_facts_per_predicate: HashMap::default(),
         }
     }
 }
@@ -549,6 +994,350 @@ impl std::default::Default for RunLimits {
 mod tests {
     use super::*;
 
+    #[test]
+    fn incremental() {
+        let mut syms = SymbolTable::new();
+        let parent = syms.insert("parent");
+        let ancestor = syms.insert("ancestor");
+        let limits = || RunLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        };
+
+        let rules = vec![
+            rule(
+                ancestor,
+                &[var(&mut syms, "a"), var(&mut syms, "b")],
+                &[pred(parent, &[var(&mut syms, "a"), var(&mut syms, "b")])],
+            ),
+            rule(
+                ancestor,
+                &[var(&mut syms, "a"), var(&mut syms, "c")],
+                &[
+                    pred(parent, &[var(&mut syms, "a"), var(&mut syms, "b")]),
+                    pred(ancestor, &[var(&mut syms, "b"), var(&mut syms, "c")]),
+                ],
+            ),
+        ];
+
+        let mut w = World::new();
+        for i in 0..5 {
+            w.add_fact(fact(parent, &[int(i), int(i + 1)]));
+        }
+        w.rules = rules.clone();
+        w.run_with_limits(&syms, limits()).unwrap();
+        assert_eq!(w.facts.len(), 5 + 15);
+
+        // links the chains 0 -> 5 and 6 -> 8
+        let mut full = w.clone();
+        let new_facts = vec![
+            fact(parent, &[int(5), int(6)]),
+            fact(parent, &[int(6), int(7)]),
+            fact(parent, &[int(7), int(8)]),
+        ];
+        w.run_incremental(new_facts.clone(), Origin::Block(1), vec![], &syms, limits())
+            .unwrap();
+
+        for f in new_facts {
+            full.add_fact(f);
+        }
+        full.run_with_limits(&syms, limits()).unwrap();
+        assert_eq!(w.facts, full.facts);
+        assert_eq!(w.facts.len(), 8 + 36);
+        assert_eq!(
+            w.facts.origin(&fact(parent, &[int(5), int(6)])),
+            Some(Origin::Block(1))
+        );
+        assert_eq!(
+            w.facts.origin(&fact(ancestor, &[int(0), int(8)])),
+            Some(Origin::Rule(1))
+        );
+        assert_eq!(
+            w.facts.origin(&fact(parent, &[int(0), int(1)])),
+            Some(Origin::Authorizer)
+        );
+
+        // new rules are applied to all the facts
+        let root = syms.insert("root");
+        let new_rule = rule(
+            root,
+            &[var(&mut syms, "a")],
+            &[pred(ancestor, &[var(&mut syms, "a"), int(8)])],
+        );
+        w.run_incremental(vec![], Origin::Block(2), vec![new_rule], &syms, limits())
+            .unwrap();
+        assert_eq!(w.query(pred(root, &[var(&mut syms, "a")])).len(), 8);
+    }
+
+    #[test]
+    fn goal_directed() {
+        let mut syms = SymbolTable::new();
+        let parent = syms.insert("parent");
+        let grandparent = syms.insert("grandparent");
+        let sibling = syms.insert("sibling");
+        let a = syms.add("A");
+        let b = syms.add("B");
+        let c = syms.add("C");
+        let d = syms.add("D");
+
+        let mut w = World::new();
+        w.add_fact(fact(parent, &[&a, &b]));
+        w.add_fact(fact(parent, &[&b, &c]));
+        w.add_fact(fact(parent, &[&b, &d]));
+        w.add_rule(rule(
+            grandparent,
+            &[var(&mut syms, "a"), var(&mut syms, "c")],
+            &[
+                pred(parent, &[var(&mut syms, "a"), var(&mut syms, "b")]),
+                pred(parent, &[var(&mut syms, "b"), var(&mut syms, "c")]),
+            ],
+        ));
+        w.add_rule(expressed_rule(
+            sibling,
+            &[var(&mut syms, "a"), var(&mut syms, "b")],
+            &[
+                pred(parent, &[var(&mut syms, "p"), var(&mut syms, "a")]),
+                pred(parent, &[var(&mut syms, "p"), var(&mut syms, "b")]),
+            ],
+            &[Expression {
+                ops: vec![
+                    Op::Value(var(&mut syms, "a")),
+                    Op::Value(var(&mut syms, "b")),
+                    Op::Binary(Binary::Equal),
+                    Op::Unary(Unary::Negate),
+                ],
+            }],
+        ));
+
+        let query = rule(
+            syms.insert("query"),
+            &[var(&mut syms, "x")],
+            &[pred(grandparent, &[&a, &var(&mut syms, "x")])],
+        );
+
+        let relevant = w.relevant_to(&query);
+        assert_eq!(relevant.rules, vec![w.rules[0].clone()]);
+        assert_eq!(relevant.facts.len(), 3);
+
+        let res = w
+            .query_rule_goal_directed(query.clone(), &syms, RunLimits::default())
+            .unwrap();
+        let mut full = w.clone();
+        full.run(&syms).unwrap();
+        let expected = full.query_rule(query.clone(), &syms);
+        assert_eq!(
+            res.into_iter().collect::<HashSet<_>>(),
+            expected.into_iter().collect::<HashSet<_>>()
+        );
+        assert!(w
+            .query_match_goal_directed(query, &syms, RunLimits::default())
+            .unwrap());
+        // the world itself was not modified
+        assert_eq!(w.facts.len(), 3);
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut syms = SymbolTable::new();
+        let blob = syms.insert("blob");
+        let copy = syms.insert("copy");
+
+        let mut w = World::new();
+        for i in 0..10 {
+            w.add_fact(fact(blob, &[int(i), Term::Bytes(vec![0; 1000])]));
+        }
+        assert!(w.estimated_memory() > 10 * 1000);
+
+        w.add_rule(rule(
+            copy,
+            &[var(&mut syms, "id"), var(&mut syms, "data")],
+            &[pred(blob, &[var(&mut syms, "id"), var(&mut syms, "data")])],
+        ));
+
+        let mut w2 = w.clone();
+        assert_eq!(
+            w.run_with_limits(
+                &syms,
+                RunLimits {
+                    max_time: Duration::from_secs(10),
+                    max_memory_bytes: 15_000,
+                    ..Default::default()
+                },
+            ),
+            Err(crate::error::RunLimit::TooMuchMemory)
+        );
+
+        w2.run_with_limits(
+            &syms,
+            RunLimits {
+                max_time: Duration::from_secs(10),
+                max_memory_bytes: 50_000,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(w2.facts.len(), 20);
+    }
+
+    #[test]
+    fn query_iter() {
+        let mut syms = SymbolTable::new();
+        let value = syms.insert("value");
+        let data = syms.insert("data");
+
+        let mut w = World::new();
+        for i in 0..1000 {
+            w.add_fact(fact(value, &[int(i)]));
+        }
+
+        let query = rule(
+            data,
+            &[var(&mut syms, "x")],
+            &[pred(value, &[var(&mut syms, "x")])],
+        );
+
+        let first = w
+            .query_rule_iter(&query, &syms)
+            .take(10)
+            .collect::<Vec<_>>();
+        assert_eq!(first.len(), 10);
+        assert!(first.iter().all(|f| f.predicate.name == data));
+        assert_eq!(w.query_rule_iter(&query, &syms).count(), 1000);
+    }
+
+    #[test]
+    fn join_planning() {
+        let mut syms = SymbolTable::new();
+        let user = syms.insert("employee");
+        let resource = syms.insert("file");
+        let access = syms.insert("access");
+        let allowed = syms.insert("allowed");
+
+        let mut w = World::new();
+        for i in 0..100 {
+            w.add_fact(fact(user, &[int(i)]));
+            w.add_fact(fact(resource, &[int(i)]));
+        }
+        for i in 0..3 {
+            w.add_fact(fact(access, &[int(i), int(i + 1)]));
+        }
+
+        // allowed($u, $r) <- employee($u), file($r), access($u, $r)
+        let r = rule(
+            allowed,
+            &[var(&mut syms, "u"), var(&mut syms, "r")],
+            &[
+                pred(user, &[var(&mut syms, "u")]),
+                pred(resource, &[var(&mut syms, "r")]),
+                pred(access, &[var(&mut syms, "u"), var(&mut syms, "r")]),
+            ],
+        );
+
+        // the cross product between employee and file is avoided
+        let names = |plan: Vec<Predicate>| plan.iter().map(|p| p.name).collect::<Vec<_>>();
+        assert_eq!(names(r.plan(&w.facts, None)), vec![access, user, resource]);
+        assert_eq!(
+            names(r.plan(&w.facts, Some(1))),
+            vec![resource, access, user]
+        );
+
+        let res = r.apply(&w.facts, &syms).collect::<HashSet<_>>();
+        assert_eq!(res.len(), 3);
+        assert!(res.contains(&fact(allowed, &[int(2), int(3)])));
+
+        // the small predicate comes first, even with more unbound variables
+        let r = rule(
+            allowed,
+            &[var(&mut syms, "u"), var(&mut syms, "r")],
+            &[
+                pred(user, &[var(&mut syms, "u")]),
+                pred(access, &[var(&mut syms, "u"), var(&mut syms, "r")]),
+            ],
+        );
+        assert_eq!(names(r.plan(&w.facts, None)), vec![access, user]);
+    }
+
+    #[test]
+    fn diff() {
+        let mut syms = SymbolTable::new();
+        let value = syms.insert("value");
+
+        let mut w1 = World::new();
+        w1.add_fact(fact(value, &[int(0)]));
+        w1.add_fact(fact(value, &[int(1)]));
+        let mut w2 = World::new();
+        w2.add_fact(fact(value, &[int(1)]));
+        w2.add_fact(fact(value, &[int(2)]));
+
+        let diff = w1.diff(&w2);
+        assert_eq!(diff.added, vec![fact(value, &[int(2)])]);
+        assert_eq!(diff.removed, vec![fact(value, &[int(0)])]);
+        assert!(w1.diff(&w1.clone()).is_empty());
+    }
+
+    #[test]
+    fn transitive_closure() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let parent = syms.insert("parent");
+        let ancestor = syms.insert("ancestor");
+
+        for i in 0..20 {
+            w.add_fact(fact(parent, &[int(i), int(i + 1)]));
+        }
+
+        // ancestor($a, $b) <- parent($a, $b)
+        w.add_rule(rule(
+            ancestor,
+            &[var(&mut syms, "a"), var(&mut syms, "b")],
+            &[pred(parent, &[var(&mut syms, "a"), var(&mut syms, "b")])],
+        ));
+        // ancestor($a, $c) <- ancestor($a, $b), ancestor($b, $c)
+        w.add_rule(rule(
+            ancestor,
+            &[var(&mut syms, "a"), var(&mut syms, "c")],
+            &[
+                pred(ancestor, &[var(&mut syms, "a"), var(&mut syms, "b")]),
+                pred(ancestor, &[var(&mut syms, "b"), var(&mut syms, "c")]),
+            ],
+        ));
+
+        w.run_with_limits(
+            &syms,
+            RunLimits {
+                max_time: Duration::from_secs(10),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let res = w.query(pred(ancestor, &[var(&mut syms, "a"), var(&mut syms, "b")]));
+        assert_eq!(res.len(), 20 * 21 / 2);
+        assert!(w.facts.contains(&fact(ancestor, &[int(0), int(20)])));
+
+        // the closure of a chain of 20 facts takes 6 doubling steps
+        let mut w2 = World::new();
+        w2.facts = w
+            .facts
+            .iter()
+            .filter(|f| f.predicate.name == parent)
+            .cloned()
+            .collect();
+        w2.rules = w.rules.clone();
+        assert_eq!(
+            w2.run_with_limits(
+                &syms,
+                RunLimits {
+                    max_iterations: 6,
+                    max_time: Duration::from_secs(10),
+                    ..Default::default()
+                },
+            ),
+            Err(crate::error::RunLimit::TooManyIterations)
+        );
+    }
+
     #[test]
     fn family() {
         let mut w = World::new();
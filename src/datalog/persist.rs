@@ -0,0 +1,145 @@
+//! serialization of derived worlds
+//!
+//! a world generated from a large set of facts can be saved once with
+//! [World::to_bytes], with its symbols, the origin of each fact and its rules,
+//! then loaded with [World::from_bytes] without running the rules again
+use super::{Origin, SymbolTable, World};
+use crate::error;
+use crate::format::convert::v2::{
+    proto_fact_to_token_fact, proto_rule_to_token_rule, token_fact_to_proto_fact,
+    token_rule_to_proto_rule,
+};
+use crate::format::schema;
+use alloc::vec::Vec;
+use prost::Message;
+
+impl World {
+    /// serializes the facts and rules, the symbols must be the ones used to
+    /// create them
+    pub fn to_bytes(&self, symbols: &SymbolTable) -> Result<Vec<u8>, error::Format> {
+        use schema::origin_fact_v2::Kind;
+
+        let facts = self
+            .facts
+            .iter_with_origin()
+            .map(|(fact, origin)| {
+                let (kind, index) = match origin {
+                    Origin::Authorizer => (Kind::Authorizer, None),
+                    Origin::Authority => (Kind::Authority, None),
+                    Origin::Block(i) => (Kind::Block, Some(i as u64)),
+                    Origin::Rule(i) => (Kind::Rule, Some(i as u64)),
+                };
+
+                schema::OriginFactV2 {
+                    fact: token_fact_to_proto_fact(fact),
+                    kind: kind as i32,
+                    index,
+                }
+            })
+            .collect();
+
+        let proto = schema::WorldSnapshot {
+            symbols: symbols.strings(),
+            facts,
+            rules: self.rules.iter().map(token_rule_to_proto_rule).collect(),
+        };
+
+        let mut v = Vec::new();
+        proto.encode(&mut v).map(|_| v).map_err(|e| {
+            error::Format::SerializationError(error::Cause::with_dependency_source(
+                format!("serialization error: {:?}", e),
+                e,
+            ))
+        })
+    }
+
+    /// deserializes the output of [World::to_bytes], with its symbols
+    pub fn from_bytes(data: &[u8]) -> Result<(World, SymbolTable), error::Format> {
+        use schema::origin_fact_v2::Kind;
+
+        let proto = schema::WorldSnapshot::decode(data).map_err(|e| {
+            error::Format::DeserializationError(error::Cause::with_dependency_source(
+                format!("deserialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+        let mut world = World::new();
+        for fact in proto.facts.iter() {
+            let index = || {
+                fact.index.map(|i| i as usize).ok_or_else(|| {
+                    error::Format::DeserializationError(
+                        "deserialization error: missing fact origin index".into(),
+                    )
+                })
+            };
+
+            let origin = match Kind::from_i32(fact.kind) {
+                Some(Kind::Authorizer) => Origin::Authorizer,
+                Some(Kind::Authority) => Origin::Authority,
+                Some(Kind::Block) => Origin::Block(index()?),
+                Some(Kind::Rule) => Origin::Rule(index()?),
+                None => {
+                    return Err(error::Format::DeserializationError(
+                        "deserialization error: invalid fact origin".into(),
+                    ))
+                }
+            };
+
+            world
+                .facts
+                .insert_with_origin(proto_fact_to_token_fact(&fact.fact)?, origin);
+        }
+
+        for rule in proto.rules.iter() {
+            world.rules.push(proto_rule_to_token_rule(rule)?);
+        }
+
+        Ok((world, SymbolTable::from(proto.symbols)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalog::{fact, pred, rule, var};
+
+    #[test]
+    fn round_trip() {
+        let mut symbols = SymbolTable::new();
+        let mut world = World::new();
+
+        let a = symbols.add("a");
+        let b = symbols.add("b");
+        let c = symbols.add("c");
+        let parent = symbols.insert("parent");
+        let grandparent = symbols.insert("grandparent");
+
+        world
+            .facts
+            .insert_with_origin(fact(parent, &[&a, &b]), Origin::Authorizer);
+        world
+            .facts
+            .insert_with_origin(fact(parent, &[&b, &c]), Origin::Block(2));
+        world.add_rule(rule(
+            grandparent,
+            &[var(&mut symbols, "x"), var(&mut symbols, "z")],
+            &[
+                pred(parent, &[var(&mut symbols, "x"), var(&mut symbols, "y")]),
+                pred(parent, &[var(&mut symbols, "y"), var(&mut symbols, "z")]),
+            ],
+        ));
+        world.run(&symbols).unwrap();
+        assert_eq!(world.facts.len(), 3);
+
+        let data = world.to_bytes(&symbols).unwrap();
+        let (loaded, loaded_symbols) = World::from_bytes(&data).unwrap();
+        assert_eq!(loaded, world);
+        assert_eq!(loaded_symbols.strings(), symbols.strings());
+        for (fact, origin) in loaded.facts.iter_with_origin() {
+            assert_eq!(world.facts.origin(fact), Some(origin));
+        }
+
+        assert!(World::from_bytes(&data[..data.len() - 1]).is_err());
+    }
+}
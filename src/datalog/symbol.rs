@@ -1,15 +1,34 @@
 //! Symbol table implementation
-use std::collections::HashSet;
-use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+//!
+//! the symbols of a token are frozen in an arena shared with `Arc` by the
+//! clones of its table. An authorizer created from the token starts with
+//! that arena, so the token's facts, rules and checks keep their indexes and
+//! are used without remapping
+use crate::collections::HashSet;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use time::OffsetDateTime;
 
 pub type SymbolIndex = u64;
-use super::{Check, Fact, Predicate, Rule, Term, World};
+use super::{Check, Expression, Fact, Op, Predicate, Rule, Term, World};
+use crate::error;
 
-#[derive(Clone, Debug, PartialEq, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct SymbolTable {
+    /// symbols shared with the clones of this table, see [SymbolTable::freeze]
+    shared: Arc<Vec<String>>,
+    /// symbols added after the shared ones
     symbols: Vec<String>,
 }
 
+/// tables are equal if they contain the same symbols, whether they are shared or not
+impl PartialEq for SymbolTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.current_offset() == other.current_offset() && self.iter().eq(other.iter())
+    }
+}
+
 const DEFAULT_SYMBOLS: [&str; 28] = [
     "read",
     "write",
@@ -43,32 +62,76 @@ const DEFAULT_SYMBOLS: [&str; 28] = [
 
 const OFFSET: usize = 1024;
 
+/// escapes the content of a string literal, so it can be parsed again
+pub fn escape_string(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// prints a timestamp as a RFC 3339 date in UTC, for the years 0 to 9999
+pub fn print_date(timestamp: u64) -> String {
+    match OffsetDateTime::from_unix_timestamp(timestamp as i64) {
+        Ok(t) if (0..=9999).contains(&t.year()) => format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            t.year(),
+            u8::from(t.month()),
+            t.day(),
+            t.hour(),
+            t.minute(),
+            t.second()
+        ),
+        _ => "<invalid date>".to_string(),
+    }
+}
+
 impl SymbolTable {
     pub fn new() -> Self {
-        SymbolTable { symbols: vec![] }
+        SymbolTable::default()
     }
 
     //FIXME: should check if symbols are already in default
     pub fn from(symbols: Vec<String>) -> Self {
-        SymbolTable { symbols }
+        SymbolTable {
+            shared: Arc::default(),
+            symbols,
+        }
+    }
+
+    /// moves all the symbols to the shared arena
+    ///
+    /// the clones of the table then share those symbols instead of copying
+    /// them, and [SymbolTable::remap_into] can skip the symbols that a
+    /// target table shares with this one
+    pub fn freeze(&mut self) {
+        if !self.symbols.is_empty() {
+            let mut shared = Vec::with_capacity(self.current_offset());
+            shared.extend(self.shared.iter().cloned());
+            shared.append(&mut self.symbols);
+            self.shared = Arc::new(shared);
+        }
+    }
+
+    /// true if both tables start with the same frozen symbols
+    pub fn shares_frozen_symbols(&self, other: &SymbolTable) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &String> {
+        self.shared.iter().chain(self.symbols.iter())
     }
 
     pub fn extend(&mut self, other: &SymbolTable) {
-        self.symbols.extend(other.symbols.iter().cloned());
+        self.symbols.extend(other.iter().cloned());
     }
 
     pub fn insert(&mut self, s: &str) -> SymbolIndex {
-        if let Some(index) = DEFAULT_SYMBOLS.iter().position(|sym| *sym == s) {
-            return index as u64;
+        if let Some(index) = self.get(s) {
+            return index;
         }
 
-        match self.symbols.iter().position(|sym| sym.as_str() == s) {
-            Some(index) => (OFFSET + index) as u64,
-            None => {
-                self.symbols.push(s.to_string());
-                (OFFSET + (self.symbols.len() - 1)) as u64
-            }
-        }
+        self.symbols.push(s.to_string());
+        (OFFSET + self.current_offset() - 1) as u64
     }
 
     pub fn add(&mut self, s: &str) -> Term {
@@ -81,38 +144,69 @@ impl SymbolTable {
             return Some(index as u64);
         }
 
-        self.symbols
-            .iter()
+        self.iter()
             .position(|sym| sym.as_str() == s)
             .map(|i| (OFFSET + i) as SymbolIndex)
     }
 
     pub fn strings(&self) -> Vec<String> {
-        self.symbols.clone()
+        self.iter().cloned().collect()
     }
 
     pub fn current_offset(&self) -> usize {
-        self.symbols.len()
+        self.shared.len() + self.symbols.len()
     }
 
     pub fn split_at(&mut self, offset: usize) -> SymbolTable {
-        let mut table = SymbolTable::new();
-        table.symbols = self.symbols.split_off(offset);
-        table
+        if offset >= self.shared.len() {
+            return SymbolTable::from(self.symbols.split_off(offset - self.shared.len()));
+        }
+
+        let mut symbols = self.shared[offset..].to_vec();
+        symbols.append(&mut self.symbols);
+        self.shared = Arc::new(self.shared[..offset].to_vec());
+        SymbolTable::from(symbols)
+    }
+
+    /// inserts the symbols of this table in `target`, and returns the
+    /// mapping from this table's indexes to the indexes in `target`
+    ///
+    /// this is used to move facts and rules between symbol tables without
+    /// going through the builder types. If `target` starts with this table's
+    /// frozen symbols, the indexes are the same and nothing is inserted
+    pub fn remap_into(&self, target: &mut SymbolTable) -> SymbolRemap {
+        let len = self.current_offset();
+        let shared = self.shared.is_empty() || self.shares_frozen_symbols(target);
+
+        if self.symbols.is_empty() && shared {
+            return SymbolRemap { symbols: None, len };
+        }
+
+        SymbolRemap {
+            symbols: Some(self.iter().map(|s| target.insert(s)).collect()),
+            len,
+        }
     }
 
     pub fn is_disjoint(&self, other: &SymbolTable) -> bool {
-        let h1 = self.symbols.iter().collect::<HashSet<_>>();
-        let h2 = other.symbols.iter().collect::<HashSet<_>>();
+        let h1 = self.iter().collect::<HashSet<_>>();
+        let h2 = other.iter().collect::<HashSet<_>>();
 
         h1.is_disjoint(&h2)
     }
 
     pub fn get_symbol(&self, i: SymbolIndex) -> Option<&str> {
-        if i >= 1024 {
-            self.symbols.get((i - 1024) as usize).map(|s| s.as_str())
+        if i >= OFFSET as u64 {
+            let index = (i - OFFSET as u64) as usize;
+            match self.shared.get(index) {
+                Some(s) => Some(s.as_str()),
+                None => self
+                    .symbols
+                    .get(index - self.shared.len())
+                    .map(|s| s.as_str()),
+            }
         } else {
-            DEFAULT_SYMBOLS.get(i as usize).map(|s| *s)
+            DEFAULT_SYMBOLS.get(i as usize).copied()
         }
     }
 
@@ -140,11 +234,8 @@ impl SymbolTable {
         match term {
             Term::Variable(i) => format!("${}", self.print_symbol(*i as u64)),
             Term::Integer(i) => i.to_string(),
-            Term::Str(index) => format!("\"{}\"", self.print_symbol(*index as u64)),
-            Term::Date(d) => OffsetDateTime::from_unix_timestamp(*d as i64)
-                .ok()
-                .and_then(|t| t.format(&Rfc3339).ok())
-                .unwrap_or_else(|| "<invalid date>".to_string()),
+            Term::Str(index) => format!("\"{}\"", escape_string(&self.print_symbol(*index))),
+            Term::Date(d) => print_date(*d),
             Term::Bytes(s) => format!("hex:{}", hex::encode(s)),
             Term::Bool(b) => {
                 if *b {
@@ -201,7 +292,20 @@ impl SymbolTable {
             format!(", {}", expressions.join(", "))
         };
 
-        format!("{}{}", preds.join(", "), e)
+        let trusted = if r.trusted_keys.is_empty() {
+            String::new()
+        } else {
+            format!(
+                " trusting {}",
+                r.trusted_keys
+                    .iter()
+                    .map(|k| k.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+
+        format!("{}{}{}", preds.join(", "), e, trusted)
     }
 
     pub fn print_rule(&self, r: &Rule) -> String {
@@ -221,6 +325,103 @@ impl SymbolTable {
     }
 }
 
+/// mapping of symbol indexes between two symbol tables, created by
+/// [SymbolTable::remap_into]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolRemap {
+    /// `None` when the indexes are the same in both tables
+    symbols: Option<Vec<SymbolIndex>>,
+    /// number of symbols in the source table
+    len: usize,
+}
+
+impl SymbolRemap {
+    /// default symbols are common to all tables and keep their index
+    ///
+    /// an index that is not part of the source table is an error: keeping it
+    /// as is would silently alias another symbol of the target table
+    pub fn symbol(&self, i: SymbolIndex) -> Result<SymbolIndex, error::Format> {
+        if i < OFFSET as u64 {
+            return Ok(i);
+        }
+
+        let index = (i - OFFSET as u64) as usize;
+        match &self.symbols {
+            None if index < self.len => Ok(i),
+            None => Err(error::Format::UnknownSymbol(i)),
+            Some(symbols) => symbols
+                .get(index)
+                .copied()
+                .ok_or(error::Format::UnknownSymbol(i)),
+        }
+    }
+
+    pub fn term(&self, term: &Term) -> Result<Term, error::Format> {
+        Ok(match term {
+            Term::Str(i) => Term::Str(self.symbol(*i)?),
+            Term::Set(s) => Term::Set(s.iter().map(|t| self.term(t)).collect::<Result<_, _>>()?),
+            t => t.clone(),
+        })
+    }
+
+    pub fn predicate(&self, p: &Predicate) -> Result<Predicate, error::Format> {
+        Ok(Predicate {
+            name: self.symbol(p.name)?,
+            terms: p
+                .terms
+                .iter()
+                .map(|t| self.term(t))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn fact(&self, f: &Fact) -> Result<Fact, error::Format> {
+        Ok(Fact {
+            predicate: self.predicate(&f.predicate)?,
+        })
+    }
+
+    pub fn expression(&self, e: &Expression) -> Result<Expression, error::Format> {
+        Ok(Expression {
+            ops: e
+                .ops
+                .iter()
+                .map(|op| match op {
+                    Op::Value(t) => self.term(t).map(Op::Value),
+                    op => Ok(op.clone()),
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn rule(&self, r: &Rule) -> Result<Rule, error::Format> {
+        Ok(Rule {
+            head: self.predicate(&r.head)?,
+            body: r
+                .body
+                .iter()
+                .map(|p| self.predicate(p))
+                .collect::<Result<_, _>>()?,
+            expressions: r
+                .expressions
+                .iter()
+                .map(|e| self.expression(e))
+                .collect::<Result<_, _>>()?,
+            trusted_keys: r.trusted_keys.clone(),
+        })
+    }
+
+    pub fn check(&self, c: &Check) -> Result<Check, error::Format> {
+        Ok(Check {
+            queries: c
+                .queries
+                .iter()
+                .map(|q| self.rule(q))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct TemporarySymbolTable<'a> {
     base: &'a SymbolTable,
@@ -263,3 +464,86 @@ impl<'a> TemporarySymbolTable<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalog::{fact, int};
+
+    #[test]
+    fn remap() {
+        let mut token_symbols = SymbolTable::new();
+        let mut authorizer_symbols = SymbolTable::new();
+        authorizer_symbols.insert("ambient");
+
+        let employee = token_symbols.insert("employee");
+        let id = token_symbols.add("1234");
+        let token_fact = fact(employee, &[id, int(1)]);
+        let right = fact(4, &[token_symbols.add("read")]);
+
+        let remap = token_symbols.remap_into(&mut authorizer_symbols);
+        assert_eq!(
+            authorizer_symbols.print_fact(&remap.fact(&token_fact).unwrap()),
+            token_symbols.print_fact(&token_fact)
+        );
+        // default symbols keep the same index
+        assert_eq!(remap.fact(&right), Ok(right));
+        assert_eq!(authorizer_symbols.get("1234"), Some(OFFSET as u64 + 2));
+
+        // unknown indexes must not alias the target's symbols
+        let unknown = fact(OFFSET as u64 + 10, &[int(1)]);
+        assert_eq!(
+            remap.fact(&unknown),
+            Err(error::Format::UnknownSymbol(OFFSET as u64 + 10))
+        );
+    }
+
+    #[test]
+    fn shared() {
+        let mut token_symbols = SymbolTable::new();
+        let employee = token_symbols.insert("employee");
+        let token_fact = fact(employee, &[token_symbols.add("1234")]);
+        token_symbols.freeze();
+
+        // clones share the frozen symbols instead of copying them
+        let mut authorizer_symbols = token_symbols.clone();
+        assert!(Arc::ptr_eq(
+            &token_symbols.shared,
+            &authorizer_symbols.shared
+        ));
+        assert_eq!(authorizer_symbols, token_symbols);
+
+        let ambient = authorizer_symbols.insert("ambient");
+        assert_eq!(ambient, OFFSET as u64 + 2);
+        assert_eq!(authorizer_symbols.get("employee"), Some(employee));
+        assert_eq!(authorizer_symbols.get_symbol(ambient), Some("ambient"));
+
+        // the token's indexes are kept, nothing is inserted
+        let remap = token_symbols.remap_into(&mut authorizer_symbols);
+        assert_eq!(remap.fact(&token_fact), Ok(token_fact.clone()));
+        assert_eq!(authorizer_symbols.current_offset(), 3);
+        let unknown = fact(ambient, &[int(1)]);
+        assert_eq!(
+            remap.fact(&unknown),
+            Err(error::Format::UnknownSymbol(ambient))
+        );
+
+        // a table with other frozen symbols is still remapped
+        let mut other = SymbolTable::new();
+        other.insert("ambient");
+        other.freeze();
+        let remap = token_symbols.remap_into(&mut other);
+        assert_eq!(
+            other.print_fact(&remap.fact(&token_fact).unwrap()),
+            token_symbols.print_fact(&token_fact)
+        );
+
+        // splitting inside the shared symbols keeps the table consistent
+        let split = authorizer_symbols.split_at(1);
+        assert_eq!(authorizer_symbols.strings(), vec!["employee".to_string()]);
+        assert_eq!(
+            split.strings(),
+            vec!["1234".to_string(), "ambient".to_string()]
+        );
+    }
+}
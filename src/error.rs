@@ -1,33 +1,130 @@
 //! error types
 //!
+//! with the `serde-error` feature, all error types implement `Serialize` and
+//! `Deserialize`, so authorization failures can be returned as structured
+//! JSON. Enums use the externally tagged representation, with the variant
+//! and field names of this module. That representation is kept stable
+//! across minor versions
+//!
+//! nested errors and the underlying Protobuf, parsing and cryptography
+//! errors are available through [core::error::Error::source]
 
-use std::convert::{From, Infallible};
-use thiserror::Error;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::{From, Infallible};
+use core::error::Error;
+use core::fmt;
 
 /// the global error type for Biscuit
-#[derive(Error, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token {
-    #[error("internal error")]
     InternalError,
-    #[error("error deserializing or verifying the token")]
     Format(Format),
-    #[error("multiple blocks declare the same symbols")]
     SymbolTableOverlap,
-    #[error("tried to append a block to a sealed token")]
     AppendOnSealed,
-    #[error("tried to seal an already sealed token")]
     AlreadySealed,
-    #[error("authorization failed")]
     FailedLogic(Logic),
-    #[error("error generating Datalog")]
     Language(LanguageError),
-    #[error("Reached Datalog execution limits")]
     RunLimit(RunLimit),
-    #[error("Cannot convert from Term: {0}")]
     ConversionError(String),
-    #[error("Cannot decode base64 token: {0}")]
     Base64(Base64Error),
+    AuthorizationHeader(AuthorizationHeaderError),
+    Revocation(Revocation),
+    AttributeLookup(Cause),
+    Query(Query),
+    WrongAudience(String),
+    Replay(Replay),
+    PolicyPack(PolicyPack),
+    PolicySource(Cause),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::InternalError => f.write_str("internal error"),
+            Token::Format(_) => f.write_str("error deserializing or verifying the token"),
+            Token::SymbolTableOverlap => f.write_str("multiple blocks declare the same symbols"),
+            Token::AppendOnSealed => f.write_str("tried to append a block to a sealed token"),
+            Token::AlreadySealed => f.write_str("tried to seal an already sealed token"),
+            Token::FailedLogic(_) => f.write_str("authorization failed"),
+            Token::Language(_) => f.write_str("error generating Datalog"),
+            Token::RunLimit(_) => f.write_str("Reached Datalog execution limits"),
+            Token::ConversionError(e) => write!(f, "Cannot convert from Term: {}", e),
+            Token::Base64(e) => write!(f, "Cannot decode base64 token: {}", e),
+            Token::AuthorizationHeader(e) => write!(f, "invalid authorization header: {}", e),
+            Token::Revocation(_) => f.write_str("revocation check failed"),
+            Token::AttributeLookup(e) => write!(f, "cannot load attributes: {}", e),
+            Token::Query(_) => f.write_str("unexpected number of query results"),
+            Token::WrongAudience(audience) => {
+                write!(f, "the token is not valid for the audience {}", audience)
+            }
+            Token::Replay(_) => f.write_str("replay detection failed"),
+            Token::PolicyPack(_) => f.write_str("cannot use the policy pack"),
+            Token::PolicySource(e) => write!(f, "cannot read the policies: {}", e),
+        }
+    }
+}
+
+impl Error for Token {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Token::Format(e) => Some(e),
+            Token::FailedLogic(e) => Some(e),
+            Token::Language(e) => Some(e),
+            Token::RunLimit(e) => Some(e),
+            Token::Revocation(e) => Some(e),
+            Token::Query(e) => Some(e),
+            Token::Replay(e) => Some(e),
+            Token::PolicyPack(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Token {
+    /// stable code for this error, that does not depend on the error's
+    /// internal structure
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Token::InternalError => ErrorCode::InternalError,
+            Token::Format(e) => e.code(),
+            Token::SymbolTableOverlap => ErrorCode::SymbolTableOverlap,
+            Token::AppendOnSealed => ErrorCode::AppendOnSealed,
+            Token::AlreadySealed => ErrorCode::AlreadySealed,
+            Token::FailedLogic(Logic::InvalidBlockRule(_, _)) => ErrorCode::InvalidBlockRule,
+            Token::FailedLogic(Logic::Unauthorized {
+                policy: MatchedPolicy::Deny(_),
+                ..
+            }) => ErrorCode::Denied,
+            Token::FailedLogic(Logic::Unauthorized { .. }) => ErrorCode::FailedCheck,
+            Token::FailedLogic(Logic::AuthorizerNotEmpty) => ErrorCode::AuthorizerNotEmpty,
+            Token::FailedLogic(Logic::NoMatchingPolicy { .. }) => ErrorCode::NoMatchingPolicy,
+            Token::FailedLogic(Logic::NoPolicies) => ErrorCode::NoPolicies,
+            Token::Language(_) => ErrorCode::InvalidDatalog,
+            Token::RunLimit(RunLimit::TooManyFacts) => ErrorCode::TooManyFacts,
+            Token::RunLimit(RunLimit::TooManyIterations) => ErrorCode::TooManyIterations,
+            Token::RunLimit(RunLimit::Timeout) => ErrorCode::Timeout,
+            Token::RunLimit(RunLimit::TooMuchMemory) => ErrorCode::TooMuchMemory,
+            Token::RunLimit(RunLimit::TooManyPredicateFacts { .. }) => {
+                ErrorCode::TooManyPredicateFacts
+            }
+            Token::ConversionError(_) => ErrorCode::ConversionError,
+            Token::Base64(_) | Token::AuthorizationHeader(_) => ErrorCode::InvalidEncoding,
+            Token::Revocation(Revocation::Revoked(_)) => ErrorCode::Revoked,
+            Token::Revocation(Revocation::Lookup(_)) => ErrorCode::RevocationLookup,
+            Token::AttributeLookup(_) => ErrorCode::AttributeLookup,
+            Token::Query(Query::NoResult { .. }) => ErrorCode::NoResult,
+            Token::Query(Query::TooManyResults { .. }) => ErrorCode::TooManyResults,
+            Token::WrongAudience(_) => ErrorCode::WrongAudience,
+            Token::Replay(Replay::Reused) => ErrorCode::NonceReused,
+            Token::Replay(Replay::Lookup(_)) => ErrorCode::ReplayLookup,
+            Token::PolicyPack(PolicyPack::InvalidVersion(_)) => ErrorCode::InvalidPackVersion,
+            Token::PolicyPack(PolicyPack::Incompatible { .. }) => ErrorCode::IncompatiblePolicyPack,
+            Token::PolicySource(_) => ErrorCode::PolicySource,
+        }
+    }
 }
 
 impl From<Infallible> for Token {
@@ -48,6 +145,30 @@ impl From<Logic> for Token {
     }
 }
 
+impl From<Query> for Token {
+    fn from(e: Query) -> Self {
+        Token::Query(e)
+    }
+}
+
+impl From<Revocation> for Token {
+    fn from(e: Revocation) -> Self {
+        Token::Revocation(e)
+    }
+}
+
+impl From<Replay> for Token {
+    fn from(e: Replay) -> Self {
+        Token::Replay(e)
+    }
+}
+
+impl From<PolicyPack> for Token {
+    fn from(e: PolicyPack) -> Self {
+        Token::PolicyPack(e)
+    }
+}
+
 impl<'a> From<crate::parser::Error<'a>> for Token {
     fn from(e: crate::parser::Error<'a>) -> Self {
         Token::Language(LanguageError::ParseError(e.into()))
@@ -62,17 +183,7 @@ impl<'a> From<Vec<crate::parser::Error<'a>>> for Token {
 
 impl From<base64::DecodeError> for Token {
     fn from(e: base64::DecodeError) -> Self {
-        let err = match e {
-            base64::DecodeError::InvalidByte(offset, byte) => {
-                Base64Error::InvalidByte(offset, byte)
-            }
-            base64::DecodeError::InvalidLength => Base64Error::InvalidLength,
-            base64::DecodeError::InvalidLastSymbol(offset, byte) => {
-                Base64Error::InvalidLastSymbol(offset, byte)
-            }
-        };
-
-        Token::Base64(err)
+        Token::Base64(e.into())
     }
 }
 
@@ -84,8 +195,22 @@ pub enum Base64Error {
     InvalidLastSymbol(usize, u8),
 }
 
-impl std::fmt::Display for Base64Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl From<base64::DecodeError> for Base64Error {
+    fn from(e: base64::DecodeError) -> Self {
+        match e {
+            base64::DecodeError::InvalidByte(offset, byte) => {
+                Base64Error::InvalidByte(offset, byte)
+            }
+            base64::DecodeError::InvalidLength => Base64Error::InvalidLength,
+            base64::DecodeError::InvalidLastSymbol(offset, byte) => {
+                Base64Error::InvalidLastSymbol(offset, byte)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Base64Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Base64Error::InvalidByte(index, byte) => {
                 write!(f, "Invalid byte {}, offset {}.", byte, index)
@@ -98,94 +223,536 @@ impl std::fmt::Display for Base64Error {
     }
 }
 
+/// stable error codes, see [Token::code]
+///
+/// the codes and their string representation do not change between
+/// releases, new codes can be added, so matching on them needs a
+/// wildcard arm
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-error", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum ErrorCode {
+    InternalError,
+    /// the token or the policies could not be parsed
+    InvalidFormat,
+    SerializationError,
+    InvalidSignature,
+    UnknownPublicKey,
+    UnsupportedVersion,
+    InvalidKey,
+    UnsupportedAlgorithm,
+    DeserializationLimit,
+    SymbolTableOverlap,
+    AppendOnSealed,
+    AlreadySealed,
+    /// a rule from a block is invalid
+    InvalidBlockRule,
+    /// an allow policy matched, but some checks failed
+    FailedCheck,
+    /// a deny policy matched
+    Denied,
+    /// no policy matched
+    NoMatchingPolicy,
+    AuthorizerNotEmpty,
+    /// the authorizer requires policies, and none were added
+    NoPolicies,
+    /// invalid Datalog code or parameters
+    InvalidDatalog,
+    TooManyFacts,
+    TooManyIterations,
+    Timeout,
+    TooMuchMemory,
+    /// too many facts with the same predicate name
+    TooManyPredicateFacts,
+    ConversionError,
+    /// invalid base64 or Authorization header
+    InvalidEncoding,
+    /// a block of the token was revoked
+    Revoked,
+    /// the revocation store could not be queried
+    RevocationLookup,
+    /// an attribute source failed
+    AttributeLookup,
+    /// a query expecting one result returned none
+    NoResult,
+    /// a query expecting at most one result returned more
+    TooManyResults,
+    /// the token was not issued for the authorizer's audience
+    WrongAudience,
+    /// the nonce of a one-time token was already used
+    NonceReused,
+    /// the replay store could not be queried
+    ReplayLookup,
+    /// the version of a policy pack does not follow semantic versioning
+    InvalidPackVersion,
+    /// the version of a policy pack does not match the requirement
+    IncompatiblePolicyPack,
+    /// the source of a policy watcher could not be read
+    PolicySource,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::InvalidFormat => "invalid_format",
+            ErrorCode::SerializationError => "serialization_error",
+            ErrorCode::InvalidSignature => "invalid_signature",
+            ErrorCode::UnknownPublicKey => "unknown_public_key",
+            ErrorCode::UnsupportedVersion => "unsupported_version",
+            ErrorCode::InvalidKey => "invalid_key",
+            ErrorCode::UnsupportedAlgorithm => "unsupported_algorithm",
+            ErrorCode::DeserializationLimit => "deserialization_limit",
+            ErrorCode::SymbolTableOverlap => "symbol_table_overlap",
+            ErrorCode::AppendOnSealed => "append_on_sealed",
+            ErrorCode::AlreadySealed => "already_sealed",
+            ErrorCode::InvalidBlockRule => "invalid_block_rule",
+            ErrorCode::FailedCheck => "failed_check",
+            ErrorCode::Denied => "denied",
+            ErrorCode::NoMatchingPolicy => "no_matching_policy",
+            ErrorCode::AuthorizerNotEmpty => "authorizer_not_empty",
+            ErrorCode::NoPolicies => "no_policies",
+            ErrorCode::InvalidDatalog => "invalid_datalog",
+            ErrorCode::TooManyFacts => "too_many_facts",
+            ErrorCode::TooManyIterations => "too_many_iterations",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::TooMuchMemory => "too_much_memory",
+            ErrorCode::TooManyPredicateFacts => "too_many_predicate_facts",
+            ErrorCode::ConversionError => "conversion_error",
+            ErrorCode::InvalidEncoding => "invalid_encoding",
+            ErrorCode::Revoked => "revoked",
+            ErrorCode::RevocationLookup => "revocation_lookup",
+            ErrorCode::AttributeLookup => "attribute_lookup",
+            ErrorCode::NoResult => "no_result",
+            ErrorCode::TooManyResults => "too_many_results",
+            ErrorCode::WrongAudience => "wrong_audience",
+            ErrorCode::NonceReused => "nonce_reused",
+            ErrorCode::ReplayLookup => "replay_lookup",
+            ErrorCode::InvalidPackVersion => "invalid_pack_version",
+            ErrorCode::IncompatiblePolicyPack => "incompatible_policy_pack",
+            ErrorCode::PolicySource => "policy_source",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// description of an underlying error, keeping the original error as its
+/// [source](Error::source) when there is one
+///
+/// causes are compared and serialized as their description, so the original
+/// error is lost when deserializing
+#[derive(Clone)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-error", serde(from = "String", into = "String"))]
+pub struct Cause {
+    message: String,
+    source: Option<Arc<dyn Error + Send + Sync + 'static>>,
+}
+
+impl Cause {
+    /// uses the error's description as message
+    pub fn from_error<E: Error + Send + Sync + 'static>(e: E) -> Self {
+        Cause {
+            message: e.to_string(),
+            source: Some(Arc::new(e)),
+        }
+    }
+
+    pub fn with_source<E: Error + Send + Sync + 'static>(message: String, source: E) -> Self {
+        Cause {
+            message,
+            source: Some(Arc::new(source)),
+        }
+    }
+
+    /// like [Cause::from_error], for the errors of dependencies that only
+    /// implement `Error` with their `std` feature: without it, only the
+    /// description is kept
+    pub(crate) fn from_dependency<E: DependencyError>(e: E) -> Self {
+        Cause::with_dependency_source(e.to_string(), e)
+    }
+
+    /// like [Cause::with_source], see [Cause::from_dependency]
+    #[cfg(feature = "std")]
+    pub(crate) fn with_dependency_source<E: DependencyError>(message: String, source: E) -> Self {
+        Cause::with_source(message, source)
+    }
+
+    /// like [Cause::with_source], see [Cause::from_dependency]
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn with_dependency_source<E: DependencyError>(message: String, _source: E) -> Self {
+        message.into()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) trait DependencyError: Error + Send + Sync + 'static {}
+
+#[cfg(feature = "std")]
+impl<E: Error + Send + Sync + 'static> DependencyError for E {}
+
+#[cfg(not(feature = "std"))]
+pub(crate) trait DependencyError: fmt::Display {}
+
+#[cfg(not(feature = "std"))]
+impl<E: fmt::Display> DependencyError for E {}
+
+impl From<String> for Cause {
+    fn from(message: String) -> Self {
+        Cause {
+            message,
+            source: None,
+        }
+    }
+}
+
+impl From<&str> for Cause {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl From<Cause> for String {
+    fn from(cause: Cause) -> Self {
+        cause.message
+    }
+}
+
+impl PartialEq for Cause {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
+}
+
+impl fmt::Debug for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.message, f)
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Cause {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|e| e.as_ref() as &(dyn Error + 'static))
+    }
+}
+
+/// Errors in the format of an HTTP Authorization header
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuthorizationHeaderError {
+    MissingBearer,
+    EmptyToken,
+}
+
+impl fmt::Display for AuthorizationHeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthorizationHeaderError::MissingBearer => {
+                f.write_str("the header does not use the Bearer scheme")
+            }
+            AuthorizationHeaderError::EmptyToken => {
+                f.write_str("the header does not contain a token")
+            }
+        }
+    }
+}
+
+impl Error for AuthorizationHeaderError {}
+
 /// Errors related to the token's serialization format or cryptographic
 /// signature
-#[derive(Error, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum Format {
-    #[error("failed verifying the signature")]
     Signature(Signature),
-    #[error("failed verifying the signature of a sealed token")]
     SealedSignature,
-    #[error("the token does not provide intermediate public keys")]
     EmptyKeys,
-    #[error("the root public key was not recognized")]
     UnknownPublicKey,
-    #[error("could not deserialize the wrapper object")]
-    DeserializationError(String),
-    #[error("could not serialize the wrapper object")]
-    SerializationError(String),
-    #[error("could not deserialize the block")]
-    BlockDeserializationError(String),
-    #[error("could not serialize the block")]
-    BlockSerializationError(String),
-    #[error("Block format version is higher than supported")]
+    DeserializationError(Cause),
+    SerializationError(Cause),
+    BlockDeserializationError(Cause),
+    BlockSerializationError(Cause),
     Version {
         maximum: u32,
         minimum: u32,
         actual: u32,
     },
-    #[error("invalid key size")]
+    /// the version is supported by this library but not accepted by the
+    /// verifier's [`FormatSettings`](crate::format::FormatSettings)
+    UnsupportedVersion {
+        found: u32,
+        min: u32,
+        max: u32,
+    },
     InvalidKeySize(usize),
-    #[error("invalid signature size")]
     InvalidSignatureSize(usize),
-    #[error("invalid key")]
-    InvalidKey(String),
+    InvalidKey(Cause),
+    Limit(DeserializationLimit),
+    KeyEncoding(KeyEncoding),
+    UnsupportedAlgorithm(i32),
+    UnknownSymbol(u64),
+    /// the base64 encoding of a token is invalid
+    Base64(Base64Error),
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Signature(_) => f.write_str("failed verifying the signature"),
+            Format::SealedSignature => {
+                f.write_str("failed verifying the signature of a sealed token")
+            }
+            Format::EmptyKeys => f.write_str("the token does not provide intermediate public keys"),
+            Format::UnknownPublicKey => f.write_str("the root public key was not recognized"),
+            Format::DeserializationError(_) => {
+                f.write_str("could not deserialize the wrapper object")
+            }
+            Format::SerializationError(_) => f.write_str("could not serialize the wrapper object"),
+            Format::BlockDeserializationError(_) => f.write_str("could not deserialize the block"),
+            Format::BlockSerializationError(_) => f.write_str("could not serialize the block"),
+            Format::Version { .. } => f.write_str("Block format version is higher than supported"),
+            Format::UnsupportedVersion { found, min, max } => write!(
+                f,
+                "block format version {} is not accepted, expected between {} and {}",
+                found, min, max
+            ),
+            Format::InvalidKeySize(_) => f.write_str("invalid key size"),
+            Format::InvalidSignatureSize(_) => f.write_str("invalid signature size"),
+            Format::InvalidKey(_) => f.write_str("invalid key"),
+            Format::Limit(_) => f.write_str("deserialization limits exceeded"),
+            Format::KeyEncoding(_) => f.write_str("invalid key encoding"),
+            Format::UnsupportedAlgorithm(algorithm) => {
+                write!(f, "unsupported signature algorithm: {}", algorithm)
+            }
+            Format::UnknownSymbol(index) => write!(f, "unknown symbol index: {}", index),
+            Format::Base64(e) => write!(f, "could not decode the base64 token: {}", e),
+        }
+    }
+}
+
+impl Error for Format {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Format::Signature(e) => Some(e),
+            Format::DeserializationError(e)
+            | Format::SerializationError(e)
+            | Format::BlockDeserializationError(e)
+            | Format::BlockSerializationError(e)
+            | Format::InvalidKey(e) => Some(e),
+            Format::Limit(e) => Some(e),
+            Format::KeyEncoding(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Format {
+    /// stable code for this error, see [Token::code]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Format::Signature(_) | Format::SealedSignature => ErrorCode::InvalidSignature,
+            Format::EmptyKeys
+            | Format::DeserializationError(_)
+            | Format::BlockDeserializationError(_)
+            | Format::InvalidSignatureSize(_)
+            | Format::UnknownSymbol(_) => ErrorCode::InvalidFormat,
+            Format::SerializationError(_) | Format::BlockSerializationError(_) => {
+                ErrorCode::SerializationError
+            }
+            Format::UnknownPublicKey => ErrorCode::UnknownPublicKey,
+            Format::Version { .. } | Format::UnsupportedVersion { .. } => {
+                ErrorCode::UnsupportedVersion
+            }
+            Format::InvalidKeySize(_) | Format::InvalidKey(_) | Format::KeyEncoding(_) => {
+                ErrorCode::InvalidKey
+            }
+            Format::Limit(_) => ErrorCode::DeserializationLimit,
+            Format::UnsupportedAlgorithm(_) => ErrorCode::UnsupportedAlgorithm,
+            Format::Base64(_) => ErrorCode::InvalidEncoding,
+        }
+    }
+}
+
+impl From<KeyEncoding> for Format {
+    fn from(e: KeyEncoding) -> Self {
+        Format::KeyEncoding(e)
+    }
+}
+
+/// errors when importing keys from PEM or DER
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyEncoding {
+    InvalidPem(String),
+    UnexpectedLabel { expected: String, found: String },
+    InvalidDer,
+    UnsupportedAlgorithm,
+    InvalidText(String),
+}
+
+impl fmt::Display for KeyEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyEncoding::InvalidPem(e) => write!(f, "invalid PEM: {}", e),
+            KeyEncoding::UnexpectedLabel { expected, found } => write!(
+                f,
+                "unexpected PEM label: expected {}, found {}",
+                expected, found
+            ),
+            KeyEncoding::InvalidDer => f.write_str("invalid DER structure"),
+            KeyEncoding::UnsupportedAlgorithm => f.write_str("unsupported key algorithm"),
+            KeyEncoding::InvalidText(e) => write!(f, "invalid textual encoding: {}", e),
+        }
+    }
+}
+
+impl Error for KeyEncoding {}
+
+/// deserialization limits errors
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeserializationLimit {
+    TokenSize,
+    TooManyBlocks,
+    TooManyFacts,
+    TooManyRules,
+    TooManyChecks,
+    TooManySymbols,
+    DecompressedSize,
+}
+
+impl fmt::Display for DeserializationLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DeserializationLimit::TokenSize => "the token is too large",
+            DeserializationLimit::TooManyBlocks => "too many blocks",
+            DeserializationLimit::TooManyFacts => "too many facts in a block",
+            DeserializationLimit::TooManyRules => "too many rules in a block",
+            DeserializationLimit::TooManyChecks => "too many checks in a block",
+            DeserializationLimit::TooManySymbols => "too many symbols",
+            DeserializationLimit::DecompressedSize => "a decompressed block is too large",
+        })
+    }
 }
 
+impl Error for DeserializationLimit {}
+
 /// Signature errors
-#[derive(Error, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum Signature {
-    #[error("could not parse the signature elements")]
     InvalidFormat,
-    #[error("the signature did not match")]
-    InvalidSignature(String),
-    #[error("could not sign")]
-    InvalidSignatureGeneration(String),
+    InvalidSignature(Cause),
+    InvalidSignatureGeneration(Cause),
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Signature::InvalidFormat => "could not parse the signature elements",
+            Signature::InvalidSignature(_) => "the signature did not match",
+            Signature::InvalidSignatureGeneration(_) => "could not sign",
+        })
+    }
+}
+
+impl Error for Signature {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Signature::InvalidFormat => None,
+            Signature::InvalidSignature(e) | Signature::InvalidSignatureGeneration(e) => Some(e),
+        }
+    }
 }
 
 /// errors in the Datalog evaluation
-#[derive(Error, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum Logic {
-    #[error("a rule provided by a block is generating facts with the authority or ambient tag, or has head variables not used in its body")]
     InvalidBlockRule(u32, String),
-    #[error("authorization failed")]
     Unauthorized {
         /// the policy that matched
         policy: MatchedPolicy,
         /// list of checks that failed validation
         checks: Vec<FailedCheck>,
     },
-    #[error("the authorizer already contains a token")]
     AuthorizerNotEmpty,
-    #[error("no matching policy was found")]
     NoMatchingPolicy {
         /// list of checks that failed validation
         checks: Vec<FailedCheck>,
     },
+    NoPolicies,
 }
 
-#[derive(Error, Clone, Debug, PartialEq)]
+impl fmt::Display for Logic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Logic::InvalidBlockRule(_, _) => "a rule provided by a block is generating facts with the authority or ambient tag, or has head variables not used in its body",
+            Logic::Unauthorized { .. } => "authorization failed",
+            Logic::AuthorizerNotEmpty => "the authorizer already contains a token",
+            Logic::NoMatchingPolicy { .. } => "no matching policy was found",
+            Logic::NoPolicies => "the authorizer has no policies",
+        })
+    }
+}
+
+impl Error for Logic {}
+
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum MatchedPolicy {
-    #[error("an allow policy matched")]
     Allow(usize),
-    #[error("a deny policy matched")]
     Deny(usize),
 }
 
+impl fmt::Display for MatchedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MatchedPolicy::Allow(_) => "an allow policy matched",
+            MatchedPolicy::Deny(_) => "a deny policy matched",
+        })
+    }
+}
+
+impl Error for MatchedPolicy {}
+
 /// check errors
-#[derive(Error, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum FailedCheck {
-    #[error("a check failed in a block")]
     Block(FailedBlockCheck),
-    #[error("a check provided by the authorizer failed")]
     Authorizer(FailedAuthorizerCheck),
 }
 
+impl fmt::Display for FailedCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FailedCheck::Block(_) => "a check failed in a block",
+            FailedCheck::Authorizer(_) => "a check provided by the authorizer failed",
+        })
+    }
+}
+
+impl Error for FailedCheck {}
+
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub struct FailedBlockCheck {
@@ -193,6 +760,18 @@ pub struct FailedBlockCheck {
     pub check_id: u32,
     /// pretty print of the rule that failed
     pub rule: String,
+    /// context string of the block
+    #[cfg_attr(
+        feature = "serde-error",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub context: Option<String>,
+    /// revocation id of the block, hex encoded
+    #[cfg_attr(
+        feature = "serde-error",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub revocation_id: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -201,29 +780,168 @@ pub struct FailedAuthorizerCheck {
     pub check_id: u32,
     /// pretty print of the rule that failed
     pub rule: String,
+    /// source text of the check, if it was added with
+    /// [Authorizer::add_code](crate::Authorizer::add_code)
+    #[cfg_attr(
+        feature = "serde-error",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub source: Option<String>,
 }
 
 /// runtime limits errors
-#[derive(Error, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum RunLimit {
-    #[error("too many facts generated")]
     TooManyFacts,
-    #[error("too many engine iterations")]
     TooManyIterations,
-    #[error("spent too much time verifying")]
     Timeout,
+    TooMuchMemory,
+    TooManyPredicateFacts { predicate: String, max: u32 },
+}
+
+impl fmt::Display for RunLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunLimit::TooManyFacts => f.write_str("too many facts generated"),
+            RunLimit::TooManyIterations => f.write_str("too many engine iterations"),
+            RunLimit::Timeout => f.write_str("spent too much time verifying"),
+            RunLimit::TooMuchMemory => f.write_str("too much memory used by the generated facts"),
+            RunLimit::TooManyPredicateFacts { predicate, max } => write!(
+                f,
+                "more than {} facts with the predicate {}",
+                max, predicate
+            ),
+        }
+    }
 }
 
-#[derive(Error, Clone, Debug, PartialEq)]
+impl Error for RunLimit {}
+
+/// errors of [Authorizer::query_one](crate::Authorizer::query_one) and
+/// [Authorizer::query_opt](crate::Authorizer::query_opt)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub enum Query {
+    NoResult { query: String },
+    TooManyResults { query: String, count: usize },
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Query::NoResult { query } => write!(f, "the query returned no result: {}", query),
+            Query::TooManyResults { query, count } => write!(
+                f,
+                "the query returned {} results instead of one: {}",
+                count, query
+            ),
+        }
+    }
+}
+
+impl Error for Query {}
+
+/// revocation check errors, see [crate::revocation]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub enum Revocation {
+    Revoked(u32),
+    Lookup(Cause),
+}
+
+impl fmt::Display for Revocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Revocation::Revoked(block) => write!(f, "the block {} was revoked", block),
+            Revocation::Lookup(e) => write!(f, "cannot query the revocation store: {}", e),
+        }
+    }
+}
+
+impl Error for Revocation {}
+
+/// replay detection errors, see [crate::nonce]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub enum Replay {
+    Reused,
+    Lookup(Cause),
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Replay::Reused => f.write_str("the token's nonce was already used"),
+            Replay::Lookup(e) => write!(f, "cannot query the replay store: {}", e),
+        }
+    }
+}
+
+impl Error for Replay {}
+
+/// policy pack errors, see [crate::policy_pack]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub enum PolicyPack {
+    InvalidVersion(String),
+    Incompatible {
+        name: String,
+        version: String,
+        requirement: String,
+    },
+}
+
+impl fmt::Display for PolicyPack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyPack::InvalidVersion(version) => write!(f, "invalid version: {}", version),
+            PolicyPack::Incompatible {
+                name,
+                version,
+                requirement,
+            } => write!(
+                f,
+                "the version {} of {} does not match {}",
+                version, name, requirement
+            ),
+        }
+    }
+}
+
+impl Error for PolicyPack {}
+
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
 pub enum LanguageError {
-    #[error("datalog parsing error")]
     ParseError(ParseErrors),
-    #[error("facts must not contain unbound parameters")]
     Builder { invalid_parameters: Vec<String> },
-    #[error("cannot set value for an unknown parameter")]
     UnknownParameter(String),
+    Enumeration(String),
+}
+
+impl fmt::Display for LanguageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LanguageError::ParseError(_) => f.write_str("datalog parsing error"),
+            LanguageError::Builder { .. } => {
+                f.write_str("facts must not contain unbound parameters")
+            }
+            LanguageError::UnknownParameter(_) => {
+                f.write_str("cannot set value for an unknown parameter")
+            }
+            LanguageError::Enumeration(message) => write!(f, "invalid enumeration: {}", message),
+        }
+    }
+}
+
+impl Error for LanguageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LanguageError::ParseError(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -237,17 +955,118 @@ pub struct ParseErrors {
 pub struct ParseError {
     pub input: String,
     pub message: Option<String>,
+    /// position of the error in the parsed text, when it is known
+    pub location: Option<SourceLocation>,
+    /// what the parser expected at this position
+    pub expected: Option<String>,
+}
+
+/// position in a Datalog source text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceLocation {
+    /// offset in bytes, from the start of the text
+    pub offset: usize,
+    /// line number, starting at 1
+    pub line: usize,
+    /// column in characters, starting at 1
+    pub column: usize,
+}
+
+impl SourceLocation {
+    /// finds the location of `input` in `source`, if it is a slice of it
+    pub fn find(source: &str, input: &str) -> Option<Self> {
+        let start = source.as_ptr() as usize;
+        let position = input.as_ptr() as usize;
+        if position < start || position > start + source.len() {
+            return None;
+        }
+
+        let offset = position - start;
+        let before = source.get(..offset)?;
+        let line = 1 + before.matches('\n').count();
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = 1 + before[line_start..].chars().count();
+
+        Some(SourceLocation {
+            offset,
+            line,
+            column,
+        })
+    }
 }
 
 impl<'a> From<crate::parser::Error<'a>> for ParseError {
     fn from(e: crate::parser::Error<'a>) -> Self {
         ParseError {
             input: e.input.to_string(),
+            expected: e.expected(),
             message: e.message,
+            location: None,
         }
     }
 }
 
+impl ParseError {
+    /// converts a parser error, locating it in the parsed text
+    pub(crate) fn located(source: &str, e: crate::parser::Error) -> Self {
+        let location = SourceLocation::find(source, e.input);
+        ParseError {
+            location,
+            ..e.into()
+        }
+    }
+}
+
+impl ParseErrors {
+    /// converts parser errors, locating them in the parsed text
+    pub(crate) fn located(source: &str, errors: Vec<crate::parser::Error>) -> Self {
+        ParseErrors {
+            errors: errors
+                .into_iter()
+                .map(|e| ParseError::located(source, e))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.errors.len() {
+            1 => write!(f, "1 parse error"),
+            n => write!(f, "{} parse errors", n),
+        }
+    }
+}
+
+/// the source is the first error
+impl Error for ParseErrors {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.errors.first().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message.as_deref().unwrap_or("parse error"))?;
+        if let Some(location) = &self.location {
+            write!(f, " at line {}, column {}", location.line, location.column)?;
+        }
+        if let Some(expected) = &self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<ParseErrors> for Token {
+    fn from(e: ParseErrors) -> Self {
+        Token::Language(LanguageError::ParseError(e))
+    }
+}
+
 impl<'a> From<crate::parser::Error<'a>> for ParseErrors {
     fn from(error: crate::parser::Error<'a>) -> Self {
         ParseErrors {
@@ -280,4 +1099,123 @@ mod tests {
             "Cannot decode base64 token: Encoded text cannot have a 6-bit remainder."
         );
     }
+
+    #[test]
+    fn source_chain() {
+        use Error;
+
+        let e = crate::Biscuit::from(&[0xffu8, 0xff][..], |_| crate::KeyPair::new().public())
+            .unwrap_err();
+        let format = e.source().unwrap();
+        assert!(format.downcast_ref::<Format>().is_some());
+        let cause = format.source().unwrap();
+        assert!(cause.downcast_ref::<Cause>().is_some());
+        assert!(cause
+            .source()
+            .unwrap()
+            .downcast_ref::<prost::DecodeError>()
+            .is_some());
+
+        let e: Token = crate::parser::parse_source("check if")
+            .map_err(|e| ParseErrors::located("check if", e))
+            .unwrap_err()
+            .into();
+        let language = e.source().unwrap();
+        let errors = language.source().unwrap();
+        assert!(errors.downcast_ref::<ParseErrors>().is_some());
+        assert!(errors
+            .source()
+            .unwrap()
+            .downcast_ref::<ParseError>()
+            .is_some());
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(
+            Token::FailedLogic(Logic::Unauthorized {
+                policy: MatchedPolicy::Deny(0),
+                checks: vec![],
+            })
+            .code(),
+            ErrorCode::Denied
+        );
+        assert_eq!(
+            Token::FailedLogic(Logic::Unauthorized {
+                policy: MatchedPolicy::Allow(0),
+                checks: vec![],
+            })
+            .code(),
+            ErrorCode::FailedCheck
+        );
+        assert_eq!(
+            Token::Format(Format::Signature(Signature::InvalidFormat)).code(),
+            ErrorCode::InvalidSignature
+        );
+        assert_eq!(
+            Token::RunLimit(RunLimit::Timeout).code().to_string(),
+            "timeout"
+        );
+    }
+
+    #[cfg(feature = "serde-error")]
+    #[test]
+    fn serialized_names() {
+        let e = Token::FailedLogic(Logic::Unauthorized {
+            policy: MatchedPolicy::Allow(0),
+            checks: vec![
+                FailedCheck::Block(FailedBlockCheck {
+                    block_id: 1,
+                    check_id: 0,
+                    rule: "check if true".to_string(),
+                    context: None,
+                    revocation_id: None,
+                }),
+                FailedCheck::Authorizer(FailedAuthorizerCheck {
+                    check_id: 2,
+                    rule: "check if false".to_string(),
+                    source: None,
+                }),
+            ],
+        });
+        let json = serde_json::to_string(&e).unwrap();
+        assert_eq!(
+            json,
+            r#"{"FailedLogic":{"Unauthorized":{"policy":{"Allow":0},"checks":[{"Block":{"block_id":1,"check_id":0,"rule":"check if true"}},{"Authorizer":{"check_id":2,"rule":"check if false"}}]}}}"#
+        );
+        assert_eq!(serde_json::from_str::<Token>(&json).unwrap(), e);
+
+        let e = FailedCheck::Block(FailedBlockCheck {
+            block_id: 1,
+            check_id: 0,
+            rule: "check if true".to_string(),
+            context: Some("issuer".to_string()),
+            revocation_id: Some("0a0b".to_string()),
+        });
+        assert_eq!(
+            serde_json::to_string(&e).unwrap(),
+            r#"{"Block":{"block_id":1,"check_id":0,"rule":"check if true","context":"issuer","revocation_id":"0a0b"}}"#
+        );
+
+        assert_eq!(
+            serde_json::to_string(&Token::Format(Format::Version {
+                minimum: 3,
+                maximum: 3,
+                actual: 2
+            }))
+            .unwrap(),
+            r#"{"Format":{"Version":{"maximum":3,"minimum":3,"actual":2}}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Token::RunLimit(RunLimit::Timeout)).unwrap(),
+            r#"{"RunLimit":"Timeout"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Token::Format(Format::Limit(
+                DeserializationLimit::TooManyBlocks
+            )))
+            .unwrap(),
+            r#"{"Format":{"Limit":"TooManyBlocks"}}"#
+        );
+    }
 }
@@ -0,0 +1,248 @@
+//! compact binary encoding of tokens, with the `compact` feature
+//!
+//! this replaces the Protobuf wrapper of a token with a fixed layout, for
+//! environments where the token size matters, like radio packets. The
+//! content of each block is still encoded with Protobuf, since the block
+//! signatures cover those bytes.
+//!
+//! Layout, with integers encoded as LEB128 varints:
+//!
+//! - version byte, currently `1`
+//! - flags byte: `1` if a root key id is present, `2` if the token is sealed
+//! - root key id (varint), if present
+//! - number of blocks, including the authority block (varint)
+//! - for each block:
+//!   - length of the block (varint) and the block's bytes
+//!   - next key: algorithm byte and 32 bytes
+//!   - signature: 64 bytes
+//!   - `0`, or `1` followed by the third party key (algorithm byte and
+//!     32 bytes) and its signature (64 bytes)
+//! - proof: next private key (32 bytes), or final signature (64 bytes)
+//!   if the token is sealed
+use super::schema;
+use crate::error;
+use alloc::vec::Vec;
+
+/// version of the compact encoding
+pub const COMPACT_VERSION: u8 = 1;
+
+const FLAG_ROOT_KEY_ID: u8 = 1;
+const FLAG_SEALED: u8 = 2;
+
+const KEY_SIZE: usize = 32;
+const SIGNATURE_SIZE: usize = 64;
+
+/// encodes a token with the compact layout
+pub fn encode(token: &schema::Biscuit) -> Vec<u8> {
+    let mut out = vec![COMPACT_VERSION];
+
+    let sealed = matches!(
+        token.proof.content,
+        Some(schema::proof::Content::FinalSignature(_))
+    );
+    let mut flags = 0;
+    if token.root_key_id.is_some() {
+        flags |= FLAG_ROOT_KEY_ID;
+    }
+    if sealed {
+        flags |= FLAG_SEALED;
+    }
+    out.push(flags);
+
+    if let Some(id) = token.root_key_id {
+        write_varint(&mut out, id as u64);
+    }
+
+    write_varint(&mut out, 1 + token.blocks.len() as u64);
+    for block in core::iter::once(&token.authority).chain(token.blocks.iter()) {
+        write_varint(&mut out, block.block.len() as u64);
+        out.extend_from_slice(&block.block);
+        write_key(&mut out, &block.next_key);
+        out.extend_from_slice(&block.signature);
+
+        match &block.external_signature {
+            None => out.push(0),
+            Some(external) => {
+                out.push(1);
+                write_key(&mut out, &external.public_key);
+                out.extend_from_slice(&external.signature);
+            }
+        }
+    }
+
+    match &token.proof.content {
+        Some(schema::proof::Content::NextSecret(v))
+        | Some(schema::proof::Content::FinalSignature(v)) => out.extend_from_slice(v),
+        None => {}
+    }
+
+    out
+}
+
+/// decodes a token from the compact layout
+///
+/// keys and signatures are not validated here, this is done when
+/// converting the token like for the Protobuf encoding
+pub fn decode(slice: &[u8]) -> Result<schema::Biscuit, error::Format> {
+    let mut input = Reader { data: slice };
+
+    let version = input.byte()?;
+    if version != COMPACT_VERSION {
+        return Err(error::Format::DeserializationError(
+            format!(
+                "deserialization error: unsupported compact encoding version {}",
+                version
+            )
+            .into(),
+        ));
+    }
+
+    let flags = input.byte()?;
+    let root_key_id = if flags & FLAG_ROOT_KEY_ID != 0 {
+        let id = input.varint()?;
+        if id > u32::MAX as u64 {
+            return Err(invalid("root key id out of range"));
+        }
+        Some(id as u32)
+    } else {
+        None
+    };
+
+    let count = input.varint()?;
+    if count == 0 {
+        return Err(invalid("missing authority block"));
+    }
+
+    let mut blocks = Vec::new();
+    for _ in 0..count {
+        let len = input.varint()? as usize;
+        let block = input.bytes(len)?.to_vec();
+        let next_key = input.key()?;
+        let signature = input.bytes(SIGNATURE_SIZE)?.to_vec();
+        let external_signature = match input.byte()? {
+            0 => None,
+            1 => Some(schema::ExternalSignature {
+                public_key: input.key()?,
+                signature: input.bytes(SIGNATURE_SIZE)?.to_vec(),
+            }),
+            _ => return Err(invalid("invalid external signature marker")),
+        };
+
+        blocks.push(schema::SignedBlock {
+            block,
+            next_key,
+            signature,
+            external_signature,
+        });
+    }
+
+    let content = if flags & FLAG_SEALED != 0 {
+        schema::proof::Content::FinalSignature(input.bytes(SIGNATURE_SIZE)?.to_vec())
+    } else {
+        schema::proof::Content::NextSecret(input.bytes(KEY_SIZE)?.to_vec())
+    };
+
+    if !input.data.is_empty() {
+        return Err(invalid("trailing data"));
+    }
+
+    let authority = blocks.remove(0);
+    Ok(schema::Biscuit {
+        root_key_id,
+        authority,
+        blocks,
+        proof: schema::Proof {
+            content: Some(content),
+        },
+    })
+}
+
+fn invalid(message: &str) -> error::Format {
+    error::Format::DeserializationError(format!("deserialization error: {}", message).into())
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_key(out: &mut Vec<u8>, key: &schema::PublicKey) {
+    out.push(key.algorithm as u8);
+    out.extend_from_slice(&key.key);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, error::Format> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], error::Format> {
+        if self.data.len() < len {
+            return Err(invalid("unexpected end of input"));
+        }
+
+        let (res, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(res)
+    }
+
+    fn varint(&mut self) -> Result<u64, error::Format> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            value |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(invalid("varint too long"))
+    }
+
+    fn key(&mut self) -> Result<schema::PublicKey, error::Format> {
+        let algorithm = self.byte()? as i32;
+        let key = self.bytes(KEY_SIZE)?.to_vec();
+        Ok(schema::PublicKey { algorithm, key })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Biscuit, KeyPair};
+
+    #[test]
+    fn roundtrip() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.set_root_key_id(1234);
+        builder
+            .add_authority_fact("right(\"file1\", \"read\")")
+            .unwrap();
+        let token = builder.build().unwrap();
+        let token = token.append(token.create_block()).unwrap();
+
+        let compact = token.to_compact().unwrap();
+        assert!(compact.len() < token.to_vec().unwrap().len());
+
+        let decoded = Biscuit::from_compact(&compact, &root.public()).unwrap();
+        assert_eq!(decoded, token);
+        assert_eq!(decoded.root_key_id(), Some(1234));
+
+        let sealed = token.seal().unwrap();
+        let decoded = Biscuit::from_compact(&sealed.to_compact().unwrap(), &root.public()).unwrap();
+        assert_eq!(decoded, sealed);
+
+        assert!(decode(&compact[..compact.len() - 1]).is_err());
+        let mut trailing = compact.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+    }
+}
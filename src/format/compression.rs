@@ -0,0 +1,168 @@
+//! compression of large blocks
+//!
+//! with the `compression` feature, blocks whose serialized size exceeds
+//! [COMPRESSION_THRESHOLD] are compressed with deflate before being signed.
+//! The compressed block is wrapped in a `Block` message containing only the
+//! version 5 and the `compressed` field. Versions of the library that do not
+//! support compression reject it as a newer format version instead of
+//! misreading it. The wrapped block keeps its own version.
+//!
+//! Decompression is bounded by
+//! [DeserializationLimits::max_decompressed_size](super::DeserializationLimits::max_decompressed_size)
+use super::schema;
+use crate::error;
+use crate::token::COMPRESSED_SCHEMA_VERSION;
+use alloc::vec::Vec;
+use prost::Message;
+
+/// serialized blocks larger than this are compressed, in bytes
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// default maximum size of a decompressed block, in bytes
+pub const MAX_DECOMPRESSED_SIZE: usize = 1024 * 1024;
+
+/// compresses a serialized block if it is larger than [COMPRESSION_THRESHOLD]
+/// and compression makes it smaller
+#[cfg(feature = "compression")]
+pub(crate) fn compress_block(data: Vec<u8>) -> Vec<u8> {
+    if data.len() <= COMPRESSION_THRESHOLD {
+        return data;
+    }
+
+    let block = schema::Block {
+        version: Some(COMPRESSED_SCHEMA_VERSION),
+        compressed: Some(miniz_oxide::deflate::compress_to_vec(&data, 6)),
+        ..Default::default()
+    };
+
+    let mut compressed = Vec::with_capacity(block.encoded_len());
+    if block.encode(&mut compressed).is_ok() && compressed.len() < data.len() {
+        compressed
+    } else {
+        data
+    }
+}
+
+/// returns the block wrapped in a compressed block, or the block itself if
+/// it is not compressed
+pub(crate) fn decompress_block(
+    block: schema::Block,
+    max_size: usize,
+) -> Result<schema::Block, error::Format> {
+    if block.version != Some(COMPRESSED_SCHEMA_VERSION) {
+        if block.compressed.is_some() {
+            return Err(error::Format::BlockDeserializationError(
+                format!(
+                    "error deserializing block: compressed content in a block of version {}",
+                    block.version.unwrap_or(0)
+                )
+                .into(),
+            ));
+        }
+        return Ok(block);
+    }
+
+    let compressed = match block {
+        schema::Block {
+            compressed: Some(compressed),
+            symbols,
+            context: None,
+            facts_v2,
+            rules_v2,
+            checks_v2,
+            ..
+        } if symbols.is_empty() && facts_v2.is_empty() && rules_v2.is_empty() && checks_v2.is_empty() => {
+            compressed
+        }
+        _ => {
+            return Err(error::Format::BlockDeserializationError(
+                "error deserializing block: a compressed block must only contain the compressed content"
+                    .into(),
+            ))
+        }
+    };
+
+    let data = decompress(&compressed, max_size)?;
+    let block = schema::Block::decode(&data[..]).map_err(|e| {
+        error::Format::BlockDeserializationError(error::Cause::with_dependency_source(
+            format!("error deserializing compressed block: {:?}", e),
+            e,
+        ))
+    })?;
+
+    if block.version.unwrap_or(0) >= COMPRESSED_SCHEMA_VERSION || block.compressed.is_some() {
+        return Err(error::Format::BlockDeserializationError(
+            "error deserializing block: nested compressed block".into(),
+        ));
+    }
+
+    Ok(block)
+}
+
+#[cfg(feature = "compression")]
+fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, error::Format> {
+    miniz_oxide::inflate::decompress_to_vec_with_limit(data, max_size).map_err(|e| {
+        if e == miniz_oxide::inflate::TINFLStatus::HasMoreOutput {
+            error::Format::Limit(error::DeserializationLimit::DecompressedSize)
+        } else {
+            error::Format::BlockDeserializationError(
+                format!("error decompressing block: {:?}", e).into(),
+            )
+        }
+    })
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_data: &[u8], _max_size: usize) -> Result<Vec<u8>, error::Format> {
+    Err(error::Format::BlockDeserializationError(
+        "error deserializing block: compressed blocks require the compression feature".into(),
+    ))
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression() {
+        let small = vec![1u8; 10];
+        assert_eq!(compress_block(small.clone()), small);
+
+        let inner = schema::Block {
+            symbols: vec!["a".repeat(10 * COMPRESSION_THRESHOLD)],
+            version: Some(crate::token::MIN_SCHEMA_VERSION),
+            ..Default::default()
+        };
+        let large = inner.encode_to_vec();
+        let compressed = compress_block(large.clone());
+        assert!(compressed.len() < large.len());
+
+        let wrapper = schema::Block::decode(&compressed[..]).unwrap();
+        assert_eq!(wrapper.version, Some(COMPRESSED_SCHEMA_VERSION));
+        assert_eq!(
+            decompress_block(wrapper.clone(), MAX_DECOMPRESSED_SIZE).unwrap(),
+            inner
+        );
+        assert_eq!(
+            decompress_block(wrapper, COMPRESSION_THRESHOLD).unwrap_err(),
+            error::Format::Limit(error::DeserializationLimit::DecompressedSize)
+        );
+
+        // uncompressed blocks are returned as is
+        assert_eq!(
+            decompress_block(inner.clone(), MAX_DECOMPRESSED_SIZE).unwrap(),
+            inner
+        );
+
+        // a compressed block cannot contain another one
+        let nested = schema::Block {
+            version: Some(COMPRESSED_SCHEMA_VERSION),
+            compressed: Some(miniz_oxide::deflate::compress_to_vec(&compressed, 6)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            decompress_block(nested, MAX_DECOMPRESSED_SIZE),
+            Err(error::Format::BlockDeserializationError(_))
+        ));
+    }
+}
@@ -25,34 +25,77 @@ pub fn token_block_to_proto_block(input: &Block) -> schema::Block {
             .iter()
             .map(v2::token_check_to_proto_check)
             .collect(),
+        compressed: None,
     }
 }
 
 pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error::Format> {
     let version = input.version.unwrap_or(0);
-    if version < crate::token::MIN_SCHEMA_VERSION || version > crate::token::MAX_SCHEMA_VERSION {
-        return Err(error::Format::Version {
+    super::FormatSettings::default().check_version(version)?;
+
+    match version {
+        3 => v3::proto_block_to_token_block(input),
+        4 => v4::proto_block_to_token_block(input),
+        crate::token::COMPRESSED_SCHEMA_VERSION => Err(error::Format::BlockDeserializationError(
+            "error deserializing block: compressed blocks must be decompressed first".into(),
+        )),
+        _ => Err(error::Format::Version {
             minimum: crate::token::MIN_SCHEMA_VERSION,
             maximum: crate::token::MAX_SCHEMA_VERSION,
             actual: version,
-        });
+        }),
+    }
+}
+
+/// reader for version 3 blocks, the first version with the `*_v2` Datalog
+/// messages. Earlier versions used a schema that is not supported anymore
+mod v3 {
+    use super::*;
+
+    pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error::Format> {
+        let block = proto_block_content(input, 3)?;
+
+        // version 3 readers would not interpret the content added in
+        // version 4, so it must not appear in a version 3 block
+        let required = crate::token::required_version(&block.facts, &block.rules, &block.checks);
+        if required > 3 {
+            return Err(error::Format::BlockDeserializationError(
+                format!(
+                    "error deserializing block: its content requires version {}, found 3",
+                    required
+                )
+                .into(),
+            ));
+        }
+
+        Ok(block)
+    }
+}
+
+/// reader for version 4 blocks, adding third party blocks, rules trusting
+/// public keys and new expression operations to version 3
+mod v4 {
+    use super::*;
+
+    pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error::Format> {
+        proto_block_content(input, 4)
     }
+}
 
+fn proto_block_content(input: &schema::Block, version: u32) -> Result<Block, error::Format> {
     let mut facts = vec![];
     let mut rules = vec![];
     let mut checks = vec![];
-    if version == crate::token::MIN_SCHEMA_VERSION {
-        for fact in input.facts_v2.iter() {
-            facts.push(v2::proto_fact_to_token_fact(fact)?);
-        }
+    for fact in input.facts_v2.iter() {
+        facts.push(v2::proto_fact_to_token_fact(fact)?);
+    }
 
-        for rule in input.rules_v2.iter() {
-            rules.push(v2::proto_rule_to_token_rule(rule)?);
-        }
+    for rule in input.rules_v2.iter() {
+        rules.push(v2::proto_rule_to_token_rule(rule)?);
+    }
 
-        for check in input.checks_v2.iter() {
-            checks.push(v2::proto_check_to_token_check(check)?);
-        }
+    for check in input.checks_v2.iter() {
+        checks.push(v2::proto_check_to_token_check(check)?);
     }
 
     let context = input.context.clone();
@@ -66,6 +109,7 @@ pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error:
         checks,
         context,
         version,
+        external_key: None,
     })
 }
 
@@ -103,10 +147,12 @@ pub fn proto_authorizer_to_authorizer(
     input: &schema::AuthorizerPolicies,
 ) -> Result<AuthorizerPolicies, error::Format> {
     let version = input.version.unwrap_or(0);
-    if version < crate::token::MIN_SCHEMA_VERSION || version > crate::token::MAX_SCHEMA_VERSION {
+    super::FormatSettings::default().check_version(version)?;
+    // authorizer policies are not compressed
+    if version == crate::token::COMPRESSED_SCHEMA_VERSION {
         return Err(error::Format::Version {
             minimum: crate::token::MIN_SCHEMA_VERSION,
-            maximum: crate::token::MAX_SCHEMA_VERSION,
+            maximum: crate::token::COMPRESSED_SCHEMA_VERSION - 1,
             actual: version,
         });
     }
@@ -144,11 +190,48 @@ pub fn proto_authorizer_to_authorizer(
     })
 }
 
+pub fn proto_public_key_to_public_key(
+    input: &schema::PublicKey,
+) -> Result<crate::crypto::PublicKey, error::Format> {
+    match crate::crypto::Algorithm::from_i32(input.algorithm) {
+        Some(algorithm) => {
+            crate::crypto::PublicKey::from_bytes_with_algorithm(&input.key, algorithm)
+        }
+        None => Err(error::Format::UnsupportedAlgorithm(input.algorithm)),
+    }
+}
+
+pub fn public_key_to_proto_public_key(input: &crate::crypto::PublicKey) -> schema::PublicKey {
+    schema::PublicKey {
+        algorithm: input.algorithm() as i32,
+        key: input.to_bytes(),
+    }
+}
+
+pub fn proto_external_signature_to_external_signature(
+    input: &schema::ExternalSignature,
+) -> Result<crate::crypto::ExternalSignature, error::Format> {
+    Ok(crate::crypto::ExternalSignature {
+        public_key: proto_public_key_to_public_key(&input.public_key)?,
+        signature: crate::crypto::Signature::from_bytes(&input.signature)?,
+    })
+}
+
+pub fn external_signature_to_proto_external_signature(
+    input: &crate::crypto::ExternalSignature,
+) -> schema::ExternalSignature {
+    schema::ExternalSignature {
+        signature: input.signature.to_bytes().to_vec(),
+        public_key: public_key_to_proto_public_key(&input.public_key),
+    }
+}
+
 pub mod v2 {
     use super::schema;
     use crate::datalog::*;
     use crate::error;
-    use std::collections::BTreeSet;
+    use alloc::collections::BTreeSet;
+    use alloc::vec::Vec;
 
     pub fn token_fact_to_proto_fact(input: &Fact) -> schema::FactV2 {
         schema::FactV2 {
@@ -213,7 +296,7 @@ pub mod v2 {
             i
         } else {
             return Err(error::Format::DeserializationError(
-                "deserialization error: invalid policy kind".to_string(),
+                "deserialization error: invalid policy kind".into(),
             ));
         };
 
@@ -238,6 +321,11 @@ pub mod v2 {
                 .iter()
                 .map(token_expression_to_proto_expression)
                 .collect(),
+            trusted_keys: input
+                .trusted_keys
+                .iter()
+                .map(super::public_key_to_proto_public_key)
+                .collect(),
         }
     }
 
@@ -254,10 +342,17 @@ pub mod v2 {
             expressions.push(proto_expression_to_token_expression(c)?);
         }
 
+        let mut trusted_keys = vec![];
+
+        for key in input.trusted_keys.iter() {
+            trusted_keys.push(super::proto_public_key_to_public_key(key)?);
+        }
+
         Ok(Rule {
             head: proto_predicate_to_token_predicate(&input.head)?,
             body,
             expressions,
+            trusted_keys,
         })
     }
 
@@ -318,7 +413,7 @@ pub mod v2 {
 
         match &input.content {
             None => Err(error::Format::DeserializationError(
-                "deserialization error: ID content enum is empty".to_string(),
+                "deserialization error: ID content enum is empty".into(),
             )),
             Some(Content::Variable(i)) => Ok(Term::Variable(*i)),
             Some(Content::Integer(i)) => Ok(Term::Integer(*i)),
@@ -334,7 +429,7 @@ pub mod v2 {
                     let index = match i.content {
                         Some(Content::Variable(_)) => {
                             return Err(error::Format::DeserializationError(
-                                "deserialization error: sets cannot contain variables".to_string(),
+                                "deserialization error: sets cannot contain variables".into(),
                             ));
                         }
                         Some(Content::Integer(_)) => 2,
@@ -344,12 +439,12 @@ pub mod v2 {
                         Some(Content::Bool(_)) => 6,
                         Some(Content::Set(_)) => {
                             return Err(error::Format::DeserializationError(
-                                "deserialization error: sets cannot contain other sets".to_string(),
+                                "deserialization error: sets cannot contain other sets".into(),
                             ));
                         }
                         None => {
                             return Err(error::Format::DeserializationError(
-                                "deserialization error: ID content enum is empty".to_string(),
+                                "deserialization error: ID content enum is empty".into(),
                             ))
                         }
                     };
@@ -358,7 +453,7 @@ pub mod v2 {
                         if *k != index {
                             return Err(error::Format::DeserializationError(
                                 "deserialization error: sets elements must have the same type"
-                                    .to_string(),
+                                    .into(),
                             ));
                         }
                     } else {
@@ -389,6 +484,11 @@ pub mod v2 {
                                     Unary::Negate => Kind::Negate,
                                     Unary::Parens => Kind::Parens,
                                     Unary::Length => Kind::Length,
+                                    Unary::Sha256 => Kind::Sha256,
+                                    Unary::ToHex => Kind::ToHex,
+                                    Unary::FromHex => Kind::FromHex,
+                                    Unary::ToBase64 => Kind::ToBase64,
+                                    Unary::FromBase64 => Kind::FromBase64,
                                 } as i32,
                             })
                         }
@@ -414,6 +514,11 @@ pub mod v2 {
                                     Binary::Or => Kind::Or,
                                     Binary::Intersection => Kind::Intersection,
                                     Binary::Union => Kind::Union,
+                                    Binary::ContainsIp => Kind::ContainsIp,
+                                    Binary::SemverGe => Kind::SemverGe,
+                                    Binary::SemverLt => Kind::SemverLt,
+                                    Binary::Take => Kind::Take,
+                                    Binary::Skip => Kind::Skip,
                                 } as i32,
                             })
                         }
@@ -440,9 +545,14 @@ pub mod v2 {
                     Some(op_unary::Kind::Negate) => Op::Unary(Unary::Negate),
                     Some(op_unary::Kind::Parens) => Op::Unary(Unary::Parens),
                     Some(op_unary::Kind::Length) => Op::Unary(Unary::Length),
+                    Some(op_unary::Kind::Sha256) => Op::Unary(Unary::Sha256),
+                    Some(op_unary::Kind::ToHex) => Op::Unary(Unary::ToHex),
+                    Some(op_unary::Kind::FromHex) => Op::Unary(Unary::FromHex),
+                    Some(op_unary::Kind::ToBase64) => Op::Unary(Unary::ToBase64),
+                    Some(op_unary::Kind::FromBase64) => Op::Unary(Unary::FromBase64),
                     None => {
                         return Err(error::Format::DeserializationError(
-                            "deserialization error: unary operation is empty".to_string(),
+                            "deserialization error: unary operation is empty".into(),
                         ))
                     }
                 },
@@ -464,15 +574,20 @@ pub mod v2 {
                     Some(op_binary::Kind::Or) => Op::Binary(Binary::Or),
                     Some(op_binary::Kind::Intersection) => Op::Binary(Binary::Intersection),
                     Some(op_binary::Kind::Union) => Op::Binary(Binary::Union),
+                    Some(op_binary::Kind::ContainsIp) => Op::Binary(Binary::ContainsIp),
+                    Some(op_binary::Kind::SemverGe) => Op::Binary(Binary::SemverGe),
+                    Some(op_binary::Kind::SemverLt) => Op::Binary(Binary::SemverLt),
+                    Some(op_binary::Kind::Take) => Op::Binary(Binary::Take),
+                    Some(op_binary::Kind::Skip) => Op::Binary(Binary::Skip),
                     None => {
                         return Err(error::Format::DeserializationError(
-                            "deserialization error: binary operation is empty".to_string(),
+                            "deserialization error: binary operation is empty".into(),
                         ))
                     }
                 },
                 None => {
                     return Err(error::Format::DeserializationError(
-                        "deserialization error: operation is empty".to_string(),
+                        "deserialization error: operation is empty".into(),
                     ))
                 }
             };
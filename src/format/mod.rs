@@ -5,13 +5,13 @@
 //! - serialization of Biscuit blocks to Protobuf then `Vec<u8>`
 //! - serialization of a wrapper structure containing serialized blocks and the signature
 use super::crypto::{self, KeyPair, PrivateKey, PublicKey, TokenNext};
+use alloc::vec::Vec;
 
 use prost::Message;
 
 use super::error;
-use super::token::Block;
-use ed25519_dalek::Signer;
-use std::convert::TryInto;
+use super::token::{Block, RootKeyProvider};
+use crypto::Signer;
 
 /// Structures generated from the Protobuf schema
 pub mod schema; /* {
@@ -20,6 +20,11 @@ pub mod schema; /* {
 
 pub mod convert;
 
+#[cfg(feature = "compact")]
+pub mod compact;
+
+pub mod compression;
+
 use self::convert::*;
 
 /// Intermediate structure for token serialization
@@ -34,6 +39,198 @@ pub struct SerializedBiscuit {
     pub proof: crypto::TokenNext,
 }
 
+/// window of format versions accepted when reading tokens and authorizer policies
+///
+/// it is always restricted to the versions this library supports, between
+/// [MIN_SCHEMA_VERSION](crate::token::MIN_SCHEMA_VERSION) and
+/// [MAX_SCHEMA_VERSION](crate::token::MAX_SCHEMA_VERSION). The minimum can be
+/// raised to stop accepting older tokens once all services produce a newer
+/// version, and the maximum lowered to reject tokens that some services in a
+/// deployment could not read yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatSettings {
+    pub min_version: u32,
+    pub max_version: u32,
+}
+
+impl Default for FormatSettings {
+    fn default() -> Self {
+        FormatSettings {
+            min_version: crate::token::MIN_SCHEMA_VERSION,
+            max_version: crate::token::MAX_SCHEMA_VERSION,
+        }
+    }
+}
+
+impl FormatSettings {
+    /// returns an error if `version` is outside of the accepted window
+    ///
+    /// versions this library cannot read are reported as
+    /// [`Format::Version`](error::Format::Version), and versions it can read
+    /// but that these settings reject as
+    /// [`Format::UnsupportedVersion`](error::Format::UnsupportedVersion)
+    pub fn check_version(&self, version: u32) -> Result<(), error::Format> {
+        if !(crate::token::MIN_SCHEMA_VERSION..=crate::token::MAX_SCHEMA_VERSION).contains(&version)
+        {
+            return Err(error::Format::Version {
+                minimum: crate::token::MIN_SCHEMA_VERSION,
+                maximum: crate::token::MAX_SCHEMA_VERSION,
+                actual: version,
+            });
+        }
+
+        if !(self.min_version..=self.max_version).contains(&version) {
+            return Err(error::Format::UnsupportedVersion {
+                found: version,
+                min: self.min_version,
+                max: self.max_version,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// limits applied when deserializing a token
+///
+/// the size of the token, the number of blocks and the keys are checked
+/// before verifying the signatures, and the content of each block when it
+/// is decoded, after verification. They are only applied by the functions
+/// taking them as argument, like
+/// [Biscuit::from_with_limits](crate::Biscuit::from_with_limits)
+#[derive(Debug, Clone)]
+pub struct DeserializationLimits {
+    /// maximum size of the serialized token, in bytes
+    pub max_size: usize,
+    /// maximum number of blocks, including the authority block
+    pub max_blocks: usize,
+    /// maximum number of facts in a block
+    pub max_facts: usize,
+    /// maximum number of rules in a block
+    pub max_rules: usize,
+    /// maximum number of checks in a block
+    pub max_checks: usize,
+    /// maximum number of symbols, for the whole token
+    pub max_symbols: usize,
+    /// format versions accepted for each block
+    pub versions: FormatSettings,
+    /// maximum size of a block after decompression, in bytes
+    pub max_decompressed_size: usize,
+    /// signature algorithms accepted for the root key, the block keys and
+    /// the external signatures
+    ///
+    /// only Ed25519 is accepted by default. With the `p256` feature, add
+    /// [Algorithm::P256](crypto::Algorithm::P256) to accept P-256 root and third party keys
+    pub algorithms: Vec<crypto::Algorithm>,
+}
+
+impl Default for DeserializationLimits {
+    fn default() -> Self {
+        DeserializationLimits {
+            max_size: 1024 * 1024,
+            max_blocks: 256,
+            max_facts: 1000,
+            max_rules: 1000,
+            max_checks: 1000,
+            max_symbols: 10_000,
+            versions: FormatSettings::default(),
+            max_decompressed_size: compression::MAX_DECOMPRESSED_SIZE,
+            algorithms: vec![crypto::Algorithm::Ed25519],
+        }
+    }
+}
+
+impl DeserializationLimits {
+    /// no limits on the size and content of the token, as applied by the
+    /// functions without a limits argument
+    ///
+    /// all the supported signature algorithms are accepted. Decompression of
+    /// blocks is still bounded by [MAX_DECOMPRESSED_SIZE](compression::MAX_DECOMPRESSED_SIZE)
+    pub fn unlimited() -> Self {
+        DeserializationLimits {
+            max_size: usize::MAX,
+            max_blocks: usize::MAX,
+            max_facts: usize::MAX,
+            max_rules: usize::MAX,
+            max_checks: usize::MAX,
+            max_symbols: usize::MAX,
+            algorithms: crypto::Algorithm::supported(),
+            ..Default::default()
+        }
+    }
+
+    fn check_key(&self, key: &schema::PublicKey) -> Result<PublicKey, error::Format> {
+        let key = proto_public_key_to_public_key(key)?;
+        self.check_algorithm(&key)?;
+
+        Ok(key)
+    }
+
+    fn check_algorithm(&self, key: &PublicKey) -> Result<(), error::Format> {
+        if !self.algorithms.contains(&key.algorithm()) {
+            return Err(error::Format::UnsupportedAlgorithm(key.algorithm() as i32));
+        }
+
+        Ok(())
+    }
+
+    fn check_block(&self, block: &schema::Block) -> Result<(), error::Format> {
+        self.versions.check_version(block.version.unwrap_or(0))?;
+
+        if block.facts_v2.len() > self.max_facts {
+            return Err(error::Format::Limit(
+                error::DeserializationLimit::TooManyFacts,
+            ));
+        }
+
+        if block.rules_v2.len() > self.max_rules {
+            return Err(error::Format::Limit(
+                error::DeserializationLimit::TooManyRules,
+            ));
+        }
+
+        if block.checks_v2.len() > self.max_checks {
+            return Err(error::Format::Limit(
+                error::DeserializationLimit::TooManyChecks,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// checks the number of symbols defined by the blocks decoded so far
+    pub(crate) fn check_symbols(&self, count: usize) -> Result<(), error::Format> {
+        if count > self.max_symbols {
+            return Err(error::Format::Limit(
+                error::DeserializationLimit::TooManySymbols,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// decodes a serialized block, decompressing it if needed, and checks its
+/// content against the limits
+pub(crate) fn decode_block(
+    data: &[u8],
+    limits: &DeserializationLimits,
+) -> Result<schema::Block, error::Format> {
+    let block = schema::Block::decode(data).map_err(|e| {
+        error::Format::BlockDeserializationError(error::Cause::with_dependency_source(
+            format!("error deserializing block: {:?}", e),
+            e,
+        ))
+    })?;
+
+    limits.versions.check_version(block.version.unwrap_or(0))?;
+    let block = compression::decompress_block(block, limits.max_decompressed_size)?;
+
+    limits.check_block(&block)?;
+
+    Ok(block)
+}
+
 impl SerializedBiscuit {
     pub fn from_slice<F: Fn(Option<u32>) -> PublicKey>(
         slice: &[u8],
@@ -47,61 +244,120 @@ impl SerializedBiscuit {
         Ok(deser)
     }
 
+    /// deserializes a token with custom deserialization limits, and validates
+    /// the signature using the root public key
+    pub fn from_slice_with_limits<F: Fn(Option<u32>) -> PublicKey>(
+        slice: &[u8],
+        f: F,
+        limits: &DeserializationLimits,
+    ) -> Result<Self, error::Format> {
+        let deser = SerializedBiscuit::deserialize_with_limits(slice, limits)?;
+
+        let root = f(deser.root_key_id);
+        limits.check_algorithm(&root)?;
+        deser.verify(&root)?;
+
+        Ok(deser)
+    }
+
+    pub fn from_slice_with_provider<KP: RootKeyProvider>(
+        slice: &[u8],
+        key_provider: &KP,
+    ) -> Result<Self, error::Format> {
+        let deser = SerializedBiscuit::deserialize(slice)?;
+
+        let root = key_provider.choose(deser.root_key_id)?;
+        deser.verify(&root)?;
+
+        Ok(deser)
+    }
+
     pub(crate) fn deserialize(slice: &[u8]) -> Result<Self, error::Format> {
+        SerializedBiscuit::deserialize_with_limits(slice, &DeserializationLimits::unlimited())
+    }
+
+    /// deserializes the token without verifying the signatures
+    ///
+    /// the size, number of blocks and keys are checked before verification,
+    /// since the data is not trusted yet. The blocks are not decoded
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(size = slice.len()))
+    )]
+    pub(crate) fn deserialize_with_limits(
+        slice: &[u8],
+        limits: &DeserializationLimits,
+    ) -> Result<Self, error::Format> {
+        if slice.len() > limits.max_size {
+            return Err(error::Format::Limit(error::DeserializationLimit::TokenSize));
+        }
+
         let data = schema::Biscuit::decode(slice).map_err(|e| {
-            error::Format::DeserializationError(format!("deserialization error: {:?}", e))
+            error::Format::DeserializationError(error::Cause::with_dependency_source(
+                format!("deserialization error: {:?}", e),
+                e,
+            ))
         })?;
 
-        if data.authority.next_key.algorithm != schema::public_key::Algorithm::Ed25519 as i32 {
-            return Err(error::Format::DeserializationError(format!(
-                "deserialization error: unexpected key algorithm {}",
-                data.authority.next_key.algorithm
-            )));
+        SerializedBiscuit::from_proto_with_limits(data, limits)
+    }
+
+    /// checks the limits on a decoded token and converts it, without
+    /// verifying the signatures or decoding the blocks
+    fn from_proto_with_limits(
+        data: schema::Biscuit,
+        limits: &DeserializationLimits,
+    ) -> Result<Self, error::Format> {
+        if 1 + data.blocks.len() > limits.max_blocks {
+            return Err(error::Format::Limit(
+                error::DeserializationLimit::TooManyBlocks,
+            ));
         }
 
-        let bytes: [u8; 64] = (&data.authority.signature[..])
-            .try_into()
-            .map_err(|_| error::Format::InvalidSignatureSize(data.authority.signature.len()))?;
+        if data.authority.external_signature.is_some() {
+            return Err(error::Format::DeserializationError(
+                "deserialization error: the authority block must not contain an external signature"
+                    .into(),
+            ));
+        }
 
         let authority = crypto::Block {
             data: data.authority.block,
-            next_key: PublicKey::from_bytes(&data.authority.next_key.key)?,
-            signature: ed25519_dalek::Signature::new(bytes),
+            next_key: limits.check_key(&data.authority.next_key)?,
+            signature: crypto::Signature::from_bytes(&data.authority.signature)?,
+            external_signature: None,
         };
 
         let mut blocks = Vec::new();
         for block in &data.blocks {
-            if block.next_key.algorithm != schema::public_key::Algorithm::Ed25519 as i32 {
-                return Err(error::Format::DeserializationError(format!(
-                    "deserialization error: unexpected key algorithm {}",
-                    block.next_key.algorithm
-                )));
-            }
+            let external_signature = match block.external_signature.as_ref() {
+                None => None,
+                Some(ex) => {
+                    limits.check_key(&ex.public_key)?;
+                    Some(proto_external_signature_to_external_signature(ex)?)
+                }
+            };
 
-            let bytes: [u8; 64] = (&block.signature[..])
-                .try_into()
-                .map_err(|_| error::Format::InvalidSignatureSize(block.signature.len()))?;
             blocks.push(crypto::Block {
                 data: block.block.clone(),
-                next_key: PublicKey::from_bytes(&block.next_key.key)?,
-                signature: ed25519_dalek::Signature::new(bytes),
+                next_key: limits.check_key(&block.next_key)?,
+                signature: crypto::Signature::from_bytes(&block.signature)?,
+                external_signature,
             });
         }
 
         let proof = match data.proof.content {
             None => {
                 return Err(error::Format::DeserializationError(
-                    "could not find proof".to_string(),
+                    "could not find proof".into(),
                 ))
             }
             Some(schema::proof::Content::NextSecret(v)) => {
+                let v = zeroize::Zeroizing::new(v);
                 TokenNext::Secret(PrivateKey::from_bytes(&v)?)
             }
             Some(schema::proof::Content::FinalSignature(v)) => {
-                let bytes: [u8; 64] = (&v[..])
-                    .try_into()
-                    .map_err(|_| error::Format::InvalidSignatureSize(v.len()))?;
-                TokenNext::Seal(ed25519_dalek::Signature::new(bytes))
+                TokenNext::Seal(crypto::Signature::from_bytes(&v)?)
             }
         };
 
@@ -119,22 +375,21 @@ impl SerializedBiscuit {
     pub fn to_proto(&self) -> schema::Biscuit {
         let authority = schema::SignedBlock {
             block: self.authority.data.clone(),
-            next_key: schema::PublicKey {
-                algorithm: schema::public_key::Algorithm::Ed25519 as i32,
-                key: self.authority.next_key.to_bytes().to_vec(),
-            },
+            next_key: public_key_to_proto_public_key(&self.authority.next_key),
             signature: self.authority.signature.to_bytes().to_vec(),
+            external_signature: None,
         };
 
         let mut blocks = Vec::new();
         for block in &self.blocks {
             let b = schema::SignedBlock {
                 block: block.data.clone(),
-                next_key: schema::PublicKey {
-                    algorithm: schema::public_key::Algorithm::Ed25519 as i32,
-                    key: block.next_key.to_bytes().to_vec(),
-                },
+                next_key: public_key_to_proto_public_key(&block.next_key),
                 signature: block.signature.to_bytes().to_vec(),
+                external_signature: block
+                    .external_signature
+                    .as_ref()
+                    .map(external_signature_to_proto_external_signature),
             };
 
             blocks.push(b);
@@ -167,58 +422,155 @@ impl SerializedBiscuit {
 
         let mut v = Vec::new();
 
-        b.encode(&mut v)
-            .map(|_| v)
-            .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
+        b.encode(&mut v).map(|_| v).map_err(|e| {
+            error::Format::SerializationError(error::Cause::with_dependency_source(
+                format!("serialization error: {:?}", e),
+                e,
+            ))
+        })
+    }
+
+    /// serializes the token with the [compact] encoding
+    #[cfg(feature = "compact")]
+    pub fn to_compact(&self) -> Vec<u8> {
+        compact::encode(&self.to_proto())
+    }
+
+    /// deserializes a token from the [compact] encoding, without verifying
+    /// the signatures
+    #[cfg(feature = "compact")]
+    pub(crate) fn deserialize_compact_with_limits(
+        slice: &[u8],
+        limits: &DeserializationLimits,
+    ) -> Result<Self, error::Format> {
+        if slice.len() > limits.max_size {
+            return Err(error::Format::Limit(error::DeserializationLimit::TokenSize));
+        }
+
+        let data = compact::decode(slice)?;
+        SerializedBiscuit::from_proto_with_limits(data, limits)
+    }
+
+    /// deserializes a token from the [compact] encoding, and validates the
+    /// signature using the root public key selected by the key provider
+    #[cfg(feature = "compact")]
+    pub fn from_compact_with_provider<KP: RootKeyProvider>(
+        slice: &[u8],
+        key_provider: &KP,
+        limits: &DeserializationLimits,
+    ) -> Result<Self, error::Format> {
+        let deser = SerializedBiscuit::deserialize_compact_with_limits(slice, limits)?;
+
+        let root = key_provider.choose(deser.root_key_id)?;
+        deser.verify(&root)?;
+
+        Ok(deser)
     }
 
     /// creates a new token
     pub fn new(
         root_key_id: Option<u32>,
-        root_keypair: &KeyPair,
+        root: &dyn crypto::Signer,
         next_keypair: &KeyPair,
         authority: &Block,
     ) -> Result<Self, error::Token> {
-        let mut v = Vec::new();
-        token_block_to_proto_block(authority)
-            .encode(&mut v)
-            .map_err(|e| {
-                error::Format::SerializationError(format!("serialization error: {:?}", e))
-            })?;
+        let v = serialize_block(authority)?;
 
-        let signature = crypto::sign(root_keypair, next_keypair, &v)?;
+        let signature = crypto::sign(root, next_keypair, &v, None)?;
 
-        Ok(SerializedBiscuit {
+        Ok(SerializedBiscuit::with_authority_signature(
+            root_key_id,
+            v,
+            next_keypair,
+            signature,
+        ))
+    }
+
+    /// creates a new token, with the authority block signed by an async function
+    ///
+    /// the function receives the data to sign with the root private key, which
+    /// can then be kept in a remote service like a KMS or HSM
+    pub async fn new_async<F, Fut>(
+        root_key_id: Option<u32>,
+        sign: F,
+        next_keypair: &KeyPair,
+        authority: &Block,
+    ) -> Result<Self, error::Token>
+    where
+        F: FnOnce(Vec<u8>) -> Fut,
+        Fut: core::future::Future<Output = Result<crypto::Signature, error::Format>>,
+    {
+        let v = serialize_block(authority)?;
+
+        let signature = sign(crypto::signature_message(&v, None, &next_keypair.public())).await?;
+
+        Ok(SerializedBiscuit::with_authority_signature(
+            root_key_id,
+            v,
+            next_keypair,
+            signature,
+        ))
+    }
+
+    fn with_authority_signature(
+        root_key_id: Option<u32>,
+        v: Vec<u8>,
+        next_keypair: &KeyPair,
+        signature: crypto::Signature,
+    ) -> Self {
+        SerializedBiscuit {
             root_key_id,
             authority: crypto::Block {
                 data: v,
                 next_key: next_keypair.public(),
                 signature,
+                external_signature: None,
             },
             blocks: vec![],
             proof: TokenNext::Secret(next_keypair.private()),
-        })
+        }
+    }
+
+    /// signature of the last block, used to bind third party blocks to this token
+    pub fn last_signature(&self) -> crypto::Signature {
+        self.blocks
+            .last()
+            .map(|b| b.signature)
+            .unwrap_or(self.authority.signature)
     }
 
     /// adds a new block, serializes it and sign a new token
     pub fn append(&self, next_keypair: &KeyPair, block: &Block) -> Result<Self, error::Token> {
-        let keypair = self.proof.keypair()?;
+        let v = serialize_block(block)?;
 
-        let mut v = Vec::new();
-        token_block_to_proto_block(block)
-            .encode(&mut v)
-            .map_err(|e| {
-                error::Format::SerializationError(format!("serialization error: {:?}", e))
-            })?;
+        self.append_serialized(next_keypair, v, None)
+    }
 
-        let signature = crypto::sign(&keypair, next_keypair, &v)?;
+    /// adds an already serialized block and sign a new token
+    ///
+    /// this is used for third party blocks, where the external signature
+    /// covers the serialized data
+    pub fn append_serialized(
+        &self,
+        next_keypair: &KeyPair,
+        data: Vec<u8>,
+        external_signature: Option<crypto::ExternalSignature>,
+    ) -> Result<Self, error::Token> {
+        if self.is_sealed() {
+            return Err(error::Token::AppendOnSealed);
+        }
+
+        let keypair = self.proof.keypair()?;
+
+        let signature = crypto::sign(&keypair, next_keypair, &data, external_signature.as_ref())?;
 
         // Add new block
         let mut blocks = self.blocks.clone();
         blocks.push(crypto::Block {
-            data: v,
+            data,
             next_key: next_keypair.public(),
             signature,
+            external_signature,
         });
 
         Ok(SerializedBiscuit {
@@ -230,24 +582,125 @@ impl SerializedBiscuit {
     }
 
     /// checks the signature on a deserialized token
+    ///
+    /// each signature is checked with strict verification. See
+    /// [SerializedBiscuit::verify_batch] to verify many tokens at once
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(blocks = self.blocks.len() + 1))
+    )]
     pub fn verify(&self, root: &PublicKey) -> Result<(), error::Format> {
-        //FIXME: try batched signature verification
+        self.verify_with(root, &crypto::DalekVerifier)
+    }
+
+    /// checks the signature on a deserialized token, with a custom signature verifier
+    pub fn verify_with(
+        &self,
+        root: &PublicKey,
+        verifier: &dyn crypto::Verifier,
+    ) -> Result<(), error::Format> {
         let mut current_pub = root;
 
-        crypto::verify_block_signature(&self.authority, current_pub)?;
-        current_pub = &self.authority.next_key;
+        for block in core::iter::once(&self.authority).chain(self.blocks.iter()) {
+            crypto::verify_block_signature(block, current_pub, verifier)?;
+            current_pub = &block.next_key;
+        }
+
+        self.verify_external_signatures(verifier)?;
+        self.verify_proof(verifier)
+    }
+
+    /// verifies the signatures of multiple tokens created with the same root key
+    ///
+    /// the block signatures are checked in one batch, with ed25519-dalek, rejecting
+    /// the small order keys and signature points that strict verification rejects.
+    /// If this fails, there is no indication of which token is invalid, so the tokens
+    /// should then be verified one by one with [SerializedBiscuit::verify]
+    ///
+    /// batches are specific to ed25519-dalek, so the external signatures and proofs
+    /// are checked with [DalekVerifier](crypto::DalekVerifier) too. Use
+    /// [SerializedBiscuit::verify_with] for other backends. If the root key or
+    /// a block key is not an Ed25519 key, the tokens are verified one by one
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(tokens = tokens.len()))
+    )]
+    pub fn verify_batch(
+        tokens: &[&SerializedBiscuit],
+        root: &PublicKey,
+    ) -> Result<(), error::Format> {
+        let mut messages = Vec::new();
+        let mut signatures = Vec::new();
+        let mut public_keys = Vec::new();
+
+        for token in tokens {
+            let mut current_pub = root;
+
+            for block in core::iter::once(&token.authority).chain(token.blocks.iter()) {
+                messages.push(crypto::block_signature_message(block));
+                signatures.push(block.signature);
+                public_keys.push(*current_pub);
+                current_pub = &block.next_key;
+            }
+        }
+
+        if public_keys
+            .iter()
+            .any(|key| key.algorithm() != crypto::Algorithm::Ed25519)
+        {
+            for token in tokens {
+                token.verify(root)?;
+            }
+            return Ok(());
+        }
+
+        let messages = messages.iter().map(|m| &m[..]).collect::<Vec<_>>();
+        crypto::verify_batch(&messages, &signatures, &public_keys)?;
+
+        for token in tokens {
+            token.verify_external_signatures(&crypto::DalekVerifier)?;
+            token.verify_proof(&crypto::DalekVerifier)?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_external_signatures(
+        &self,
+        verifier: &dyn crypto::Verifier,
+    ) -> Result<(), error::Format> {
+        let mut previous_signature = &self.authority.signature;
 
         for block in &self.blocks {
-            crypto::verify_block_signature(block, current_pub)?;
-            current_pub = &block.next_key;
+            if let Some(external_signature) = block.external_signature.as_ref() {
+                crypto::verify_external_signature(
+                    &block.data,
+                    previous_signature,
+                    external_signature,
+                    verifier,
+                )?;
+            }
+
+            previous_signature = &block.signature;
         }
 
+        Ok(())
+    }
+
+    fn verify_proof(&self, verifier: &dyn crypto::Verifier) -> Result<(), error::Format> {
+        let block = if self.blocks.is_empty() {
+            &self.authority
+        } else {
+            &self.blocks[self.blocks.len() - 1]
+        };
+        let current_pub = &block.next_key;
+
         match &self.proof {
             TokenNext::Secret(private) => {
                 if current_pub != &private.public() {
                     return Err(error::Format::Signature(
                         error::Signature::InvalidSignature(
-                            "the last public key does not match the private key".to_string(),
+                            "the last public key does not match the private key".into(),
                         ),
                     ));
                 }
@@ -256,30 +709,50 @@ impl SerializedBiscuit {
                 //FIXME: replace with SHA512 hashing
                 let mut to_verify = Vec::new();
 
-                let block = if self.blocks.is_empty() {
-                    &self.authority
-                } else {
-                    &self.blocks[self.blocks.len() - 1]
-                };
                 to_verify.extend(&block.data);
-                to_verify.extend(
-                    &(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes(),
-                );
+                to_verify.extend(&(block.next_key.algorithm() as i32).to_le_bytes());
                 to_verify.extend(&block.next_key.to_bytes());
                 to_verify.extend(&block.signature.to_bytes());
 
-                current_pub
-                    .0
-                    .verify_strict(&to_verify, signature)
-                    .map_err(|s| s.to_string())
-                    .map_err(error::Signature::InvalidSignature)
-                    .map_err(error::Format::Signature)?;
+                verifier.verify(current_pub, &to_verify, signature)?;
             }
         }
 
         Ok(())
     }
 
+    /// SHA-256 hash of the token's blocks and signatures
+    ///
+    /// for each block, starting with the authority block, the hash covers the
+    /// length of the serialized block as a little endian u64, the serialized block,
+    /// the external signature and its public key if present, the next public key
+    /// and the signature. The proof (next private key or seal signature) is not included
+    pub fn content_hash(&self) -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+
+        for block in core::iter::once(&self.authority).chain(self.blocks.iter()) {
+            hasher.update((block.data.len() as u64).to_le_bytes());
+            hasher.update(&block.data);
+            if let Some(external_signature) = block.external_signature.as_ref() {
+                hasher.update(external_signature.signature.to_bytes());
+                hasher.update(external_signature.public_key.to_bytes());
+            }
+            hasher.update(block.next_key.to_bytes());
+            hasher.update(block.signature.to_bytes());
+        }
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hasher.finalize());
+        hash
+    }
+
+    /// a sealed token contains a final signature instead of the next private key
+    pub fn is_sealed(&self) -> bool {
+        matches!(self.proof, TokenNext::Seal(_))
+    }
+
     pub fn seal(&self) -> Result<Self, error::Token> {
         let keypair = self.proof.keypair()?;
 
@@ -291,17 +764,11 @@ impl SerializedBiscuit {
             &self.blocks[self.blocks.len() - 1]
         };
         to_sign.extend(&block.data);
-        to_sign
-            .extend(&(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes());
+        to_sign.extend(&(block.next_key.algorithm() as i32).to_le_bytes());
         to_sign.extend(&block.next_key.to_bytes());
         to_sign.extend(&block.signature.to_bytes());
 
-        let signature = keypair
-            .kp
-            .try_sign(&to_sign)
-            .map_err(|s| s.to_string())
-            .map_err(error::Signature::InvalidSignatureGeneration)
-            .map_err(error::Format::Signature)?;
+        let signature = keypair.sign(&to_sign)?;
 
         Ok(SerializedBiscuit {
             root_key_id: self.root_key_id,
@@ -311,3 +778,20 @@ impl SerializedBiscuit {
         })
     }
 }
+
+fn serialize_block(block: &Block) -> Result<Vec<u8>, error::Format> {
+    let mut v = Vec::new();
+    token_block_to_proto_block(block)
+        .encode(&mut v)
+        .map_err(|e| {
+            error::Format::SerializationError(error::Cause::with_dependency_source(
+                format!("serialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+    #[cfg(feature = "compression")]
+    let v = compression::compress_block(v);
+
+    Ok(v)
+}
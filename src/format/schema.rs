@@ -1,3 +1,4 @@
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Biscuit {
     #[prost(uint32, optional, tag = "1")]
@@ -9,6 +10,7 @@ pub struct Biscuit {
     #[prost(message, required, tag = "4")]
     pub proof: Proof,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SignedBlock {
     #[prost(bytes = "vec", required, tag = "1")]
@@ -17,7 +19,18 @@ pub struct SignedBlock {
     pub next_key: PublicKey,
     #[prost(bytes = "vec", required, tag = "3")]
     pub signature: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, optional, tag = "4")]
+    pub external_signature: ::core::option::Option<ExternalSignature>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExternalSignature {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, required, tag = "2")]
+    pub public_key: PublicKey,
+}
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PublicKey {
     #[prost(enumeration = "public_key::Algorithm", required, tag = "1")]
@@ -27,12 +40,15 @@ pub struct PublicKey {
 }
 /// Nested message and enum types in `PublicKey`.
 pub mod public_key {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
     #[repr(i32)]
     pub enum Algorithm {
         Ed25519 = 0,
+        Secp256r1 = 1,
     }
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Proof {
     #[prost(oneof = "proof::Content", tags = "1, 2")]
@@ -40,6 +56,7 @@ pub struct Proof {
 }
 /// Nested message and enum types in `Proof`.
 pub mod proof {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Content {
         #[prost(bytes, tag = "1")]
@@ -48,6 +65,7 @@ pub mod proof {
         FinalSignature(::prost::alloc::vec::Vec<u8>),
     }
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Block {
     #[prost(string, repeated, tag = "1")]
@@ -62,12 +80,16 @@ pub struct Block {
     pub rules_v2: ::prost::alloc::vec::Vec<RuleV2>,
     #[prost(message, repeated, tag = "6")]
     pub checks_v2: ::prost::alloc::vec::Vec<CheckV2>,
+    #[prost(bytes = "vec", optional, tag = "7")]
+    pub compressed: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FactV2 {
     #[prost(message, required, tag = "1")]
     pub predicate: PredicateV2,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct RuleV2 {
     #[prost(message, required, tag = "1")]
@@ -76,12 +98,16 @@ pub struct RuleV2 {
     pub body: ::prost::alloc::vec::Vec<PredicateV2>,
     #[prost(message, repeated, tag = "3")]
     pub expressions: ::prost::alloc::vec::Vec<ExpressionV2>,
+    #[prost(message, repeated, tag = "4")]
+    pub trusted_keys: ::prost::alloc::vec::Vec<PublicKey>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CheckV2 {
     #[prost(message, repeated, tag = "1")]
     pub queries: ::prost::alloc::vec::Vec<RuleV2>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PredicateV2 {
     #[prost(uint64, required, tag = "1")]
@@ -89,6 +115,7 @@ pub struct PredicateV2 {
     #[prost(message, repeated, tag = "2")]
     pub terms: ::prost::alloc::vec::Vec<TermV2>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct TermV2 {
     #[prost(oneof = "term_v2::Content", tags = "1, 2, 3, 4, 5, 6, 7")]
@@ -96,6 +123,7 @@ pub struct TermV2 {
 }
 /// Nested message and enum types in `TermV2`.
 pub mod term_v2 {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Content {
         #[prost(uint32, tag = "1")]
@@ -114,31 +142,37 @@ pub mod term_v2 {
         Set(super::TermSet),
     }
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct TermSet {
     #[prost(message, repeated, tag = "1")]
     pub set: ::prost::alloc::vec::Vec<TermV2>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct IntSet {
     #[prost(int64, repeated, tag = "7")]
     pub set: ::prost::alloc::vec::Vec<i64>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StringSet {
     #[prost(uint64, repeated, tag = "1")]
     pub set: ::prost::alloc::vec::Vec<u64>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BytesSet {
     #[prost(bytes = "vec", repeated, tag = "1")]
     pub set: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ExpressionV2 {
     #[prost(message, repeated, tag = "1")]
     pub ops: ::prost::alloc::vec::Vec<Op>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Op {
     #[prost(oneof = "op::Content", tags = "1, 2, 3")]
@@ -146,6 +180,7 @@ pub struct Op {
 }
 /// Nested message and enum types in `Op`.
 pub mod op {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Content {
         #[prost(message, tag = "1")]
@@ -156,6 +191,7 @@ pub mod op {
         Binary(super::OpBinary),
     }
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct OpUnary {
     #[prost(enumeration = "op_unary::Kind", required, tag = "1")]
@@ -163,14 +199,21 @@ pub struct OpUnary {
 }
 /// Nested message and enum types in `OpUnary`.
 pub mod op_unary {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
     #[repr(i32)]
     pub enum Kind {
         Negate = 0,
         Parens = 1,
         Length = 2,
+        Sha256 = 3,
+        ToHex = 4,
+        FromHex = 5,
+        ToBase64 = 6,
+        FromBase64 = 7,
     }
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct OpBinary {
     #[prost(enumeration = "op_binary::Kind", required, tag = "1")]
@@ -178,6 +221,7 @@ pub struct OpBinary {
 }
 /// Nested message and enum types in `OpBinary`.
 pub mod op_binary {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
     #[repr(i32)]
     pub enum Kind {
@@ -198,8 +242,14 @@ pub mod op_binary {
         Or = 14,
         Intersection = 15,
         Union = 16,
+        ContainsIp = 17,
+        SemverGe = 18,
+        SemverLt = 19,
+        Take = 20,
+        Skip = 21,
     }
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Policy {
     #[prost(message, repeated, tag = "1")]
@@ -209,6 +259,7 @@ pub struct Policy {
 }
 /// Nested message and enum types in `Policy`.
 pub mod policy {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
     #[repr(i32)]
     pub enum Kind {
@@ -216,6 +267,7 @@ pub mod policy {
         Deny = 1,
     }
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AuthorizerPolicies {
     #[prost(string, repeated, tag = "1")]
@@ -231,3 +283,83 @@ pub struct AuthorizerPolicies {
     #[prost(message, repeated, tag = "6")]
     pub policies: ::prost::alloc::vec::Vec<Policy>,
 }
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignedAuthorizerPolicies {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub policies: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", required, tag = "2")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AuthorizerSnapshot {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub policies: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub token: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(uint32, required, tag = "3")]
+    pub max_facts: u32,
+    #[prost(uint32, required, tag = "4")]
+    pub max_iterations: u32,
+    #[prost(uint64, required, tag = "5")]
+    pub max_time: u64,
+    #[prost(uint64, required, tag = "6")]
+    pub max_memory_bytes: u64,
+    #[prost(message, repeated, tag = "7")]
+    pub predicate_limits: ::prost::alloc::vec::Vec<PredicateLimit>,
+}
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PredicateLimit {
+    #[prost(string, required, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint32, required, tag = "2")]
+    pub max: u32,
+}
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WorldSnapshot {
+    #[prost(string, repeated, tag = "1")]
+    pub symbols: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(message, repeated, tag = "2")]
+    pub facts: ::prost::alloc::vec::Vec<OriginFactV2>,
+    #[prost(message, repeated, tag = "3")]
+    pub rules: ::prost::alloc::vec::Vec<RuleV2>,
+}
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct OriginFactV2 {
+    #[prost(message, required, tag = "1")]
+    pub fact: FactV2,
+    #[prost(enumeration = "origin_fact_v2::Kind", required, tag = "2")]
+    pub kind: i32,
+    #[prost(uint64, optional, tag = "3")]
+    pub index: ::core::option::Option<u64>,
+}
+/// Nested message and enum types in `OriginFactV2`.
+pub mod origin_fact_v2 {
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Kind {
+        Authorizer = 0,
+        Authority = 1,
+        Block = 2,
+        Rule = 3,
+    }
+}
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ThirdPartyBlockRequest {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub previous_signature: ::prost::alloc::vec::Vec<u8>,
+}
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ThirdPartyBlockContents {
+    #[prost(bytes = "vec", required, tag = "1")]
+    pub payload: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, required, tag = "2")]
+    pub external_signature: ExternalSignature,
+}
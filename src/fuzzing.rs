@@ -0,0 +1,86 @@
+//! generators for structure-aware fuzzing, with the `arbitrary` feature
+//!
+//! the datalog, builder and format types implement [arbitrary::Arbitrary]
+//! directly. This module adds generators for values that need to be
+//! consistent, like signed tokens
+use crate::builder::{BlockBuilder, Check, Fact, Rule};
+use crate::{Biscuit, KeyPair};
+use arbitrary::{Arbitrary, Unstructured};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// maximum number of blocks appended after the authority block
+const MAX_BLOCKS: usize = 4;
+
+/// a token correctly signed by `root`, with arbitrary facts, rules and checks
+///
+/// facts, rules and checks that the builders reject are skipped, so the
+/// token can always be serialized and verified
+#[derive(Debug)]
+pub struct ArbitraryToken {
+    pub root: KeyPair,
+    pub token: Biscuit,
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryToken {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let root = KeyPair::arbitrary(u)?;
+        let mut rng = StdRng::from_seed(u.arbitrary()?);
+
+        let mut builder = Biscuit::builder(&root);
+        for fact in u.arbitrary_iter::<Fact>()? {
+            let _ = builder.add_authority_fact(fact?);
+        }
+        for rule in u.arbitrary_iter::<Rule>()? {
+            let _ = builder.add_authority_rule(rule?);
+        }
+        for check in u.arbitrary_iter::<Check>()? {
+            let _ = builder.add_authority_check(check?);
+        }
+        let mut token = builder
+            .build_with_rng(&mut rng)
+            .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+
+        for _ in 0..u.int_in_range(0..=MAX_BLOCKS)? {
+            let block = arbitrary_block(u)?;
+            token = token
+                .append_with_rng(&mut rng, block)
+                .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+        }
+
+        Ok(ArbitraryToken { root, token })
+    }
+}
+
+/// generates a block builder containing only valid facts, rules and checks
+pub fn arbitrary_block(u: &mut Unstructured) -> arbitrary::Result<BlockBuilder> {
+    let mut block = BlockBuilder::new();
+    for fact in u.arbitrary_iter::<Fact>()? {
+        let _ = block.add_fact(fact?);
+    }
+    for rule in u.arbitrary_iter::<Rule>()? {
+        let _ = block.add_rule(rule?);
+    }
+    for check in u.arbitrary_iter::<Check>()? {
+        let _ = block.add_check(check?);
+    }
+
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arbitrary_token() {
+        let data = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let mut u = Unstructured::new(&data);
+
+        let ArbitraryToken { root, token } = ArbitraryToken::arbitrary(&mut u).unwrap();
+        let serialized = token.to_vec().unwrap();
+        let deserialized = Biscuit::from(&serialized, |_| root.public()).unwrap();
+        assert_eq!(deserialized.block_count(), token.block_count());
+    }
+}
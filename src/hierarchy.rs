@@ -0,0 +1,139 @@
+//! facts and checks for hierarchical resources, like file paths or URLs
+//!
+//! [Authorizer::add_resource_hierarchy](crate::Authorizer::add_resource_hierarchy)
+//! adds, for the resource `/a/b/c`:
+//! * `resource("/a/b/c")`
+//! * `resource_prefix($p)` for `/`, `/a`, `/a/b` and `/a/b/c`
+//! * `resource_parent($child, $parent)` for each level: `("/a/b/c", "/a/b")`,
+//!   `("/a/b", "/a")` and `("/a", "/")`
+//!
+//! and [BlockBuilder::check_resource_prefix](crate::builder::BlockBuilder::check_resource_prefix)
+//! restricts a token to a subtree with `check if resource_prefix("/a")`.
+//! Unlike [BlockBuilder::resource_prefix](crate::builder::BlockBuilder::resource_prefix),
+//! the prefix must match whole segments: `/a` does not match `/ab`
+//!
+//! For URLs, the scheme and host are the root of the hierarchy:
+//! `https://example.com/a` has the prefixes `https://example.com` and
+//! `https://example.com/a`
+use crate::builder::{check, fact, pred, string, Check, Fact};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// returns the path and its ancestors, from the root to the path
+///
+/// empty segments and trailing separators are ignored, so the last
+/// element is the normalized path
+///
+/// ```rust
+/// # use biscuit_auth::hierarchy::ancestors;
+/// assert_eq!(ancestors("/a/b/"), vec!["/", "/a", "/a/b"]);
+/// assert_eq!(ancestors("a//b"), vec!["a", "a/b"]);
+/// assert_eq!(
+///     ancestors("https://example.com/a"),
+///     vec!["https://example.com", "https://example.com/a"]
+/// );
+/// ```
+pub fn ancestors(path: &str) -> Vec<String> {
+    let (mut current, rest) = match path.find("://") {
+        Some(index) => {
+            let after_scheme = &path[index + 3..];
+            let host_end = after_scheme.find('/').unwrap_or(after_scheme.len());
+            (
+                path[..index + 3 + host_end].to_string(),
+                &after_scheme[host_end..],
+            )
+        }
+        None if path.starts_with('/') => ("/".to_string(), path),
+        None => (String::new(), path),
+    };
+
+    let mut ancestors = Vec::new();
+    if !current.is_empty() {
+        ancestors.push(current.clone());
+    }
+
+    for segment in rest.split('/').filter(|s| !s.is_empty()) {
+        if !current.is_empty() && !current.ends_with('/') {
+            current.push('/');
+        }
+        current.push_str(segment);
+        ancestors.push(current.clone());
+    }
+
+    ancestors
+}
+
+/// facts describing a resource and its ancestors, see the [module documentation](self)
+pub fn facts(path: &str) -> Vec<Fact> {
+    let ancestors = ancestors(path);
+    let mut facts = Vec::new();
+
+    if let Some(resource) = ancestors.last() {
+        facts.push(fact("resource", &[string(resource)]));
+    }
+    for ancestor in ancestors.iter() {
+        facts.push(fact("resource_prefix", &[string(ancestor)]));
+    }
+    for pair in ancestors.windows(2) {
+        facts.push(fact(
+            "resource_parent",
+            &[string(&pair[1]), string(&pair[0])],
+        ));
+    }
+
+    facts
+}
+
+/// `check if resource_prefix(prefix)`, with the prefix normalized like in [ancestors]
+pub fn check_prefix(prefix: &str) -> Check {
+    let prefix = ancestors(prefix)
+        .pop()
+        .unwrap_or_else(|| prefix.to_string());
+
+    check(&[pred("resource_prefix", &[string(&prefix)])])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Authorizer, Biscuit, KeyPair};
+
+    #[test]
+    fn subtree() {
+        assert_eq!(ancestors(""), Vec::<String>::new());
+        assert_eq!(ancestors("/"), vec!["/"]);
+        assert_eq!(
+            facts("/a/b")
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "resource(\"/a/b\")",
+                "resource_prefix(\"/\")",
+                "resource_prefix(\"/a\")",
+                "resource_prefix(\"/a/b\")",
+                "resource_parent(\"/a\", \"/\")",
+                "resource_parent(\"/a/b\", \"/a\")",
+            ]
+        );
+
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        let mut block = token.create_block();
+        block.check_resource_prefix("/a/");
+        let token = token.append(block).unwrap();
+
+        let authorize = |resource: &str| {
+            let mut authorizer = Authorizer::new().unwrap();
+            authorizer.add_resource_hierarchy(resource);
+            authorizer.allow().unwrap();
+            authorizer.add_token(&token).unwrap();
+            authorizer.authorize()
+        };
+
+        assert!(authorize("/a/b/c").is_ok());
+        assert!(authorize("/a").is_ok());
+        assert!(authorize("/ab").is_err());
+        assert!(authorize("/b/a").is_err());
+    }
+}
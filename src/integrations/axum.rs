@@ -0,0 +1,338 @@
+//! [axum](https://docs.rs/axum) middleware, with the `axum-integration` feature
+//!
+//! [BiscuitLayer] authorizes each request with the token of its
+//! `Authorization: Bearer` header, and an authorizer prepared by a closure.
+//! Requests without a valid token are rejected with `401 Unauthorized`,
+//! and failed authorizations with `403 Forbidden`. Handlers receive the
+//! token and the authorizer's facts through the [Authorized] extractor.
+//!
+//! ```rust,ignore
+//! let app = Router::new()
+//!     .route("/files/:name", get(read_file))
+//!     .layer(BiscuitLayer::new(root.public(), |request, authorizer| {
+//!         authorizer.add_fact(fact("path", &[string(request.uri.path())]))?;
+//!         authorizer.add_code(r#"allow if path($path), right($path, "read")"#)
+//!     }));
+//!
+//! async fn read_file(authorized: Authorized) -> String {
+//!     format!("{} facts", authorized.facts.len())
+//! }
+//! ```
+//!
+//! The authorization runs on the current task for small tokens, and with
+//! `spawn_blocking` for the larger ones, like with
+//! [TokioAuthorizer](super::tokio::TokioAuthorizer)
+//!
+//! The [Token] extractor only deserializes and verifies the token, with the
+//! root key of a [RootKey] extension
+use super::tokio::{offload, token_size};
+pub use super::Authorized;
+use super::Failure;
+use crate::error;
+use crate::{Authorizer, Biscuit, PublicKey};
+use ::axum::async_trait;
+use ::axum::extract::{FromRequest, RequestParts};
+use ::axum::http::{header::AUTHORIZATION, request::Parts, HeaderMap, Request, StatusCode};
+use ::axum::response::{IntoResponse, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// deserialized and verified token from the `Authorization` header
+///
+/// the root public key comes from a [RootKey] request extension
+#[derive(Clone, Debug)]
+pub struct Token(pub Biscuit);
+
+/// root public key used by the [Token] extractor
+#[derive(Clone, Copy, Debug)]
+pub struct RootKey(pub PublicKey);
+
+/// reasons for rejecting a request
+#[derive(Debug)]
+pub enum Rejection {
+    /// the request has no `Authorization` header
+    MissingToken,
+    /// the token could not be deserialized or verified
+    InvalidToken(error::Token),
+    /// the closure preparing the authorizer failed
+    Setup(error::Token),
+    /// the authorization failed
+    Unauthorized(error::Token),
+    /// the extractor was used without [BiscuitLayer] or [RootKey]
+    MissingExtension,
+    /// the authorization task panicked or the runtime is shutting down
+    Internal(error::Token),
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        match self {
+            Rejection::MissingToken => (StatusCode::UNAUTHORIZED, "missing token").into_response(),
+            Rejection::InvalidToken(e) => {
+                (StatusCode::UNAUTHORIZED, e.code().to_string()).into_response()
+            }
+            Rejection::Unauthorized(e) => {
+                (StatusCode::FORBIDDEN, e.code().to_string()).into_response()
+            }
+            Rejection::Setup(_) | Rejection::MissingExtension | Rejection::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// [tower](https://docs.rs/tower) layer authorizing requests, see the
+/// [module documentation](self)
+pub struct BiscuitLayer<F> {
+    root: PublicKey,
+    build: Arc<F>,
+    blocking_threshold: usize,
+}
+
+impl<F> BiscuitLayer<F>
+where
+    F: Fn(&Parts, &mut Authorizer) -> Result<(), error::Token>,
+{
+    /// `build` adds the request's facts and the policies to the authorizer,
+    /// which already contains the token
+    ///
+    /// tokens with more than 1000 facts, rules and checks are authorized
+    /// with `spawn_blocking`, see [BiscuitLayer::with_blocking_threshold]
+    pub fn new(root: PublicKey, build: F) -> Self {
+        BiscuitLayer {
+            root,
+            build: Arc::new(build),
+            blocking_threshold: 1000,
+        }
+    }
+
+    /// sets the number of facts, rules and checks of the token above which
+    /// the authorization runs on the blocking thread pool
+    pub fn with_blocking_threshold(mut self, threshold: usize) -> Self {
+        self.blocking_threshold = threshold;
+        self
+    }
+}
+
+impl<F> Clone for BiscuitLayer<F> {
+    fn clone(&self) -> Self {
+        BiscuitLayer {
+            root: self.root,
+            build: self.build.clone(),
+            blocking_threshold: self.blocking_threshold,
+        }
+    }
+}
+
+impl<S, F> Layer<S> for BiscuitLayer<F> {
+    type Service = BiscuitService<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BiscuitService {
+            inner,
+            root: self.root,
+            build: self.build.clone(),
+            blocking_threshold: self.blocking_threshold,
+        }
+    }
+}
+
+/// service created by [BiscuitLayer]
+pub struct BiscuitService<S, F> {
+    inner: S,
+    root: PublicKey,
+    build: Arc<F>,
+    blocking_threshold: usize,
+}
+
+impl<S: Clone, F> Clone for BiscuitService<S, F> {
+    fn clone(&self) -> Self {
+        BiscuitService {
+            inner: self.inner.clone(),
+            root: self.root,
+            build: self.build.clone(),
+            blocking_threshold: self.blocking_threshold,
+        }
+    }
+}
+
+impl<S, F, B> Service<Request<B>> for BiscuitService<S, F>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    F: Fn(&Parts, &mut Authorizer) -> Result<(), error::Token> + Send + Sync + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // the inner service was polled ready, the clone takes its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let root = self.root;
+        let build = self.build.clone();
+        let blocking_threshold = self.blocking_threshold;
+
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+
+            let token = match token_from_headers(&parts.headers, &root) {
+                Ok(token) => token,
+                Err(rejection) => return Ok(rejection.into_response()),
+            };
+
+            let size = token_size(&token);
+            let authorization = offload(size, blocking_threshold, move || {
+                let result = authorize(token, &parts, &*build);
+                (parts, result)
+            })
+            .await;
+
+            match authorization {
+                Ok((mut parts, Ok(authorized))) => {
+                    parts.extensions.insert(authorized);
+                    inner.call(Request::from_parts(parts, body)).await
+                }
+                Ok((_, Err(rejection))) => Ok(rejection.into_response()),
+                Err(e) => Ok(Rejection::Internal(e).into_response()),
+            }
+        })
+    }
+}
+
+fn authorize<F>(token: Biscuit, parts: &Parts, build: &F) -> Result<Authorized, Rejection>
+where
+    F: Fn(&Parts, &mut Authorizer) -> Result<(), error::Token>,
+{
+    super::authorize(token, parts, build).map_err(|e| match e {
+        Failure::InvalidToken(e) => Rejection::InvalidToken(e),
+        Failure::Setup(e) => Rejection::Setup(e),
+        Failure::Unauthorized(e) => Rejection::Unauthorized(e),
+    })
+}
+
+fn token_from_headers(headers: &HeaderMap, root: &PublicKey) -> Result<Biscuit, Rejection> {
+    let header = headers
+        .get(AUTHORIZATION)
+        .ok_or(Rejection::MissingToken)?
+        .to_str()
+        .map_err(|_| {
+            Rejection::InvalidToken(error::Token::AuthorizationHeader(
+                error::AuthorizationHeaderError::MissingBearer,
+            ))
+        })?;
+
+    let root = *root;
+    Biscuit::from_authorization_header(header, |_| root).map_err(Rejection::InvalidToken)
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Authorized {
+    type Rejection = Rejection;
+
+    async fn from_request(request: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        request
+            .extensions()
+            .get::<Authorized>()
+            .cloned()
+            .ok_or(Rejection::MissingExtension)
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Token {
+    type Rejection = Rejection;
+
+    async fn from_request(request: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let RootKey(root) = *request
+            .extensions()
+            .get::<RootKey>()
+            .ok_or(Rejection::MissingExtension)?;
+
+        token_from_headers(request.headers(), &root).map(Token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{fact, string};
+    use crate::KeyPair;
+    use std::convert::Infallible;
+    use std::future::{ready, Ready};
+
+    #[derive(Clone)]
+    struct Handler;
+
+    impl Service<Request<()>> for Handler {
+        type Response = Response;
+        type Error = Infallible;
+        type Future = Ready<Result<Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let response = match request.extensions().get::<Authorized>() {
+                Some(authorized) => (StatusCode::OK, authorized.facts.len().to_string()),
+                None => (StatusCode::INTERNAL_SERVER_ERROR, String::new()),
+            };
+            ready(Ok(response.into_response()))
+        }
+    }
+
+    #[test]
+    fn layer() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"alice\")").unwrap();
+        let header = builder.build().unwrap().to_authorization_header().unwrap();
+
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let layer = BiscuitLayer::new(root.public(), |request, authorizer| {
+            authorizer.add_fact(fact("path", &[string(request.uri.path())]))?;
+            authorizer.add_code(r#"allow if path("/allowed"), user("alice")"#)
+        });
+
+        // the second service authorizes all the requests with spawn_blocking
+        for layer in [layer.clone(), layer.with_blocking_threshold(0)] {
+            let mut service = layer.layer(Handler);
+
+            let mut status = |path: &str, header: Option<&str>| {
+                let mut request = Request::builder().uri(path);
+                if let Some(header) = header {
+                    request = request.header(AUTHORIZATION, header);
+                }
+                runtime
+                    .block_on(service.call(request.body(()).unwrap()))
+                    .unwrap()
+                    .status()
+            };
+
+            assert_eq!(status("/allowed", Some(header.as_str())), StatusCode::OK);
+            assert_eq!(
+                status("/other", Some(header.as_str())),
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(status("/allowed", None), StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                status("/allowed", Some("Bearer invalid")),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! integrations with web frameworks and async runtimes
+use crate::builder::Fact;
+use crate::Biscuit;
+#[cfg(any(feature = "axum-integration", feature = "tonic-integration"))]
+use crate::{error, Authorizer};
+
+#[cfg(feature = "axum-integration")]
+pub mod axum;
+#[cfg(feature = "tokio-integration")]
+pub mod tokio;
+#[cfg(feature = "tonic-integration")]
+pub mod tonic;
+
+/// result of a successful authorization, added to the request extensions
+#[derive(Clone, Debug)]
+pub struct Authorized {
+    pub token: Biscuit,
+    /// index of the allow policy that matched
+    pub policy: usize,
+    /// facts of the authorizer after running the token's rules
+    pub facts: Vec<Fact>,
+}
+
+#[cfg(any(feature = "axum-integration", feature = "tonic-integration"))]
+pub(crate) enum Failure {
+    InvalidToken(error::Token),
+    Setup(error::Token),
+    Unauthorized(error::Token),
+}
+
+/// authorizes a token, with the facts and policies added by `build` from
+/// the request
+#[cfg(any(feature = "axum-integration", feature = "tonic-integration"))]
+pub(crate) fn authorize<R, F>(token: Biscuit, request: &R, build: &F) -> Result<Authorized, Failure>
+where
+    F: Fn(&R, &mut Authorizer) -> Result<(), error::Token>,
+{
+    let (policy, facts) = {
+        let mut authorizer = token.authorizer().map_err(Failure::InvalidToken)?;
+        build(request, &mut authorizer).map_err(Failure::Setup)?;
+        let policy = authorizer.authorize().map_err(Failure::Unauthorized)?;
+        (policy, authorizer.dump().0)
+    };
+
+    Ok(Authorized {
+        token,
+        policy,
+        facts,
+    })
+}
@@ -0,0 +1,119 @@
+//! authorization in [tokio](https://docs.rs/tokio) tasks, with the `tokio-integration` feature
+//!
+//! the Datalog evaluation does not yield, so a token with a lot of facts
+//! and rules can keep a runtime thread busy until it reaches the
+//! [limits](crate::AuthorizerLimits). [TokioAuthorizer] consumes the task's
+//! cooperative scheduling budget before each authorization, and runs the
+//! largest ones with `spawn_blocking`, where they do not delay other tasks
+//!
+//! ```rust,ignore
+//! let authorizer = TokioAuthorizer::new(authorizer).with_blocking_threshold(500);
+//!
+//! async fn handle(authorizer: &TokioAuthorizer, token: Biscuit) -> Result<usize, error::Token> {
+//!     authorizer.authorize(token).await
+//! }
+//! ```
+use crate::error;
+use crate::{Authorizer, AuthorizerLimits, Biscuit};
+use std::sync::Arc;
+
+/// authorizer template shared by tasks, see the [module documentation](self)
+#[derive(Clone)]
+pub struct TokioAuthorizer {
+    authorizer: Arc<Authorizer<'static>>,
+    limits: AuthorizerLimits,
+    blocking_threshold: usize,
+}
+
+impl TokioAuthorizer {
+    /// authorizations with more than 1000 facts, rules, checks and policies
+    /// run with `spawn_blocking`, see [TokioAuthorizer::with_blocking_threshold]
+    pub fn new(authorizer: Authorizer<'static>) -> Self {
+        TokioAuthorizer {
+            authorizer: Arc::new(authorizer),
+            limits: AuthorizerLimits::default(),
+            blocking_threshold: 1000,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: AuthorizerLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// sets the number of facts, rules, checks and policies, from the
+    /// authorizer and the token, above which the authorization runs on
+    /// the blocking thread pool
+    pub fn with_blocking_threshold(mut self, threshold: usize) -> Self {
+        self.blocking_threshold = threshold;
+        self
+    }
+
+    /// authorizes a token with a copy of the authorizer, see [Authorizer::authorize_token]
+    pub async fn authorize(&self, token: Biscuit) -> Result<usize, error::Token> {
+        let size = self.authorizer.size() + token_size(&token);
+        let authorizer = self.authorizer.clone();
+        let limits = self.limits.clone();
+
+        offload(size, self.blocking_threshold, move || {
+            authorizer.authorize_token_with_limits(&token, limits)
+        })
+        .await?
+    }
+}
+
+/// runs `f` on the current task if `size` is below `threshold`, and with
+/// `spawn_blocking` otherwise, after consuming the task's budget
+pub(crate) async fn offload<T, F>(size: usize, threshold: usize, f: F) -> Result<T, error::Token>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    // yields to the runtime if the task used its budget
+    ::tokio::task::consume_budget().await;
+
+    if size <= threshold {
+        return Ok(f());
+    }
+
+    ::tokio::task::spawn_blocking(f)
+        .await
+        // the task panicked or the runtime is shutting down
+        .map_err(|_| error::Token::InternalError)
+}
+
+pub(crate) fn token_size(token: &Biscuit) -> usize {
+    std::iter::once(&token.authority)
+        .chain(token.blocks.iter())
+        .map(|block| block.facts.len() + block.rules.len() + block.checks.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+
+    #[test]
+    fn blocking_threshold() {
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.add_code("allow if user(\"alice\")").unwrap();
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"alice\")").unwrap();
+        let token = builder.build().unwrap();
+
+        let inline = TokioAuthorizer::new(authorizer);
+        let blocking = inline.clone().with_blocking_threshold(0);
+
+        runtime.block_on(async {
+            assert_eq!(inline.authorize(token.clone()).await, Ok(0));
+            assert_eq!(blocking.authorize(token).await, Ok(0));
+        });
+    }
+}
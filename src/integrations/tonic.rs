@@ -0,0 +1,192 @@
+//! [tonic](https://docs.rs/tonic) interceptor, with the `tonic-integration` feature
+//!
+//! [BiscuitInterceptor] authorizes each call with the token from the
+//! `authorization` metadata (`Bearer <token>`), and an authorizer prepared
+//! by a closure from the [CallInfo] of the call. On success, the request gets
+//! an [Authorized] extension. Calls are rejected with `UNAUTHENTICATED` if
+//! the token is missing or invalid, and `PERMISSION_DENIED` if the
+//! authorization fails. The error code is in the message and in the
+//! `biscuit-error-code` metadata of the status.
+//!
+//! ```rust,ignore
+//! // the service, method and peer facts are added by the interceptor
+//! let interceptor = BiscuitInterceptor::new(root.public(), |_call, authorizer| {
+//!     authorizer.add_code("allow if service($s), method($m), right($s, $m)")
+//! });
+//! Server::builder()
+//!     .add_service(FilesServer::with_interceptor(files, interceptor))
+//! ```
+pub use super::Authorized;
+use super::Failure;
+use crate::builder::{fact, string};
+use crate::error;
+use crate::{Authorizer, Biscuit, PublicKey};
+use ::tonic::metadata::{MetadataMap, MetadataValue};
+use ::tonic::service::Interceptor;
+use ::tonic::{Code, GrpcMethod, Request, Status};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// metadata of the status containing the [error code](crate::error::Token::code)
+pub const ERROR_CODE_METADATA: &str = "biscuit-error-code";
+
+/// description of a call, used to add ambient facts to the authorizer
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallInfo {
+    /// fully qualified name of the service, like `package.Service`
+    pub service: Option<String>,
+    pub method: Option<String>,
+    pub peer: Option<SocketAddr>,
+}
+
+impl CallInfo {
+    fn from_request(request: &Request<()>) -> Self {
+        let method = request.extensions().get::<GrpcMethod>();
+
+        CallInfo {
+            service: method.map(|m| m.service().to_string()),
+            method: method.map(|m| m.method().to_string()),
+            peer: request.remote_addr(),
+        }
+    }
+
+    /// adds the `service($name)`, `method($name)` and `peer($address)`
+    /// facts that are known
+    pub fn add_facts(&self, authorizer: &mut Authorizer) -> Result<(), error::Token> {
+        if let Some(service) = &self.service {
+            authorizer.add_fact(fact("service", &[string(service)]))?;
+        }
+        if let Some(method) = &self.method {
+            authorizer.add_fact(fact("method", &[string(method)]))?;
+        }
+        if let Some(peer) = &self.peer {
+            authorizer.add_fact(fact("peer", &[string(&peer.ip().to_string())]))?;
+        }
+        Ok(())
+    }
+}
+
+/// interceptor authorizing calls, see the [module documentation](self)
+pub struct BiscuitInterceptor<F> {
+    root: PublicKey,
+    build: Arc<F>,
+}
+
+impl<F> BiscuitInterceptor<F>
+where
+    F: Fn(&CallInfo, &mut Authorizer) -> Result<(), error::Token>,
+{
+    /// `build` adds the policies and other facts to the authorizer, which
+    /// already contains the token
+    pub fn new(root: PublicKey, build: F) -> Self {
+        BiscuitInterceptor {
+            root,
+            build: Arc::new(build),
+        }
+    }
+}
+
+impl<F> Clone for BiscuitInterceptor<F> {
+    fn clone(&self) -> Self {
+        BiscuitInterceptor {
+            root: self.root,
+            build: self.build.clone(),
+        }
+    }
+}
+
+impl<F> Interceptor for BiscuitInterceptor<F>
+where
+    F: Fn(&CallInfo, &mut Authorizer) -> Result<(), error::Token>,
+{
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let header = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("missing token"))?;
+
+        let root = self.root;
+        let token = Biscuit::from_authorization_header(header, |_| root)
+            .map_err(|e| status(Code::Unauthenticated, e))?;
+
+        let call = CallInfo::from_request(&request);
+        let build = |call: &CallInfo, authorizer: &mut Authorizer| {
+            call.add_facts(authorizer)?;
+            (self.build)(call, authorizer)
+        };
+
+        let authorized = super::authorize(token, &call, &build).map_err(|e| match e {
+            Failure::InvalidToken(e) => status(Code::Unauthenticated, e),
+            Failure::Setup(e) => status(Code::Internal, e),
+            Failure::Unauthorized(e) => status(Code::PermissionDenied, e),
+        })?;
+
+        request.extensions_mut().insert(authorized);
+        Ok(request)
+    }
+}
+
+fn status(code: Code, e: error::Token) -> Status {
+    let mut metadata = MetadataMap::new();
+    metadata.insert(
+        ERROR_CODE_METADATA,
+        MetadataValue::from_static(e.code().as_str()),
+    );
+
+    Status::with_metadata(code, format!("{}: {}", e.code(), e), metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+
+    #[test]
+    fn interceptor() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(\"Files\", \"Read\")")
+            .unwrap();
+        let header = builder.build().unwrap().to_authorization_header().unwrap();
+
+        let mut interceptor = BiscuitInterceptor::new(root.public(), |_, authorizer| {
+            authorizer.add_code("allow if service($s), method($m), right($s, $m)")
+        });
+
+        let request = |method: &'static str, header: Option<&str>| {
+            let mut request = Request::new(());
+            request
+                .extensions_mut()
+                .insert(GrpcMethod::new("Files", method));
+            if let Some(header) = header {
+                request
+                    .metadata_mut()
+                    .insert("authorization", header.parse().unwrap());
+            }
+            request
+        };
+
+        let request_ok = interceptor
+            .call(request("Read", Some(header.as_str())))
+            .unwrap();
+        let authorized = request_ok.extensions().get::<Authorized>().unwrap();
+        assert!(authorized
+            .facts
+            .iter()
+            .any(|f| f.to_string() == "method(\"Read\")"));
+
+        let denied = interceptor
+            .call(request("Write", Some(header.as_str())))
+            .unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+        assert_eq!(
+            denied.metadata().get(ERROR_CODE_METADATA).unwrap(),
+            "no_matching_policy"
+        );
+
+        let missing = interceptor.call(request("Read", None)).unwrap_err();
+        assert_eq!(missing.code(), Code::Unauthenticated);
+    }
+}
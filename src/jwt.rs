@@ -0,0 +1,241 @@
+//! conversions between JWT claims and tokens, with the `jwt` feature
+//!
+//! this helps migrating from JWT: a service receiving a JWT can mint a
+//! token from its claims, and a service authorizing tokens can export
+//! query results as claims for the services still expecting them.
+//!
+//! The JWT must be verified before calling [ClaimMapping::to_biscuit], this module
+//! only handles the claims
+//!
+//! ```rust
+//! # use biscuit_auth::KeyPair;
+//! # use biscuit_auth::jwt::ClaimMapping;
+//! let claims = serde_json::json!({
+//!     "sub": "alice",
+//!     "roles": ["admin", "dev"],
+//!     "exp": 1_700_000_000,
+//! });
+//!
+//! let mut mapping = ClaimMapping::new();
+//! mapping.add("sub", "user");
+//! mapping.add("roles", "role");
+//! mapping.add_date("exp", "expiration");
+//!
+//! let root = KeyPair::new();
+//! let token = mapping.to_biscuit(&root, claims.as_object().unwrap()).unwrap();
+//! assert!(token.print_block_source(0).unwrap().contains("role(\"dev\")"));
+//! ```
+use crate::builder::{date, BiscuitBuilder, Fact, Term};
+use crate::error;
+use crate::Authorizer;
+use serde_json::{Map, Number, Value};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// converts claims to authority facts
+///
+/// each mapped claim generates facts with one term, like `user("alice")`
+/// for `"sub": "alice"`. Arrays generate one fact per element, and `null`
+/// values and claims absent from the mapping are ignored
+#[derive(Clone, Debug, Default)]
+pub struct ClaimMapping {
+    claims: Vec<(String, String, ClaimKind)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ClaimKind {
+    Value,
+    Date,
+}
+
+impl ClaimMapping {
+    pub fn new() -> Self {
+        ClaimMapping::default()
+    }
+
+    /// maps strings, integers and booleans of `claim` to `predicate` facts
+    pub fn add(&mut self, claim: &str, predicate: &str) {
+        self.claims
+            .push((claim.to_string(), predicate.to_string(), ClaimKind::Value));
+    }
+
+    /// maps a timestamp in seconds, like `exp` or `iat`, to a `predicate` fact with a date
+    pub fn add_date(&mut self, claim: &str, predicate: &str) {
+        self.claims
+            .push((claim.to_string(), predicate.to_string(), ClaimKind::Date));
+    }
+
+    /// generates the facts for the mapped claims
+    pub fn facts(&self, claims: &Map<String, Value>) -> Result<Vec<Fact>, error::Token> {
+        let mut facts = Vec::new();
+
+        for (claim, predicate, kind) in self.claims.iter() {
+            let values = match claims.get(claim) {
+                None | Some(Value::Null) => continue,
+                Some(Value::Array(values)) => values.iter().collect(),
+                Some(value) => vec![value],
+            };
+
+            for value in values {
+                let term = match kind {
+                    ClaimKind::Value => value_to_term(value),
+                    ClaimKind::Date => value
+                        .as_u64()
+                        .map(|seconds| date(&(UNIX_EPOCH + Duration::from_secs(seconds)))),
+                }
+                .ok_or_else(|| {
+                    error::Token::ConversionError(format!(
+                        "unsupported value for claim {}: {}",
+                        claim, value
+                    ))
+                })?;
+
+                facts.push(Fact::new(predicate.clone(), vec![term]));
+            }
+        }
+
+        Ok(facts)
+    }
+
+    /// adds the facts for the mapped claims to the authority block
+    pub fn add_facts(
+        &self,
+        claims: &Map<String, Value>,
+        builder: &mut BiscuitBuilder,
+    ) -> Result<(), error::Token> {
+        for fact in self.facts(claims)? {
+            builder.add_authority_fact(fact)?;
+        }
+
+        Ok(())
+    }
+
+    /// creates a token with the facts for the mapped claims in its authority block
+    #[cfg(feature = "std")]
+    pub fn to_biscuit(
+        &self,
+        root: &crate::KeyPair,
+        claims: &Map<String, Value>,
+    ) -> Result<crate::Biscuit, error::Token> {
+        let mut builder = crate::Biscuit::builder(root);
+        self.add_facts(claims, &mut builder)?;
+        builder.build()
+    }
+}
+
+fn value_to_term(value: &Value) -> Option<Term> {
+    match value {
+        Value::String(s) => Some(Term::Str(s.clone())),
+        Value::Number(n) => n.as_i64().map(Term::Integer),
+        Value::Bool(b) => Some(Term::Bool(*b)),
+        _ => None,
+    }
+}
+
+fn term_to_value(term: &Term) -> Result<Value, error::Token> {
+    match term {
+        Term::Str(s) => Ok(Value::String(s.clone())),
+        Term::Integer(i) => Ok(Value::Number(Number::from(*i))),
+        Term::Bool(b) => Ok(Value::Bool(*b)),
+        Term::Date(d) => Ok(Value::Number(Number::from(*d))),
+        Term::Bytes(bytes) => Ok(Value::String(hex::encode(bytes))),
+        Term::Set(terms) => terms
+            .iter()
+            .map(term_to_value)
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        Term::Variable(_) | Term::Parameter(_) => Err(error::Token::ConversionError(format!(
+            "cannot convert {} to a claim",
+            term
+        ))),
+    }
+}
+
+/// runs a query on the authorizer and returns the results as claims
+///
+/// the query's head must have two terms, the claim name and its value,
+/// like `claim("sub", $user) <- user($user)`. Dates are converted to
+/// timestamps in seconds and byte arrays to hex strings. If a claim has
+/// multiple values, they are returned in an array
+pub fn export_claims(
+    authorizer: &mut Authorizer,
+    query: &str,
+) -> Result<Map<String, Value>, error::Token> {
+    let facts: Vec<Fact> = authorizer.query(query)?;
+    let mut claims = Map::new();
+
+    for fact in facts {
+        let (name, value) = match &fact.predicate.terms[..] {
+            [Term::Str(name), value] => (name.clone(), term_to_value(value)?),
+            _ => {
+                return Err(error::Token::ConversionError(format!(
+                    "expected a claim name and value, got {}",
+                    fact
+                )))
+            }
+        };
+
+        match claims.get_mut(&name) {
+            None => {
+                claims.insert(name, value);
+            }
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+        }
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+    use serde_json::json;
+
+    #[test]
+    fn roundtrip() {
+        let claims = json!({
+            "sub": "alice",
+            "roles": ["admin", "dev"],
+            "exp": 1_700_000_000,
+            "email_verified": true,
+            "nonce": null,
+        });
+
+        let mut mapping = ClaimMapping::new();
+        mapping.add("sub", "user");
+        mapping.add("roles", "role");
+        mapping.add("email_verified", "verified");
+        mapping.add("nonce", "nonce");
+        mapping.add_date("exp", "expiration");
+
+        let root = KeyPair::new();
+        let token = mapping
+            .to_biscuit(&root, claims.as_object().unwrap())
+            .unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        let mut exported = Map::new();
+        for query in [
+            r#"claim("sub", $u) <- user($u)"#,
+            r#"claim("roles", $r) <- role($r)"#,
+            r#"claim("exp", $e) <- expiration($e)"#,
+        ]
+        .iter()
+        {
+            exported.extend(export_claims(&mut authorizer, query).unwrap());
+        }
+
+        let mut roles = exported["roles"].as_array().unwrap().clone();
+        roles.sort_by_key(|r| r.to_string());
+        assert_eq!(roles, vec![json!("admin"), json!("dev")]);
+        assert_eq!(exported["sub"], json!("alice"));
+        assert_eq!(exported["exp"], json!(1_700_000_000));
+
+        let invalid = json!({ "sub": { "name": "alice" } });
+        assert!(mapping.facts(invalid.as_object().unwrap()).is_err());
+    }
+}
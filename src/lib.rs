@@ -215,19 +215,89 @@
 //!
 //! biscuit implementations come with a default symbol table to avoid transmitting
 //! frequent values with every token.
+//!
+//! ## `no_std` support
+//!
+//! Without the default `std` feature, the crate is `no_std` and only requires
+//! an allocator, to verify and attenuate tokens on embedded targets:
+//! - keys and tokens are created with an explicit random number generator,
+//!   with [KeyPair::new_with_rng] and [builder::BiscuitBuilder::build_with_rng]
+//! - the current time has to be provided as a `time` fact, since there is no
+//!   clock for [Authorizer::set_time], and the `max_time` limit is not enforced
+//! - `.matches()` expressions always fail, as the regex engine requires the
+//!   standard library
+//! - the block signatures are verified one by one instead of in a batch
+//! - the modules reading files, using threads or caching with the system
+//!   clock are not available
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[macro_use]
+extern crate alloc;
 
+pub mod attributes;
+mod collections;
 mod crypto;
 pub mod datalog;
 pub mod error;
 pub mod format;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+pub mod hierarchy;
+#[cfg(any(
+    feature = "axum-integration",
+    feature = "tokio-integration",
+    feature = "tonic-integration"
+))]
+pub mod integrations;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "macaroon")]
+pub mod macaroon;
+pub mod nonce;
 pub mod parser;
+pub mod policy_pack;
+#[cfg(feature = "std")]
+pub mod policy_watcher;
+#[cfg(feature = "rbac")]
+pub mod rbac;
+#[cfg(feature = "repl")]
+pub mod repl;
+pub mod revocation;
+#[cfg(feature = "std")]
+pub mod rotation;
+#[cfg(feature = "samples")]
+pub mod samples;
+#[cfg(feature = "std")]
+mod sync;
+pub mod tenant;
 mod token;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use crypto::{KeyPair, PrivateKey, PublicKey};
-pub use token::authorizer::{Authorizer, AuthorizerLimits};
+#[cfg(feature = "p256")]
+pub use crypto::P256KeyPair;
+pub use crypto::{
+    Algorithm, DalekVerifier, Jwk, KeyPair, PrivateKey, PublicKey, Signature, Signer, Verifier,
+};
+pub use format::{DeserializationLimits, FormatSettings};
+pub use token::authorizer::{
+    AuthorizationMetrics, Authorizer, AuthorizerLimits, BlockEffects, DryRun, GraphFormat,
+    MetricsSink, PartialResults, RuleAnalysis,
+};
 pub use token::builder;
+#[cfg(feature = "json")]
+pub use token::json::{BlockJson, TokenJson};
+#[cfg(feature = "std")]
+pub use token::lazy::LazyBiscuit;
+#[cfg(feature = "std")]
+pub use token::pool::AuthorizerPool;
+pub use token::third_party::{ThirdPartyBlock, ThirdPartyRequest};
 pub use token::unverified::UnverifiedBiscuit;
-pub use token::Biscuit;
+pub use token::{Biscuit, BlockView, RootKeyProvider};
 
 #[cfg(cargo_c)]
 mod capi;
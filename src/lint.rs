@@ -0,0 +1,528 @@
+//! detection of suspicious patterns in tokens and authorizers
+//!
+//! [lint] looks at the facts, rules, checks and policies without running
+//! them, and reports:
+//! * checks that can never succeed
+//! * checks repeated in the same block
+//! * pairs of checks of the same block with the same predicates and opposite
+//!   expressions, like `$u == "admin"` and `!($u == "admin")`
+//! * facts from attenuation blocks using a predicate of the authority
+//!   block or the authorizer
+//! * allow policies that match without looking at any fact
+//! * rules generating a predicate that nothing reads
+//! * policies placed after one that always matches
+//!
+//! A token alone does not contain the authorizer's facts and policies, so
+//! linting a [Biscuit] only reports the problems that do not depend on them
+//!
+//! ```rust
+//! # use biscuit_auth::Authorizer;
+//! # use biscuit_auth::lint::{lint, Warning};
+//! let mut authorizer = Authorizer::new().unwrap();
+//! authorizer.add_code("allow if true; deny if user($u)").unwrap();
+//!
+//! assert_eq!(
+//!     lint(&authorizer),
+//!     vec![
+//!         Warning::BroadPolicy { policy: "allow if true".to_string() },
+//!         Warning::UnreachablePolicy {
+//!             policy: "deny if user($u)".to_string(),
+//!             after: "allow if true".to_string(),
+//!         },
+//!     ]
+//! );
+//! ```
+use crate::builder::{Check, Expression, Fact, Op, Policy, PolicyKind, Rule, Unary};
+use crate::collections::HashSet;
+use crate::datalog;
+use crate::token::default_symbol_table;
+use crate::{Authorizer, Biscuit};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// suspicious pattern found by [lint]
+///
+/// `block_id` is `None` for the authorizer, and `Some(0)` for the authority block
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// a check that can never succeed
+    UnsatisfiableCheck {
+        block_id: Option<usize>,
+        check: String,
+        reason: String,
+    },
+    /// a check identical to a previous check of the same block
+    DuplicateCheck {
+        block_id: Option<usize>,
+        check: String,
+    },
+    /// two checks of the same block with the same predicates, and opposite
+    /// expressions. They can only both succeed if different facts match
+    /// their predicates
+    ContradictoryChecks {
+        block_id: Option<usize>,
+        first: String,
+        second: String,
+    },
+    /// a fact from an attenuation block, using a predicate of the authority
+    /// block or the authorizer. It is only visible to the checks of its block
+    ShadowingFact { block_id: usize, fact: String },
+    /// an allow policy that matches without looking at any fact
+    BroadPolicy { policy: String },
+    /// a rule generating a predicate that no rule, check or policy reads
+    UnusedRule {
+        block_id: Option<usize>,
+        rule: String,
+    },
+    /// a policy that is never tested, because a previous one always matches
+    UnreachablePolicy { policy: String, after: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnsatisfiableCheck {
+                block_id,
+                check,
+                reason,
+            } => write!(
+                f,
+                "{}: `{}` can never succeed: {}",
+                location(*block_id),
+                check,
+                reason
+            ),
+            Warning::DuplicateCheck { block_id, check } => {
+                write!(f, "{}: `{}` is repeated", location(*block_id), check)
+            }
+            Warning::ContradictoryChecks {
+                block_id,
+                first,
+                second,
+            } => write!(
+                f,
+                "{}: `{}` and `{}` contradict each other",
+                location(*block_id),
+                first,
+                second
+            ),
+            Warning::ShadowingFact { block_id, fact } => write!(
+                f,
+                "block {}: `{}` uses a predicate of the authority block or the authorizer",
+                block_id, fact
+            ),
+            Warning::BroadPolicy { policy } => {
+                write!(f, "`{}` matches without looking at any fact", policy)
+            }
+            Warning::UnusedRule { block_id, rule } => write!(
+                f,
+                "{}: the facts generated by `{}` are never used",
+                location(*block_id),
+                rule
+            ),
+            Warning::UnreachablePolicy { policy, after } => write!(
+                f,
+                "`{}` is never tested, `{}` always matches before it",
+                policy, after
+            ),
+        }
+    }
+}
+
+fn location(block_id: Option<usize>) -> String {
+    match block_id {
+        None => "authorizer".to_string(),
+        Some(id) => format!("block {}", id),
+    }
+}
+
+/// types that can be checked by [lint]
+pub trait Lint {
+    fn lint(&self) -> Vec<Warning>;
+}
+
+/// looks for suspicious patterns, see the [module documentation](self)
+pub fn lint<L: Lint + ?Sized>(value: &L) -> Vec<Warning> {
+    value.lint()
+}
+
+impl Lint for Authorizer<'_> {
+    fn lint(&self) -> Vec<Warning> {
+        self.lint_program().lint()
+    }
+}
+
+impl Lint for Biscuit {
+    fn lint(&self) -> Vec<Warning> {
+        Program::from_token(self).lint()
+    }
+}
+
+/// content of a token or authorizer, grouped by block
+#[derive(Default)]
+pub(crate) struct Program {
+    pub blocks: Vec<ProgramBlock>,
+    pub policies: Vec<Policy>,
+    /// predicates generated by external predicates
+    pub externals: HashSet<String>,
+    /// false for a token alone, since the authorizer adds facts, rules and policies
+    pub complete: bool,
+}
+
+pub(crate) struct ProgramBlock {
+    /// `None` for the authorizer
+    pub id: Option<usize>,
+    pub facts: Vec<Fact>,
+    pub rules: Vec<Rule>,
+    pub checks: Vec<Check>,
+}
+
+impl Program {
+    pub fn from_token(token: &Biscuit) -> Self {
+        let mut program = Program::default();
+
+        for (i, block) in core::iter::once(&token.authority)
+            .chain(token.blocks.iter())
+            .enumerate()
+        {
+            program.blocks.push(ProgramBlock {
+                id: Some(i),
+                facts: block
+                    .facts
+                    .iter()
+                    .map(|f| Fact::convert_from(f, &token.symbols))
+                    .collect(),
+                rules: block
+                    .rules
+                    .iter()
+                    .map(|r| Rule::convert_from(r, &token.symbols))
+                    .collect(),
+                checks: block
+                    .checks
+                    .iter()
+                    .map(|c| Check::convert_from(c, &token.symbols))
+                    .collect(),
+            });
+        }
+
+        program
+    }
+
+    pub fn lint(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        self.lint_checks(&mut warnings);
+        self.lint_check_pairs(&mut warnings);
+        self.lint_facts(&mut warnings);
+        self.lint_policies(&mut warnings);
+        if self.complete {
+            self.lint_rules(&mut warnings);
+        }
+        warnings
+    }
+
+    fn lint_checks(&self, warnings: &mut Vec<Warning>) {
+        let generated = self
+            .blocks
+            .iter()
+            .flat_map(|block| {
+                block
+                    .facts
+                    .iter()
+                    .map(|f| &f.predicate.name)
+                    .chain(block.rules.iter().map(|r| &r.head.name))
+            })
+            .chain(self.externals.iter())
+            .collect::<HashSet<_>>();
+
+        for block in self.blocks.iter() {
+            for check in block.checks.iter() {
+                let reasons = check
+                    .queries
+                    .iter()
+                    .map(|query| {
+                        if let Some(expression) = query
+                            .expressions
+                            .iter()
+                            .find(|e| constant(e) == Some(false))
+                        {
+                            return Some(format!(
+                                "the expression `{}` is always false",
+                                expression
+                            ));
+                        }
+                        if self.complete {
+                            if let Some(predicate) =
+                                query.body.iter().find(|p| !generated.contains(&p.name))
+                            {
+                                return Some(format!("there are no `{}` facts", predicate.name));
+                            }
+                        }
+                        None
+                    })
+                    .collect::<Option<Vec<_>>>();
+
+                if let Some(mut reasons) = reasons {
+                    if !reasons.is_empty() {
+                        warnings.push(Warning::UnsatisfiableCheck {
+                            block_id: block.id,
+                            check: check.to_string(),
+                            reason: reasons.swap_remove(0),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn lint_check_pairs(&self, warnings: &mut Vec<Warning>) {
+        for block in self.blocks.iter() {
+            for (i, check) in block.checks.iter().enumerate() {
+                let previous = &block.checks[..i];
+
+                if previous.contains(check) {
+                    warnings.push(Warning::DuplicateCheck {
+                        block_id: block.id,
+                        check: check.to_string(),
+                    });
+                } else if let Some(other) = previous.iter().find(|other| contradict(other, check)) {
+                    warnings.push(Warning::ContradictoryChecks {
+                        block_id: block.id,
+                        first: other.to_string(),
+                        second: check.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn lint_facts(&self, warnings: &mut Vec<Warning>) {
+        let trusted = self
+            .blocks
+            .iter()
+            .filter(|block| block.id.unwrap_or(0) == 0)
+            .flat_map(|block| block.facts.iter().map(|f| &f.predicate.name))
+            .collect::<HashSet<_>>();
+
+        for block in self.blocks.iter() {
+            let block_id = match block.id {
+                Some(id) if id > 0 => id,
+                _ => continue,
+            };
+
+            for fact in block.facts.iter() {
+                if trusted.contains(&fact.predicate.name) {
+                    warnings.push(Warning::ShadowingFact {
+                        block_id,
+                        fact: fact.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn lint_policies(&self, warnings: &mut Vec<Warning>) {
+        let mut always_matching: Option<&Policy> = None;
+
+        for policy in self.policies.iter() {
+            if let Some(previous) = always_matching {
+                warnings.push(Warning::UnreachablePolicy {
+                    policy: policy.to_string(),
+                    after: previous.to_string(),
+                });
+                continue;
+            }
+
+            let matches_anything = policy.queries.iter().any(|query| {
+                query.body.is_empty() && query.expressions.iter().all(|e| constant(e) == Some(true))
+            });
+            if matches_anything {
+                if policy.kind == PolicyKind::Allow {
+                    warnings.push(Warning::BroadPolicy {
+                        policy: policy.to_string(),
+                    });
+                }
+                always_matching = Some(policy);
+            }
+        }
+    }
+
+    fn lint_rules(&self, warnings: &mut Vec<Warning>) {
+        let read_by_queries = self
+            .blocks
+            .iter()
+            .flat_map(|block| block.checks.iter().flat_map(|c| c.queries.iter()))
+            .chain(self.policies.iter().flat_map(|p| p.queries.iter()))
+            .flat_map(|query| query.body.iter().map(|p| &p.name))
+            .collect::<HashSet<_>>();
+
+        for block in self.blocks.iter() {
+            for (i, rule) in block.rules.iter().enumerate() {
+                let used = read_by_queries.contains(&rule.head.name)
+                    || self.blocks.iter().any(|other| {
+                        other.rules.iter().enumerate().any(|(j, other_rule)| {
+                            !(other.id == block.id && i == j)
+                                && other_rule.body.iter().any(|p| p.name == rule.head.name)
+                        })
+                    });
+
+                if !used {
+                    warnings.push(Warning::UnusedRule {
+                        block_id: block.id,
+                        rule: rule.to_string(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// checks with a single query each, with the same predicates, where an
+/// expression of one is the negation of an expression of the other
+fn contradict(first: &Check, second: &Check) -> bool {
+    let (first, second) = match (first.queries.as_slice(), second.queries.as_slice()) {
+        ([first], [second]) => (first, second),
+        _ => return false,
+    };
+
+    let same_body = first.body.len() == second.body.len()
+        && first.body.iter().all(|p| second.body.contains(p))
+        && second.body.iter().all(|p| first.body.contains(p));
+
+    same_body
+        && first.expressions.iter().any(|a| {
+            second
+                .expressions
+                .iter()
+                .any(|b| negates(a, b) || negates(b, a))
+        })
+}
+
+/// `negated` is `!expression`, ignoring parentheses
+fn negates(negated: &Expression, expression: &Expression) -> bool {
+    match without_parens(&negated.ops).split_last() {
+        Some((Op::Unary(Unary::Negate), inner)) => {
+            without_parens(inner) == without_parens(&expression.ops)
+        }
+        _ => false,
+    }
+}
+
+fn without_parens(mut ops: &[Op]) -> &[Op] {
+    while let Some((Op::Unary(Unary::Parens), inner)) = ops.split_last() {
+        ops = inner;
+    }
+    ops
+}
+
+/// value of an expression that does not depend on variables
+fn constant(expression: &Expression) -> Option<bool> {
+    let mut symbols = default_symbol_table();
+    match expression
+        .convert(&mut symbols)
+        .fold(&symbols)
+        .ops
+        .as_slice()
+    {
+        [datalog::Op::Value(datalog::Term::Bool(b))] => Some(*b),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+
+    #[test]
+    fn authorizer() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_code("user(\"alice\"); right(\"file1\");")
+            .unwrap();
+        let token = builder.build().unwrap();
+        let mut block = token.create_block();
+        block
+            .add_code("user(\"admin\"); check if user($u), $u == \"admin\";")
+            .unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                owner($u, $f) <- user($u), right($f);
+                check if operation("read");
+                check if user($u), 1 > 2;
+                allow if user("alice");
+                "#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            lint(&authorizer),
+            vec![
+                Warning::UnsatisfiableCheck {
+                    block_id: None,
+                    check: "check if operation(\"read\")".to_string(),
+                    reason: "there are no `operation` facts".to_string(),
+                },
+                Warning::UnsatisfiableCheck {
+                    block_id: None,
+                    check: "check if user($u), 1 > 2".to_string(),
+                    reason: "the expression `1 > 2` is always false".to_string(),
+                },
+                Warning::ShadowingFact {
+                    block_id: 1,
+                    fact: "user(\"admin\")".to_string(),
+                },
+                Warning::UnusedRule {
+                    block_id: None,
+                    rule: "owner($u, $f) <- user($u), right($f)".to_string(),
+                },
+            ]
+        );
+
+        // the authorizer provides the facts missing in the token
+        assert_eq!(
+            lint(&token),
+            vec![Warning::ShadowingFact {
+                block_id: 1,
+                fact: "user(\"admin\")".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn check_pairs() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                user("alice");
+                check if user($u), $u == "admin";
+                check if user($u);
+                check if user($u), !($u == "admin");
+                check if user($u);
+                allow if user($u);
+                "#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            lint(&authorizer),
+            vec![
+                Warning::ContradictoryChecks {
+                    block_id: None,
+                    first: "check if user($u), $u == \"admin\"".to_string(),
+                    second: "check if user($u), !($u == \"admin\")".to_string(),
+                },
+                Warning::DuplicateCheck {
+                    block_id: None,
+                    check: "check if user($u)".to_string(),
+                },
+            ]
+        );
+    }
+}
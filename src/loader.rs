@@ -0,0 +1,371 @@
+//! bulk loading of facts from CSV and JSON
+//!
+//! [Authorizer::add_facts_from_csv](crate::Authorizer::add_facts_from_csv)
+//! reads one fact per record, with a term per column:
+//!
+//! ```rust
+//! # use biscuit_auth::Authorizer;
+//! let csv = "user,resource,level\nalice,file1,2\nbob,\"file,2\",1\n";
+//!
+//! let mut authorizer = Authorizer::new().unwrap();
+//! let loaded = authorizer.add_facts_from_csv(csv.as_bytes(), "access").unwrap();
+//! assert_eq!(loaded, 2);
+//!
+//! let access: Vec<(String, String, i64)> = authorizer
+//!     .query("data($u, $r, $l) <- access($u, $r, $l)")
+//!     .unwrap();
+//! assert!(access.contains(&("bob".to_string(), "file,2".to_string(), 1)));
+//! ```
+//!
+//! With the `json-facts` feature,
+//! [Authorizer::add_facts_from_json](crate::Authorizer::add_facts_from_json)
+//! reads a stream of JSON objects like `{"name": "access", "terms": ["alice", "file1", 2]}`,
+//! or arrays of them. Records are converted as they are read, so large files
+//! are not kept in memory
+//!
+//! The type of each column, or of each term for JSON, is guessed by default,
+//! and can be set with [LoadOptions::column]
+use crate::builder::{Fact, Term};
+use crate::error;
+use std::convert::TryInto;
+use std::io::{BufRead, BufReader, Read};
+
+/// type of the terms generated from a column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    /// integers and booleans are recognized, other values are strings.
+    /// For JSON, the type of the value is used, and arrays are sets
+    Auto,
+    String,
+    Integer,
+    Bool,
+    /// RFC 3339 date, or a number of seconds since the epoch
+    Date,
+    /// hexadecimal string
+    Bytes,
+}
+
+/// options for [Authorizer::add_facts_from_csv_with_options](crate::Authorizer::add_facts_from_csv_with_options)
+/// and [Authorizer::add_facts_from_json_with_options](crate::Authorizer::add_facts_from_json_with_options)
+#[derive(Clone, Debug)]
+pub struct LoadOptions {
+    delimiter: char,
+    header: bool,
+    columns: Vec<ColumnType>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            delimiter: ',',
+            header: true,
+            columns: Vec::new(),
+        }
+    }
+}
+
+impl LoadOptions {
+    /// comma separated values with a header line, and guessed types
+    pub fn new() -> Self {
+        LoadOptions::default()
+    }
+
+    /// sets the CSV field separator
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// indicates if the first CSV record is a header, which is skipped
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// sets the type of the column at `index`, starting from 0
+    pub fn column(mut self, index: usize, column_type: ColumnType) -> Self {
+        if self.columns.len() <= index {
+            self.columns.resize(index + 1, ColumnType::Auto);
+        }
+        self.columns[index] = column_type;
+        self
+    }
+
+    fn column_type(&self, index: usize) -> ColumnType {
+        self.columns.get(index).copied().unwrap_or(ColumnType::Auto)
+    }
+}
+
+/// calls `f` with a fact for each record, returns the number of facts
+pub fn for_each_csv_fact<R, F>(
+    reader: R,
+    predicate: &str,
+    options: &LoadOptions,
+    mut f: F,
+) -> Result<usize, error::Token>
+where
+    R: Read,
+    F: FnMut(Fact) -> Result<(), error::Token>,
+{
+    let mut lines = BufReader::new(reader).lines();
+    let mut line_number = 0;
+    let mut count = 0;
+
+    while let Some(line) = lines.next() {
+        line_number += 1;
+        let start = line_number;
+        let mut record = line.map_err(|e| io_error(start, e))?;
+
+        // a quoted field can contain line breaks
+        while record.matches('"').count() % 2 == 1 {
+            match lines.next() {
+                Some(line) => {
+                    line_number += 1;
+                    record.push('\n');
+                    record.push_str(&line.map_err(|e| io_error(line_number, e))?);
+                }
+                None => return Err(load_error("line", start, "unterminated quoted field")),
+            }
+        }
+
+        if record.trim().is_empty() || (options.header && start == 1) {
+            continue;
+        }
+
+        let terms = split_record(&record, options.delimiter)
+            .iter()
+            .enumerate()
+            .map(|(index, field)| csv_term(field, options.column_type(index)))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| load_error("line", start, &e))?;
+
+        f(Fact::new(predicate.to_string(), terms))?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// calls `f` with a fact for each JSON record, returns the number of facts
+#[cfg(feature = "json-facts")]
+pub fn for_each_json_fact<R, F>(
+    reader: R,
+    options: &LoadOptions,
+    mut f: F,
+) -> Result<usize, error::Token>
+where
+    R: Read,
+    F: FnMut(Fact) -> Result<(), error::Token>,
+{
+    use serde_json::Value;
+
+    let mut count = 0;
+
+    for value in serde_json::Deserializer::from_reader(BufReader::new(reader)).into_iter::<Value>()
+    {
+        let value = value.map_err(|e| error::Token::ConversionError(e.to_string()))?;
+        let records = match value {
+            Value::Array(records) => records,
+            record => vec![record],
+        };
+
+        for record in records {
+            count += 1;
+            let fact = json_fact(&record, options).map_err(|e| load_error("record", count, &e))?;
+            f(fact)?;
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(feature = "json-facts")]
+fn json_fact(record: &serde_json::Value, options: &LoadOptions) -> Result<Fact, String> {
+    let name = record
+        .get("name")
+        .and_then(|name| name.as_str())
+        .ok_or_else(|| "missing name".to_string())?;
+    let terms = record
+        .get("terms")
+        .and_then(|terms| terms.as_array())
+        .ok_or_else(|| "missing terms".to_string())?;
+
+    let terms = terms
+        .iter()
+        .enumerate()
+        .map(|(index, value)| json_term(value, options.column_type(index)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Fact::new(name.to_string(), terms))
+}
+
+#[cfg(feature = "json-facts")]
+fn json_term(value: &serde_json::Value, column_type: ColumnType) -> Result<Term, String> {
+    use serde_json::Value;
+    use std::collections::BTreeSet;
+
+    match (value, column_type) {
+        (Value::String(s), ColumnType::Auto) => Ok(Term::Str(s.clone())),
+        (Value::String(s), _) => csv_term(s, column_type),
+        (Value::Number(n), ColumnType::Auto) | (Value::Number(n), ColumnType::Integer) => n
+            .as_i64()
+            .map(Term::Integer)
+            .ok_or_else(|| format!("unsupported number: {}", n)),
+        (Value::Number(n), ColumnType::Date) => n
+            .as_u64()
+            .map(Term::Date)
+            .ok_or_else(|| format!("invalid date: {}", n)),
+        (Value::Bool(b), ColumnType::Auto) | (Value::Bool(b), ColumnType::Bool) => {
+            Ok(Term::Bool(*b))
+        }
+        (Value::Array(values), ColumnType::Auto) => values
+            .iter()
+            .map(|v| json_term(v, ColumnType::Auto))
+            .collect::<Result<BTreeSet<_>, _>>()
+            .map(Term::Set),
+        (value, column_type) => Err(format!("expected {:?}, got {}", column_type, value)),
+    }
+}
+
+fn csv_term(field: &str, column_type: ColumnType) -> Result<Term, String> {
+    match column_type {
+        ColumnType::Auto => Ok(match field {
+            "true" => Term::Bool(true),
+            "false" => Term::Bool(false),
+            _ => field
+                .parse()
+                .map(Term::Integer)
+                .unwrap_or_else(|_| Term::Str(field.to_string())),
+        }),
+        ColumnType::String => Ok(Term::Str(field.to_string())),
+        ColumnType::Integer => field
+            .parse()
+            .map(Term::Integer)
+            .map_err(|_| format!("invalid integer: {}", field)),
+        ColumnType::Bool => field
+            .parse()
+            .map(Term::Bool)
+            .map_err(|_| format!("invalid boolean: {}", field)),
+        ColumnType::Date => field
+            .parse()
+            .ok()
+            .or_else(|| {
+                time::OffsetDateTime::parse(field, &time::format_description::well_known::Rfc3339)
+                    .ok()
+                    .and_then(|t| t.unix_timestamp().try_into().ok())
+            })
+            .map(Term::Date)
+            .ok_or_else(|| format!("invalid date: {}", field)),
+        ColumnType::Bytes => hex::decode(field)
+            .map(Term::Bytes)
+            .map_err(|_| format!("invalid hexadecimal: {}", field)),
+    }
+}
+
+/// splits a record on the delimiter, quotes are removed and `""` is unescaped
+fn split_record(record: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = record.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+fn io_error(line: usize, e: std::io::Error) -> error::Token {
+    load_error("line", line, &e.to_string())
+}
+
+fn load_error(location: &str, index: usize, message: &str) -> error::Token {
+    error::Token::ConversionError(format!("{} {}: {}", location, index, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            split_record("a,\"b,\"\"c\"\"\",,d", ','),
+            vec!["a", "b,\"c\"", "", "d"]
+        );
+
+        let data =
+            "id;created;key;admin\n1;2022-01-01T00:00:00Z;00ff;true\n\n2;10;\"ab\ncd\";false\n";
+        let options = LoadOptions::new()
+            .delimiter(';')
+            .column(1, ColumnType::Date)
+            .column(2, ColumnType::String);
+
+        let mut facts = Vec::new();
+        let count = for_each_csv_fact(data.as_bytes(), "key", &options, |fact| {
+            facts.push(fact.to_string());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            facts,
+            vec![
+                "key(1, 2022-01-01T00:00:00Z, \"00ff\", true)",
+                "key(2, 1970-01-01T00:00:10Z, \"ab\\ncd\", false)",
+            ]
+        );
+
+        let options = LoadOptions::new()
+            .header(false)
+            .column(0, ColumnType::Integer);
+        assert_eq!(
+            for_each_csv_fact("1\nx\n".as_bytes(), "n", &options, |_| Ok(())),
+            Err(error::Token::ConversionError(
+                "line 2: invalid integer: x".to_string()
+            ))
+        );
+    }
+
+    #[cfg(feature = "json-facts")]
+    #[test]
+    fn json() {
+        let data = r#"
+            {"name": "user", "terms": ["alice", 1, [true, "admin"]]}
+            [{"name": "key", "terms": ["00ff", 0]}]
+        "#;
+        let options = LoadOptions::new()
+            .column(0, ColumnType::Bytes)
+            .column(1, ColumnType::Date);
+
+        let mut facts = Vec::new();
+        let count = for_each_json_fact(data.as_bytes(), &LoadOptions::new(), |fact| {
+            facts.push(fact.to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(facts[0], "user(\"alice\", 1, [ \"admin\", true])");
+
+        assert!(for_each_json_fact(data.as_bytes(), &options, |_| Ok(())).is_err());
+        let key = r#"{"name": "key", "terms": ["00ff", 0]}"#;
+        let mut facts = Vec::new();
+        for_each_json_fact(key.as_bytes(), &options, |fact| {
+            facts.push(fact.to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(facts, vec!["key(hex:00ff, 1970-01-01T00:00:00Z)"]);
+    }
+}
@@ -0,0 +1,231 @@
+//! conversion of first party macaroon caveats to checks, with the `macaroon` feature
+//!
+//! this helps migrating a macaroon based deployment: the caveats of an
+//! existing macaroon can be translated to a block, appended to a token
+//! carrying the same rights. The verifier then provides the `time`,
+//! `operation` and `resource` facts, and the facts for declared values.
+//!
+//! Operation caveats are converted to `check if` checks, which succeed as
+//! soon as one `operation` fact matches: the verifier should provide a
+//! single `operation` fact per request.
+//!
+//! The supported caveats are:
+//! * `time < 2022-01-01T00:00:00Z`, `time <= ...`, `time > ...`, `time >= ...`, `time-before ...`
+//! * `op = read`, `operation = read`
+//! * `op in read,write`, `allow read write`, and `deny write` for the forbidden operations
+//! * `resource = /files/*`, where `*` matches any sequence of characters
+//! * `key = value` and `declared key value`, checked with a `key("value")` fact
+//!
+//! ```rust
+//! # use biscuit_auth::macaroon::caveat_to_check;
+//! let check = caveat_to_check("time < 2022-01-01T00:00:00Z").unwrap();
+//! assert_eq!(
+//!     check.to_string(),
+//!     "check if time($time), $time < 2022-01-01T00:00:00Z"
+//! );
+//! ```
+use crate::builder::{BlockBuilder, Check, Term};
+use crate::error;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// converts a first party caveat to a check
+pub fn caveat_to_check(caveat: &str) -> Result<Check, error::Token> {
+    let caveat = caveat.trim();
+    let unsupported =
+        || error::Token::ConversionError(format!("unsupported macaroon caveat: {}", caveat));
+
+    let (key, operator, value) = split(caveat).ok_or_else(unsupported)?;
+
+    match (key, operator) {
+        ("time", "<") | ("time-before", "") => time_check("<", value),
+        ("time", "<=") | ("time", ">") | ("time", ">=") => time_check(operator, value),
+        ("op", "=") | ("operation", "=") => {
+            let mut check = Check::try_from("check if operation({op})")?;
+            check.set("op", value)?;
+            Ok(check)
+        }
+        ("op", "in") | ("operation", "in") => operations_check(true, value.split(',')),
+        ("allow", "") => operations_check(true, value.split_whitespace()),
+        ("deny", "") => operations_check(false, value.split_whitespace()),
+        ("resource", "=") if value.contains('*') => {
+            let mut check =
+                Check::try_from("check if resource($resource), $resource.matches({pattern})")?;
+            check.set("pattern", glob_to_regex(value))?;
+            Ok(check)
+        }
+        ("declared", "") => {
+            let mut parts = value.splitn(2, char::is_whitespace);
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if is_identifier(key) => fact_check(key, value.trim()),
+                _ => Err(unsupported()),
+            }
+        }
+        (key, "=") if is_identifier(key) => fact_check(key, value),
+        _ => Err(unsupported()),
+    }
+}
+
+/// converts the first party caveats of a macaroon to a block of checks
+pub fn caveats_to_block<S: AsRef<str>>(caveats: &[S]) -> Result<BlockBuilder, error::Token> {
+    let mut block = BlockBuilder::new();
+
+    for caveat in caveats {
+        block.add_check(caveat_to_check(caveat.as_ref())?)?;
+    }
+
+    Ok(block)
+}
+
+/// splits a caveat in a key, an operator and a value
+///
+/// the operator is empty for caveats like `time-before <date>`
+fn split(caveat: &str) -> Option<(&str, &str, &str)> {
+    for operator in ["<=", ">=", "<", ">", "="].iter() {
+        if let Some(index) = caveat.find(operator) {
+            let key = caveat[..index].trim();
+            if is_identifier(key) || key == "time" {
+                return Some((key, *operator, caveat[index + operator.len()..].trim()));
+            }
+        }
+    }
+
+    let mut parts = caveat.splitn(2, char::is_whitespace);
+    let key = parts.next()?;
+    let rest = parts.next()?.trim();
+
+    match rest.strip_prefix("in ") {
+        Some(value) => Some((key, "in", value.trim())),
+        None => Some((key, "", rest)),
+    }
+}
+
+fn time_check(operator: &str, value: &str) -> Result<Check, error::Token> {
+    let date = OffsetDateTime::parse(value, &Rfc3339)
+        .ok()
+        .and_then(|date| u64::try_from(date.unix_timestamp()).ok())
+        .ok_or_else(|| error::Token::ConversionError(format!("invalid date: {}", value)))?;
+
+    let mut check =
+        Check::try_from(format!("check if time($time), $time {} {{date}}", operator).as_str())?;
+    check.set("date", Term::Date(date))?;
+    Ok(check)
+}
+
+fn operations_check<'a, I: Iterator<Item = &'a str>>(
+    allow: bool,
+    operations: I,
+) -> Result<Check, error::Token> {
+    let operations = operations
+        .map(str::trim)
+        .filter(|op| !op.is_empty())
+        .map(|op| Term::Str(op.to_string()))
+        .collect::<BTreeSet<_>>();
+
+    let mut check = if allow {
+        Check::try_from("check if operation($op), {operations}.contains($op)")?
+    } else {
+        Check::try_from("check if operation($op), !{operations}.contains($op)")?
+    };
+    check.set("operations", Term::Set(operations))?;
+    Ok(check)
+}
+
+fn fact_check(key: &str, value: &str) -> Result<Check, error::Token> {
+    let mut check = Check::try_from(format!("check if {}({{value}})", key).as_str())?;
+    check.set("value", value)?;
+    Ok(check)
+}
+
+/// converts a pattern where `*` matches any sequence of characters to an anchored regex
+fn glob_to_regex(pattern: &str) -> String {
+    let parts = pattern.split('*').map(regex::escape).collect::<Vec<_>>();
+    format!("^{}$", parts.join(".*"))
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic())
+        .unwrap_or(false)
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::authorizer::AuthorizerLimits;
+    use std::time::Duration;
+
+    // compiling the regular expressions takes longer than the default limit
+    // in debug builds
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn caveats() {
+        let checks = [
+            "time < 2022-01-01T00:00:00Z",
+            "time-before 2022-01-01T00:00:00Z",
+            "op = read",
+            "op in read, write",
+            "deny delete",
+            "resource = /files/*.txt",
+            "resource = /files/report.txt",
+            "declared user alice",
+            "account = 3735928559",
+        ]
+        .iter()
+        .map(|caveat| caveat_to_check(caveat).unwrap().to_string())
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            checks,
+            vec![
+                "check if time($time), $time < 2022-01-01T00:00:00Z",
+                "check if time($time), $time < 2022-01-01T00:00:00Z",
+                "check if operation(\"read\")",
+                "check if operation($op), [\"read\", \"write\"].contains($op)",
+                "check if operation($op), ![\"delete\"].contains($op)",
+                "check if resource($resource), $resource.matches(\"^/files/.*\\\\.txt$\")",
+                "check if resource(\"/files/report.txt\")",
+                "check if user(\"alice\")",
+                "check if account(\"3735928559\")",
+            ]
+        );
+
+        assert!(caveat_to_check("time < tomorrow").is_err());
+        assert!(caveat_to_check("some opaque caveat").is_err());
+    }
+
+    #[test]
+    fn block() {
+        use crate::{Authorizer, Biscuit, KeyPair};
+
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        let block = caveats_to_block(&["op in read,write", "resource = /files/*"]).unwrap();
+        let token = token.append(block).unwrap();
+
+        let authorize = |operation: &str, resource: &str| {
+            let mut authorizer: Authorizer = token.authorizer().unwrap();
+            authorizer
+                .add_code(&format!(
+                    "operation(\"{}\"); resource(\"{}\"); allow if true",
+                    operation, resource
+                ))
+                .unwrap();
+            authorizer.authorize_with_limits(limits()).is_ok()
+        };
+
+        assert!(authorize("read", "/files/a"));
+        assert!(!authorize("delete", "/files/a"));
+        assert!(!authorize("read", "/other/a"));
+    }
+}
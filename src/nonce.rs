@@ -0,0 +1,162 @@
+//! one-time tokens, with a nonce recorded by the authorizer
+//!
+//! [BiscuitBuilder::add_nonce](crate::builder::BiscuitBuilder::add_nonce) adds
+//! the authority fact `nonce(hex:...)`. An authorizer with a [ReplayStore],
+//! set with [Authorizer::set_replay_store](crate::Authorizer::set_replay_store),
+//! records the nonce when the token is authorized, and rejects it the next
+//! times with [error::Replay::Reused].
+//!
+//! Nonces are only recorded when the authorization succeeds, so a failed
+//! attempt does not consume the token
+//!
+//! ```rust
+//! # use biscuit_auth::{error, Authorizer, Biscuit, KeyPair};
+//! # use biscuit_auth::nonce::MemoryStore;
+//! # use std::time::Duration;
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_random_nonce();
+//! let token = builder.build().unwrap();
+//!
+//! let mut authorizer = Authorizer::new().unwrap();
+//! authorizer.set_replay_store(MemoryStore::new(Duration::from_secs(3600)));
+//! authorizer.allow().unwrap();
+//!
+//! assert_eq!(authorizer.authorize_token(&token), Ok(0));
+//! assert_eq!(
+//!     authorizer.authorize_token(&token),
+//!     Err(error::Token::Replay(error::Replay::Reused))
+//! );
+//! ```
+use crate::error;
+#[cfg(feature = "std")]
+use crate::sync::lock;
+#[cfg(feature = "std")]
+use crate::time::Instant;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// records the nonces of the authorized tokens
+pub trait ReplayStore: Send + Sync {
+    /// records a nonce, returns false if it was already recorded
+    ///
+    /// this must be atomic: when multiple authorizers record the same
+    /// nonce at the same time, only one of them gets `true`
+    fn record(&self, nonce: &[u8]) -> Result<bool, error::Cause>;
+}
+
+#[cfg(feature = "std")]
+impl ReplayStore for Mutex<HashSet<Vec<u8>>> {
+    fn record(&self, nonce: &[u8]) -> Result<bool, error::Cause> {
+        Ok(lock(self).insert(nonce.to_vec()))
+    }
+}
+
+/// keeps the nonces in memory for a duration
+///
+/// the duration must be longer than the validity of the tokens, a nonce
+/// can be used again once it is forgotten
+#[cfg(feature = "std")]
+pub struct MemoryStore {
+    ttl: Duration,
+    nonces: Mutex<HashMap<Vec<u8>, Instant>>,
+}
+
+#[cfg(feature = "std")]
+impl MemoryStore {
+    pub fn new(ttl: Duration) -> Self {
+        MemoryStore {
+            ttl,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// number of recorded nonces, including the expired ones
+    pub fn len(&self) -> usize {
+        self.nonces().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// removes the expired nonces
+    pub fn purge(&self) {
+        let ttl = self.ttl;
+        self.nonces().retain(|_, time| time.elapsed() < ttl);
+    }
+
+    fn nonces(&self) -> std::sync::MutexGuard<'_, HashMap<Vec<u8>, Instant>> {
+        lock(&self.nonces)
+    }
+}
+
+#[cfg(feature = "std")]
+impl ReplayStore for MemoryStore {
+    fn record(&self, nonce: &[u8]) -> Result<bool, error::Cause> {
+        let mut nonces = self.nonces();
+        match nonces.get(nonce) {
+            Some(time) if time.elapsed() < self.ttl => Ok(false),
+            _ => {
+                nonces.insert(nonce.to_vec(), Instant::now());
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// generates a random 16 bytes nonce
+#[cfg(feature = "std")]
+pub fn generate() -> [u8; 16] {
+    use rand_core::RngCore;
+
+    let mut nonce = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Authorizer, Biscuit, KeyPair};
+
+    #[test]
+    fn one_time_token() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_nonce(b"reset-1234");
+        let token = builder.build().unwrap();
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.set_replay_store(Mutex::new(HashSet::new()));
+        authorizer
+            .add_code("allow if operation(\"reset\")")
+            .unwrap();
+
+        // failed authorizations do not consume the nonce
+        assert!(authorizer.authorize_token(&token).is_err());
+
+        authorizer.add_code("operation(\"reset\")").unwrap();
+        assert_eq!(authorizer.authorize_token(&token), Ok(0));
+        assert_eq!(
+            authorizer.authorize_token(&token),
+            Err(error::Token::Replay(error::Replay::Reused))
+        );
+
+        // tokens without a nonce are not recorded
+        let token = Biscuit::builder(&root).build().unwrap();
+        for _ in 0..2 {
+            assert_eq!(authorizer.authorize_token(&token), Ok(0));
+        }
+
+        let store = MemoryStore::new(Duration::from_secs(60));
+        assert_eq!(store.record(b"a"), Ok(true));
+        assert_eq!(store.record(b"a"), Ok(false));
+        store.purge();
+        assert_eq!(store.len(), 1);
+    }
+}
@@ -13,26 +13,32 @@
 //! All of the methods in [BiscuitBuilder](`crate::token::builder::BiscuitBuilder`)
 //! and [BlockBuilder](`crate::token::builder::BlockBuilder`) can take strings
 //! as arguments too
+use crate::collections::HashSet;
 use crate::{error, token::builder};
+use alloc::{
+    boxed::Box,
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    str::FromStr,
+};
 use nom::{
     branch::alt,
     bytes::complete::{escaped_transform, tag, tag_no_case, take_until, take_while, take_while1},
     character::{
-        complete::{char, digit1, multispace0 as space0},
+        complete::{alphanumeric1, char, digit1, multispace0 as space0},
         is_alphanumeric,
     },
-    combinator::{consumed, cut, eof, map, map_res, opt, recognize, value},
+    combinator::{consumed, cut, eof, map, map_opt, map_res, opt, recognize, value},
     error::{ErrorKind, FromExternalError, ParseError},
-    multi::{many0, separated_list0, separated_list1},
+    multi::{many0, many1, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, terminated, tuple},
     Finish, IResult, Offset,
 };
-use std::{
-    collections::BTreeSet,
-    convert::{TryFrom, TryInto},
-    str::FromStr,
-};
-use thiserror::Error;
 
 /// parse a Datalog fact
 pub fn fact(i: &str) -> IResult<&str, builder::Fact, Error> {
@@ -151,25 +157,48 @@ pub fn deny(i: &str) -> IResult<&str, builder::Policy, Error> {
 pub fn check_body(i: &str) -> IResult<&str, Vec<builder::Rule>, Error> {
     let (i, mut queries) = separated_list1(
         preceded(space0, tag_no_case("or")),
-        preceded(space0, cut(rule_body)),
+        preceded(space0, pair(cut(rule_body), opt(trusting))),
     )(i)?;
 
     let queries = queries
         .drain(..)
-        .map(|rule_body| {
-            builder::Rule::new(
+        .map(|(rule_body, trusted_keys)| {
+            let mut rule = builder::Rule::new(
                 builder::Predicate {
                     name: "query".to_string(),
                     terms: Vec::new(),
                 },
                 rule_body.0,
                 rule_body.1,
-            )
+            );
+            rule.trusted_keys = trusted_keys.unwrap_or_default();
+            rule
         })
         .collect();
     Ok((i, queries))
 }
 
+/// parse the list of third party keys a check or policy query trusts
+fn trusting(i: &str) -> IResult<&str, Vec<crate::crypto::PublicKey>, Error<'_>> {
+    let (i, _) = preceded(space0, tag("trusting"))(i)?;
+
+    cut(separated_list1(
+        preceded(space0, char(',')),
+        preceded(space0, public_key),
+    ))(i)
+}
+
+fn public_key(i: &str) -> IResult<&str, crate::crypto::PublicKey, Error<'_>> {
+    let (i, algorithm) = map_opt(
+        terminated(alphanumeric1, char('/')),
+        crate::crypto::Algorithm::from_name,
+    )(i)?;
+
+    map_res(parse_hex, move |bytes| {
+        crate::crypto::PublicKey::from_bytes_with_algorithm(&bytes, algorithm)
+    })(i)
+}
+
 /// parse a Datalog rule
 pub fn rule(i: &str) -> IResult<&str, builder::Rule, Error> {
     let (i, rule) = rule_inner(i)?;
@@ -214,7 +243,10 @@ impl TryFrom<&str> for builder::Fact {
     type Error = error::Token;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(fact(value).finish().map(|(_, o)| o)?)
+        fact(value)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(value, e))
     }
 }
 
@@ -222,7 +254,10 @@ impl TryFrom<&str> for builder::Rule {
     type Error = error::Token;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(rule(value).finish().map(|(_, o)| o)?)
+        rule(value)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(value, e))
     }
 }
 
@@ -230,7 +265,7 @@ impl FromStr for builder::Fact {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(fact(s).finish().map(|(_, o)| o)?)
+        fact(s).finish().map(|(_, o)| o).map_err(|e| located(s, e))
     }
 }
 
@@ -238,7 +273,7 @@ impl FromStr for builder::Rule {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(rule(s).finish().map(|(_, o)| o)?)
+        rule(s).finish().map(|(_, o)| o).map_err(|e| located(s, e))
     }
 }
 
@@ -246,7 +281,10 @@ impl TryFrom<&str> for builder::Check {
     type Error = error::Token;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(check(value).finish().map(|(_, o)| o)?)
+        check(value)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(value, e))
     }
 }
 
@@ -254,7 +292,7 @@ impl FromStr for builder::Check {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(check(s).finish().map(|(_, o)| o)?)
+        check(s).finish().map(|(_, o)| o).map_err(|e| located(s, e))
     }
 }
 
@@ -262,7 +300,10 @@ impl TryFrom<&str> for builder::Policy {
     type Error = error::Token;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(policy(value).finish().map(|(_, o)| o)?)
+        policy(value)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(value, e))
     }
 }
 
@@ -270,7 +311,10 @@ impl FromStr for builder::Policy {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(policy(s).finish().map(|(_, o)| o)?)
+        policy(s)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(s, e))
     }
 }
 
@@ -278,7 +322,10 @@ impl FromStr for builder::Predicate {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(predicate(s).finish().map(|(_, o)| o)?)
+        predicate(s)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(s, e))
     }
 }
 
@@ -292,12 +339,25 @@ impl TryFrom<&str> for builder::BlockBuilder {
                 rules: result.rules.drain(..).map(|(_, rule)| rule).collect(),
                 checks: result.checks.drain(..).map(|(_, check)| check).collect(),
                 context: None,
+                enumerations: result
+                    .enumerations
+                    .drain(..)
+                    .map(|(_, enumeration)| enumeration)
+                    .collect(),
             }),
-            Err(e) => Err(e.into()),
+            Err(e) => Err(error::ParseErrors::located(value, e).into()),
         }
     }
 }
 
+/// converts a parser error to a [error::Token], with its location in `source`
+fn located(source: &str, e: Error) -> error::Token {
+    error::ParseErrors {
+        errors: vec![error::ParseError::located(source, e)],
+    }
+    .into()
+}
+
 fn predicate(i: &str) -> IResult<&str, builder::Predicate, Error> {
     let (i, _) = space0(i)?;
     let (i, fact_name) = name(i)?;
@@ -407,7 +467,7 @@ impl Expr {
 }
 
 fn unary(i: &str) -> IResult<&str, Expr, Error> {
-    alt((unary_parens, unary_negate, unary_length))(i)
+    alt((unary_parens, unary_negate, unary_method))(i)
 }
 
 fn unary_negate(i: &str) -> IResult<&str, Expr, Error> {
@@ -436,18 +496,33 @@ fn unary_parens(i: &str) -> IResult<&str, Expr, Error> {
     ))
 }
 
-fn unary_length(i: &str) -> IResult<&str, Expr, Error> {
+/// methods without arguments, which can be chained: `$data.sha256().to_hex()`
+fn unary_method(i: &str) -> IResult<&str, Expr, Error<'_>> {
     let (i, _) = space0(i)?;
     let (i, value) = alt((map(term, Expr::Value), unary_parens))(i)?;
-    let (i, _) = space0(i)?;
-    let (i, _) = tag(".length()")(i)?;
+    let (i, methods) = many1(preceded(space0, unary_method_name))(i)?;
 
     Ok((
         i,
-        Expr::Unary(builder::Op::Unary(builder::Unary::Length), Box::new(value)),
+        methods.into_iter().fold(value, |value, method| {
+            Expr::Unary(builder::Op::Unary(method), Box::new(value))
+        }),
     ))
 }
 
+fn unary_method_name(i: &str) -> IResult<&str, builder::Unary, Error<'_>> {
+    use builder::Unary;
+
+    alt((
+        value(Unary::Length, tag(".length()")),
+        value(Unary::Sha256, tag(".sha256()")),
+        value(Unary::ToHex, tag(".to_hex()")),
+        value(Unary::FromHex, tag(".from_hex()")),
+        value(Unary::ToBase64, tag(".to_base64()")),
+        value(Unary::FromBase64, tag(".from_base64()")),
+    ))(i)
+}
+
 fn binary_op_0(i: &str) -> IResult<&str, builder::Binary, Error> {
     use builder::Binary;
     alt((value(Binary::And, tag("&&")), value(Binary::Or, tag("||"))))(i)
@@ -478,12 +553,17 @@ fn binary_op_4(i: &str) -> IResult<&str, builder::Binary, Error> {
     use builder::Binary;
 
     alt((
+        value(Binary::ContainsIp, tag("contains_ip")),
         value(Binary::Contains, tag("contains")),
         value(Binary::Prefix, tag("starts_with")),
         value(Binary::Suffix, tag("ends_with")),
         value(Binary::Regex, tag("matches")),
         value(Binary::Intersection, tag("intersection")),
         value(Binary::Union, tag("union")),
+        value(Binary::SemverGe, tag("semver_ge")),
+        value(Binary::SemverLt, tag("semver_lt")),
+        value(Binary::Take, tag("take")),
+        value(Binary::Skip, tag("skip")),
     ))(i)
 }
 
@@ -603,19 +683,12 @@ fn date(i: &str) -> IResult<&str, builder::Term, Error> {
     parse_date(i).map(|(i, t)| (i, builder::Term::Date(t)))
 }
 
+fn parse_hex(i: &str) -> IResult<&str, Vec<u8>, Error<'_>> {
+    map_res(take_while1(|c: char| c.is_ascii_hexdigit()), hex::decode)(i)
+}
+
 fn parse_bytes(i: &str) -> IResult<&str, Vec<u8>, Error> {
-    preceded(
-        tag("hex:"),
-        map_res(
-            take_while1(|c| {
-                let c = c as u8;
-                (b'0'..=b'9').contains(&c)
-                    || (b'a'..=b'f').contains(&c)
-                    || (b'A'..=b'F').contains(&c)
-            }),
-            hex::decode,
-        ),
-    )(i)
+    preceded(tag("hex:"), parse_hex)(i)
 }
 
 fn bytes(i: &str) -> IResult<&str, builder::Term, Error> {
@@ -733,15 +806,169 @@ fn multiline_comment(i: &str) -> IResult<&str, (), Error> {
     Ok((i, ()))
 }
 
+/// strings allowed in a term of a predicate, declared in Datalog source
+/// with `@operation = {read, write, delete}`
+///
+/// the declaration applies to the first term of the predicate, another
+/// term is selected with its position, starting at 0: `@right[1] = {read, write}`.
+/// The facts, rules, checks and policies of the source can only use the
+/// declared members in this term, as a string or a set of strings. A typo
+/// like `"raed"` is reported as a parse error instead of making a check fail
+/// silently.
+///
+/// Enumerations can also be added to a [BlockBuilder](builder::BlockBuilder),
+/// a [BiscuitBuilder](builder::BiscuitBuilder) or an
+/// [Authorizer](crate::Authorizer), to check the statements added after them.
+/// They are not stored in the token
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enumeration {
+    pub predicate: String,
+    /// position of the term in the predicate
+    pub position: usize,
+    pub members: BTreeSet<String>,
+}
+
+impl Enumeration {
+    pub fn new<I, S>(predicate: &str, position: usize, members: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Enumeration {
+            predicate: predicate.to_string(),
+            position,
+            members: members.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// strings of the predicate's term that are not members of the enumeration
+    pub fn invalid_members<'p>(&self, predicate: &'p builder::Predicate) -> Vec<&'p str> {
+        fn collect<'p>(
+            members: &BTreeSet<String>,
+            term: &'p builder::Term,
+            out: &mut Vec<&'p str>,
+        ) {
+            match term {
+                builder::Term::Str(s) if !members.contains(s) => out.push(s),
+                builder::Term::Set(set) => {
+                    for term in set.iter() {
+                        collect(members, term, out);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut invalid = Vec::new();
+        if predicate.name == self.predicate {
+            if let Some(term) = predicate.terms.get(self.position) {
+                collect(&self.members, term, &mut invalid);
+            }
+        }
+        invalid
+    }
+
+    /// describes a string that is not a member, with the closest member as suggestion
+    pub fn error_message(&self, s: &str) -> String {
+        let mut message = format!("\"{}\" is not a member of {}", s, self.declared_name());
+        if let Some(member) = closest_member(self, s) {
+            message.push_str(&format!(", did you mean \"{}\"?", member));
+        }
+        message
+    }
+
+    /// `@predicate`, with the position if it is not the first term
+    pub(crate) fn declared_name(&self) -> String {
+        if self.position == 0 {
+            format!("@{}", self.predicate)
+        } else {
+            format!("@{}[{}]", self.predicate, self.position)
+        }
+    }
+}
+
+impl fmt::Display for Enumeration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let members = self
+            .members
+            .iter()
+            .map(|member| match name(member) {
+                Ok(("", _)) => member.clone(),
+                _ => format!("\"{}\"", crate::datalog::escape_string(member)),
+            })
+            .collect::<Vec<_>>();
+
+        write!(f, "{} = {{{}}}", self.declared_name(), members.join(", "))
+    }
+}
+
+impl TryFrom<&str> for Enumeration {
+    type Error = error::Token;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        enumeration(value)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(value, e))
+    }
+}
+
+impl FromStr for Enumeration {
+    type Err = error::Token;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enumeration(s)
+            .finish()
+            .map(|(_, o)| o)
+            .map_err(|e| located(s, e))
+    }
+}
+
+fn enumeration(i: &str) -> IResult<&str, Enumeration, Error<'_>> {
+    let member = preceded(
+        space0,
+        error(
+            alt((parse_string, map(name, |n: &str| n.to_string()))),
+            |_| "expected a name or a string".to_string(),
+            " ,}\n;",
+        ),
+    );
+
+    let (i, _) = preceded(space0, char('@'))(i)?;
+    let (i, predicate) = cut(name)(i)?;
+    let (i, position) = opt(delimited(
+        char('['),
+        cut(map_res(delimited(space0, digit1, space0), |s: &str| {
+            s.parse::<usize>()
+        })),
+        cut(char(']')),
+    ))(i)?;
+    let (i, _) = cut(preceded(space0, char('=')))(i)?;
+    let (i, _) = cut(preceded(space0, char('{')))(i)?;
+    let (i, members) = cut(separated_list1(preceded(space0, char(',')), member))(i)?;
+    let (i, _) = cut(preceded(space0, char('}')))(i)?;
+
+    Ok((
+        i,
+        Enumeration {
+            predicate: predicate.to_string(),
+            position: position.unwrap_or(0),
+            members: members.into_iter().collect(),
+        },
+    ))
+}
+
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct SourceResult<'a> {
     pub facts: Vec<(&'a str, builder::Fact)>,
     pub rules: Vec<(&'a str, builder::Rule)>,
     pub checks: Vec<(&'a str, builder::Check)>,
     pub policies: Vec<(&'a str, builder::Policy)>,
+    pub enumerations: Vec<(&'a str, Enumeration)>,
 }
 
 enum SourceElement<'a> {
+    Enumeration(&'a str, Enumeration),
     Fact(&'a str, builder::Fact),
     Rule(&'a str, builder::Rule),
     Check(&'a str, builder::Check),
@@ -760,6 +987,7 @@ pub fn parse_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
 
     loop {
         if i.is_empty() {
+            check_enumerations(&result, &mut errors);
             if errors.is_empty() {
                 return Ok(result);
             } else {
@@ -769,6 +997,9 @@ pub fn parse_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
 
         match terminated(
             alt((
+                map(terminated(consumed(enumeration), sep), |(i, e)| {
+                    SourceElement::Enumeration(i, e)
+                }),
                 map(terminated(consumed(rule_inner), sep), |(i, r)| {
                     SourceElement::Rule(i, r)
                 }),
@@ -789,6 +1020,7 @@ pub fn parse_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
         {
             Ok((i2, o)) => {
                 match o {
+                    SourceElement::Enumeration(i, e) => result.enumerations.push((i, e)),
                     SourceElement::Fact(i, f) => result.facts.push((i, f)),
                     SourceElement::Rule(i, r) => result.rules.push((i, r)),
                     SourceElement::Check(i, c) => result.checks.push((i, c)),
@@ -800,12 +1032,12 @@ pub fn parse_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
             }
             Err(nom::Err::Incomplete(_)) => panic!(),
             Err(nom::Err::Error(mut e)) => {
-                if let Some(index) = e.input.find(|c| c == ';') {
+                if let Some(index) = e.input.find(';') {
                     e.input = &(e.input)[..index];
                 }
 
                 let offset = i.offset(e.input);
-                if let Some(index) = &i[offset..].find(|c| c == ';') {
+                if let Some(index) = &i[offset..].find(';') {
                     i = &i[offset + index + 1..];
                 } else {
                     i = &i[i.len()..];
@@ -814,12 +1046,12 @@ pub fn parse_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
                 errors.push(e);
             }
             Err(nom::Err::Failure(mut e)) => {
-                if let Some(index) = e.input.find(|c| c == ';') {
+                if let Some(index) = e.input.find(';') {
                     e.input = &(e.input)[..index];
                 }
 
                 let offset = i.offset(e.input);
-                if let Some(index) = &i[offset..].find(|c| c == ';') {
+                if let Some(index) = &i[offset..].find(';') {
                     i = &i[offset + index + 1..];
                 } else {
                     i = &i[i.len()..];
@@ -837,6 +1069,7 @@ pub fn parse_block_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
 
     loop {
         if i.is_empty() {
+            check_enumerations(&result, &mut errors);
             if errors.is_empty() {
                 return Ok(result);
             } else {
@@ -846,6 +1079,9 @@ pub fn parse_block_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
 
         match terminated(
             alt((
+                map(terminated(consumed(enumeration), sep), |(i, e)| {
+                    SourceElement::Enumeration(i, e)
+                }),
                 map(terminated(consumed(rule_inner), sep), |(i, r)| {
                     SourceElement::Rule(i, r)
                 }),
@@ -863,6 +1099,7 @@ pub fn parse_block_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
         {
             Ok((i2, o)) => {
                 match o {
+                    SourceElement::Enumeration(i, e) => result.enumerations.push((i, e)),
                     SourceElement::Fact(i, f) => result.facts.push((i, f)),
                     SourceElement::Rule(i, r) => result.rules.push((i, r)),
                     SourceElement::Check(i, c) => result.checks.push((i, c)),
@@ -874,12 +1111,12 @@ pub fn parse_block_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
             }
             Err(nom::Err::Incomplete(_)) => panic!(),
             Err(nom::Err::Error(mut e)) => {
-                if let Some(index) = e.input.find(|c| c == ';') {
+                if let Some(index) = e.input.find(';') {
                     e.input = &(e.input)[..index];
                 }
 
                 let offset = i.offset(e.input);
-                if let Some(index) = &i[offset..].find(|c| c == ';') {
+                if let Some(index) = &i[offset..].find(';') {
                     i = &i[offset + index + 1..];
                 } else {
                     i = &i[i.len()..];
@@ -888,12 +1125,12 @@ pub fn parse_block_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
                 errors.push(e);
             }
             Err(nom::Err::Failure(mut e)) => {
-                if let Some(index) = e.input.find(|c| c == ';') {
+                if let Some(index) = e.input.find(';') {
                     e.input = &(e.input)[..index];
                 }
 
                 let offset = i.offset(e.input);
-                if let Some(index) = &i[offset..].find(|c| c == ';') {
+                if let Some(index) = &i[offset..].find(';') {
                     i = &i[offset + index + 1..];
                 } else {
                     i = &i[i.len()..];
@@ -905,14 +1142,336 @@ pub fn parse_block_source(mut i: &str) -> Result<SourceResult, Vec<Error>> {
     }
 }
 
-#[derive(Error, Debug, PartialEq)]
-#[error("Parse error on input: {input}. Message: {message:?}")]
+/// reports the string terms of declared predicates that are not members of
+/// their enumeration, see [Enumeration]
+fn check_enumerations<'a>(result: &SourceResult<'a>, errors: &mut Vec<Error<'a>>) {
+    let mut declared: HashSet<_> = HashSet::default();
+    for (source, enumeration) in result.enumerations.iter() {
+        if !declared.insert((enumeration.predicate.as_str(), enumeration.position)) {
+            errors.push(Error {
+                input: source,
+                code: ErrorKind::Verify,
+                message: Some(format!("{} is declared twice", enumeration.declared_name())),
+            });
+        }
+    }
+
+    if declared.is_empty() {
+        return;
+    }
+
+    let statements = result
+        .facts
+        .iter()
+        .map(|(source, fact)| (*source, fact.predicates().collect::<Vec<_>>()))
+        .chain(
+            result
+                .rules
+                .iter()
+                .map(|(source, rule)| (*source, rule.predicates().collect())),
+        )
+        .chain(
+            result
+                .checks
+                .iter()
+                .map(|(source, check)| (*source, check.predicates().collect())),
+        )
+        .chain(
+            result
+                .policies
+                .iter()
+                .map(|(source, policy)| (*source, policy.predicates().collect())),
+        );
+
+    for (source, predicates) in statements {
+        for predicate in predicates {
+            for (_, enumeration) in result.enumerations.iter() {
+                for s in enumeration.invalid_members(predicate) {
+                    // points at the string if it is written as is in the source
+                    let quoted = format!("\"{}\"", s);
+                    let input = match source.find(&quoted) {
+                        Some(index) => &source[index..index + quoted.len()],
+                        None => source,
+                    };
+
+                    errors.push(Error {
+                        input,
+                        code: ErrorKind::Verify,
+                        message: Some(enumeration.error_message(s)),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// member at an edit distance of at most 2
+fn closest_member<'e>(enumeration: &'e Enumeration, s: &str) -> Option<&'e str> {
+    enumeration
+        .members
+        .iter()
+        .map(|member| (edit_distance(member, s), member))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, member)| member.as_str())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// parses Datalog source and prints it in the canonical format
+///
+/// statements are grouped by kind: enumerations come first, facts and rules
+/// are sorted, while checks and policies keep their order, since it changes
+/// the authorization result. Each statement is printed on one line, with the `or` alternatives of checks
+/// and policies on indented lines, and strings are escaped the same way.
+/// Comments stay attached to the statement following them, and the comments
+/// at the beginning of the source, followed by an empty line, stay in place.
+///
+/// formatting the output again does not change it
+///
+/// ```rust
+/// # use biscuit_auth::parser::format_source;
+/// let source = r#"allow if   user( $u ) ;
+/// // users
+/// user("bob"); user("alice");"#;
+///
+/// assert_eq!(
+///     format_source(source).unwrap(),
+///     "user(\"alice\");\n// users\nuser(\"bob\");\n\nallow if user($u);\n"
+/// );
+/// ```
+pub fn format_source(input: &str) -> Result<String, error::ParseErrors> {
+    // reports all the errors with their location
+    parse_source(input).map_err(|e| error::ParseErrors::located(input, e))?;
+
+    let mut header: Vec<String> = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+    // enumerations, facts, rules, checks and policies
+    let mut groups: [Vec<String>; 5] = Default::default();
+    let mut seen_statement = false;
+    let mut i = input.trim_start();
+
+    while !i.is_empty() {
+        let (rest, (group, text)) = alt((
+            map(recognize(line_comment), |c: &str| {
+                (None, c.trim().to_string())
+            }),
+            map(recognize(multiline_comment), |c: &str| {
+                (None, c.trim().to_string())
+            }),
+            map(terminated(enumeration, sep), |e| (Some(0), e.to_string())),
+            map(terminated(consumed(rule_inner), sep), |(source, r)| {
+                (Some(2), print_rule(source, &r))
+            }),
+            map(terminated(consumed(fact_inner), sep), |(_, f)| {
+                (Some(1), f.to_string())
+            }),
+            map(terminated(consumed(check_inner), sep), |(source, c)| {
+                (Some(3), print_check(source, &c))
+            }),
+            map(terminated(consumed(policy_inner), sep), |(source, p)| {
+                (Some(4), print_policy(source, &p))
+            }),
+        ))(i)
+        .map_err(|e| match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => {
+                error::ParseErrors::located(input, vec![e])
+            }
+            nom::Err::Incomplete(_) => error::ParseErrors::located(input, vec![]),
+        })?;
+
+        let trimmed = rest.trim_start();
+        let gap = &rest[..rest.len() - trimmed.len()];
+        i = trimmed;
+
+        match group {
+            None => {
+                // a line comment already consumed its line break
+                let line_breaks =
+                    gap.matches('\n').count() + if text.starts_with("//") { 1 } else { 0 };
+                comments.push(text);
+                if !seen_statement && line_breaks > 1 {
+                    header.append(&mut comments);
+                }
+            }
+            Some(group) => {
+                seen_statement = true;
+                let mut statement = core::mem::take(&mut comments).join("\n");
+                if !statement.is_empty() {
+                    statement.push('\n');
+                }
+                statement.push_str(&text);
+                statement.push(';');
+                groups[group].push(statement);
+            }
+        }
+    }
+
+    for group in groups[1..3].iter_mut() {
+        // sorts on the statement, after the comments
+        group.sort_by(|a, b| {
+            a.rsplit('\n')
+                .next()
+                .cmp(&b.rsplit('\n').next())
+                .then_with(|| a.cmp(b))
+        });
+    }
+
+    let mut sections = Vec::new();
+    if !header.is_empty() {
+        sections.push(header.join("\n"));
+    }
+    sections.extend(
+        groups
+            .iter()
+            .filter(|group| !group.is_empty())
+            .map(|group| group.join("\n")),
+    );
+    if !comments.is_empty() {
+        sections.push(comments.join("\n"));
+    }
+
+    if sections.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(sections.join("\n\n") + "\n")
+    }
+}
+
+fn print_rule(source: &str, rule: &builder::Rule) -> String {
+    match print_body(rule) {
+        Some(body) => format!("{} <- {}", rule.head, body),
+        None => source.trim().to_string(),
+    }
+}
+
+fn print_check(source: &str, check: &builder::Check) -> String {
+    match print_queries(&check.queries) {
+        Some(queries) => format!("check if {}", queries),
+        None => source.trim().to_string(),
+    }
+}
+
+fn print_policy(source: &str, policy: &builder::Policy) -> String {
+    let kind = match policy.kind {
+        builder::PolicyKind::Allow => "allow",
+        builder::PolicyKind::Deny => "deny",
+    };
+
+    match print_queries(&policy.queries) {
+        Some(queries) => format!("{} if {}", kind, queries),
+        None => source.trim().to_string(),
+    }
+}
+
+fn print_queries(queries: &[builder::Rule]) -> Option<String> {
+    Some(
+        queries
+            .iter()
+            .map(print_body)
+            .collect::<Option<Vec<_>>>()?
+            .join("\n  or "),
+    )
+}
+
+/// prints the body of a rule, without evaluating constant expressions like
+/// the `Display` implementation does. Expressions containing parameters
+/// cannot be printed, the source is kept for them
+fn print_body(rule: &builder::Rule) -> Option<String> {
+    let mut symbols = crate::token::default_symbol_table();
+    let mut elements = rule.body.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+
+    for expression in rule.expressions.iter() {
+        if expression.ops.iter().any(|op| match op {
+            builder::Op::Value(term) => has_parameter(term),
+            _ => false,
+        }) {
+            return None;
+        }
+
+        let expression = crate::datalog::Expression {
+            ops: expression
+                .ops
+                .iter()
+                .map(|op| op.convert(&mut symbols))
+                .collect(),
+        };
+        elements.push(expression.print(&symbols)?);
+    }
+
+    let mut body = elements.join(", ");
+    if !rule.trusted_keys.is_empty() {
+        let keys = rule
+            .trusted_keys
+            .iter()
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>();
+        body.push_str(" trusting ");
+        body.push_str(&keys.join(", "));
+    }
+
+    Some(body)
+}
+
+fn has_parameter(term: &builder::Term) -> bool {
+    match term {
+        builder::Term::Parameter(_) => true,
+        builder::Term::Set(set) => set.iter().any(has_parameter),
+        _ => false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Error<'a> {
     pub input: &'a str,
     pub code: ErrorKind,
     pub message: Option<String>,
 }
 
+impl<'a> fmt::Display for Error<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Parse error on input: {}. Message: {:?}",
+            self.input, self.message
+        )
+    }
+}
+
+impl<'a> core::error::Error for Error<'a> {}
+
+impl<'a> Error<'a> {
+    /// describes what the parser expected where it failed
+    pub fn expected(&self) -> Option<String> {
+        let expected = match self.code {
+            ErrorKind::Char | ErrorKind::Tag => "a keyword or punctuation",
+            ErrorKind::Digit => "a number",
+            ErrorKind::Alpha | ErrorKind::AlphaNumeric | ErrorKind::TakeWhile1 => "a name",
+            ErrorKind::Eof => "the end of the input",
+            ErrorKind::TakeUntil => "a closing delimiter",
+            ErrorKind::Escaped | ErrorKind::EscapedTransform => "a string",
+            ErrorKind::Alt => "a term, fact, rule, check or policy",
+            _ => return None,
+        };
+
+        Some(expected.to_string())
+    }
+}
+
 impl<'a> ParseError<&'a str> for Error<'a> {
     fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
         Self {
@@ -1219,6 +1778,59 @@ mod tests {
             ))
         );
 
+        assert_eq!(
+            super::expr("\"10.0.0.0/8\".contains_ip($0)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(string("10.0.0.0/8")),
+                    Op::Value(var("0")),
+                    Op::Binary(Binary::ContainsIp),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0.semver_ge(\"2.3.0\")").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(string("2.3.0")),
+                    Op::Binary(Binary::SemverGe),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("$0.sha256().to_hex() == \"abc\"").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Unary(Unary::Sha256),
+                    Op::Unary(Unary::ToHex),
+                    Op::Value(string("abc")),
+                    Op::Binary(Binary::Equal),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("($0.skip(2)).take(4)").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("0")),
+                    Op::Value(int(2)),
+                    Op::Binary(Binary::Skip),
+                    Op::Unary(Unary::Parens),
+                    Op::Value(int(4)),
+                    Op::Binary(Binary::Take),
+                ],
+            ))
+        );
+
         let h = [string("abc"), string("def")]
             .iter()
             .cloned()
@@ -1421,6 +2033,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn check_trusting() {
+        let key = crate::crypto::KeyPair::new().public();
+        let input = format!(
+            "check if right($0, \"read\") trusting {} or admin(\"authority\")",
+            key
+        );
+
+        let (remaining, check) = super::check(&input).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(check.queries.len(), 2);
+        assert_eq!(check.queries[0].trusted_keys, vec![key]);
+        assert!(check.queries[1].trusted_keys.is_empty());
+        assert_eq!(check.to_string(), input);
+    }
+
     #[test]
     fn invalid_check() {
         assert_eq!(
@@ -1893,4 +2521,132 @@ mod tests {
             expected_checks
         );
     }
+
+    #[test]
+    fn error_location() {
+        use crate::error;
+        use std::convert::TryFrom;
+
+        let e = builder::Rule::try_from("head($a) <- body($a), ").unwrap_err();
+        let errors = match e {
+            error::Token::Language(error::LanguageError::ParseError(errors)) => errors.errors,
+            e => panic!("unexpected error: {:?}", e),
+        };
+        assert_eq!(errors.len(), 1);
+        let location = errors[0].location.unwrap();
+        assert_eq!(location.line, 1);
+        assert_eq!(location.column, location.offset + 1);
+
+        let mut block = builder::BlockBuilder::new();
+        let e = block
+            .add_code("fact(\"a\");\ncheck if fact(\"a\");\nfact(\"b\" ;")
+            .unwrap_err();
+        let errors = match e {
+            error::Token::Language(error::LanguageError::ParseError(errors)) => errors.errors,
+            e => panic!("unexpected error: {:?}", e),
+        };
+        let location = errors[0].location.unwrap();
+        assert_eq!(location.line, 3);
+        assert_eq!(error::SourceLocation::find("abc", "def"), None,);
+    }
+
+    #[test]
+    fn format_source() {
+        let source = r#"// policies for the file service
+
+check if operation($op),["read","write"].contains($op)   or admin(true);
+allow if  time($t), $t < 2022-01-01T00:00:00Z, 3600 * 24 > 0 ;
+right($f, "read") <-   owner($u,$f) ;
+/* escaped */ name("a \"quoted\" \\ name");
+  // first resource
+resource("file2"); resource("file1");
+// end"#;
+
+        let formatted = super::format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            r#"// policies for the file service
+
+/* escaped */
+name("a \"quoted\" \\ name");
+resource("file1");
+// first resource
+resource("file2");
+
+right($f, "read") <- owner($u, $f);
+
+check if operation($op), ["read", "write"].contains($op)
+  or admin(true);
+
+allow if time($t), $t < 2022-01-01T00:00:00Z, 3600 * 24 > 0;
+
+// end
+"#
+        );
+        assert_eq!(super::format_source(&formatted).unwrap(), formatted);
+
+        assert!(super::format_source("fact(;").is_err());
+        assert_eq!(super::format_source("").unwrap(), "");
+    }
+
+    #[test]
+    fn enumerations() {
+        use crate::error;
+
+        let source = r#"
+            @operation = {read, write, "list files"};
+            operation("read");
+            right($f, $op) <- owner($f), operation($op);
+            check if operation("write") or operation(["read", "list files"]);
+        "#;
+        let result = super::parse_source(source).unwrap();
+        assert_eq!(
+            result.enumerations[0].1.to_string(),
+            "@operation = {\"list files\", read, write}"
+        );
+
+        let mut block = builder::BlockBuilder::new();
+        let e = block
+            .add_code("@operation = {read, write};\ncheck if operation(\"raed\");")
+            .unwrap_err();
+        let errors = match e {
+            error::Token::Language(error::LanguageError::ParseError(errors)) => errors.errors,
+            e => panic!("unexpected error: {:?}", e),
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message.as_deref(),
+            Some("\"raed\" is not a member of @operation, did you mean \"read\"?")
+        );
+        let location = errors[0].location.unwrap();
+        assert_eq!((location.line, location.column), (2, 20));
+
+        // only the declared term is checked
+        let source = r#"
+            @right[1] = {read, write};
+            right("/file", "read");
+            check if right("/other", ["read", "write"]);
+        "#;
+        let result = super::parse_source(source).unwrap();
+        assert_eq!(
+            result.enumerations[0].1.to_string(),
+            "@right[1] = {read, write}"
+        );
+        assert!(
+            super::parse_source("@operation = {read};\noperation(\"read\", \"/file\");").is_ok()
+        );
+        let e = super::parse_source("@right[1] = {read};\nright(\"read\", \"raed\");").unwrap_err();
+        assert_eq!(
+            e[0].message.as_deref(),
+            Some("\"raed\" is not a member of @right[1], did you mean \"read\"?")
+        );
+
+        assert!(super::parse_source("@a = {x}; @a = {y};").is_err());
+        assert!(super::parse_source("@a = {x}; @a[1] = {y};").is_ok());
+        assert!(super::parse_source("@operation = {};").is_err());
+        assert_eq!(
+            super::format_source("fact(true); @operation = {write, read};").unwrap(),
+            "@operation = {read, write};\n\nfact(true);\n"
+        );
+    }
 }
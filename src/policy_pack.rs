@@ -0,0 +1,243 @@
+//! versioned bundles of checks and policies
+//!
+//! a [PolicyPack] groups named checks and policies under a name and a
+//! semantic version, so they can be distributed to multiple services and
+//! loaded with [Authorizer::add_policy_pack](crate::Authorizer::add_policy_pack).
+//! With the `serde` feature, packs can be stored as JSON or any other format
+//! supported by serde, with the Datalog source of each check and policy
+//!
+//! ```rust
+//! # use biscuit_auth::Authorizer;
+//! # use biscuit_auth::policy_pack::PolicyPack;
+//! let mut pack = PolicyPack::new("platform", "1.4.0").unwrap();
+//! pack.add_check("operation", "check if operation($op), [\"read\", \"write\"].contains($op)")
+//!     .unwrap();
+//! pack.add_policy("users", "allow if user($u)").unwrap();
+//!
+//! // the service was written for version 1.2 of the pack
+//! pack.check_compatible("1.2").unwrap();
+//!
+//! let mut authorizer = Authorizer::new().unwrap();
+//! authorizer.add_code("user(\"alice\"); operation(\"read\");").unwrap();
+//! authorizer.add_policy_pack(&pack).unwrap();
+//! assert_eq!(authorizer.authorize(), Ok(0));
+//! ```
+use crate::builder::{Check, Policy};
+use crate::datalog::semver_cmp;
+use crate::error;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::convert::TryInto;
+
+/// a named check or policy, with its Datalog source
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackItem {
+    pub name: String,
+    pub source: String,
+}
+
+/// checks and policies with a name and a version, see the [module documentation](self)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolicyPack {
+    pub name: String,
+    pub version: String,
+    pub checks: Vec<PackItem>,
+    pub policies: Vec<PackItem>,
+}
+
+impl PolicyPack {
+    /// creates an empty pack, the version must follow semantic versioning
+    pub fn new(name: &str, version: &str) -> Result<Self, error::Token> {
+        let pack = PolicyPack {
+            name: name.to_string(),
+            version: version.to_string(),
+            checks: Vec::new(),
+            policies: Vec::new(),
+        };
+        pack.parse_version()?;
+
+        Ok(pack)
+    }
+
+    /// adds a check, the source is validated
+    pub fn add_check(&mut self, name: &str, source: &str) -> Result<(), error::Token> {
+        let _: Check = source.try_into()?;
+        self.checks.push(PackItem {
+            name: name.to_string(),
+            source: source.to_string(),
+        });
+        Ok(())
+    }
+
+    /// adds an allow or deny policy, the source is validated
+    pub fn add_policy(&mut self, name: &str, source: &str) -> Result<(), error::Token> {
+        let _: Policy = source.try_into()?;
+        self.policies.push(PackItem {
+            name: name.to_string(),
+            source: source.to_string(),
+        });
+        Ok(())
+    }
+
+    /// indicates if the pack can be used by a service written for the
+    /// `requirement` version
+    ///
+    /// like cargo's default requirements, the pack must have the same major
+    /// version, or the same minor version for `0.x` versions, and be greater
+    /// than or equal to the requirement
+    pub fn is_compatible(&self, requirement: &str) -> Result<bool, error::Token> {
+        let version = self.parse_version()?;
+        let required = major_minor(requirement)
+            .ok_or_else(|| error::PolicyPack::InvalidVersion(requirement.to_string()))?;
+
+        let same_series = if required.0 == 0 {
+            version == required
+        } else {
+            version.0 == required.0
+        };
+
+        Ok(same_series && semver_cmp(&self.version, requirement) != Some(Ordering::Less))
+    }
+
+    /// returns an error if the pack is not compatible with `requirement`,
+    /// see [PolicyPack::is_compatible]
+    pub fn check_compatible(&self, requirement: &str) -> Result<(), error::Token> {
+        if self.is_compatible(requirement)? {
+            Ok(())
+        } else {
+            Err(error::PolicyPack::Incompatible {
+                name: self.name.clone(),
+                version: self.version.clone(),
+                requirement: requirement.to_string(),
+            }
+            .into())
+        }
+    }
+
+    /// parses the checks and policies, with their sources
+    pub(crate) fn parse(&self) -> Result<ParsedPolicyPack, error::Token> {
+        self.parse_version()?;
+
+        let checks = self
+            .checks
+            .iter()
+            .map(|item| {
+                let check: Check = item.source.as_str().try_into()?;
+                Ok((item.source.trim().to_string(), check))
+            })
+            .collect::<Result<Vec<_>, error::Token>>()?;
+        let policies = self
+            .policies
+            .iter()
+            .map(|item| item.source.as_str().try_into())
+            .collect::<Result<Vec<Policy>, error::Token>>()?;
+
+        Ok((checks, policies))
+    }
+
+    fn parse_version(&self) -> Result<(u64, u64), error::Token> {
+        match (
+            major_minor(&self.version),
+            semver_cmp(&self.version, &self.version),
+        ) {
+            (Some(version), Some(_)) => Ok(version),
+            _ => Err(error::PolicyPack::InvalidVersion(self.version.clone()).into()),
+        }
+    }
+}
+
+/// checks with their source code, and policies, of a [PolicyPack]
+pub(crate) type ParsedPolicyPack = (Vec<(String, Check)>, Vec<Policy>);
+
+fn major_minor(version: &str) -> Option<(u64, u64)> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let mut parts = version.split(['.', '-', '+']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map(|p| p.parse().ok()).unwrap_or(Some(0))?;
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Authorizer, AuthorizerLimits};
+
+    #[test]
+    fn compatibility() {
+        let pack = |version: &str| PolicyPack::new("pack", version).unwrap();
+
+        assert!(pack("1.4.2").is_compatible("1.2").unwrap());
+        assert!(pack("1.4.2").is_compatible("1.4.2").unwrap());
+        assert!(!pack("1.4.2").is_compatible("1.5").unwrap());
+        assert!(!pack("2.0.0").is_compatible("1.2").unwrap());
+        assert!(!pack("0.3.0").is_compatible("0.2.1").unwrap());
+        assert!(pack("0.3.1").is_compatible("0.3").unwrap());
+        assert!(PolicyPack::new("pack", "latest").is_err());
+        assert!(pack("1.0.0").is_compatible("x").is_err());
+        assert_eq!(
+            pack("2.0.0").check_compatible("1.0"),
+            Err(error::Token::PolicyPack(error::PolicyPack::Incompatible {
+                name: "pack".to_string(),
+                version: "2.0.0".to_string(),
+                requirement: "1.0".to_string(),
+            }))
+        );
+
+        let mut pack = pack("1.0.0");
+        assert!(pack.add_check("invalid", "check if").is_err());
+        pack.add_check("resource", "check if resource($r)").unwrap();
+        pack.add_policy("default", "deny if true").unwrap();
+        pack.policies.push(PackItem {
+            name: "broken".to_string(),
+            source: "allow".to_string(),
+        });
+
+        // nothing is added if the pack is invalid
+        let mut authorizer = Authorizer::new().unwrap();
+        assert!(authorizer.add_policy_pack(&pack).is_err());
+        pack.policies.pop();
+        authorizer.add_policy_pack(&pack).unwrap();
+        assert!(authorizer.authorize().is_err());
+        assert_eq!(authorizer.dump().2.len(), 1);
+    }
+
+    #[test]
+    fn dry_run() {
+        let mut active = PolicyPack::new("pack", "1.0.0").unwrap();
+        active.add_check("user", "check if user($u)").unwrap();
+        active
+            .add_policy("read", "allow if operation(\"read\")")
+            .unwrap();
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.add_policy_pack(&active).unwrap();
+        authorizer.set_tenant("acme");
+        authorizer
+            .add_code("user(\"alice\"); operation(\"write\"); tenant(\"acme\");")
+            .unwrap();
+
+        let mut proposed = PolicyPack::new("pack", "1.1.0").unwrap();
+        proposed
+            .add_policy("write", "allow if operation(\"write\")")
+            .unwrap();
+
+        let report = authorizer
+            .dry_run(&proposed, AuthorizerLimits::default())
+            .unwrap();
+        assert!(report.active.is_err());
+        assert_eq!(report.proposed, Ok(0));
+        assert!(report.differs());
+
+        // the authorizer is not modified by the dry run
+        assert_eq!(authorizer.dump().2.len(), 2);
+
+        // the pack is replaced in place, the tenant check is kept
+        authorizer.add_policy_pack(&proposed).unwrap();
+        assert_eq!(authorizer.dump().2.len(), 1);
+        authorizer.set_tenant("other");
+        assert!(authorizer.authorize().is_err());
+    }
+}
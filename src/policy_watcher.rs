@@ -0,0 +1,269 @@
+//! policies reloaded without restarting the service
+//!
+//! a [PolicyWatcher] reads Datalog code from a file, or from any other
+//! source, and builds an [AuthorizerPool] from it, on top of a base authorizer
+//! holding the facts, rules and external predicates defined by the service.
+//! The request handlers share the pool through a [SharedAuthorizerPool],
+//! which is replaced atomically when the code changes: authorizations in
+//! progress finish with the previous policies.
+//!
+//! Code that cannot be read, parsed or run is rejected: the pool keeps the
+//! previous policies, and the callback set with [PolicyWatcher::on_error]
+//! is called
+//!
+//! ```rust
+//! # use biscuit_auth::{Authorizer, AuthorizerLimits, Biscuit, KeyPair};
+//! # use biscuit_auth::policy_watcher::PolicyWatcher;
+//! let path = std::env::temp_dir().join("biscuit-policy-watcher-example.datalog");
+//! std::fs::write(&path, "allow if user(\"alice\")").unwrap();
+//!
+//! let mut watcher =
+//!     PolicyWatcher::from_file(Authorizer::new().unwrap(), AuthorizerLimits::default(), &path)
+//!         .unwrap()
+//!         .on_error(|e| eprintln!("invalid policies: {}", e));
+//! let pool = watcher.pool();
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_authority_fact("user(\"bob\")").unwrap();
+//! let token = builder.build().unwrap();
+//! assert!(pool.authorize(&token).is_err());
+//!
+//! std::fs::write(&path, "allow if user($u)").unwrap();
+//! assert_eq!(watcher.reload(), Ok(true));
+//! assert_eq!(pool.authorize(&token), Ok(0));
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+use crate::builder::Fact;
+use crate::error;
+use crate::{Authorizer, AuthorizerLimits, AuthorizerPool, Biscuit};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// authorizer pool replaced atomically by a [PolicyWatcher]
+#[derive(Clone)]
+pub struct SharedAuthorizerPool {
+    pool: Arc<RwLock<Arc<AuthorizerPool>>>,
+}
+
+impl SharedAuthorizerPool {
+    pub fn new(pool: AuthorizerPool) -> Self {
+        SharedAuthorizerPool {
+            pool: Arc::new(RwLock::new(Arc::new(pool))),
+        }
+    }
+
+    /// replaces the pool for all the clones
+    pub fn replace(&self, pool: AuthorizerPool) {
+        *self.pool.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(pool);
+    }
+
+    /// current pool, not modified by later replacements
+    pub fn snapshot(&self) -> Arc<AuthorizerPool> {
+        self.pool.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// authorizes a token with the current pool, see [AuthorizerPool::authorize]
+    pub fn authorize(&self, token: &Biscuit) -> Result<usize, error::Token> {
+        self.snapshot().authorize(token)
+    }
+
+    /// see [AuthorizerPool::authorize_with_facts]
+    pub fn authorize_with_facts(
+        &self,
+        token: &Biscuit,
+        facts: &[Fact],
+    ) -> Result<usize, error::Token> {
+        self.snapshot().authorize_with_facts(token, facts)
+    }
+}
+
+type Source = Box<dyn FnMut() -> Result<String, error::Cause> + Send>;
+type ErrorCallback = Box<dyn FnMut(&error::Token) + Send>;
+
+/// loads Datalog code into a [SharedAuthorizerPool], see the [module documentation](self)
+pub struct PolicyWatcher {
+    source: Source,
+    base: Authorizer<'static>,
+    limits: AuthorizerLimits,
+    code: String,
+    pool: SharedAuthorizerPool,
+    on_error: Option<ErrorCallback>,
+}
+
+impl PolicyWatcher {
+    /// loads the code returned by `source` into a copy of `base`
+    ///
+    /// this fails if the first version of the code is invalid. `base` must
+    /// not contain a token, and `limits` are used by the pool
+    pub fn new<S>(
+        base: Authorizer<'static>,
+        limits: AuthorizerLimits,
+        mut source: S,
+    ) -> Result<Self, error::Token>
+    where
+        S: FnMut() -> Result<String, error::Cause> + Send + 'static,
+    {
+        let code = source().map_err(error::Token::PolicySource)?;
+        let pool = SharedAuthorizerPool::new(build(&base, &limits, &code)?);
+
+        Ok(PolicyWatcher {
+            source: Box::new(source),
+            base,
+            limits,
+            code,
+            pool,
+            on_error: None,
+        })
+    }
+
+    /// reads the code from a file
+    pub fn from_file<P: Into<PathBuf>>(
+        base: Authorizer<'static>,
+        limits: AuthorizerLimits,
+        path: P,
+    ) -> Result<Self, error::Token> {
+        let path = path.into();
+        PolicyWatcher::from_reader(base, limits, move || std::fs::File::open(&path))
+    }
+
+    /// reads the code from the readers returned by `open`
+    pub fn from_reader<F, R>(
+        base: Authorizer<'static>,
+        limits: AuthorizerLimits,
+        mut open: F,
+    ) -> Result<Self, error::Token>
+    where
+        F: FnMut() -> std::io::Result<R> + Send + 'static,
+        R: Read,
+    {
+        PolicyWatcher::new(base, limits, move || {
+            let mut code = String::new();
+            open()
+                .and_then(|mut reader| reader.read_to_string(&mut code))
+                .map_err(error::Cause::from_error)?;
+            Ok(code)
+        })
+    }
+
+    /// calls `f` when a reload fails
+    pub fn on_error<F: FnMut(&error::Token) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// the pool updated by the watcher
+    pub fn pool(&self) -> SharedAuthorizerPool {
+        self.pool.clone()
+    }
+
+    /// code of the current pool
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// reads the code, and replaces the pool if the code changed
+    ///
+    /// returns `Ok(false)` if the code did not change. On error, the pool
+    /// keeps the previous policies
+    pub fn reload(&mut self) -> Result<bool, error::Token> {
+        let result = self.try_reload();
+        if let (Err(e), Some(on_error)) = (result.as_ref(), self.on_error.as_mut()) {
+            on_error(e);
+        }
+        result
+    }
+
+    fn try_reload(&mut self) -> Result<bool, error::Token> {
+        let code = (self.source)().map_err(error::Token::PolicySource)?;
+        if code == self.code {
+            return Ok(false);
+        }
+
+        self.pool.replace(build(&self.base, &self.limits, &code)?);
+        self.code = code;
+        Ok(true)
+    }
+
+    /// reloads the code each time `changes` yields, until it ends
+    ///
+    /// `changes` can be the receiving end of a channel fed by a file system
+    /// notification library. Errors are passed to the [PolicyWatcher::on_error]
+    /// callback
+    pub fn watch<I: IntoIterator>(mut self, changes: I) {
+        for _ in changes {
+            let _ = self.reload();
+        }
+    }
+
+    /// reloads the code on a tokio task, after each `period`
+    ///
+    /// errors are passed to the [PolicyWatcher::on_error] callback. The source
+    /// is read on the task, so it should not block for long
+    #[cfg(feature = "tokio-integration")]
+    pub fn spawn(mut self, period: std::time::Duration) -> ::tokio::task::JoinHandle<()> {
+        ::tokio::spawn(async move {
+            let mut interval = ::tokio::time::interval(period);
+            // the first tick completes immediately, the code was loaded by `new`
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let _ = self.reload();
+            }
+        })
+    }
+}
+
+fn build(
+    base: &Authorizer<'static>,
+    limits: &AuthorizerLimits,
+    code: &str,
+) -> Result<AuthorizerPool, error::Token> {
+    let mut authorizer = base.clone();
+    authorizer.add_code(code)?;
+    AuthorizerPool::with_limits(authorizer, limits.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+    use std::sync::Mutex;
+
+    #[test]
+    fn reload() {
+        let code = Arc::new(Mutex::new("allow if user(\"alice\")".to_string()));
+        let errors = Arc::new(Mutex::new(Vec::new()));
+
+        let source = code.clone();
+        let failures = errors.clone();
+        let mut watcher = PolicyWatcher::new(
+            Authorizer::new().unwrap(),
+            AuthorizerLimits::default(),
+            move || Ok(source.lock().unwrap().clone()),
+        )
+        .unwrap()
+        .on_error(move |e| failures.lock().unwrap().push(e.clone()));
+        let pool = watcher.pool();
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"alice\")").unwrap();
+        let token = builder.build().unwrap();
+
+        assert_eq!(pool.authorize(&token), Ok(0));
+        assert_eq!(watcher.reload(), Ok(false));
+
+        // the previous policies are kept
+        *code.lock().unwrap() = "deny if".to_string();
+        assert!(watcher.reload().is_err());
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert_eq!(pool.authorize(&token), Ok(0));
+
+        *code.lock().unwrap() = "deny if user(\"alice\")".to_string();
+        assert_eq!(watcher.reload(), Ok(true));
+        assert!(pool.authorize(&token).is_err());
+        assert_eq!(watcher.code(), "deny if user(\"alice\")");
+    }
+}
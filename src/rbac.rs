@@ -0,0 +1,211 @@
+//! role based access control, with the `rbac` feature
+//!
+//! an [Rbac] definition lists the permissions of each role and the roles
+//! of each user. [Rbac::apply] adds them to an authorizer as facts:
+//! * `role_permission($role, $permission)`
+//! * `user_role($user, $role)`
+//!
+//! and the rule `permission($user, $permission) <- user_role($user, $role), role_permission($role, $permission)`,
+//! that policies and checks can use with the user from the token
+//!
+//! ```rust
+//! # use biscuit_auth::{Authorizer, Biscuit, KeyPair};
+//! # use biscuit_auth::rbac::{self, Rbac};
+//! let mut rbac = Rbac::new();
+//! rbac.add_role("reader", &["read"]);
+//! rbac.add_role("editor", &["read", "write"]);
+//! rbac.assign("alice", "editor");
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.add_authority_fact("user(\"alice\")").unwrap();
+//! let token = builder.build().unwrap();
+//!
+//! let mut authorizer = token.authorizer().unwrap();
+//! rbac.apply(&mut authorizer).unwrap();
+//! authorizer.add_code(r#"
+//!     operation("write");
+//!     allow if user($user), operation($op), permission($user, $op);
+//! "#).unwrap();
+//! assert!(authorizer.authorize().is_ok());
+//!
+//! let permissions = rbac::effective_permissions(&mut authorizer, "alice").unwrap();
+//! assert_eq!(permissions.len(), 2);
+//! ```
+use crate::builder::{fact, pred, rule, string, var, Fact, Rule};
+use crate::error;
+use crate::Authorizer;
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// a role and the permissions it grants
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Role {
+    pub name: String,
+    pub permissions: BTreeSet<String>,
+}
+
+/// a user and the roles assigned to them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct User {
+    pub name: String,
+    pub roles: BTreeSet<String>,
+}
+
+/// roles and their assignment to users, see the [module documentation](self)
+///
+/// with the `serde` feature, it can be loaded from a configuration file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rbac {
+    pub roles: Vec<Role>,
+    pub users: Vec<User>,
+}
+
+impl Rbac {
+    pub fn new() -> Self {
+        Rbac::default()
+    }
+
+    /// adds permissions to a role, creating it if needed
+    pub fn add_role(&mut self, role: &str, permissions: &[&str]) {
+        let index = match self.roles.iter().position(|r| r.name == role) {
+            Some(index) => index,
+            None => {
+                self.roles.push(Role {
+                    name: role.to_string(),
+                    permissions: BTreeSet::new(),
+                });
+                self.roles.len() - 1
+            }
+        };
+
+        self.roles[index]
+            .permissions
+            .extend(permissions.iter().map(|p| p.to_string()));
+    }
+
+    /// assigns a role to a user
+    pub fn assign(&mut self, user: &str, role: &str) {
+        match self.users.iter_mut().find(|u| u.name == user) {
+            Some(u) => {
+                u.roles.insert(role.to_string());
+            }
+            None => self.users.push(User {
+                name: user.to_string(),
+                roles: [role.to_string()].iter().cloned().collect(),
+            }),
+        }
+    }
+
+    /// permissions granted to a user by all their roles, without an authorizer
+    pub fn effective_permissions(&self, user: &str) -> BTreeSet<String> {
+        let roles = self
+            .users
+            .iter()
+            .filter(|u| u.name == user)
+            .flat_map(|u| u.roles.iter())
+            .collect::<BTreeSet<_>>();
+
+        self.roles
+            .iter()
+            .filter(|r| roles.contains(&r.name))
+            .flat_map(|r| r.permissions.iter().cloned())
+            .collect()
+    }
+
+    /// `role_permission` and `user_role` facts
+    pub fn facts(&self) -> Vec<Fact> {
+        let mut facts = Vec::new();
+
+        for role in self.roles.iter() {
+            for permission in role.permissions.iter() {
+                facts.push(fact(
+                    "role_permission",
+                    &[string(&role.name), string(permission)],
+                ));
+            }
+        }
+        for user in self.users.iter() {
+            for role in user.roles.iter() {
+                facts.push(fact("user_role", &[string(&user.name), string(role)]));
+            }
+        }
+
+        facts
+    }
+
+    /// the rule generating `permission($user, $permission)` facts
+    pub fn permission_rule() -> Rule {
+        rule(
+            "permission",
+            &[var("user"), var("permission")],
+            &[
+                pred("user_role", &[var("user"), var("role")]),
+                pred("role_permission", &[var("role"), var("permission")]),
+            ],
+        )
+    }
+
+    /// adds the facts and the permission rule to the authorizer
+    pub fn apply(&self, authorizer: &mut Authorizer) -> Result<(), error::Token> {
+        for fact in self.facts() {
+            authorizer.add_fact(fact)?;
+        }
+        authorizer.add_rule(Rbac::permission_rule())
+    }
+}
+
+/// queries the permissions of a user in an authorizer where [Rbac::apply] was called
+///
+/// this includes the `permission` facts from the token and the authorizer
+pub fn effective_permissions(
+    authorizer: &mut Authorizer,
+    user: &str,
+) -> Result<BTreeSet<String>, error::Token> {
+    let permissions: Vec<(String,)> = authorizer.query(rule(
+        "data",
+        &[var("permission")],
+        &[pred("permission", &[string(user), var("permission")])],
+    ))?;
+
+    Ok(permissions.into_iter().map(|(p,)| p).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permissions() {
+        let mut rbac = Rbac::new();
+        rbac.add_role("reader", &["read"]);
+        rbac.add_role("editor", &["read", "write"]);
+        rbac.add_role("editor", &["delete"]);
+        rbac.assign("alice", "reader");
+        rbac.assign("bob", "reader");
+        rbac.assign("bob", "editor");
+
+        let expected = |permissions: &[&str]| -> BTreeSet<String> {
+            permissions.iter().map(|p| p.to_string()).collect()
+        };
+        assert_eq!(rbac.effective_permissions("alice"), expected(&["read"]));
+        assert_eq!(
+            rbac.effective_permissions("bob"),
+            expected(&["delete", "read", "write"])
+        );
+        assert!(rbac.effective_permissions("carol").is_empty());
+
+        let mut authorizer = Authorizer::new().unwrap();
+        rbac.apply(&mut authorizer).unwrap();
+        for user in ["alice", "bob", "carol"].iter() {
+            assert_eq!(
+                effective_permissions(&mut authorizer, user).unwrap(),
+                rbac.effective_permissions(user)
+            );
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! interactive Datalog evaluation, with the `repl` feature
+//!
+//! [Repl] loads a token and authorizer code, and re-runs the authorization
+//! after each line of code, to debug policies without writing a test
+//! harness. It is used by the `biscuit-repl` binary:
+//!
+//! ```text
+//! > :key 1055c750b1a1505937af1537c626ba3263995c33a64758aaafb1275b0312e284
+//! > :token token.bc
+//! token loaded, 2 blocks
+//! > resource("file1");
+//! denied: no matching policy
+//!   block 1, check 0: check if operation("read")
+//! > operation("read"); allow if true;
+//! allowed by policy 0
+//! ```
+//!
+//! Lines that do not start with `:` are Datalog code added to the
+//! authorizer, see `:help` for the commands
+use crate::builder::Fact;
+use crate::error;
+use crate::{Authorizer, Biscuit, PublicKey};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "commands:
+  :key <hex>            sets the root public key
+  :token <file|base64>  loads a token, verified with the root key
+  :load <file>          adds the Datalog code of a file to the authorizer
+  :query <rule>         runs a query, like `:query data($x) <- right($x)`
+  :authorize            runs the authorization again
+  :world                prints the authorizer's facts, rules, checks and policies
+  :time                 adds a fact with the current time
+  :reset                removes the authorizer code, keeping the token
+  :help                 prints this message
+  :quit                 exits
+other lines are Datalog code added to the authorizer";
+
+/// interactive session, see the [module documentation](self)
+#[derive(Default)]
+pub struct Repl {
+    root: Option<PublicKey>,
+    token: Option<Biscuit>,
+    /// code accepted by the authorizer, replayed on each evaluation
+    code: Vec<String>,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl::default()
+    }
+
+    /// evaluates a line, returning the text to display, or `None` to exit
+    pub fn eval(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        let (command, argument) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+
+        let output = match command {
+            "" => String::new(),
+            ":quit" | ":exit" => return None,
+            ":help" => HELP.to_string(),
+            ":key" => self.set_key(argument),
+            ":token" => self.load_token(argument),
+            ":load" => match std::fs::read_to_string(argument) {
+                Ok(code) => self.add_code(&code),
+                Err(e) => format!("cannot read {}: {}", argument, e),
+            },
+            ":query" => self.query(argument),
+            ":authorize" => self.authorize(),
+            ":world" => match self.authorizer() {
+                Ok(authorizer) => authorizer.print_world(),
+                Err(e) => print_error(&e),
+            },
+            ":time" => self.add_time(),
+            ":reset" => {
+                self.code.clear();
+                "authorizer code removed".to_string()
+            }
+            c if c.starts_with(':') => format!("unknown command {}, see :help", c),
+            _ => self.add_code(line),
+        };
+
+        Some(output)
+    }
+
+    /// reads lines from `input` until it ends or `:quit` is entered
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            match self.eval(&line?) {
+                Some(text) if text.is_empty() => {}
+                Some(text) => writeln!(output, "{}", text)?,
+                None => return Ok(()),
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn set_key(&mut self, hex_key: &str) -> String {
+        match hex::decode(hex_key)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| PublicKey::from_bytes(&bytes).map_err(|e| e.to_string()))
+        {
+            Ok(key) => {
+                self.root = Some(key);
+                "root key set".to_string()
+            }
+            Err(e) => format!("invalid key: {}", e),
+        }
+    }
+
+    fn load_token(&mut self, source: &str) -> String {
+        let root = match self.root {
+            Some(root) => root,
+            None => return "set the root key first, with :key".to_string(),
+        };
+
+        let token = match std::fs::read(source) {
+            Ok(data) => Biscuit::from(&data, |_| root),
+            Err(_) => Biscuit::from_base64(source, |_| root),
+        };
+
+        match token {
+            Ok(token) => {
+                let blocks = token.block_count();
+                self.token = Some(token);
+                let mut output = format!("token loaded, {} blocks", blocks);
+                if !self.code.is_empty() {
+                    output.push('\n');
+                    output.push_str(&self.authorize());
+                }
+                output
+            }
+            Err(e) => print_error(&e),
+        }
+    }
+
+    fn add_code(&mut self, code: &str) -> String {
+        let result = self
+            .authorizer()
+            .and_then(|mut authorizer| authorizer.add_code(code));
+
+        match result {
+            Ok(()) => {
+                self.code.push(code.to_string());
+                self.authorize()
+            }
+            Err(e) => print_error(&e),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn add_time(&mut self) -> String {
+        let now = crate::builder::date(&std::time::SystemTime::now());
+        let code = crate::builder::fact("time", &[now]).to_string();
+        self.add_code(&code)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn add_time(&mut self) -> String {
+        "the current time requires the std feature".to_string()
+    }
+
+    fn query(&self, rule: &str) -> String {
+        let result = self
+            .authorizer()
+            .and_then(|mut authorizer| authorizer.query::<_, Fact, _>(rule));
+
+        match result {
+            Ok(facts) if facts.is_empty() => "no results".to_string(),
+            Ok(facts) => facts
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Err(e) => print_error(&e),
+        }
+    }
+
+    fn authorize(&self) -> String {
+        let result = self
+            .authorizer()
+            .and_then(|mut authorizer| authorizer.authorize());
+
+        match result {
+            Ok(index) => format!("allowed by policy {}", index),
+            Err(error::Token::FailedLogic(error::Logic::Unauthorized { policy, checks })) => {
+                let mut output = match policy {
+                    error::MatchedPolicy::Allow(i) => {
+                        format!("denied: policy {} matched, but checks failed", i)
+                    }
+                    error::MatchedPolicy::Deny(i) => format!("denied by policy {}", i),
+                };
+                print_checks(&mut output, &checks);
+                output
+            }
+            Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy { checks })) => {
+                let mut output = "denied: no matching policy".to_string();
+                print_checks(&mut output, &checks);
+                output
+            }
+            Err(e) => print_error(&e),
+        }
+    }
+
+    /// creates an authorizer with the token and the accepted code
+    fn authorizer(&self) -> Result<Authorizer<'_>, error::Token> {
+        let mut authorizer = match self.token.as_ref() {
+            Some(token) => token.authorizer()?,
+            None => Authorizer::new()?,
+        };
+
+        for code in self.code.iter() {
+            authorizer.add_code(code)?;
+        }
+
+        Ok(authorizer)
+    }
+}
+
+fn print_checks(output: &mut String, checks: &[error::FailedCheck]) {
+    for check in checks {
+        match check {
+            error::FailedCheck::Block(c) => output.push_str(&format!(
+                "\n  block {}, check {}: {}",
+                c.block_id, c.check_id, c.rule
+            )),
+            error::FailedCheck::Authorizer(c) => {
+                output.push_str(&format!("\n  authorizer check {}: {}", c.check_id, c.rule))
+            }
+        }
+    }
+}
+
+fn print_error(e: &error::Token) -> String {
+    format!("error: {}: {}", e.code(), e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+
+    #[test]
+    fn session() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("right(\"file1\")").unwrap();
+        let token = builder.build().unwrap();
+        let mut block = token.create_block();
+        block.add_check("check if operation(\"read\")").unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut repl = Repl::new();
+        assert_eq!(
+            repl.eval(&format!(":token {}", token.to_base64().unwrap()))
+                .unwrap(),
+            "set the root key first, with :key"
+        );
+        repl.eval(&format!(":key {}", hex::encode(root.public().to_bytes())));
+        assert_eq!(
+            repl.eval(&format!(":token {}", token.to_base64().unwrap()))
+                .unwrap(),
+            "token loaded, 2 blocks"
+        );
+
+        assert_eq!(
+            repl.eval("allow if right(\"file1\")").unwrap(),
+            "denied: policy 0 matched, but checks failed\n  block 1, check 0: check if operation(\"read\")"
+        );
+        assert_eq!(
+            repl.eval("operation(\"read\");").unwrap(),
+            "allowed by policy 0"
+        );
+        assert_eq!(
+            repl.eval(":query data($x) <- right($x)").unwrap(),
+            "data(\"file1\")"
+        );
+        assert!(repl
+            .eval("invalid(")
+            .unwrap()
+            .starts_with("error: invalid_datalog"));
+
+        assert_eq!(repl.eval(":reset").unwrap(), "authorizer code removed");
+        assert!(repl
+            .eval(":authorize")
+            .unwrap()
+            .starts_with("denied: no matching policy"));
+        assert_eq!(repl.eval(":quit"), None);
+    }
+}
@@ -0,0 +1,108 @@
+//! HTTP revocation store, with the `http-revocation` feature
+//!
+//! the store sends a `GET` request to `<base url>/<hex encoded identifier>`
+//! for each identifier: a `200` response means the identifier is revoked,
+//! and a `404` that it is not. Other responses are reported as errors.
+//!
+//! Each token requires a request per block, so the store should be wrapped
+//! in a [Cached](super::Cached) store, like with [HttpStore::cached]
+use super::{Cached, RevocationStore};
+use crate::error;
+use std::time::Duration;
+
+/// revocation store querying an HTTP endpoint
+pub struct HttpStore {
+    base_url: String,
+    agent: ::ureq::Agent,
+}
+
+impl HttpStore {
+    /// requests time out after 5 seconds
+    pub fn new(base_url: &str) -> Self {
+        let agent = ::ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(5))
+            .build();
+        HttpStore::with_agent(base_url, agent)
+    }
+
+    /// uses an agent configured for the endpoint, with its own timeouts,
+    /// proxy or TLS settings
+    pub fn with_agent(base_url: &str, agent: ::ureq::Agent) -> Self {
+        HttpStore {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent,
+        }
+    }
+
+    /// creates a store caching the responses for `ttl`
+    pub fn cached(base_url: &str, ttl: Duration) -> Cached<Self> {
+        Cached::new(HttpStore::new(base_url), ttl)
+    }
+}
+
+impl RevocationStore for HttpStore {
+    fn is_revoked(&self, id: &[u8]) -> Result<bool, error::Cause> {
+        let url = format!("{}/{}", self.base_url, hex::encode(id));
+
+        match self.agent.get(&url).call() {
+            Ok(response) if response.status() == 200 => Ok(true),
+            Ok(response) => Err(format!(
+                "unexpected status from the revocation endpoint: {}",
+                response.status()
+            )
+            .into()),
+            Err(::ureq::Error::Status(404, _)) => Ok(false),
+            Err(e) => Err(format!("revocation endpoint error: {}", e).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn lookup() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // reads the headers until the empty line
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+
+                let status = if request_line.starts_with("GET /revoked/0a0b ") {
+                    "200 OK"
+                } else {
+                    "404 Not Found"
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+
+        let store = HttpStore::cached(
+            &format!("http://{}/revoked/", address),
+            Duration::from_secs(60),
+        );
+        assert_eq!(store.is_revoked(&[0x0a, 0x0b]), Ok(true));
+        assert_eq!(store.is_revoked(&[0x0c]), Ok(false));
+        // cached, the server only answers two requests
+        assert_eq!(store.is_revoked(&[0x0a, 0x0b]), Ok(true));
+
+        server.join().unwrap();
+    }
+}
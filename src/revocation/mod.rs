@@ -0,0 +1,189 @@
+//! revocation stores, to check the [revocation identifiers](crate::Biscuit::revocation_identifiers)
+//! of tokens with [Biscuit::check_revocation](crate::Biscuit::check_revocation)
+//!
+//! [RevocationStore] is implemented for a `HashSet` of identifiers, and
+//! by the backends in the submodules:
+//! * `redis`, with the `redis-revocation` feature
+//! * `http`, with the `http-revocation` feature
+//!
+//! Remote stores can be wrapped in [Cached] to avoid a lookup for each token,
+//! and revocation lists can be reloaded periodically with a [RevocationWatcher]
+//!
+//! ```rust
+//! # use biscuit_auth::{Biscuit, KeyPair};
+//! # use std::collections::HashSet;
+//! let root = KeyPair::new();
+//! let token = Biscuit::builder(&root).build().unwrap();
+//!
+//! let mut revoked = HashSet::new();
+//! assert!(token.check_revocation(&revoked).is_ok());
+//!
+//! revoked.insert(token.revocation_identifiers()[0].clone());
+//! assert!(token.check_revocation(&revoked).is_err());
+//! ```
+use crate::collections::HashSet;
+use crate::error;
+#[cfg(feature = "std")]
+use crate::sync::lock;
+#[cfg(feature = "std")]
+use crate::time::Instant;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+#[cfg(feature = "http-revocation")]
+pub mod http;
+#[cfg(feature = "redis-revocation")]
+pub mod redis;
+#[cfg(feature = "std")]
+mod watcher;
+
+#[cfg(feature = "std")]
+pub use watcher::{RevocationWatcher, SharedRevocationList};
+
+/// lists the revoked identifiers
+pub trait RevocationStore {
+    fn is_revoked(&self, id: &[u8]) -> Result<bool, error::Cause>;
+
+    /// returns the index of the first revoked identifier
+    ///
+    /// stores that can check multiple identifiers in one request should
+    /// override it
+    fn first_revoked(&self, ids: &[Vec<u8>]) -> Result<Option<usize>, error::Cause> {
+        for (i, id) in ids.iter().enumerate() {
+            if self.is_revoked(id)? {
+                return Ok(Some(i));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl RevocationStore for HashSet<Vec<u8>> {
+    fn is_revoked(&self, id: &[u8]) -> Result<bool, error::Cause> {
+        Ok(self.contains(id))
+    }
+}
+
+/// caches the results of another store for a duration
+///
+/// revoked and valid identifiers are cached for the same time, so a
+/// revocation can take up to `ttl` to be seen
+#[cfg(feature = "std")]
+pub struct Cached<S> {
+    store: S,
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<Vec<u8>, (bool, Instant)>>,
+}
+
+#[cfg(feature = "std")]
+impl<S> Cached<S> {
+    /// the cache keeps up to 10000 identifiers, see [Cached::with_max_entries]
+    pub fn new(store: S, ttl: Duration) -> Self {
+        Cached {
+            store,
+            ttl,
+            max_entries: 10_000,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// sets the maximum number of cached identifiers. When it is reached,
+    /// the expired entries are removed, then the whole cache if it is still full
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// the underlying store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// removes all cached results
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<Vec<u8>, (bool, Instant)>> {
+        lock(&self.entries)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: RevocationStore> RevocationStore for Cached<S> {
+    fn is_revoked(&self, id: &[u8]) -> Result<bool, error::Cause> {
+        if let Some((revoked, time)) = self.entries().get(id) {
+            if time.elapsed() < self.ttl {
+                return Ok(*revoked);
+            }
+        }
+
+        let revoked = self.store.is_revoked(id)?;
+
+        let mut entries = self.entries();
+        if entries.len() >= self.max_entries {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, time)| time.elapsed() < ttl);
+            if entries.len() >= self.max_entries {
+                entries.clear();
+            }
+        }
+        entries.insert(id.to_vec(), (revoked, Instant::now()));
+
+        Ok(revoked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Biscuit, KeyPair};
+    use std::cell::Cell;
+
+    struct Counting {
+        revoked: HashSet<Vec<u8>>,
+        lookups: Cell<usize>,
+    }
+
+    impl RevocationStore for Counting {
+        fn is_revoked(&self, id: &[u8]) -> Result<bool, error::Cause> {
+            self.lookups.set(self.lookups.get() + 1);
+            self.revoked.is_revoked(id)
+        }
+    }
+
+    #[test]
+    fn revoked_block() {
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        let token = token.append(token.create_block()).unwrap();
+        let ids = token.revocation_identifiers();
+
+        let store = Cached::new(
+            Counting {
+                revoked: [ids[1].clone()].iter().cloned().collect(),
+                lookups: Cell::new(0),
+            },
+            Duration::from_secs(60),
+        );
+
+        for _ in 0..2 {
+            assert_eq!(
+                token.check_revocation(&store),
+                Err(error::Token::Revocation(error::Revocation::Revoked(1)))
+            );
+        }
+        assert_eq!(store.store().lookups.get(), 2);
+
+        store.clear();
+        assert!(token.check_revocation(&store).is_err());
+        assert_eq!(store.store().lookups.get(), 4);
+    }
+}
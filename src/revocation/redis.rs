@@ -0,0 +1,94 @@
+//! [Redis](https://redis.io) revocation store, with the `redis-revocation` feature
+//!
+//! each revoked identifier is stored as a key made of a prefix and the
+//! hex encoded identifier, so entries can expire with the tokens
+//!
+//! ```rust,ignore
+//! let store = RedisStore::new(redis::Client::open("redis://127.0.0.1/")?);
+//! store.revoke(&token.revocation_identifiers()[0], None)?;
+//! token.check_revocation(&store)?;
+//! ```
+use super::RevocationStore;
+use crate::error;
+use crate::sync::lock;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// revocation store checking the existence of keys in Redis
+///
+/// the connection is opened on the first lookup, and reopened after an error
+pub struct RedisStore {
+    client: ::redis::Client,
+    prefix: String,
+    connection: Mutex<Option<::redis::Connection>>,
+}
+
+impl RedisStore {
+    /// the keys use the `biscuit:revoked:` prefix
+    pub fn new(client: ::redis::Client) -> Self {
+        RedisStore {
+            client,
+            prefix: "biscuit:revoked:".to_string(),
+            connection: Mutex::new(None),
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// adds an identifier, expiring after `ttl` if there is one
+    pub fn revoke(&self, id: &[u8], ttl: Option<Duration>) -> Result<(), error::Cause> {
+        let mut command = ::redis::cmd("SET");
+        command.arg(self.key(id)).arg(1);
+        if let Some(ttl) = ttl {
+            command.arg("EX").arg(ttl.as_secs().max(1));
+        }
+
+        self.query(|connection| command.query::<()>(connection))
+    }
+
+    fn key(&self, id: &[u8]) -> String {
+        format!("{}{}", self.prefix, hex::encode(id))
+    }
+
+    fn query<T, F>(&self, f: F) -> Result<T, error::Cause>
+    where
+        F: FnOnce(&mut ::redis::Connection) -> ::redis::RedisResult<T>,
+    {
+        let mut connection = lock(&self.connection);
+        if connection.is_none() {
+            *connection = Some(
+                self.client
+                    .get_connection()
+                    .map_err(error::Cause::from_error)?,
+            );
+        }
+
+        let res = f(connection.as_mut().unwrap());
+        if res.is_err() {
+            *connection = None;
+        }
+
+        res.map_err(error::Cause::from_error)
+    }
+}
+
+impl RevocationStore for RedisStore {
+    fn is_revoked(&self, id: &[u8]) -> Result<bool, error::Cause> {
+        let key = self.key(id);
+        self.query(|connection| ::redis::cmd("EXISTS").arg(&key).query(connection))
+    }
+
+    /// checks all the identifiers in one pipeline
+    fn first_revoked(&self, ids: &[Vec<u8>]) -> Result<Option<usize>, error::Cause> {
+        let mut pipeline = ::redis::pipe();
+        for id in ids {
+            pipeline.cmd("EXISTS").arg(self.key(id));
+        }
+
+        let revoked: Vec<bool> = self.query(|connection| pipeline.query(connection))?;
+        Ok(revoked.iter().position(|revoked| *revoked))
+    }
+}
@@ -0,0 +1,186 @@
+//! revocation list refreshed in the background
+//!
+//! a [RevocationWatcher] loads the revoked identifiers from an async source,
+//! like a database query or an HTTP request, and replaces the content of a
+//! [SharedRevocationList] with them. The list is cheap to clone and is used
+//! as a [RevocationStore] by the request handlers, which see each new list
+//! as a whole
+//!
+//! ```rust,ignore
+//! let watcher = RevocationWatcher::new(|| async {
+//!     let ids = database.revoked_ids().await.map_err(error::Cause::from_error)?;
+//!     Ok(ids.into_iter().collect())
+//! });
+//! let revoked = watcher.list();
+//! watcher.spawn(Duration::from_secs(30));
+//!
+//! // in the handlers
+//! token.check_revocation(&revoked)?;
+//! ```
+use super::RevocationStore;
+use crate::error;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+/// revoked identifiers, replaced atomically by a [RevocationWatcher]
+#[derive(Clone, Default)]
+pub struct SharedRevocationList {
+    ids: Arc<RwLock<Arc<HashSet<Vec<u8>>>>>,
+}
+
+impl SharedRevocationList {
+    pub fn new() -> Self {
+        SharedRevocationList::default()
+    }
+
+    /// replaces the revoked identifiers for all the clones of the list
+    pub fn replace(&self, ids: HashSet<Vec<u8>>) {
+        *self.ids.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(ids);
+    }
+
+    /// current revoked identifiers, not modified by later replacements
+    pub fn snapshot(&self) -> Arc<HashSet<Vec<u8>>> {
+        self.ids.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshot().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshot().is_empty()
+    }
+}
+
+impl RevocationStore for SharedRevocationList {
+    fn is_revoked(&self, id: &[u8]) -> Result<bool, error::Cause> {
+        Ok(self.snapshot().contains(id))
+    }
+
+    /// checks all the identifiers with the same list
+    fn first_revoked(&self, ids: &[Vec<u8>]) -> Result<Option<usize>, error::Cause> {
+        let revoked = self.snapshot();
+        Ok(ids.iter().position(|id| revoked.contains(id)))
+    }
+}
+
+/// loads the revoked identifiers from an async source into a [SharedRevocationList]
+pub struct RevocationWatcher<F> {
+    source: F,
+    list: SharedRevocationList,
+}
+
+impl<F, Fut> RevocationWatcher<F>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<HashSet<Vec<u8>>, error::Cause>>,
+{
+    /// the list is empty until the first refresh
+    pub fn new(source: F) -> Self {
+        RevocationWatcher {
+            source,
+            list: SharedRevocationList::new(),
+        }
+    }
+
+    /// updates an existing list instead of a new one
+    pub fn with_list(mut self, list: SharedRevocationList) -> Self {
+        self.list = list;
+        self
+    }
+
+    /// the list updated by the watcher
+    pub fn list(&self) -> SharedRevocationList {
+        self.list.clone()
+    }
+
+    /// loads the identifiers from the source, returning their number
+    ///
+    /// if the source fails, the list keeps the previous identifiers
+    pub async fn refresh(&mut self) -> Result<usize, error::Cause> {
+        let ids = (self.source)().await?;
+        let count = ids.len();
+        self.list.replace(ids);
+        Ok(count)
+    }
+
+    /// refreshes the list on a tokio task, immediately then after each `period`
+    ///
+    /// errors from the source are ignored, the list keeps the previous identifiers
+    /// until the next successful refresh
+    #[cfg(feature = "tokio-integration")]
+    pub fn spawn(mut self, period: std::time::Duration) -> ::tokio::task::JoinHandle<()>
+    where
+        F: Send + 'static,
+        Fut: Send + 'static,
+    {
+        ::tokio::spawn(async move {
+            let mut interval = ::tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let result = self.refresh().await;
+                #[cfg(feature = "tracing")]
+                if let Err(e) = result {
+                    tracing::warn!(error = %e.message(), "revocation list refresh failed");
+                }
+                #[cfg(not(feature = "tracing"))]
+                let _ = result;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Biscuit, KeyPair};
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    /// polls a future that does not wait on anything
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        fn raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { Waker::from_raw(raw_waker()) };
+        let mut future = Box::pin(future);
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("the future is not ready"),
+        }
+    }
+
+    #[test]
+    fn refresh() {
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        let id = token.revocation_identifiers()[0].clone();
+
+        let mut responses = vec![
+            Err(error::Cause::from("unavailable".to_string())),
+            Ok([id].iter().cloned().collect::<HashSet<_>>()),
+            Err(error::Cause::from("unavailable".to_string())),
+        ];
+        let mut watcher = RevocationWatcher::new(move || {
+            let response = responses.pop().unwrap();
+            async move { response }
+        });
+        let list = watcher.list();
+
+        assert!(ready(watcher.refresh()).is_err());
+        assert!(token.check_revocation(&list).is_ok());
+
+        assert_eq!(ready(watcher.refresh()).unwrap(), 1);
+        assert!(token.check_revocation(&list).is_err());
+
+        // the previous list is kept
+        assert!(ready(watcher.refresh()).is_err());
+        assert_eq!(list.len(), 1);
+    }
+}
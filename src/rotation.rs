@@ -0,0 +1,189 @@
+//! root key rotation
+//!
+//! during a rotation, verifiers accept the old and the new root keys. A
+//! [RootKeySet] holds the accepted keys, each with an optional expiration
+//! date, so the old key stops being accepted at the end of the transition.
+//!
+//! Tokens minted with the old key can be endorsed by the new key with
+//! [endorse]: this appends a third party block, signed by the new key, with
+//! the fact `root_endorsement(hex:...)` naming the old key. A [RootKeySet]
+//! accepts a token signed by a key it does not know if an accepted key endorses
+//! it, so endorsed tokens can be verified by services that already dropped the
+//! old key, and by services that do not know the new key yet
+//!
+//! ```rust
+//! # use biscuit_auth::{Biscuit, KeyPair};
+//! # use biscuit_auth::rotation::{endorse, RootKeySet};
+//! # use std::time::{Duration, SystemTime};
+//! let old_root = KeyPair::new();
+//! let new_root = KeyPair::new();
+//!
+//! let token = Biscuit::builder(&old_root).build().unwrap();
+//! let token = endorse(&token, &old_root.public(), &new_root).unwrap();
+//! let serialized = token.to_vec().unwrap();
+//!
+//! // during the transition
+//! let mut keys = RootKeySet::new();
+//! keys.add_until(old_root.public(), SystemTime::now() + Duration::from_secs(86400));
+//! keys.add(new_root.public());
+//! assert!(keys.verify(&serialized).is_ok());
+//!
+//! // after the transition
+//! let mut keys = RootKeySet::new();
+//! keys.add(new_root.public());
+//! assert!(keys.verify(&serialized).is_ok());
+//! ```
+use crate::crypto::PublicKey;
+use crate::datalog;
+use crate::error;
+use crate::{Biscuit, UnverifiedBiscuit};
+use std::time::SystemTime;
+
+/// root public keys accepted by a verifier
+#[derive(Clone, Debug, Default)]
+pub struct RootKeySet {
+    keys: Vec<(PublicKey, Option<SystemTime>)>,
+}
+
+impl RootKeySet {
+    pub fn new() -> Self {
+        RootKeySet::default()
+    }
+
+    /// accepts a key without expiration
+    pub fn add(&mut self, key: PublicKey) {
+        self.keys.push((key, None));
+    }
+
+    /// accepts a key until a date
+    pub fn add_until(&mut self, key: PublicKey, until: SystemTime) {
+        self.keys.push((key, Some(until)));
+    }
+
+    /// keys accepted at `now`
+    pub fn keys_at(&self, now: SystemTime) -> Vec<PublicKey> {
+        self.keys
+            .iter()
+            .filter(|(_, until)| until.map(|until| now < until).unwrap_or(true))
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// deserializes a token and verifies it with the keys accepted now
+    #[cfg(feature = "std")]
+    pub fn verify<T: AsRef<[u8]>>(&self, slice: T) -> Result<Biscuit, error::Token> {
+        self.verify_at(slice, SystemTime::now())
+    }
+
+    /// deserializes a token and verifies it with the keys accepted at `now`
+    ///
+    /// the token must be signed by one of these keys, or by a key endorsed by
+    /// one of them in a third party block
+    pub fn verify_at<T: AsRef<[u8]>>(
+        &self,
+        slice: T,
+        now: SystemTime,
+    ) -> Result<Biscuit, error::Token> {
+        let keys = self.keys_at(now);
+        let token = UnverifiedBiscuit::from(slice)?;
+        let mut error = error::Format::UnknownPublicKey;
+
+        for key in keys.iter().copied().chain(endorsed_keys(&token, &keys)) {
+            match token.clone().check_signature(|_| key) {
+                Ok(token) => return Ok(token),
+                Err(e) => error = e,
+            }
+        }
+
+        Err(error.into())
+    }
+}
+
+/// appends a block signed by `new_root`, endorsing the token's root key `old_root`
+#[cfg(feature = "std")]
+pub fn endorse(
+    token: &Biscuit,
+    old_root: &PublicKey,
+    new_root: &crate::KeyPair,
+) -> Result<Biscuit, error::Token> {
+    use crate::builder::{bytes, fact, BlockBuilder};
+
+    let mut block = BlockBuilder::new();
+    block.add_fact(fact("root_endorsement", &[bytes(&old_root.to_bytes())]))?;
+
+    let block = token.third_party_request()?.create_block(new_root, block)?;
+    token.append_third_party(block)
+}
+
+/// keys named in `root_endorsement` facts of third party blocks signed by `keys`
+fn endorsed_keys(token: &UnverifiedBiscuit, keys: &[PublicKey]) -> Vec<PublicKey> {
+    let name = match token.symbols.get("root_endorsement") {
+        Some(name) => name,
+        None => return Vec::new(),
+    };
+
+    token
+        .blocks
+        .iter()
+        .filter(|block| {
+            block
+                .external_key
+                .map(|key| keys.contains(&key))
+                .unwrap_or(false)
+        })
+        .flat_map(|block| block.facts.iter())
+        .filter(|fact| fact.predicate.name == name)
+        .filter_map(|fact| match fact.predicate.terms.first() {
+            Some(datalog::Term::Bytes(key)) => PublicKey::from_bytes(key).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+    use std::time::Duration;
+
+    #[test]
+    fn transition() {
+        let old_root = KeyPair::new();
+        let new_root = KeyPair::new();
+        let other = KeyPair::new();
+        let now = SystemTime::now();
+        let end = now + Duration::from_secs(3600);
+
+        let mut keys = RootKeySet::new();
+        keys.add_until(old_root.public(), end);
+        keys.add(new_root.public());
+
+        let old = Biscuit::builder(&old_root)
+            .build()
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let new = Biscuit::builder(&new_root)
+            .build()
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        assert!(keys.verify_at(&old, now).is_ok());
+        assert!(keys.verify_at(&new, now).is_ok());
+        assert!(keys.verify_at(&old, end).is_err());
+        assert!(keys.verify_at(&new, end).is_ok());
+
+        // only endorsements from accepted keys are used
+        let token = Biscuit::builder(&old_root).build().unwrap();
+        let endorsed = endorse(&token, &old_root.public(), &new_root)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let forged = endorse(&token, &old_root.public(), &other)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        assert!(keys.verify_at(&endorsed, end).is_ok());
+        assert!(keys.verify_at(&forged, end).is_err());
+    }
+}
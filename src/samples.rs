@@ -0,0 +1,341 @@
+//! test vectors for interoperability between implementations, with the `samples` feature
+//!
+//! a test vector directory contains the serialized tokens and a `samples.json`
+//! file describing, for each token, its content and the expected result of
+//! authorizing it with some authorizer code. The format is the one produced
+//! by the `testcases` example, which generates the official set.
+//!
+//! [SampleSet] records new test vectors from tokens built with this
+//! implementation, and [run_directory] checks a directory generated by any
+//! implementation against this one
+use crate::error;
+use crate::{Authorizer, Biscuit, KeyPair, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::{Component, Path};
+
+/// content of a `samples.json` file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestCases {
+    pub root_private_key: String,
+    pub root_public_key: String,
+    pub testcases: Vec<TestCase>,
+}
+
+/// a token and its expected authorization results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestCase {
+    pub title: String,
+    /// name of the token file in the samples directory. It cannot contain
+    /// a path separator
+    pub filename: String,
+    pub token: Vec<BlockContent>,
+    /// validations indexed by name, the empty name being the default one
+    pub validations: BTreeMap<String, Validation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockContent {
+    pub symbols: Vec<String>,
+    pub code: String,
+}
+
+/// result of authorizing a token with some authorizer code
+///
+/// the authorizer code does not include the final `allow if true` policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Validation {
+    pub world: Option<AuthorizerWorld>,
+    pub result: AuthorizerResult,
+    pub authorizer_code: String,
+    pub revocation_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizerWorld {
+    pub facts: BTreeSet<String>,
+    pub rules: BTreeSet<String>,
+    pub checks: BTreeSet<String>,
+    pub policies: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AuthorizerResult {
+    /// index of the matching policy
+    Ok(usize),
+    Err(error::Token),
+}
+
+/// authorizes a serialized token with this implementation
+pub fn validate(root: &PublicKey, data: &[u8], authorizer_code: &str) -> Validation {
+    let failure = |e, revocation_ids| Validation {
+        world: None,
+        result: AuthorizerResult::Err(e),
+        authorizer_code: authorizer_code.to_string(),
+        revocation_ids,
+    };
+
+    let token = match Biscuit::from(data, |_| *root) {
+        Ok(token) => token,
+        Err(e) => return failure(e, vec![]),
+    };
+
+    let revocation_ids = token
+        .revocation_identifiers()
+        .iter()
+        .map(hex::encode)
+        .collect::<Vec<_>>();
+
+    let mut authorizer = match prepare_authorizer(&token, authorizer_code) {
+        Ok(authorizer) => authorizer,
+        Err(e) => return failure(e, revocation_ids),
+    };
+
+    let result = match authorizer.authorize() {
+        Ok(i) => AuthorizerResult::Ok(i),
+        Err(e) => AuthorizerResult::Err(e),
+    };
+    let (facts, rules, checks, policies) = authorizer.dump();
+
+    Validation {
+        world: Some(AuthorizerWorld {
+            facts: facts.iter().map(|f| f.to_string()).collect(),
+            rules: rules.iter().map(|r| r.to_string()).collect(),
+            checks: checks.iter().map(|c| c.to_string()).collect(),
+            policies: policies.iter().map(|p| p.to_string()).collect(),
+        }),
+        result,
+        authorizer_code: authorizer_code.to_string(),
+        revocation_ids,
+    }
+}
+
+fn prepare_authorizer<'t>(
+    token: &'t Biscuit,
+    authorizer_code: &str,
+) -> Result<Authorizer<'t>, error::Token> {
+    let mut authorizer = token.authorizer()?;
+    authorizer.add_code(authorizer_code)?;
+    authorizer.allow()?;
+    Ok(authorizer)
+}
+
+/// test vectors being generated
+pub struct SampleSet {
+    root: KeyPair,
+    cases: Vec<(TestCase, Vec<u8>)>,
+}
+
+impl SampleSet {
+    /// the tokens of the set must be signed by `root`, unless they test
+    /// a signature failure
+    pub fn new(root: KeyPair) -> Self {
+        SampleSet {
+            root,
+            cases: Vec::new(),
+        }
+    }
+
+    /// records a token, and the results of authorizing it with each of
+    /// the named authorizer codes
+    pub fn add_case(
+        &mut self,
+        title: &str,
+        filename: &str,
+        token: &Biscuit,
+        validations: &[(&str, &str)],
+    ) -> Result<(), error::Token> {
+        let data = token.to_vec()?;
+
+        let blocks = (0..token.block_count())
+            .map(|i| BlockContent {
+                symbols: token.block_symbols(i).unwrap_or_default(),
+                code: token.print_block_source(i).unwrap_or_default(),
+            })
+            .collect();
+
+        let validations = validations
+            .iter()
+            .map(|(name, code)| (name.to_string(), validate(&self.root.public(), &data, code)))
+            .collect();
+
+        self.cases.push((
+            TestCase {
+                title: title.to_string(),
+                filename: filename.to_string(),
+                token: blocks,
+                validations,
+            },
+            data,
+        ));
+        Ok(())
+    }
+
+    /// writes the tokens and the `samples.json` file to `dir`
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+
+        for (case, data) in self.cases.iter() {
+            std::fs::write(dir.join(token_file(&case.filename)?), data)?;
+        }
+
+        let cases = TestCases {
+            root_private_key: hex::encode(self.root.private().to_bytes()),
+            root_public_key: hex::encode(self.root.public().to_bytes()),
+            testcases: self.cases.iter().map(|(case, _)| case.clone()).collect(),
+        };
+        let json = serde_json::to_string_pretty(&cases)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(dir.join("samples.json"), json)
+    }
+}
+
+/// result of running one validation of a test vector
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseReport {
+    pub title: String,
+    pub filename: String,
+    /// name of the validation, empty for the default one
+    pub validation: String,
+    /// description of the difference with the expected result, if any
+    pub failure: Option<String>,
+}
+
+impl CaseReport {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl fmt::Display for CaseReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.passed() { "ok" } else { "FAILED" };
+        write!(f, "{} ({})", self.title, self.filename)?;
+        if !self.validation.is_empty() {
+            write!(f, " [{}]", self.validation)?;
+        }
+        write!(f, ": {}", status)?;
+        if let Some(failure) = &self.failure {
+            write!(f, ": {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// runs the test vectors of a directory containing a `samples.json` file
+///
+/// the authorization result and revocation ids of each validation are
+/// compared with the expected ones
+pub fn run_directory(dir: &Path) -> io::Result<Vec<CaseReport>> {
+    let json = std::fs::read_to_string(dir.join("samples.json"))?;
+    let cases: TestCases =
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let root = hex::decode(&cases.root_public_key)
+        .ok()
+        .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid root public key"))?;
+
+    let mut reports = Vec::new();
+    for case in cases.testcases.iter() {
+        let data = std::fs::read(dir.join(token_file(&case.filename)?))?;
+
+        for (name, expected) in case.validations.iter() {
+            let actual = validate(&root, &data, &expected.authorizer_code);
+
+            let failure = if actual.result != expected.result {
+                Some(format!(
+                    "expected {:?}, got {:?}",
+                    expected.result, actual.result
+                ))
+            } else if actual.revocation_ids != expected.revocation_ids {
+                Some(format!(
+                    "expected revocation ids {:?}, got {:?}",
+                    expected.revocation_ids, actual.revocation_ids
+                ))
+            } else {
+                None
+            };
+
+            reports.push(CaseReport {
+                title: case.title.clone(),
+                filename: case.filename.clone(),
+                validation: name.clone(),
+                failure,
+            });
+        }
+    }
+
+    Ok(reports)
+}
+
+/// the file names come from `samples.json`, they must not point outside
+/// of the samples directory
+fn token_file(filename: &str) -> io::Result<&Path> {
+    let path = Path::new(filename);
+    let mut components = path.components();
+
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), None) if name == path.as_os_str() => Ok(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid token file name: {:?}", filename),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{fact, string};
+
+    #[test]
+    fn roundtrip() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact(fact("right", &[string("file1"), string("read")]))
+            .unwrap();
+        let token = builder.build().unwrap();
+        let mut block = token.create_block();
+        block
+            .add_check("check if resource($r), right($r, \"read\")")
+            .unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut samples = SampleSet::new(root);
+        samples
+            .add_case(
+                "basic token",
+                "basic.bc",
+                &token,
+                &[
+                    ("", "resource(\"file1\");"),
+                    ("other file", "resource(\"file2\");"),
+                ],
+            )
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("biscuit-samples-{}", std::process::id()));
+        samples.write(&dir).unwrap();
+
+        let reports = run_directory(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|r| r.passed()), "{:?}", reports);
+    }
+
+    #[test]
+    fn token_file_names() {
+        assert!(token_file("test1_basic.bc").is_ok());
+        assert!(token_file("../secret").is_err());
+        assert!(token_file("/etc/passwd").is_err());
+        assert!(token_file("dir/token.bc").is_err());
+        assert!(token_file("./token.bc").is_err());
+        assert!(token_file("..").is_err());
+        assert!(token_file("").is_err());
+    }
+}
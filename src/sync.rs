@@ -0,0 +1,11 @@
+//! locking helpers for the caches and stores shared between threads
+use std::sync::{Mutex, MutexGuard};
+
+/// locks a mutex, even if a thread panicked while holding it
+///
+/// the caches and stores only insert, replace or remove whole entries while
+/// they hold the lock, so their content stays consistent after a panic, and
+/// a poisoned lock would make them unusable for the rest of the process
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
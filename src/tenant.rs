@@ -0,0 +1,112 @@
+//! isolation of tenants sharing an authorizer
+//!
+//! a token is bound to a tenant with
+//! [BiscuitBuilder::set_tenant](crate::builder::BiscuitBuilder::set_tenant),
+//! which adds the authority fact `tenant("acme")`.
+//! [Authorizer::set_tenant](crate::Authorizer::set_tenant) adds the check
+//! `check if tenant("acme")` to the authorizer, which also fails if the
+//! authorizer or the authority block contains a `tenant` fact for another
+//! tenant.
+//!
+//! When the data of multiple tenants is loaded in the same authorizer,
+//! [namespace_fact] and [namespace_rule] add the tenant as first term of
+//! each predicate, so rules and policies only join the data of one tenant:
+//!
+//! ```rust
+//! # use biscuit_auth::{Authorizer, Biscuit, KeyPair};
+//! # use biscuit_auth::builder::{fact, string};
+//! # use biscuit_auth::tenant::namespace_fact;
+//! let mut authorizer = Authorizer::new().unwrap();
+//! authorizer.set_tenant("acme");
+//! for (tenant, user) in [("acme", "alice"), ("globex", "bob")].iter() {
+//!     let owner = fact("owner", &[string(user), string("file1")]);
+//!     authorizer.add_fact(namespace_fact(tenant, &owner)).unwrap();
+//! }
+//! authorizer.add_code(r#"
+//!     resource("file1");
+//!     allow if tenant($t), user($u), resource($r), owner($t, $u, $r);
+//! "#).unwrap();
+//!
+//! let root = KeyPair::new();
+//! let mut builder = Biscuit::builder(&root);
+//! builder.set_tenant("acme");
+//! builder.add_authority_fact("user(\"alice\")").unwrap();
+//! let token = builder.build().unwrap();
+//!
+//! assert_eq!(authorizer.authorize_token(&token), Ok(0));
+//! ```
+use crate::builder::{check, pred, string, Check, Fact, Predicate, Rule};
+
+/// `check if tenant(tenant)`
+pub fn tenant_check(tenant: &str) -> Check {
+    check(&[pred("tenant", &[string(tenant)])])
+}
+
+/// adds the tenant as first term of the fact
+pub fn namespace_fact(tenant: &str, fact: &Fact) -> Fact {
+    Fact {
+        predicate: namespace_predicate(tenant, &fact.predicate),
+        parameters: fact.parameters.clone(),
+    }
+}
+
+/// adds the tenant as first term of the head and of each predicate of the body
+pub fn namespace_rule(tenant: &str, rule: &Rule) -> Rule {
+    let mut rule = rule.clone();
+    rule.head = namespace_predicate(tenant, &rule.head);
+    rule.body = rule
+        .body
+        .iter()
+        .map(|p| namespace_predicate(tenant, p))
+        .collect();
+    rule
+}
+
+fn namespace_predicate(tenant: &str, predicate: &Predicate) -> Predicate {
+    let mut terms = vec![string(tenant)];
+    terms.extend(predicate.terms.iter().cloned());
+    Predicate::new(predicate.name.clone(), terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Authorizer, Biscuit, KeyPair};
+    use std::convert::TryInto;
+
+    #[test]
+    fn isolation() {
+        let rule: Rule = "can_read($u, $r) <- owner($u, $r)".try_into().unwrap();
+        assert_eq!(
+            namespace_rule("acme", &rule).to_string(),
+            "can_read(\"acme\", $u, $r) <- owner(\"acme\", $u, $r)"
+        );
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.set_tenant("acme");
+        authorizer.allow().unwrap();
+
+        let root = KeyPair::new();
+        let token = |tenants: &[&str]| {
+            let mut builder = Biscuit::builder(&root);
+            for tenant in tenants {
+                builder.set_tenant(tenant);
+            }
+            builder.build().unwrap()
+        };
+
+        assert_eq!(authorizer.authorize_token(&token(&["acme"])), Ok(0));
+        assert!(authorizer.authorize_token(&token(&[])).is_err());
+        assert!(authorizer.authorize_token(&token(&["globex"])).is_err());
+        assert!(authorizer
+            .authorize_token(&token(&["acme", "globex"]))
+            .is_err());
+
+        // block facts are not seen by the authorizer's checks
+        let token = token(&["globex"]);
+        let mut block = token.create_block();
+        block.add_fact("tenant(\"acme\")").unwrap();
+        let token = token.append(block).unwrap();
+        assert!(authorizer.authorize_token(&token).is_err());
+    }
+}
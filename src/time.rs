@@ -1,20 +1,23 @@
-//! std::time::Instant compatibility for WebAssembly
+//! std::time::Instant compatibility for WebAssembly and `no_std`
 //!
 //! code from <https://github.com/rust-lang/rust/issues/48564#issuecomment-698712971>
+//!
+//! without the `std` feature there is no clock: the time never advances, so
+//! the execution time limit is never reached
 
-#[cfg(feature = "wasm")]
-use std::convert::TryInto;
-use std::ops::{Add, AddAssign, Sub, SubAssign};
-#[cfg(feature = "wasm")]
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+use core::convert::TryInto;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
 use wasm_bindgen::prelude::*;
 
-pub use std::time::*;
+pub use core::time::Duration;
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Instant(std::time::Instant);
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
 #[allow(dead_code)]
 impl Instant {
     pub fn now() -> Self {
@@ -44,11 +47,11 @@ extern "C" {
     fn performance_now() -> f64;
 }
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Instant(u64);
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
 impl Instant {
     pub fn now() -> Self {
         Self((performance_now() * 1000.0) as u64)
@@ -73,6 +76,31 @@ impl Instant {
     }
 }
 
+/// elapsed time since the creation of the instant, which is always zero
+#[cfg(not(feature = "std"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(Duration);
+
+#[cfg(not(feature = "std"))]
+#[allow(dead_code)]
+impl Instant {
+    pub fn now() -> Self {
+        Self(Duration::from_secs(0))
+    }
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration).map(Self)
+    }
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_sub(duration).map(Self)
+    }
+}
+
 impl Add<Duration> for Instant {
     type Output = Instant;
     fn add(self, other: Duration) -> Instant {
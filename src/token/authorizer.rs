@@ -1,17 +1,31 @@
 //! Authorizer structure and associated functions
-use super::builder::{date, fact, Check, Fact, Policy, PolicyKind, Rule};
+use super::builder::{
+    bytes, check_enumerations, declare_enumeration, fact, int, pred, string, Check, Enumeration,
+    Fact, Policy, PolicyKind, Rule, Term,
+};
 use super::Biscuit;
+use crate::attributes::{AsyncAttributeSource, AttributeSource};
+use crate::collections::{HashMap, HashSet};
+use crate::crypto::{DalekVerifier, PublicKey, Signer, Verifier};
 use crate::datalog::{self, RunLimits};
 use crate::error;
+use crate::format::FormatSettings;
+#[cfg(feature = "std")]
+use crate::loader::LoadOptions;
+use crate::nonce::ReplayStore;
 use crate::parser::parse_source;
+use crate::policy_pack::PolicyPack;
 use crate::time::Instant;
-use prost::Message;
-use std::{
-    collections::HashSet,
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::{
     convert::{TryFrom, TryInto},
     default::Default,
-    time::{Duration, SystemTime},
+    ops::Range,
+    time::Duration,
 };
+use prost::Message;
 
 /// used to check authorization policies on a token
 ///
@@ -22,9 +36,32 @@ pub struct Authorizer<'t> {
     block_worlds: Vec<datalog::World>,
     pub(crate) symbols: datalog::SymbolTable,
     checks: Vec<Check>,
+    /// source text of the checks added with [Authorizer::add_code], by index
+    check_sources: HashMap<usize, String>,
     token_checks: Vec<Vec<datalog::Check>>,
     policies: Vec<Policy>,
     token: Option<&'t Biscuit>,
+    externals: datalog::ExternalPredicates,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    /// rules removed from the world after it was run, kept for [Authorizer::export_graph]
+    run_rules: Vec<datalog::Rule>,
+    /// predicates whose identifiers are looked up before each authorization
+    attribute_sources: Vec<(String, Arc<dyn AttributeSource>)>,
+    /// tenant set with [Authorizer::set_tenant], and the index of its check
+    tenant: Option<(String, usize)>,
+    /// audience set with [Authorizer::set_audience], and the index of its check
+    audience: Option<(String, usize)>,
+    /// records the nonces of one-time tokens
+    pub(crate) replay_store: Option<Arc<dyn ReplayStore>>,
+    /// indexes of the checks and policies of each policy pack, by name
+    policy_packs: HashMap<String, (Range<usize>, Range<usize>)>,
+    /// set with [Authorizer::set_require_policies]
+    require_policies: bool,
+    /// set with [Authorizer::add_revocation_id_facts], with the facts
+    /// that were inserted in the world
+    revocation_id_facts: Option<Vec<datalog::Fact>>,
+    /// declared with [Authorizer::add_enumeration]
+    enumerations: Vec<Enumeration>,
 }
 
 impl<'t> Authorizer<'t> {
@@ -53,17 +90,42 @@ impl<'t> Authorizer<'t> {
             block_worlds: vec![],
             symbols,
             checks: vec![],
+            check_sources: HashMap::default(),
             token_checks: vec![],
             policies: vec![],
             token: None,
+            externals: datalog::ExternalPredicates::new(),
+            metrics: None,
+            run_rules: vec![],
+            attribute_sources: vec![],
+            tenant: None,
+            audience: None,
+            replay_store: None,
+            policy_packs: HashMap::default(),
+            require_policies: false,
+            revocation_id_facts: None,
+            enumerations: vec![],
         })
     }
 
     /// creates an `Authorizer` from a serialized [crate::format::schema::AuthorizerPolicies]
     pub fn from(slice: &[u8]) -> Result<Self, error::Token> {
+        Authorizer::from_with_settings(slice, &FormatSettings::default())
+    }
+
+    /// creates an `Authorizer` from a serialized [crate::format::schema::AuthorizerPolicies],
+    /// accepting only the format versions allowed by `settings`
+    pub fn from_with_settings(
+        slice: &[u8],
+        settings: &FormatSettings,
+    ) -> Result<Self, error::Token> {
         let data = crate::format::schema::AuthorizerPolicies::decode(slice).map_err(|e| {
-            error::Format::DeserializationError(format!("deserialization error: {:?}", e))
+            error::Format::DeserializationError(error::Cause::with_dependency_source(
+                format!("deserialization error: {:?}", e),
+                e,
+            ))
         })?;
+        settings.check_version(data.version.unwrap_or(0))?;
 
         let AuthorizerPolicies {
             version: _,
@@ -74,6 +136,16 @@ impl<'t> Authorizer<'t> {
             policies,
         } = crate::format::convert::proto_authorizer_to_authorizer(&data)?;
 
+        for rule in rules.iter() {
+            Rule::convert_from(rule, &symbols)
+                .validate_variables()
+                .map_err(|message| {
+                    error::Format::DeserializationError(
+                        format!("deserialization error: {}", message).into(),
+                    )
+                })?;
+        }
+
         let world = datalog::World {
             facts: facts.drain(..).collect(),
             rules,
@@ -88,9 +160,193 @@ impl<'t> Authorizer<'t> {
             block_worlds: vec![],
             symbols,
             checks,
+            check_sources: HashMap::default(),
             token_checks: vec![],
             policies,
             token: None,
+            externals: datalog::ExternalPredicates::new(),
+            metrics: None,
+            run_rules: vec![],
+            attribute_sources: vec![],
+            tenant: None,
+            audience: None,
+            replay_store: None,
+            policy_packs: HashMap::default(),
+            require_policies: false,
+            revocation_id_facts: None,
+            enumerations: vec![],
+        })
+    }
+
+    /// serializes the authorizer's content like [Authorizer::save], signed with
+    /// a policy distribution key
+    ///
+    /// this lets policies be distributed over untrusted channels, they are
+    /// loaded with [Authorizer::from_signed]
+    pub fn save_signed(&self, signer: &dyn Signer) -> Result<Vec<u8>, error::Token> {
+        let policies = self.save()?;
+        let signature = signer.sign(&signed_policies_message(&policies))?;
+
+        let proto = crate::format::schema::SignedAuthorizerPolicies {
+            policies,
+            signature: signature.to_bytes().to_vec(),
+        };
+
+        let mut v = Vec::new();
+        proto
+            .encode(&mut v)
+            .map(|_| v)
+            .map_err(|e| {
+                error::Format::SerializationError(error::Cause::with_dependency_source(
+                    format!("serialization error: {:?}", e),
+                    e,
+                ))
+            })
+            .map_err(error::Token::Format)
+    }
+
+    /// creates an `Authorizer` from the output of [Authorizer::save_signed],
+    /// after verifying the signature with the policy distribution public key
+    pub fn from_signed(slice: &[u8], public_key: &PublicKey) -> Result<Self, error::Token> {
+        Authorizer::from_signed_with(slice, public_key, &DalekVerifier)
+    }
+
+    /// creates an `Authorizer` from the output of [Authorizer::save_signed],
+    /// verifying the signature with a custom signature verifier
+    pub fn from_signed_with(
+        slice: &[u8],
+        public_key: &PublicKey,
+        verifier: &dyn Verifier,
+    ) -> Result<Self, error::Token> {
+        let data = crate::format::schema::SignedAuthorizerPolicies::decode(slice).map_err(|e| {
+            error::Format::DeserializationError(error::Cause::with_dependency_source(
+                format!("deserialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+        let signature = crate::crypto::Signature::from_bytes(&data.signature)?;
+
+        verifier.verify(
+            public_key,
+            &signed_policies_message(&data.policies),
+            &signature,
+        )?;
+
+        Authorizer::from(&data.policies)
+    }
+
+    /// captures what is needed to replay the authorization offline with
+    /// [Authorizer::replay]: the token, the facts added to the authorizer,
+    /// including the `time` fact, its rules, checks and policies, and `limits`
+    ///
+    /// facts from attribute sources and external predicates are only included
+    /// when the snapshot is taken after the authorization
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Authorizer, AuthorizerLimits, Biscuit, KeyPair};
+    /// # use std::time::Duration;
+    /// let root = KeyPair::new();
+    /// let mut builder = Biscuit::builder(&root);
+    /// builder.add_authority_fact("user(\"alice\")").unwrap();
+    /// let token = builder.build().unwrap();
+    ///
+    /// let mut authorizer = token.authorizer().unwrap();
+    /// authorizer.set_time();
+    /// authorizer.add_code("allow if user(\"alice\"), time($t)").unwrap();
+    /// let limits = AuthorizerLimits {
+    ///     max_time: Duration::from_secs(1),
+    ///     ..Default::default()
+    /// };
+    /// let result = authorizer.authorize_with_limits(limits.clone());
+    ///
+    /// let snapshot = authorizer.save_snapshot(&limits).unwrap();
+    /// assert_eq!(Authorizer::replay(&snapshot), result);
+    /// ```
+    pub fn save_snapshot(&self, limits: &AuthorizerLimits) -> Result<Vec<u8>, error::Token> {
+        let facts = self
+            .world
+            .facts
+            .iter_with_origin()
+            .filter(|(_, origin)| *origin == datalog::Origin::Authorizer)
+            .map(|(fact, _)| fact);
+        let rules = self.world.rules.iter().chain(self.run_rules.iter());
+        let policies = self.save_content(facts, rules)?;
+
+        let token = match self.token {
+            Some(token) => Some(token.to_vec()?),
+            None => None,
+        };
+
+        let mut predicate_limits = limits
+            .max_facts_per_predicate
+            .iter()
+            .map(|(name, max)| crate::format::schema::PredicateLimit {
+                name: name.clone(),
+                max: *max,
+            })
+            .collect::<Vec<_>>();
+        predicate_limits.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let proto = crate::format::schema::AuthorizerSnapshot {
+            policies,
+            token,
+            max_facts: limits.max_facts,
+            max_iterations: limits.max_iterations,
+            max_time: limits.max_time.as_nanos() as u64,
+            max_memory_bytes: limits.max_memory_bytes,
+            predicate_limits,
+        };
+
+        let mut v = Vec::new();
+        proto
+            .encode(&mut v)
+            .map(|_| v)
+            .map_err(|e| {
+                error::Format::SerializationError(error::Cause::with_dependency_source(
+                    format!("serialization error: {:?}", e),
+                    e,
+                ))
+            })
+            .map_err(error::Token::Format)
+    }
+
+    /// runs again the authorization captured by [Authorizer::save_snapshot],
+    /// and returns its result
+    ///
+    /// the token's signatures are not verified, so the root key is not needed
+    pub fn replay(snapshot: &[u8]) -> Result<usize, error::Token> {
+        let data = crate::format::schema::AuthorizerSnapshot::decode(snapshot).map_err(|e| {
+            error::Format::DeserializationError(error::Cause::with_dependency_source(
+                format!("deserialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+        let token = match data.token.as_ref() {
+            Some(token) => Some(Biscuit::from_serialized_container(
+                crate::format::SerializedBiscuit::deserialize(token)?,
+                super::default_symbol_table(),
+                &crate::format::DeserializationLimits::unlimited(),
+            )?),
+            None => None,
+        };
+
+        let mut authorizer = Authorizer::from(&data.policies)?;
+        if let Some(token) = token.as_ref() {
+            authorizer.add_token(token)?;
+        }
+
+        authorizer.authorize_with_limits(AuthorizerLimits {
+            max_facts: data.max_facts,
+            max_iterations: data.max_iterations,
+            max_time: Duration::from_nanos(data.max_time),
+            max_memory_bytes: data.max_memory_bytes,
+            max_facts_per_predicate: data
+                .predicate_limits
+                .into_iter()
+                .map(|limit| (limit.name, limit.max))
+                .collect(),
         })
     }
 
@@ -100,24 +356,30 @@ impl<'t> Authorizer<'t> {
             return Err(error::Logic::AuthorizerNotEmpty.into());
         }
 
-        // add authority facts and rules right away to make them available to queries
-        for fact in token.authority.facts.iter().cloned() {
-            let fact = Fact::convert_from(&fact, &token.symbols).convert(&mut self.symbols);
-            self.world.facts.insert(fact);
+        // a fresh authorizer shares the token's symbols, so the token's facts,
+        // rules and checks can be used without remapping
+        if self.symbols.current_offset() == 0 {
+            self.symbols = token.symbols.clone();
         }
+        let remap = token.symbols.remap_into(&mut self.symbols);
 
-        for rule in token.authority.rules.iter().cloned() {
-            let r = Rule::convert_from(&rule, &token.symbols);
-            let rule = r.convert(&mut self.symbols);
+        // add authority facts and rules right away to make them available to queries
+        for fact in token.authority.facts.iter() {
+            self.world
+                .facts
+                .insert_with_origin(remap.fact(fact)?, datalog::Origin::Authority);
+        }
 
-            if let Err(_message) = r.validate_variables() {
+        for rule in token.authority.rules.iter() {
+            if !rule.head_variables_bound() {
                 return Err(
-                    error::Logic::InvalidBlockRule(0, token.symbols.print_rule(&rule)).into(),
+                    error::Logic::InvalidBlockRule(0, token.symbols.print_rule(rule)).into(),
                 );
             }
         }
 
         self.token = Some(token);
+        self.insert_revocation_id_facts();
 
         Ok(())
     }
@@ -126,22 +388,127 @@ impl<'t> Authorizer<'t> {
     ///
     /// you can use this to save a set of policies and load them quickly before
     /// verification, or to store a verification context to debug it later
+    ///
+    /// the output is deterministic: facts are sorted and the symbol table is
+    /// rebuilt from the content, so the same facts, and the same rules, checks
+    /// and policies in the same order, always produce the same bytes, whatever
+    /// order the facts were added in
     pub fn save(&self) -> Result<Vec<u8>, error::Token> {
-        let mut symbols = self.symbols.clone();
+        self.save_content(self.world.facts.iter(), self.world.rules.iter())
+    }
+
+    /// runs the rules and serializes the resulting facts and rules, without
+    /// the checks and policies
+    ///
+    /// loading the output with [Authorizer::load_world] does not run the rules
+    /// again on the saved facts, which is faster than [Authorizer::save] for
+    /// large sets of facts. This must be called before adding a token
+    ///
+    /// the rules are run with the default limits, see
+    /// [Authorizer::save_world_with_limits] for larger sets of facts
+    pub fn save_world(&mut self) -> Result<Vec<u8>, error::Token> {
+        self.save_world_with_limits(AuthorizerLimits::default())
+    }
+
+    /// runs the rules with custom limits and serializes the resulting facts
+    /// and rules, see [Authorizer::save_world]
+    pub fn save_world_with_limits(
+        &mut self,
+        limits: AuthorizerLimits,
+    ) -> Result<Vec<u8>, error::Token> {
+        if self.token.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        let rules = core::mem::take(&mut self.run_rules);
+        self.world.rules.extend(rules);
+        self.run_world(limits.into(), &[])?;
+
+        Ok(self.world.to_bytes(&self.symbols)?)
+    }
+
+    /// adds the facts and rules saved with [Authorizer::save_world]
+    pub fn load_world(&mut self, data: &[u8]) -> Result<(), error::Token> {
+        let (world, symbols) = datalog::World::from_bytes(data)?;
+        self.add_world(world, symbols)
+    }
+
+    fn add_world(
+        &mut self,
+        world: datalog::World,
+        symbols: datalog::SymbolTable,
+    ) -> Result<(), error::Token> {
+        let remap = symbols.remap_into(&mut self.symbols);
+        let offset = self.world.rules.len();
+
+        for (fact, origin) in world.facts.iter_with_origin() {
+            let origin = match origin {
+                datalog::Origin::Rule(i) => datalog::Origin::Rule(offset + i),
+                origin => origin,
+            };
+            self.world
+                .facts
+                .insert_with_origin(remap.fact(fact)?, origin);
+        }
+        for rule in world.rules.iter() {
+            Rule::convert_from(rule, &symbols)
+                .validate_variables()
+                .map_err(|message| {
+                    error::Format::DeserializationError(
+                        format!("deserialization error: {}", message).into(),
+                    )
+                })?;
+            self.world.rules.push(remap.rule(rule)?);
+        }
+        Ok(())
+    }
+
+    fn save_content<'a>(
+        &self,
+        facts: impl Iterator<Item = &'a datalog::Fact>,
+        rules: impl Iterator<Item = &'a datalog::Rule>,
+    ) -> Result<Vec<u8>, error::Token> {
+        let mut symbols = super::default_symbol_table();
+
+        let mut facts: Vec<Fact> = facts
+            .map(|f| Fact::convert_from(f, &self.symbols))
+            .collect();
+        facts.sort_by_cached_key(|f| f.to_string());
+        let facts: Vec<datalog::Fact> = facts.iter().map(|f| f.convert(&mut symbols)).collect();
+
+        let rules: Vec<datalog::Rule> = rules
+            .map(|r| Rule::convert_from(r, &self.symbols).convert(&mut symbols))
+            .collect();
+
         let mut checks: Vec<datalog::Check> = self
             .checks
             .iter()
             .map(|c| c.convert(&mut symbols))
             .collect();
         for block_checks in &self.token_checks {
-            checks.extend_from_slice(&block_checks[..]);
+            checks.extend(
+                block_checks
+                    .iter()
+                    .map(|c| Check::convert_from(c, &self.symbols).convert(&mut symbols)),
+            );
         }
 
+        // saved at the lowest version supporting its content, like blocks
+        let mut policy_symbols = symbols.clone();
+        let policy_queries: Vec<datalog::Rule> = self
+            .policies
+            .iter()
+            .flat_map(|p| p.queries.iter())
+            .map(|q| q.convert(&mut policy_symbols))
+            .chain(rules.iter().cloned())
+            .collect();
+        let version = crate::token::required_version(&facts, &policy_queries, &checks);
+
         let policies = AuthorizerPolicies {
-            version: crate::token::MAX_SCHEMA_VERSION,
+            version,
             symbols,
-            facts: self.world.facts.iter().cloned().collect(),
-            rules: self.world.rules.clone(),
+            facts,
+            rules,
             checks,
             policies: self.policies.clone(),
         };
@@ -153,7 +520,12 @@ impl<'t> Authorizer<'t> {
         proto
             .encode(&mut v)
             .map(|_| v)
-            .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
+            .map_err(|e| {
+                error::Format::SerializationError(error::Cause::with_dependency_source(
+                    format!("serialization error: {:?}", e),
+                    e,
+                ))
+            })
             .map_err(error::Token::Format)
     }
 
@@ -164,21 +536,201 @@ impl<'t> Authorizer<'t> {
     {
         let fact = fact.try_into()?;
         fact.validate()?;
+        check_enumerations(&self.enumerations, fact.predicates())?;
 
         self.world.facts.insert(fact.convert(&mut self.symbols));
         Ok(())
     }
 
+    /// adds a `predicate` fact for each record of a CSV file, with a header
+    /// line, returns the number of facts. See the [loader module](crate::loader)
+    #[cfg(feature = "std")]
+    pub fn add_facts_from_csv<R: std::io::Read>(
+        &mut self,
+        reader: R,
+        predicate: &str,
+    ) -> Result<usize, error::Token> {
+        self.add_facts_from_csv_with_options(reader, predicate, &LoadOptions::new())
+    }
+
+    /// adds a `predicate` fact for each record of a CSV file, with the
+    /// delimiter and column types of `options`
+    #[cfg(feature = "std")]
+    pub fn add_facts_from_csv_with_options<R: std::io::Read>(
+        &mut self,
+        reader: R,
+        predicate: &str,
+        options: &LoadOptions,
+    ) -> Result<usize, error::Token> {
+        crate::loader::for_each_csv_fact(reader, predicate, options, |fact| self.add_fact(fact))
+    }
+
+    /// adds the facts of a stream of JSON records, returns the number of
+    /// facts. See the [loader module](crate::loader)
+    #[cfg(feature = "json-facts")]
+    pub fn add_facts_from_json<R: std::io::Read>(
+        &mut self,
+        reader: R,
+    ) -> Result<usize, error::Token> {
+        self.add_facts_from_json_with_options(reader, &LoadOptions::new())
+    }
+
+    /// adds the facts of a stream of JSON records, with the term types of `options`
+    #[cfg(feature = "json-facts")]
+    pub fn add_facts_from_json_with_options<R: std::io::Read>(
+        &mut self,
+        reader: R,
+        options: &LoadOptions,
+    ) -> Result<usize, error::Token> {
+        crate::loader::for_each_json_fact(reader, options, |fact| self.add_fact(fact))
+    }
+
     /// add a rule to the authorizer
     pub fn add_rule<R: TryInto<Rule>>(&mut self, rule: R) -> Result<(), error::Token>
     where
         error::Token: From<<R as TryInto<Rule>>::Error>,
     {
         let rule = rule.try_into()?;
+        rule.validate()?;
+        check_enumerations(&self.enumerations, rule.predicates())?;
         self.world.rules.push(rule.convert(&mut self.symbols));
         Ok(())
     }
 
+    /// registers a predicate whose facts are generated by a callback
+    ///
+    /// when a rule, check or policy uses the predicate, the callback receives
+    /// its arguments, with `None` for the variables that are not bound by the
+    /// other predicates, and returns the matching rows of terms. The results are
+    /// added to the authorizer's facts, so a callback is only called once for
+    /// the same arguments.
+    ///
+    /// Block rules and checks only see the results generated for the authorizer's
+    /// rules, checks and policies, and for the authority block's checks
+    ///
+    /// ```rust
+    /// # use biscuit_auth::Authorizer;
+    /// # use biscuit_auth::builder::{string, Term};
+    /// let mut authorizer = Authorizer::new().unwrap();
+    /// authorizer.add_external_predicate("group", |arguments: &[Option<Term>]| {
+    ///     match &arguments[0] {
+    ///         Some(Term::Str(user)) if user == "alice" => vec![vec![string("alice"), string("admins")]],
+    ///         _ => vec![],
+    ///     }
+    /// });
+    /// authorizer.add_code(r#"
+    ///   user("alice");
+    ///   check if user($user), group($user, "admins");
+    ///   allow if true;
+    /// "#).unwrap();
+    /// assert_eq!(authorizer.authorize(), Ok(0));
+    /// ```
+    pub fn add_external_predicate<F>(&mut self, name: &str, predicate: F)
+    where
+        F: Fn(&[Option<Term>]) -> Vec<Vec<Term>> + Send + Sync + 'static,
+    {
+        let name = self.symbols.insert(name);
+        self.externals.insert(
+            name,
+            Arc::new(
+                move |arguments: &[Option<datalog::Term>], symbols: &mut datalog::SymbolTable| {
+                    let arguments = arguments
+                        .iter()
+                        .map(|arg| arg.as_ref().map(|t| Term::convert_from(t, symbols)))
+                        .collect::<Vec<_>>();
+
+                    predicate(&arguments)
+                        .iter()
+                        .filter(|row| {
+                            row.iter()
+                                .all(|t| !matches!(t, Term::Variable(_) | Term::Parameter(_)))
+                        })
+                        .map(|row| row.iter().map(|t| t.convert(symbols)).collect())
+                        .collect()
+                },
+            ),
+        );
+    }
+
+    /// loads facts for the identifiers of a predicate before each authorization
+    ///
+    /// for each `predicate($id, ...)` fact of the authorizer or the authority
+    /// block with a string as first term, the facts returned by
+    /// `source.attributes(id)` are added to the authorizer. Facts generated
+    /// by rules are not looked up. See the [attributes module](crate::attributes)
+    pub fn add_attribute_source<S: AttributeSource + 'static>(
+        &mut self,
+        predicate: &str,
+        source: S,
+    ) {
+        self.attribute_sources
+            .push((predicate.to_string(), Arc::new(source)));
+    }
+
+    /// calls the sources registered with [Authorizer::add_attribute_source]
+    ///
+    /// this is done by [Authorizer::authorize], it can be called earlier to
+    /// make the attributes available to queries
+    pub fn load_attributes(&mut self) -> Result<(), error::Token> {
+        let sources = self.attribute_sources.clone();
+
+        for (predicate, source) in sources.iter() {
+            for id in self.attribute_ids(predicate) {
+                let facts = source
+                    .attributes(&id)
+                    .map_err(error::Token::AttributeLookup)?;
+                self.add_attributes(facts);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// loads facts for the identifiers of a predicate from an async source,
+    /// like [Authorizer::add_attribute_source] but only once
+    ///
+    /// this must be called after adding the token, and before authorizing it
+    pub async fn load_attributes_async<S: AsyncAttributeSource + ?Sized>(
+        &mut self,
+        predicate: &str,
+        source: &S,
+    ) -> Result<(), error::Token> {
+        for id in self.attribute_ids(predicate) {
+            let facts = source
+                .attributes(&id)
+                .await
+                .map_err(error::Token::AttributeLookup)?;
+            self.add_attributes(facts);
+        }
+
+        Ok(())
+    }
+
+    fn attribute_ids(&self, predicate: &str) -> Vec<String> {
+        let name = match self.symbols.get(predicate) {
+            Some(name) => name,
+            None => return Vec::new(),
+        };
+
+        let ids = self
+            .world
+            .facts
+            .iter_predicate(name)
+            .filter_map(|f| match f.predicate.terms.first() {
+                Some(datalog::Term::Str(id)) => self.symbols.get_symbol(*id),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        ids.into_iter().map(|id| id.to_string()).collect()
+    }
+
+    fn add_attributes(&mut self, facts: Vec<Fact>) {
+        for fact in facts {
+            self.world.facts.insert(fact.convert(&mut self.symbols));
+        }
+    }
+
     /// adds some datalog code to the authorizer
     ///
     /// ```rust
@@ -200,22 +752,33 @@ impl<'t> Authorizer<'t> {
     pub fn add_code<T: AsRef<str>>(&mut self, source: T) -> Result<(), error::Token> {
         let input = source.as_ref();
 
-        let source_result = parse_source(input)?;
+        let source_result =
+            parse_source(input).map_err(|e| error::ParseErrors::located(input, e))?;
+
+        for (_, enumeration) in source_result.enumerations.into_iter() {
+            declare_enumeration(&mut self.enumerations, enumeration)?;
+        }
 
         for (_, fact) in source_result.facts.into_iter() {
             fact.validate()?;
+            check_enumerations(&self.enumerations, fact.predicates())?;
             self.world.facts.insert(fact.convert(&mut self.symbols));
         }
 
         for (_, rule) in source_result.rules.into_iter() {
+            check_enumerations(&self.enumerations, rule.predicates())?;
             self.world.rules.push(rule.convert(&mut self.symbols));
         }
 
-        for (_, check) in source_result.checks.into_iter() {
+        for (check_source, check) in source_result.checks.into_iter() {
+            check_enumerations(&self.enumerations, check.predicates())?;
+            self.check_sources
+                .insert(self.checks.len(), check_source.trim().to_string());
             self.checks.push(check);
         }
 
         for (_, policy) in source_result.policies.into_iter() {
+            check_enumerations(&self.enumerations, policy.predicates())?;
             self.policies.push(policy);
         }
 
@@ -263,14 +826,20 @@ impl<'t> Authorizer<'t> {
     where
         error::Token: From<<R as TryInto<Rule>>::Error>,
     {
-        let rule = rule.try_into()?;
+        self.query_rule(rule.try_into()?, limits)
+    }
 
-        self.world
-            .run_with_limits(&self.symbols, limits.into())
-            .map_err(error::Token::RunLimit)?;
-        let mut res = self
-            .world
-            .query_rule(rule.convert(&mut self.symbols), &self.symbols);
+    // generic callers cannot call `query_with_limits` with a concrete `Rule`:
+    // their `From<R::Error>` bound prevents the conversion to `Infallible`
+    fn query_rule<T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: Rule,
+        limits: AuthorizerLimits,
+    ) -> Result<Vec<T>, error::Token> {
+        let rule = rule.convert(&mut self.symbols);
+
+        self.run_world(self.run_limits(limits), core::slice::from_ref(&rule))?;
+        let mut res = self.world.query_rule(rule, &self.symbols);
 
         res.drain(..)
             .map(|f| Fact::convert_from(&f, &self.symbols))
@@ -278,41 +847,94 @@ impl<'t> Authorizer<'t> {
             .collect()
     }
 
-    /// run a query over the authorizer's Datalog engine to gather data
+    /// run a query, returning the results found so far if the limits are reached
     ///
-    /// this has access to the facts generated when evaluating all the blocks
+    /// when the rules exceed `max_facts`, `max_time` or the other limits,
+    /// the query is applied to the facts generated before, and the results
+    /// are marked as truncated. This is useful for listings where partial
+    /// results are better than an error. Errors in the query itself, or in
+    /// the conversion of the results, are still returned
     ///
     /// ```rust
-    /// # use biscuit_auth::KeyPair;
-    /// # use biscuit_auth::Biscuit;
-    /// let keypair = KeyPair::new();
-    /// let mut builder = Biscuit::builder(&keypair);
-    /// builder.add_authority_fact("user(\"John Doe\", 42)");
-    ///
-    /// let biscuit = builder.build().unwrap();
+    /// # use biscuit_auth::{Authorizer, AuthorizerLimits};
+    /// # use std::time::Duration;
+    /// let mut authorizer = Authorizer::new().unwrap();
+    /// for i in 0..20 {
+    ///     authorizer.add_fact(format!("edge({}, {})", i, i + 1).as_str()).unwrap();
+    /// }
+    /// authorizer.add_code(r#"
+    ///     path($a, $b) <- edge($a, $b);
+    ///     path($a, $c) <- path($a, $b), edge($b, $c);
+    /// "#).unwrap();
     ///
-    /// let mut authorizer = biscuit.authorizer().unwrap();
-    /// let res: Vec<(String, i64)> = authorizer.query("data($name, $id) <- user($name, $id)").unwrap();
-    /// # assert_eq!(res.len(), 1);
-    /// # assert_eq!(res[0].0, "John Doe");
-    /// # assert_eq!(res[0].1, 42);
+    /// let limits = AuthorizerLimits {
+    ///     max_facts: 50,
+    ///     max_time: Duration::from_secs(1),
+    ///     ..Default::default()
+    /// };
+    /// let res = authorizer
+    ///     .query_partial::<_, (i64, i64), _>("data($a, $b) <- path($a, $b)", limits)
+    ///     .unwrap();
+    /// assert!(res.truncated);
+    /// // 210 paths without limits
+    /// assert!(!res.results.is_empty() && res.results.len() < 210);
     /// ```
-    pub fn query_all<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+    pub fn query_partial<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
         &mut self,
         rule: R,
-    ) -> Result<Vec<T>, error::Token>
+        limits: AuthorizerLimits,
+    ) -> Result<PartialResults<T>, error::Token>
     where
         error::Token: From<<R as TryInto<Rule>>::Error>,
     {
-        self.query_all_with_limits(rule, AuthorizerLimits::default())
+        let rule = rule.try_into()?.convert(&mut self.symbols);
+
+        // the facts generated before reaching the limit stay in the world
+        let truncated = match self.run_world(self.run_limits(limits), core::slice::from_ref(&rule))
+        {
+            Ok(()) => false,
+            Err(error::Token::RunLimit(_)) => true,
+            Err(e) => return Err(e),
+        };
+        let mut res = self.world.query_rule(rule, &self.symbols);
+
+        let results = res
+            .drain(..)
+            .map(|f| Fact::convert_from(&f, &self.symbols))
+            .map(|fact| fact.try_into().map_err(Into::into))
+            .collect::<Result<Vec<T>, error::Token>>()?;
+
+        Ok(PartialResults { results, truncated })
     }
 
-    /// run a query over the authorizer's Datalog engine to gather data
+    /// run a query that must return exactly one result
     ///
-    /// this has access to the facts generated when evaluating all the blocks
+    /// ```rust
+    /// # use biscuit_auth::{error, Authorizer};
+    /// let mut authorizer = Authorizer::new().unwrap();
+    /// authorizer.add_code("user(\"alice\"); role(\"admin\"); role(\"dev\");").unwrap();
     ///
-    /// this method can specify custom runtime limits
-    pub fn query_all_with_limits<
+    /// let (user,): (String,) = authorizer.query_one("data($u) <- user($u)").unwrap();
+    /// assert_eq!(user, "alice");
+    ///
+    /// let res: Result<(String,), _> = authorizer.query_one("data($r) <- role($r)");
+    /// assert!(matches!(
+    ///     res,
+    ///     Err(error::Token::Query(error::Query::TooManyResults { count: 2, .. }))
+    /// ));
+    /// ```
+    pub fn query_one<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: R,
+    ) -> Result<T, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        self.query_one_with_limits(rule, AuthorizerLimits::default())
+    }
+
+    /// run a query that must return exactly one result, with custom limits
+    pub fn query_one_with_limits<
         R: TryInto<Rule>,
         T: TryFrom<Fact, Error = E>,
         E: Into<error::Token>,
@@ -320,220 +942,895 @@ impl<'t> Authorizer<'t> {
         &mut self,
         rule: R,
         limits: AuthorizerLimits,
-    ) -> Result<Vec<T>, error::Token>
+    ) -> Result<T, error::Token>
     where
         error::Token: From<<R as TryInto<Rule>>::Error>,
     {
         let rule = rule.try_into()?;
+        let query = rule.to_string();
 
-        self.world
-            .run_with_limits(&self.symbols, limits.into())
-            .map_err(error::Token::RunLimit)?;
-        let rule = rule.convert(&mut self.symbols);
-        let mut res = self.world.query_rule(rule.clone(), &self.symbols);
-
-        let r: HashSet<_> = res
-            .drain(..)
-            .chain(
-                self.block_worlds
-                    .iter()
-                    .map(|world| rule.apply(&world.facts, &self.symbols))
-                    .flatten(),
-            )
-            .collect();
-
-        r.into_iter()
-            .map(|f| Fact::convert_from(&f, &self.symbols))
-            .map(|fact| fact.try_into().map_err(Into::into))
-            .collect()
+        self.query_opt_rule(rule, limits)?
+            .ok_or_else(|| error::Query::NoResult { query }.into())
     }
 
-    /// add a check to the authorizer
-    pub fn add_check<C: TryInto<Check>>(&mut self, check: C) -> Result<(), error::Token>
+    /// run a query that must return at most one result
+    pub fn query_opt<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: R,
+    ) -> Result<Option<T>, error::Token>
     where
-        error::Token: From<<C as TryInto<Check>>::Error>,
+        error::Token: From<<R as TryInto<Rule>>::Error>,
     {
-        let check = check.try_into()?;
-        self.checks.push(check);
-        Ok(())
+        self.query_opt_with_limits(rule, AuthorizerLimits::default())
     }
 
-    /// adds a fact with the current time
-    pub fn set_time(&mut self) {
-        let fact = fact("time", &[date(&SystemTime::now())]);
-        self.world.facts.insert(fact.convert(&mut self.symbols));
+    /// run a query that must return at most one result, with custom limits
+    pub fn query_opt_with_limits<
+        R: TryInto<Rule>,
+        T: TryFrom<Fact, Error = E>,
+        E: Into<error::Token>,
+    >(
+        &mut self,
+        rule: R,
+        limits: AuthorizerLimits,
+    ) -> Result<Option<T>, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        self.query_opt_rule(rule.try_into()?, limits)
     }
 
-    /// add a policy to the authorizer
-    pub fn add_policy<P: TryInto<Policy>>(&mut self, policy: P) -> Result<(), error::Token>
+    fn query_opt_rule<T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: Rule,
+        limits: AuthorizerLimits,
+    ) -> Result<Option<T>, error::Token> {
+        let query = rule.to_string();
+        let rule = rule.convert(&mut self.symbols);
+
+        self.run_world(self.run_limits(limits), core::slice::from_ref(&rule))?;
+        // the same fact can be generated by multiple combinations of facts
+        let results: HashSet<datalog::Fact> = self
+            .world
+            .query_rule(rule, &self.symbols)
+            .into_iter()
+            .collect();
+
+        match results.len() {
+            0 | 1 => results
+                .iter()
+                .next()
+                .map(|f| Fact::convert_from(f, &self.symbols).try_into())
+                .transpose()
+                .map_err(Into::into),
+            count => Err(error::Query::TooManyResults { query, count }.into()),
+        }
+    }
+
+    /// run a query over the authorizer's Datalog engine, calling `f` on each
+    /// result as it is found instead of collecting them
+    ///
+    /// `f` returns `false` to stop the query. The same result can be seen
+    /// multiple times, if it is generated by different facts
+    ///
+    /// this only sees facts from the authorizer and the authority block
+    ///
+    /// ```rust
+    /// # use biscuit_auth::KeyPair;
+    /// # use biscuit_auth::Biscuit;
+    /// let keypair = KeyPair::new();
+    /// let mut builder = Biscuit::builder(&keypair);
+    /// for i in 0..100 {
+    ///     builder
+    ///         .add_authority_fact(format!("file(\"/file{}.txt\")", i).as_str())
+    ///         .unwrap();
+    /// }
+    ///
+    /// let biscuit = builder.build().unwrap();
+    ///
+    /// let mut authorizer = biscuit.authorizer().unwrap();
+    /// let mut files: Vec<String> = Vec::new();
+    /// authorizer.query_iter("data($name) <- file($name)", |(name,): (String,)| {
+    ///     files.push(name);
+    ///     files.len() < 10
+    /// }).unwrap();
+    /// # assert_eq!(files.len(), 10);
+    /// ```
+    pub fn query_iter<R, T, E, F>(&mut self, rule: R, f: F) -> Result<(), error::Token>
     where
-        error::Token: From<<P as TryInto<Policy>>::Error>,
+        R: TryInto<Rule>,
+        T: TryFrom<Fact, Error = E>,
+        E: Into<error::Token>,
+        F: FnMut(T) -> bool,
+        error::Token: From<<R as TryInto<Rule>>::Error>,
     {
-        let policy = policy.try_into()?;
-        self.policies.push(policy);
-        Ok(())
+        self.query_iter_with_limits(rule, AuthorizerLimits::default(), f)
     }
 
-    /// adds a `allow if true` policy
-    pub fn allow(&mut self) -> Result<(), error::Token> {
-        self.add_policy("allow if true")
-    }
+    /// run a query over the authorizer's Datalog engine, calling `f` on each
+    /// result as it is found instead of collecting them
+    ///
+    /// this method can specify custom runtime limits
+    pub fn query_iter_with_limits<R, T, E, F>(
+        &mut self,
+        rule: R,
+        limits: AuthorizerLimits,
+        mut f: F,
+    ) -> Result<(), error::Token>
+    where
+        R: TryInto<Rule>,
+        T: TryFrom<Fact, Error = E>,
+        E: Into<error::Token>,
+        F: FnMut(T) -> bool,
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let rule = rule.try_into()?.convert(&mut self.symbols);
 
-    /// adds a `deny if true` policy
-    pub fn deny(&mut self) -> Result<(), error::Token> {
-        self.add_policy("deny if true")
+        self.run_world(self.run_limits(limits), core::slice::from_ref(&rule))?;
+
+        for fact in self.world.query_rule_iter(&rule, &self.symbols) {
+            let value = Fact::convert_from(&fact, &self.symbols)
+                .try_into()
+                .map_err(|e: E| -> error::Token { e.into() })?;
+
+            if !f(value) {
+                break;
+            }
+        }
+
+        Ok(())
     }
 
-    /// verifies the checks and policiies
+    /// run a query over the authorizer's Datalog engine to gather data
     ///
-    /// on error, this can return a list of all the failed checks or deny policy
-    /// on success, it returns the index of the policy that matched
-    pub fn authorize(&mut self) -> Result<usize, error::Token> {
-        self.authorize_with_limits(AuthorizerLimits::default())
+    /// this has access to the facts generated when evaluating all the blocks
+    ///
+    /// ```rust
+    /// # use biscuit_auth::KeyPair;
+    /// # use biscuit_auth::Biscuit;
+    /// let keypair = KeyPair::new();
+    /// let mut builder = Biscuit::builder(&keypair);
+    /// builder.add_authority_fact("user(\"John Doe\", 42)");
+    ///
+    /// let biscuit = builder.build().unwrap();
+    ///
+    /// let mut authorizer = biscuit.authorizer().unwrap();
+    /// let res: Vec<(String, i64)> = authorizer.query("data($name, $id) <- user($name, $id)").unwrap();
+    /// # assert_eq!(res.len(), 1);
+    /// # assert_eq!(res[0].0, "John Doe");
+    /// # assert_eq!(res[0].1, 42);
+    /// ```
+    pub fn query_all<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: R,
+    ) -> Result<Vec<T>, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        self.query_all_with_limits(rule, AuthorizerLimits::default())
     }
 
-    /// verifies the checks and policiies
+    /// run a query over the authorizer's Datalog engine to gather data
     ///
-    /// on error, this can return a list of all the failed checks or deny policy
+    /// this has access to the facts generated when evaluating all the blocks
     ///
     /// this method can specify custom runtime limits
-    pub fn authorize_with_limits(
+    pub fn query_all_with_limits<
+        R: TryInto<Rule>,
+        T: TryFrom<Fact, Error = E>,
+        E: Into<error::Token>,
+    >(
         &mut self,
+        rule: R,
         limits: AuthorizerLimits,
-    ) -> Result<usize, error::Token> {
-        let start = Instant::now();
-        let time_limit = start + limits.max_time;
-        let mut errors = vec![];
-        let mut policy_result: Option<Result<usize, usize>> = None;
+    ) -> Result<Vec<T>, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let rule = rule.try_into()?.convert(&mut self.symbols);
 
-        //FIXME: the authorizer should be generated with run limits
-        // that are "consumed" after each use
-        // Note: the authority facts and rules were already inserted
-        // in add_token
-        self.world
-            .run_with_limits(&self.symbols, RunLimits::default())
-            .map_err(error::Token::RunLimit)?;
-        self.world.rules.clear();
+        self.run_world(self.run_limits(limits), core::slice::from_ref(&rule))?;
+        let mut res = self.world.query_rule(rule.clone(), &self.symbols);
 
-        for (i, check) in self.checks.iter().enumerate() {
-            let c = check.convert(&mut self.symbols);
-            let mut successful = false;
+        let r: HashSet<_> = res
+            .drain(..)
+            .chain(
+                self.block_worlds
+                    .iter()
+                    .map(|world| rule.apply(&world.facts, &self.symbols))
+                    .flatten(),
+            )
+            .collect();
 
-            for query in check.queries.iter() {
-                let res = self
-                    .world
-                    .query_match(query.convert(&mut self.symbols), &self.symbols);
+        r.into_iter()
+            .map(|f| Fact::convert_from(&f, &self.symbols))
+            .map(|fact| fact.try_into().map_err(Into::into))
+            .collect()
+    }
 
-                let now = Instant::now();
-                if now >= time_limit {
-                    return Err(error::Token::RunLimit(error::RunLimit::Timeout));
-                }
+    /// returns where a fact comes from: the authorizer, the authority block,
+    /// another block, or a rule
+    ///
+    /// this looks first in the authorizer's world, then in the worlds
+    /// generated for the token's blocks during authorization
+    pub fn fact_origin<F: TryInto<Fact>>(
+        &mut self,
+        fact: F,
+    ) -> Result<Option<datalog::Origin>, error::Token>
+    where
+        error::Token: From<<F as TryInto<Fact>>::Error>,
+    {
+        let fact = fact.try_into()?;
+        fact.validate()?;
+        let fact = fact.convert(&mut self.symbols);
 
-                if res {
-                    successful = true;
-                    break;
-                }
-            }
+        Ok(core::iter::once(&self.world)
+            .chain(self.block_worlds.iter())
+            .find_map(|world| world.facts.origin(&fact)))
+    }
 
-            if !successful {
-                errors.push(error::FailedCheck::Authorizer(
-                    error::FailedAuthorizerCheck {
-                        check_id: i as u32,
-                        rule: self.symbols.print_check(&c),
-                    },
-                ));
+    /// add a check to the authorizer
+    pub fn add_check<C: TryInto<Check>>(&mut self, check: C) -> Result<(), error::Token>
+    where
+        error::Token: From<<C as TryInto<Check>>::Error>,
+    {
+        let check = check.try_into()?;
+        check_enumerations(&self.enumerations, check.predicates())?;
+        self.checks.push(check);
+        Ok(())
+    }
+
+    /// declares the strings allowed in a term of a predicate, see
+    /// [BlockBuilder::add_enumeration](crate::builder::BlockBuilder::add_enumeration)
+    ///
+    /// the facts, rules, checks and policies added afterwards are checked,
+    /// not the ones coming from the token
+    pub fn add_enumeration<E: TryInto<Enumeration>>(
+        &mut self,
+        enumeration: E,
+    ) -> Result<(), error::Token>
+    where
+        error::Token: From<<E as TryInto<Enumeration>>::Error>,
+    {
+        declare_enumeration(&mut self.enumerations, enumeration.try_into()?)
+    }
+
+    /// adds the checks and policies of a pack, after the existing ones
+    ///
+    /// if a pack with the same name was already added, its checks and
+    /// policies are replaced in place. Nothing is changed if a check or a
+    /// policy is invalid, see the [policy_pack module](crate::policy_pack)
+    pub fn add_policy_pack(&mut self, pack: &PolicyPack) -> Result<(), error::Token> {
+        let (checks, policies) = pack.parse()?;
+
+        let (check_range, policy_range) = self.policy_packs.remove(&pack.name).unwrap_or((
+            self.checks.len()..self.checks.len(),
+            self.policies.len()..self.policies.len(),
+        ));
+        let (check_count, policy_count) = (checks.len(), policies.len());
+        let shift = |index: usize, range: &Range<usize>, count: usize| {
+            if index >= range.end {
+                index - range.len() + count
+            } else {
+                index
             }
+        };
+
+        // indexes after the replaced checks and policies are moved
+        self.check_sources = core::mem::take(&mut self.check_sources)
+            .into_iter()
+            .filter(|(index, _)| !check_range.contains(index))
+            .map(|(index, source)| (shift(index, &check_range, check_count), source))
+            .collect();
+        for (_, index) in self.tenant.iter_mut().chain(self.audience.iter_mut()) {
+            *index = shift(*index, &check_range, check_count);
+        }
+        for (checks, policies) in self.policy_packs.values_mut() {
+            *checks = shift(checks.start, &check_range, check_count)
+                ..shift(checks.end, &check_range, check_count);
+            *policies = shift(policies.start, &policy_range, policy_count)
+                ..shift(policies.end, &policy_range, policy_count);
         }
 
-        if let Some(token) = self.token.as_ref() {
-            for (j, check) in token.authority.checks.iter().enumerate() {
-                let mut successful = false;
+        let (sources, checks): (Vec<_>, Vec<_>) = checks.into_iter().unzip();
+        for (offset, source) in sources.into_iter().enumerate() {
+            self.check_sources
+                .insert(check_range.start + offset, source);
+        }
+        self.checks.splice(check_range.clone(), checks);
+        self.policies.splice(policy_range.clone(), policies);
+        self.policy_packs.insert(
+            pack.name.clone(),
+            (
+                check_range.start..check_range.start + check_count,
+                policy_range.start..policy_range.start + policy_count,
+            ),
+        );
 
-                let c = Check::convert_from(check, &token.symbols);
-                let check = c.convert(&mut self.symbols);
+        Ok(())
+    }
 
-                for query in check.queries.iter() {
-                    let res = self.world.query_match(query.clone(), &self.symbols);
+    /// authorizes with the active checks and policies, and with a proposed
+    /// version of a policy pack, to see if the decision would change before
+    /// rolling it out
+    ///
+    /// the pack replaces the one with the same name, or is added after the
+    /// existing checks and policies. The authorizer is not modified, and
+    /// nonces of one-time tokens are not recorded
+    pub fn dry_run(
+        &self,
+        pack: &PolicyPack,
+        limits: AuthorizerLimits,
+    ) -> Result<DryRun, error::Token> {
+        let mut active = self.clone();
+        active.replay_store = None;
+        let mut proposed = active.clone();
+        proposed.metrics = None;
+        proposed.add_policy_pack(pack)?;
+
+        Ok(DryRun {
+            active: active.authorize_with_limits(limits.clone()),
+            proposed: proposed.authorize_with_limits(limits),
+        })
+    }
 
-                    let now = Instant::now();
-                    if now >= time_limit {
-                        return Err(error::Token::RunLimit(error::RunLimit::Timeout));
-                    }
+    /// adds a fact with the current time
+    #[cfg(feature = "std")]
+    pub fn set_time(&mut self) {
+        let fact = fact(
+            "time",
+            &[super::builder::date(&std::time::SystemTime::now())],
+        );
+        self.world.facts.insert(fact.convert(&mut self.symbols));
+    }
 
-                    if res {
-                        successful = true;
-                        break;
-                    }
-                }
+    /// adds the resource, its ancestors and their parent relations, see
+    /// the [hierarchy module](crate::hierarchy)
+    pub fn add_resource_hierarchy(&mut self, path: &str) {
+        for fact in crate::hierarchy::facts(path) {
+            self.world.facts.insert(fact.convert(&mut self.symbols));
+        }
+    }
 
-                if !successful {
-                    errors.push(error::FailedCheck::Block(error::FailedBlockCheck {
-                        block_id: 0u32,
-                        check_id: j as u32,
-                        rule: self.symbols.print_check(&check),
-                    }));
-                }
+    /// only accepts tokens whose authority block contains `tenant(tenant)`
+    ///
+    /// this adds the check `check if tenant(tenant)`, which also fails if
+    /// the authorizer or the authority block has a `tenant` fact for another
+    /// tenant. See the [tenant module](crate::tenant)
+    pub fn set_tenant(&mut self, tenant: &str) {
+        let check = crate::tenant::tenant_check(tenant);
+        match self.tenant.as_ref() {
+            Some((_, index)) => {
+                let index = *index;
+                self.checks[index] = check;
+                self.check_sources.remove(&index);
+                self.tenant = Some((tenant.to_string(), index));
+            }
+            None => {
+                self.tenant = Some((tenant.to_string(), self.checks.len()));
+                self.checks.push(check);
             }
         }
+    }
 
-        'policies_test: for (i, policy) in self.policies.iter().enumerate() {
-            for query in policy.queries.iter() {
-                let res = self
-                    .world
-                    .query_match(query.convert(&mut self.symbols), &self.symbols);
+    /// index of the tenant check, if there are `tenant` facts for other tenants
+    fn foreign_tenant_check(&self) -> Option<usize> {
+        let (tenant, index) = self.tenant.as_ref()?;
+        let name = self.symbols.get("tenant")?;
 
-                let now = Instant::now();
-                if now >= time_limit {
-                    return Err(error::Token::RunLimit(error::RunLimit::Timeout));
-                }
+        let foreign =
+            self.world
+                .facts
+                .iter_predicate(name)
+                .any(|f| match f.predicate.terms.first() {
+                    Some(datalog::Term::Str(t)) => {
+                        self.symbols.get_symbol(*t) != Some(tenant.as_str())
+                    }
+                    _ => true,
+                });
 
-                if res {
-                    match policy.kind {
-                        PolicyKind::Allow => policy_result = Some(Ok(i)),
-                        PolicyKind::Deny => policy_result = Some(Err(i)),
-                    };
-                    break 'policies_test;
-                }
+        if foreign {
+            Some(*index)
+        } else {
+            None
+        }
+    }
+
+    /// only accepts tokens whose authority block contains `audience(audience)`,
+    /// added with [BiscuitBuilder::add_audience](crate::builder::BiscuitBuilder::add_audience)
+    ///
+    /// this adds the check `check if audience(audience)`. When it fails, the
+    /// authorization returns [error::Token::WrongAudience]
+    pub fn set_audience(&mut self, audience: &str) {
+        let check = super::builder::check(&[super::builder::pred("audience", &[string(audience)])]);
+        match self.audience.as_ref() {
+            Some((_, index)) => {
+                let index = *index;
+                self.checks[index] = check;
+                self.check_sources.remove(&index);
+                self.audience = Some((audience.to_string(), index));
+            }
+            None => {
+                self.audience = Some((audience.to_string(), self.checks.len()));
+                self.checks.push(check);
             }
         }
+    }
 
-        if let Some(token) = self.token.as_ref() {
-            for (i, block) in token.blocks.iter().enumerate() {
-                let mut world = self.world.clone();
+    /// records the nonces of one-time tokens when they are authorized, and
+    /// rejects them if they were already used. See the [nonce module](crate::nonce)
+    pub fn set_replay_store<S: ReplayStore + 'static>(&mut self, store: S) {
+        self.replay_store = Some(Arc::new(store));
+    }
 
-                // blocks cannot provide authority or ambient facts
-                for fact in block.facts.iter().cloned() {
-                    let fact = Fact::convert_from(&fact, &token.symbols).convert(&mut self.symbols);
-                    world.facts.insert(fact);
-                }
+    /// records the `nonce` facts of the authority block
+    fn record_nonces(&self) -> Result<(), error::Token> {
+        let (store, name) = match (self.replay_store.as_ref(), self.symbols.get("nonce")) {
+            (Some(store), Some(name)) => (store, name),
+            _ => return Ok(()),
+        };
 
-                for rule in block.rules.iter().cloned() {
-                    let r = Rule::convert_from(&rule, &token.symbols);
+        for fact in self.world.facts.iter_predicate(name) {
+            if self.world.facts.origin(fact) != Some(datalog::Origin::Authority) {
+                continue;
+            }
 
-                    if let Err(_message) = r.validate_variables() {
-                        return Err(error::Logic::InvalidBlockRule(
-                            i as u32,
-                            token.symbols.print_rule(&rule),
-                        )
-                        .into());
-                    }
+            let nonce = match fact.predicate.terms.first() {
+                Some(datalog::Term::Bytes(nonce)) => nonce,
+                _ => continue,
+            };
+            if !store.record(nonce).map_err(error::Replay::Lookup)? {
+                return Err(error::Replay::Reused.into());
+            }
+        }
 
-                    let rule = r.convert(&mut self.symbols);
-                    world.rules.push(rule);
-                }
+        Ok(())
+    }
 
-                world
-                    .run_with_limits(&self.symbols, RunLimits::default())
+    /// adds the `client_ip` fact, checked with `$range.contains_ip($ip)`
+    pub fn set_client_ip(&mut self, ip: core::net::IpAddr) {
+        let fact = fact("client_ip", &[string(&ip.to_string())]);
+        self.world.facts.insert(fact.convert(&mut self.symbols));
+    }
+
+    /// adds the `root_key` fact, for the tokens pinned to a root key with
+    /// [BlockBuilder::pin_root_keys](crate::builder::BlockBuilder::pin_root_keys)
+    pub fn set_root_key(&mut self, key: &PublicKey) {
+        let fact = fact("root_key", &[bytes(&key.to_bytes())]);
+        self.world.facts.insert(fact.convert(&mut self.symbols));
+    }
+
+    /// add a policy to the authorizer
+    pub fn add_policy<P: TryInto<Policy>>(&mut self, policy: P) -> Result<(), error::Token>
+    where
+        error::Token: From<<P as TryInto<Policy>>::Error>,
+    {
+        let policy = policy.try_into()?;
+        check_enumerations(&self.enumerations, policy.predicates())?;
+        self.policies.push(policy);
+        Ok(())
+    }
+
+    /// adds a `allow if true` policy
+    pub fn allow(&mut self) -> Result<(), error::Token> {
+        self.add_policy("allow if true")
+    }
+
+    /// adds a `deny if true` policy
+    pub fn deny(&mut self) -> Result<(), error::Token> {
+        self.add_policy("deny if true")
+    }
+
+    /// adds an allow policy matching the body of `rule`
+    ///
+    /// the head of the rule is ignored
+    ///
+    /// ```rust
+    /// # use biscuit_auth::Authorizer;
+    /// # use biscuit_auth::builder::{pred, rule, string, var};
+    /// let mut authorizer = Authorizer::new().unwrap();
+    /// authorizer.add_fact("user(\"alice\")").unwrap();
+    /// authorizer
+    ///     .allow_if(rule("allowed", &[var("u")], &[pred("user", &[var("u")])]))
+    ///     .unwrap();
+    /// authorizer
+    ///     .deny_if(rule("denied", &[string("bob")], &[pred("user", &[string("bob")])]))
+    ///     .unwrap();
+    /// assert_eq!(authorizer.authorize(), Ok(0));
+    /// ```
+    pub fn allow_if(&mut self, rule: Rule) -> Result<(), error::Token> {
+        self.add_policy_query(rule, PolicyKind::Allow)
+    }
+
+    /// adds a deny policy matching the body of `rule`, see [Authorizer::allow_if]
+    pub fn deny_if(&mut self, rule: Rule) -> Result<(), error::Token> {
+        self.add_policy_query(rule, PolicyKind::Deny)
+    }
+
+    fn add_policy_query(&mut self, mut rule: Rule, kind: PolicyKind) -> Result<(), error::Token> {
+        let empty_terms: &[Term] = &[];
+        rule.head = pred("query", empty_terms);
+        rule.validate_parameters()?;
+
+        self.policies.push(Policy {
+            queries: vec![rule],
+            kind,
+        });
+        Ok(())
+    }
+
+    /// adds the revocation ids of the token as `revocation_id(block index, id)`
+    /// facts, now if the token was already added, or when it is added
+    ///
+    /// these facts are not counted against the [AuthorizerLimits]
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Biscuit, KeyPair};
+    /// let root = KeyPair::new();
+    /// let token = Biscuit::builder(&root).build().unwrap();
+    /// let revoked = hex::encode(&token.revocation_identifiers()[0]);
+    ///
+    /// let mut authorizer = token.authorizer().unwrap();
+    /// authorizer.add_revocation_id_facts();
+    /// authorizer
+    ///     .add_code(&format!("deny if revocation_id($i, $id), [hex:{}].contains($id); allow if true", revoked))
+    ///     .unwrap();
+    /// assert!(authorizer.authorize().is_err());
+    /// ```
+    pub fn add_revocation_id_facts(&mut self) {
+        if self.revocation_id_facts.is_none() {
+            self.revocation_id_facts = Some(Vec::new());
+            self.insert_revocation_id_facts();
+        }
+    }
+
+    fn insert_revocation_id_facts(&mut self) {
+        let (token, inserted) = match (self.token, self.revocation_id_facts.as_mut()) {
+            (Some(token), Some(inserted)) => (token, inserted),
+            _ => return,
+        };
+
+        for (i, id) in token.revocation_identifiers().iter().enumerate() {
+            let revocation_id =
+                fact("revocation_id", &[int(i as i64), bytes(id)]).convert(&mut self.symbols);
+            if self.world.facts.insert(revocation_id.clone()) {
+                inserted.push(revocation_id);
+            }
+        }
+    }
+
+    /// converts the limits, leaving room for the facts added by
+    /// [Authorizer::add_revocation_id_facts]
+    fn run_limits(&self, limits: AuthorizerLimits) -> RunLimits {
+        let mut run_limits: RunLimits = limits.into();
+
+        if let Some(facts) = self.revocation_id_facts.as_ref() {
+            let count = facts.len() as u32;
+            run_limits.max_facts = run_limits.max_facts.saturating_add(count);
+            run_limits.max_memory_bytes = run_limits
+                .max_memory_bytes
+                .saturating_add(facts.iter().map(|f| f.estimated_memory() as u64).sum());
+            if let Some(max) = run_limits.max_facts_per_predicate.get_mut("revocation_id") {
+                *max = max.saturating_add(count);
+            }
+        }
+
+        run_limits
+    }
+
+    /// makes [Authorizer::authorize] fail with [error::Logic::NoPolicies]
+    /// if no policies were added
+    ///
+    /// without policies, authorization always fails with
+    /// [error::Logic::NoMatchingPolicy], even if the checks pass
+    pub fn set_require_policies(&mut self, required: bool) {
+        self.require_policies = required;
+    }
+
+    /// sets a sink receiving the [AuthorizationMetrics] of each call to
+    /// [Authorizer::authorize] and [Authorizer::authorize_with_limits]
+    ///
+    /// ```rust
+    /// # use biscuit_auth::Authorizer;
+    /// # use biscuit_auth::AuthorizationMetrics;
+    /// # use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    /// let denied = Arc::new(AtomicUsize::new(0));
+    /// let counter = denied.clone();
+    ///
+    /// let mut authorizer = Authorizer::new().unwrap();
+    /// authorizer.set_metrics_sink(move |metrics: &AuthorizationMetrics| {
+    ///     if metrics.result.is_err() {
+    ///         counter.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// });
+    /// authorizer.add_code("deny if true").unwrap();
+    /// assert!(authorizer.authorize().is_err());
+    /// assert_eq!(denied.load(Ordering::Relaxed), 1);
+    /// ```
+    pub fn set_metrics_sink<M: MetricsSink + 'static>(&mut self, sink: M) {
+        self.metrics = Some(Arc::new(sink));
+    }
+
+    /// runs the authorizer's world, calling the external predicates used
+    /// by its rules and by `queries`
+    fn run_world(
+        &mut self,
+        limits: RunLimits,
+        queries: &[datalog::Rule],
+    ) -> Result<(), error::Token> {
+        let res = if self.externals.is_empty() {
+            self.world.run_with_limits(&self.symbols, limits)
+        } else {
+            self.world
+                .run_with_externals(&mut self.symbols, limits, &self.externals, queries)
+        };
+
+        res.map_err(error::Token::RunLimit)
+    }
+
+    /// the queries of the checks and policies that can call external predicates
+    fn external_queries(&mut self) -> Result<Vec<datalog::Rule>, error::Token> {
+        if self.externals.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut queries = Vec::new();
+        for check in self.checks.iter() {
+            for query in check.queries.iter() {
+                queries.push(query.convert(&mut self.symbols));
+            }
+        }
+
+        for policy in self.policies.iter() {
+            for query in policy.queries.iter() {
+                queries.push(query.convert(&mut self.symbols));
+            }
+        }
+
+        if let Some(token) = self.token.as_ref() {
+            let remap = token.symbols.remap_into(&mut self.symbols);
+            for check in token.authority.checks.iter() {
+                queries.extend(remap.check(check)?.queries);
+            }
+        }
+
+        Ok(queries)
+    }
+
+    /// verifies the checks and policiies
+    ///
+    /// on error, this can return a list of all the failed checks or deny policy
+    /// on success, it returns the index of the policy that matched
+    pub fn authorize(&mut self) -> Result<usize, error::Token> {
+        self.authorize_with_limits(AuthorizerLimits::default())
+    }
+
+    /// verifies the checks and policiies
+    ///
+    /// on error, this can return a list of all the failed checks or deny policy
+    ///
+    /// this method can specify custom runtime limits
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                blocks = self.token.map(|t| t.block_count()).unwrap_or(0),
+                checks = self.checks.len(),
+                policies = self.policies.len(),
+            )
+        )
+    )]
+    pub fn authorize_with_limits(
+        &mut self,
+        limits: AuthorizerLimits,
+    ) -> Result<usize, error::Token> {
+        let start = Instant::now();
+        let result = self.authorize_inner(start, &limits);
+
+        if let Some(sink) = self.metrics.as_ref() {
+            let authorizer_facts = self.world.facts.len();
+            let block_facts: usize = self
+                .block_worlds
+                .iter()
+                .map(|w| w.facts.len().saturating_sub(authorizer_facts))
+                .sum();
+
+            sink.record(&AuthorizationMetrics {
+                result: &result,
+                duration: start.elapsed(),
+                facts: authorizer_facts + block_facts,
+                limits: &limits,
+            });
+        }
+
+        result
+    }
+
+    fn authorize_inner(
+        &mut self,
+        start: Instant,
+        limits: &AuthorizerLimits,
+    ) -> Result<usize, error::Token> {
+        if self.require_policies && self.policies.is_empty() {
+            return Err(error::Logic::NoPolicies.into());
+        }
+
+        let time_limit = start + limits.max_time;
+        let mut errors = vec![];
+        let mut policy_result: Option<Result<usize, usize>> = None;
+        let run_limits = self.run_limits(limits.clone());
+
+        //FIXME: the authorizer should be generated with run limits
+        // that are "consumed" after each use
+        // Note: the authority facts and rules were already inserted
+        // in add_token
+        self.load_attributes()?;
+        let queries = self.external_queries()?;
+        self.run_world(run_limits.clone(), &queries)?;
+        self.run_rules = core::mem::take(&mut self.world.rules);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(facts = self.world.facts.len(), "authorizer world run");
+        let mut runner = QueryRunner::new(self.token, run_limits.clone());
+
+        let foreign_tenant_check = self.foreign_tenant_check();
+        for (i, check) in self.checks.iter().enumerate() {
+            let c = check.convert(&mut self.symbols);
+            let mut successful = false;
+
+            for query in check.queries.iter() {
+                let query = query.convert(&mut self.symbols);
+                let res = runner.query_match(&self.world, 0, &mut self.symbols, query)?;
+
+                let now = Instant::now();
+                if now >= time_limit {
+                    return Err(error::Token::RunLimit(error::RunLimit::Timeout));
+                }
+
+                if res {
+                    successful = true;
+                    break;
+                }
+            }
+
+            if foreign_tenant_check == Some(i) {
+                successful = false;
+            }
+
+            if let Some((audience, index)) = self.audience.as_ref() {
+                if !successful && *index == i {
+                    return Err(error::Token::WrongAudience(audience.clone()));
+                }
+            }
+
+            if !successful {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(check_id = i, "authorizer check failed");
+                errors.push(error::FailedCheck::Authorizer(
+                    error::FailedAuthorizerCheck {
+                        check_id: i as u32,
+                        rule: self.symbols.print_check(&c),
+                        source: self.check_sources.get(&i).cloned(),
+                    },
+                ));
+            }
+        }
+
+        if let Some(token) = self.token.as_ref() {
+            let remap = token.symbols.remap_into(&mut self.symbols);
+            let revocation_ids = token.revocation_identifiers();
+
+            for (j, check) in token.authority.checks.iter().enumerate() {
+                let mut successful = false;
+
+                let check = remap.check(check)?;
+
+                for query in check.queries.iter() {
+                    let res =
+                        runner.query_match(&self.world, 0, &mut self.symbols, query.clone())?;
+
+                    let now = Instant::now();
+                    if now >= time_limit {
+                        return Err(error::Token::RunLimit(error::RunLimit::Timeout));
+                    }
+
+                    if res {
+                        successful = true;
+                        break;
+                    }
+                }
+
+                if !successful {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(block_id = 0, check_id = j, "block check failed");
+                    errors.push(error::FailedCheck::Block(error::FailedBlockCheck {
+                        block_id: 0u32,
+                        check_id: j as u32,
+                        rule: self.symbols.print_check(&check),
+                        context: token.authority.context.clone(),
+                        revocation_id: revocation_ids.first().map(hex::encode),
+                    }));
+                }
+            }
+        }
+
+        'policies_test: for (i, policy) in self.policies.iter().enumerate() {
+            for query in policy.queries.iter() {
+                let query = query.convert(&mut self.symbols);
+                let res = runner.query_match(&self.world, 0, &mut self.symbols, query)?;
+
+                let now = Instant::now();
+                if now >= time_limit {
+                    return Err(error::Token::RunLimit(error::RunLimit::Timeout));
+                }
+
+                if res {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(policy = i, kind = ?policy.kind, "policy matched");
+                    match policy.kind {
+                        PolicyKind::Allow => policy_result = Some(Ok(i)),
+                        PolicyKind::Deny => policy_result = Some(Err(i)),
+                    };
+                    break 'policies_test;
+                }
+            }
+        }
+
+        if let Some(token) = self.token.as_ref() {
+            let remap = token.symbols.remap_into(&mut self.symbols);
+            let revocation_ids = token.revocation_identifiers();
+            self.block_worlds.clear();
+
+            for (i, block) in token.blocks.iter().enumerate() {
+                let mut world = self.world.clone();
+
+                // blocks cannot provide authority or ambient facts
+                let facts = block
+                    .facts
+                    .iter()
+                    .map(|fact| remap.fact(fact))
+                    .collect::<Result<_, _>>()?;
+                let mut rules = Vec::new();
+
+                for rule in block.rules.iter() {
+                    if !rule.head_variables_bound() {
+                        return Err(error::Logic::InvalidBlockRule(
+                            i as u32,
+                            token.symbols.print_rule(rule),
+                        )
+                        .into());
+                    }
+
+                    rules.push(remap.rule(rule)?);
+                }
+
+                // the authorizer's world was already run, only the consequences
+                // of the block's facts and rules have to be derived
+                world
+                    .run_incremental(
+                        facts,
+                        datalog::Origin::Block(i + 1),
+                        rules,
+                        &self.symbols,
+                        run_limits.clone(),
+                    )
                     .map_err(error::Token::RunLimit)?;
                 world.rules.clear();
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    block_id = i + 1,
+                    facts = world.facts.len(),
+                    "block world run"
+                );
 
                 for (j, check) in block.checks.iter().enumerate() {
                     let mut successful = false;
-                    let c = Check::convert_from(check, &token.symbols);
-                    let check = c.convert(&mut self.symbols);
+                    let check = remap.check(check)?;
 
                     for query in check.queries.iter() {
-                        let res = world.query_match(query.clone(), &self.symbols);
+                        let res =
+                            runner.query_match(&world, i + 1, &mut self.symbols, query.clone())?;
 
                         let now = Instant::now();
                         if now >= time_limit {
@@ -547,204 +1844,1932 @@ impl<'t> Authorizer<'t> {
                     }
 
                     if !successful {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(block_id = i + 1, check_id = j, "block check failed");
                         errors.push(error::FailedCheck::Block(error::FailedBlockCheck {
                             block_id: (i + 1) as u32,
                             check_id: j as u32,
                             rule: self.symbols.print_check(&check),
+                            context: block.context.clone(),
+                            revocation_id: revocation_ids.get(i + 1).map(hex::encode),
                         }));
                     }
                 }
 
-                self.block_worlds.push(world);
-            }
-        }
+                self.block_worlds.push(world);
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            failed_checks = errors.len(),
+            policy = ?policy_result,
+            "authorization complete"
+        );
+
+        match (policy_result, errors.is_empty()) {
+            (Some(Ok(i)), true) => self.record_nonces().map(|_| i),
+            (None, _) => Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy {
+                checks: errors,
+            })),
+            (Some(Ok(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
+                policy: error::MatchedPolicy::Allow(i),
+                checks: errors,
+            })),
+            (Some(Err(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
+                policy: error::MatchedPolicy::Deny(i),
+                checks: errors,
+            })),
+        }
+    }
+
+    /// authorizes a token with a copy of this authorizer, leaving it unchanged
+    ///
+    /// the authorizer must not contain a token. The copy shares the facts
+    /// with it, and only copies the facts of a predicate when the token or
+    /// the rules add facts with the same name, so an authorizer loaded with
+    /// a lot of data can be kept in an `Arc` and used by multiple threads
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Authorizer, Biscuit, KeyPair};
+    /// # use std::sync::Arc;
+    /// let mut authorizer = Authorizer::new().unwrap();
+    /// authorizer.add_code("resource(\"file1\"); allow if user($u)").unwrap();
+    /// let authorizer = Arc::new(authorizer);
+    ///
+    /// let root = KeyPair::new();
+    /// let mut builder = Biscuit::builder(&root);
+    /// builder.add_authority_fact("user(\"alice\")").unwrap();
+    /// let token = builder.build().unwrap();
+    ///
+    /// let shared = authorizer.clone();
+    /// std::thread::spawn(move || assert_eq!(shared.authorize_token(&token), Ok(0)))
+    ///     .join()
+    ///     .unwrap();
+    /// ```
+    pub fn authorize_token(&self, token: &Biscuit) -> Result<usize, error::Token> {
+        self.authorize_token_with_limits(token, AuthorizerLimits::default())
+    }
+
+    /// authorizes a token with a copy of this authorizer, with custom limits,
+    /// see [Authorizer::authorize_token]
+    pub fn authorize_token_with_limits(
+        &self,
+        token: &Biscuit,
+        limits: AuthorizerLimits,
+    ) -> Result<usize, error::Token> {
+        if self.token.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        let mut authorizer = self.template_copy();
+        authorizer.add_token(token)?;
+        authorizer.authorize_with_limits(limits)
+    }
+
+    /// number of facts, rules, checks and policies, to estimate the cost of an authorization
+    #[cfg(feature = "tokio-integration")]
+    pub(crate) fn size(&self) -> usize {
+        self.world.facts.len()
+            + self.world.rules.len()
+            + self.run_rules.len()
+            + self.checks.len()
+            + self.policies.len()
+    }
+
+    /// runs the rules on the authorizer's facts, keeping the rules to apply
+    /// them to the facts added later, see [AuthorizerPool](super::pool::AuthorizerPool)
+    #[cfg(feature = "std")]
+    pub(crate) fn prewarm(&mut self, limits: &AuthorizerLimits) -> Result<(), error::Token> {
+        if self.token.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        let queries = self.external_queries()?;
+        self.run_world(self.run_limits(limits.clone()), &queries)
+    }
+
+    /// restores the state of `template` after an authorization, keeping
+    /// the checks and policies
+    #[cfg(feature = "std")]
+    pub(crate) fn reset<'a>(mut self, template: &Authorizer) -> Authorizer<'a> {
+        self.world = template.world.clone();
+        self.block_worlds.clear();
+        // the token's symbols were added after the template's
+        self.symbols.split_at(template.symbols.current_offset());
+        self.token_checks = template.token_checks.clone();
+        self.run_rules.clear();
+
+        Authorizer {
+            world: self.world,
+            block_worlds: self.block_worlds,
+            symbols: self.symbols,
+            checks: self.checks,
+            check_sources: self.check_sources,
+            token_checks: self.token_checks,
+            policies: self.policies,
+            token: None,
+            externals: self.externals,
+            metrics: self.metrics,
+            run_rules: self.run_rules,
+            attribute_sources: self.attribute_sources,
+            tenant: self.tenant,
+            audience: self.audience,
+            replay_store: self.replay_store,
+            policy_packs: self.policy_packs,
+            require_policies: self.require_policies,
+            revocation_id_facts: template.revocation_id_facts.clone(),
+            enumerations: self.enumerations,
+        }
+    }
+
+    /// copies the authorizer's data, without the token and the results of
+    /// a previous authorization
+    pub(crate) fn template_copy<'a>(&self) -> Authorizer<'a> {
+        let mut world = self.world.clone();
+        world.rules.extend(self.run_rules.iter().cloned());
+
+        Authorizer {
+            world,
+            block_worlds: vec![],
+            symbols: self.symbols.clone(),
+            checks: self.checks.clone(),
+            check_sources: self.check_sources.clone(),
+            token_checks: vec![],
+            policies: self.policies.clone(),
+            token: None,
+            externals: self.externals.clone(),
+            metrics: self.metrics.clone(),
+            run_rules: vec![],
+            attribute_sources: self.attribute_sources.clone(),
+            tenant: self.tenant.clone(),
+            audience: self.audience.clone(),
+            replay_store: self.replay_store.clone(),
+            policy_packs: self.policy_packs.clone(),
+            require_policies: self.require_policies,
+            revocation_id_facts: self.revocation_id_facts.clone(),
+            enumerations: self.enumerations.clone(),
+        }
+    }
+
+    /// runs the authorizer's checks and policies on multiple tokens
+    ///
+    /// the authorizer is used as a template: it must not contain a token, and
+    /// each token is authorized with a copy of its facts, rules, checks and policies
+    pub fn authorize_batch(
+        &self,
+        tokens: &[Biscuit],
+        limits: AuthorizerLimits,
+    ) -> Result<Vec<Result<usize, error::Token>>, error::Token> {
+        if self.token.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        let mut results = Vec::new();
+
+        for token in tokens {
+            let mut authorizer = self.template_copy();
+
+            results.push(
+                authorizer
+                    .add_token(token)
+                    .and_then(|_| authorizer.authorize_with_limits(limits.clone())),
+            );
+        }
+
+        Ok(results)
+    }
+
+    /// prints the content of the authorizer
+    pub fn print_world(&self) -> String {
+        let mut facts = self
+            .world
+            .facts
+            .iter_with_origin()
+            .map(|(f, origin)| format!("{} // {}", self.symbols.print_fact(f), origin))
+            .collect::<Vec<_>>();
+        facts.sort();
+
+        let mut rules = self
+            .world
+            .rules
+            .iter()
+            .map(|r| self.symbols.print_rule(r))
+            .collect::<Vec<_>>();
+        rules.sort();
+
+        let mut checks = Vec::new();
+        for (index, check) in self.checks.iter().enumerate() {
+            checks.push(format!("Authorizer[{}]: {}", index, check));
+        }
+
+        for (i, block_checks) in self.token_checks.iter().enumerate() {
+            for (j, check) in block_checks.iter().enumerate() {
+                checks.push(format!(
+                    "Block[{}][{}]: {}",
+                    i,
+                    j,
+                    self.symbols.print_check(check)
+                ));
+            }
+        }
+
+        let mut policies = Vec::new();
+        for policy in self.policies.iter() {
+            policies.push(policy.to_string());
+        }
+
+        format!(
+            "World {{\n  facts: {:#?}\n  rules: {:#?}\n  checks: {:#?}\n  policies: {:#?}\n}}",
+            facts, rules, checks, policies
+        )
+    }
+
+    /// looks for problems in the authorizer's rules, without running them
+    ///
+    /// this finds the rules that depend on each other recursively without a
+    /// base case, and the rules that can never match because a predicate in
+    /// their body has no facts, is not external and is not generated by
+    /// another rule
+    pub fn analyze_rules(&self) -> RuleAnalysis {
+        let diagnostics = self.world.analyze_rules_with_externals(&self.externals);
+
+        RuleAnalysis {
+            cycles: diagnostics
+                .cycles
+                .iter()
+                .map(|cycle| {
+                    cycle
+                        .iter()
+                        .map(|i| self.symbols.print_rule(&self.world.rules[*i]))
+                        .collect()
+                })
+                .collect(),
+            unproductive: diagnostics
+                .unproductive
+                .iter()
+                .map(|u| {
+                    (
+                        self.symbols.print_rule(&self.world.rules[u.rule]),
+                        self.symbols.print_symbol(u.missing),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// returns the facts each block added to the authorizer's world,
+    /// either directly or through its rules
+    ///
+    /// this is available after calling [Authorizer::authorize]
+    pub fn debug_block_effects(&self) -> Vec<BlockEffects> {
+        self.block_worlds
+            .iter()
+            .enumerate()
+            .map(|(i, world)| {
+                let diff = self.world.diff(world);
+                let print = |facts: Vec<datalog::Fact>| {
+                    let mut facts = facts
+                        .iter()
+                        .map(|f| Fact::convert_from(f, &self.symbols))
+                        .collect::<Vec<_>>();
+                    facts.sort_by_cached_key(|f| f.to_string());
+                    facts
+                };
+
+                BlockEffects {
+                    block_id: (i + 1) as u32,
+                    added: print(diff.added),
+                    removed: print(diff.removed),
+                }
+            })
+            .collect()
+    }
+
+    /// returns all of the data loaded in the authorizer
+    pub fn dump(&self) -> (Vec<Fact>, Vec<Rule>, Vec<Check>, Vec<Policy>) {
+        let mut checks = self.checks.clone();
+        checks.extend(
+            self.token_checks
+                .iter()
+                .flatten()
+                .map(|c| Check::convert_from(c, &self.symbols)),
+        );
+
+        (
+            self.world
+                .facts
+                .iter()
+                .chain(
+                    self.block_worlds
+                        .iter()
+                        .map(|world| world.facts.iter())
+                        .flatten(),
+                )
+                .map(|f| Fact::convert_from(f, &self.symbols))
+                .collect(),
+            self.world
+                .rules
+                .iter()
+                .map(|r| Rule::convert_from(r, &self.symbols))
+                .collect(),
+            checks,
+            self.policies.clone(),
+        )
+    }
+
+    /// content of the authorizer and its token, for [crate::lint]
+    pub(crate) fn lint_program(&self) -> crate::lint::Program {
+        let mut program = match self.token {
+            Some(token) => crate::lint::Program::from_token(token),
+            None => crate::lint::Program::default(),
+        };
+
+        let mut checks = self.checks.clone();
+        checks.extend(
+            self.token_checks
+                .iter()
+                .flatten()
+                .map(|c| Check::convert_from(c, &self.symbols)),
+        );
+
+        program.blocks.push(crate::lint::ProgramBlock {
+            id: None,
+            facts: self
+                .world
+                .facts
+                .iter_with_origin()
+                .filter(|(_, origin)| *origin == datalog::Origin::Authorizer)
+                .map(|(f, _)| Fact::convert_from(f, &self.symbols))
+                .collect(),
+            rules: self
+                .world
+                .rules
+                .iter()
+                .chain(self.run_rules.iter())
+                .map(|r| Rule::convert_from(r, &self.symbols))
+                .collect(),
+            checks,
+        });
+        program.policies = self.policies.clone();
+        program.externals = self
+            .externals
+            .names()
+            .map(|name| self.symbols.print_symbol(name))
+            .collect();
+        program.complete = true;
+
+        program
+    }
+
+    /// exports the dependencies between predicates, rules, checks and policies
+    ///
+    /// each predicate is shown with its number of facts, so calling this after
+    /// [Authorizer::authorize] shows what the rules derived. Rules link the
+    /// predicates of their body to the predicate they generate, and checks and
+    /// policies are linked to the predicates they query
+    pub fn export_graph(&self, format: GraphFormat) -> String {
+        let mut graph = Graph::default();
+
+        for fact in self.world.facts.iter() {
+            graph.count_fact(self.symbols.print_symbol(fact.predicate.name));
+        }
+        for world in self.block_worlds.iter() {
+            for fact in world.facts.iter() {
+                if !self.world.facts.contains(fact) {
+                    graph.count_fact(self.symbols.print_symbol(fact.predicate.name));
+                }
+            }
+        }
+
+        for rule in self.world.rules.iter().chain(self.run_rules.iter()) {
+            graph.add_rule(&Rule::convert_from(rule, &self.symbols));
+        }
+        if let Some(token) = self.token {
+            for block in core::iter::once(&token.authority).chain(token.blocks.iter()) {
+                for rule in block.rules.iter() {
+                    graph.add_rule(&Rule::convert_from(rule, &token.symbols));
+                }
+            }
+        }
+
+        for (i, check) in self.checks.iter().enumerate() {
+            graph.add_query(
+                format!("authorizer check {}: {}", i, check),
+                GraphNode::Check,
+                &check.queries,
+            );
+        }
+        for (i, block_checks) in self.token_checks.iter().enumerate() {
+            for (j, check) in block_checks.iter().enumerate() {
+                let check = Check::convert_from(check, &self.symbols);
+                graph.add_query(
+                    format!("block {} check {}: {}", i, j, check),
+                    GraphNode::Check,
+                    &check.queries,
+                );
+            }
+        }
+        if let Some(token) = self.token {
+            for (i, block) in core::iter::once(&token.authority)
+                .chain(token.blocks.iter())
+                .enumerate()
+            {
+                for (j, check) in block.checks.iter().enumerate() {
+                    let check = Check::convert_from(check, &token.symbols);
+                    graph.add_query(
+                        format!("block {} check {}: {}", i, j, check),
+                        GraphNode::Check,
+                        &check.queries,
+                    );
+                }
+            }
+        }
+        for (i, policy) in self.policies.iter().enumerate() {
+            graph.add_query(
+                format!("policy {}: {}", i, policy),
+                GraphNode::Policy,
+                &policy.queries,
+            );
+        }
+
+        graph.print(format)
+    }
+}
+
+/// output format of [Authorizer::export_graph]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz `dot` language
+    Graphviz,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GraphNode {
+    Rule,
+    Check,
+    Policy,
+}
+
+/// dependency graph built by [Authorizer::export_graph]
+#[derive(Default)]
+struct Graph {
+    /// predicate names with their number of facts
+    predicates: alloc::collections::BTreeMap<String, usize>,
+    nodes: Vec<(GraphNode, String)>,
+    /// edges between predicates and nodes, as (predicate, node index)
+    inputs: Vec<(String, usize)>,
+    outputs: Vec<(usize, String)>,
+}
+
+impl Graph {
+    fn count_fact(&mut self, name: String) {
+        *self.predicates.entry(name).or_insert(0) += 1;
+    }
+
+    fn add_rule(&mut self, rule: &Rule) {
+        let index = self.add_query(
+            rule.to_string(),
+            GraphNode::Rule,
+            core::slice::from_ref(rule),
+        );
+        self.predicates.entry(rule.head.name.clone()).or_insert(0);
+        self.outputs.push((index, rule.head.name.clone()));
+    }
+
+    fn add_query(&mut self, label: String, kind: GraphNode, queries: &[Rule]) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push((kind, label));
+
+        let mut names = queries
+            .iter()
+            .flat_map(|query| query.body.iter().map(|p| p.name.clone()))
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        for name in names {
+            self.predicates.entry(name.clone()).or_insert(0);
+            self.inputs.push((name, index));
+        }
+
+        index
+    }
+
+    fn print(&self, format: GraphFormat) -> String {
+        let predicate_ids = self
+            .predicates
+            .keys()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), format!("p{}", i)))
+            .collect::<HashMap<_, _>>();
+        let node_id = |index: usize| match self.nodes[index].0 {
+            GraphNode::Rule => format!("r{}", index),
+            GraphNode::Check => format!("c{}", index),
+            GraphNode::Policy => format!("a{}", index),
+        };
+
+        let mut lines = Vec::new();
+        match format {
+            GraphFormat::Graphviz => lines.push("digraph authorizer {".to_string()),
+            GraphFormat::Mermaid => lines.push("flowchart LR".to_string()),
+        }
+
+        for (name, facts) in self.predicates.iter() {
+            let label = format!("{} ({} facts)", name, facts);
+            lines.push(match format {
+                GraphFormat::Graphviz => format!(
+                    "  {} [label=\"{}\", shape=ellipse];",
+                    predicate_ids[name.as_str()],
+                    escape_dot(&label)
+                ),
+                GraphFormat::Mermaid => format!(
+                    "  {}([\"{}\"])",
+                    predicate_ids[name.as_str()],
+                    escape_mermaid(&label)
+                ),
+            });
+        }
+
+        for (index, (kind, label)) in self.nodes.iter().enumerate() {
+            lines.push(match format {
+                GraphFormat::Graphviz => {
+                    let shape = match kind {
+                        GraphNode::Rule => "box",
+                        GraphNode::Check => "hexagon",
+                        GraphNode::Policy => "octagon",
+                    };
+                    format!(
+                        "  {} [label=\"{}\", shape={}];",
+                        node_id(index),
+                        escape_dot(label),
+                        shape
+                    )
+                }
+                GraphFormat::Mermaid => {
+                    let (open, close) = match kind {
+                        GraphNode::Rule => ("[", "]"),
+                        GraphNode::Check => ("{{", "}}"),
+                        GraphNode::Policy => ("[/", "/]"),
+                    };
+                    format!(
+                        "  {}{}\"{}\"{}",
+                        node_id(index),
+                        open,
+                        escape_mermaid(label),
+                        close
+                    )
+                }
+            });
+        }
+
+        let arrow = match format {
+            GraphFormat::Graphviz => "->",
+            GraphFormat::Mermaid => "-->",
+        };
+        let end = match format {
+            GraphFormat::Graphviz => ";",
+            GraphFormat::Mermaid => "",
+        };
+        for (name, index) in self.inputs.iter() {
+            lines.push(format!(
+                "  {} {} {}{}",
+                predicate_ids[name.as_str()],
+                arrow,
+                node_id(*index),
+                end
+            ));
+        }
+        for (index, name) in self.outputs.iter() {
+            lines.push(format!(
+                "  {} {} {}{}",
+                node_id(*index),
+                arrow,
+                predicate_ids[name.as_str()],
+                end
+            ));
+        }
+
+        if format == GraphFormat::Graphviz {
+            lines.push("}".to_string());
+        }
+
+        lines.join("\n")
+    }
+}
+
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Mermaid labels cannot contain quotes, they are replaced with entity codes
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
+
+/// runs the check and policy queries of an authorization
+///
+/// if a query trusts third party keys, it is matched on a copy of the world
+/// extended with the facts and rules from blocks signed by those keys. The
+/// copy is made once for each world and set of trusted blocks
+struct QueryRunner<'a> {
+    token: Option<&'a Biscuit>,
+    limits: RunLimits,
+    /// by world (0 for the authorizer, the block index for block worlds)
+    /// and indexes of the trusted blocks
+    trusted_worlds: HashMap<(usize, Vec<usize>), datalog::World>,
+}
+
+impl<'a> QueryRunner<'a> {
+    fn new(token: Option<&'a Biscuit>, limits: RunLimits) -> Self {
+        QueryRunner {
+            token,
+            limits,
+            trusted_worlds: HashMap::default(),
+        }
+    }
+
+    fn query_match(
+        &mut self,
+        world: &datalog::World,
+        world_id: usize,
+        symbols: &mut datalog::SymbolTable,
+        query: datalog::Rule,
+    ) -> Result<bool, error::Token> {
+        let token = match self.token {
+            Some(token) if !query.trusted_keys.is_empty() => token,
+            _ => return Ok(world.query_match(query, symbols)),
+        };
+
+        let trusted: Vec<usize> = token
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| {
+                block
+                    .external_key
+                    .map(|key| query.trusted_keys.contains(&key))
+                    .unwrap_or(false)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if trusted.is_empty() {
+            return Ok(world.query_match(query, symbols));
+        }
+
+        let key = (world_id, trusted);
+        if !self.trusted_worlds.contains_key(&key) {
+            let extended = trusted_world(world, token, &key.1, symbols)?;
+            self.trusted_worlds.insert(key.clone(), extended);
+        }
+
+        // only the rules the query depends on need to be run
+        self.trusted_worlds[&key]
+            .query_match_goal_directed(query, symbols, self.limits.clone())
+            .map_err(error::Token::RunLimit)
+    }
+}
+
+/// copy of `world` with the facts and rules of the `trusted` blocks
+fn trusted_world(
+    world: &datalog::World,
+    token: &Biscuit,
+    trusted: &[usize],
+    symbols: &mut datalog::SymbolTable,
+) -> Result<datalog::World, error::Token> {
+    let mut world = world.clone();
+    let remap = token.symbols.remap_into(symbols);
+
+    for i in trusted.iter().copied() {
+        let block = &token.blocks[i];
+        for fact in block.facts.iter() {
+            world
+                .facts
+                .insert_with_origin(remap.fact(fact)?, datalog::Origin::Block(i + 1));
+        }
+
+        for rule in block.rules.iter() {
+            if !rule.head_variables_bound() {
+                return Err(error::Logic::InvalidBlockRule(
+                    (i + 1) as u32,
+                    token.symbols.print_rule(rule),
+                )
+                .into());
+            }
+
+            world.rules.push(remap.rule(rule)?);
+        }
+    }
+
+    Ok(world)
+}
+
+/// data covered by the signature of authorizer policies, the prefix keeps
+/// those signatures from being valid for other messages
+fn signed_policies_message(policies: &[u8]) -> Vec<u8> {
+    let mut message = b"biscuit authorizer policies\0".to_vec();
+    message.extend_from_slice(policies);
+    message
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthorizerPolicies {
+    pub version: u32,
+    /// list of symbols introduced by this block
+    pub symbols: datalog::SymbolTable,
+    /// list of facts provided by this block
+    pub facts: Vec<datalog::Fact>,
+    /// list of rules provided by blocks
+    pub rules: Vec<datalog::Rule>,
+    /// checks that the token and ambient data must validate
+    pub checks: Vec<datalog::Check>,
+    pub policies: Vec<Policy>,
+}
+
+/// facts added or removed by a block, see [Authorizer::debug_block_effects]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockEffects {
+    pub block_id: u32,
+    pub added: Vec<Fact>,
+    pub removed: Vec<Fact>,
+}
+
+/// results of [Authorizer::dry_run]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRun {
+    /// result with the active checks and policies
+    pub active: Result<usize, error::Token>,
+    /// result with the proposed policy pack
+    pub proposed: Result<usize, error::Token>,
+}
+
+impl DryRun {
+    /// indicates if the proposed pack would allow a token that is denied
+    /// now, or deny a token that is allowed now
+    pub fn differs(&self) -> bool {
+        self.active.is_ok() != self.proposed.is_ok()
+    }
+}
+
+/// results of [Authorizer::query_partial]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialResults<T> {
+    pub results: Vec<T>,
+    /// the limits were reached, some results may be missing
+    pub truncated: bool,
+}
+
+/// problems found in the authorizer's rules, see [Authorizer::analyze_rules]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RuleAnalysis {
+    /// groups of rules that depend on each other, without facts or
+    /// another rule to start the recursion
+    pub cycles: Vec<Vec<String>>,
+    /// rules that can never match, with the name of the predicate missing
+    pub unproductive: Vec<(String, String)>,
+}
+
+/// measurements of one authorization, see [Authorizer::set_metrics_sink]
+#[derive(Debug, Clone)]
+pub struct AuthorizationMetrics<'a> {
+    /// index of the matching allow policy, or the error
+    pub result: &'a Result<usize, error::Token>,
+    /// time spent evaluating the token, the checks and the policies
+    pub duration: Duration,
+    /// number of facts in the authorizer's world, and derived from the blocks
+    pub facts: usize,
+    /// limits used for this authorization
+    pub limits: &'a AuthorizerLimits,
+}
+
+impl AuthorizationMetrics<'_> {
+    /// the runtime limit that stopped the authorization, if any
+    pub fn limit_hit(&self) -> Option<&error::RunLimit> {
+        match self.result {
+            Err(error::Token::RunLimit(limit)) => Some(limit),
+            _ => None,
+        }
+    }
+}
+
+/// receives the metrics of each authorization, to export them as counters
+/// and histograms
+///
+/// this is implemented for closures taking an [AuthorizationMetrics]
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, metrics: &AuthorizationMetrics);
+}
+
+impl<F> MetricsSink for F
+where
+    F: Fn(&AuthorizationMetrics) + Send + Sync,
+{
+    fn record(&self, metrics: &AuthorizationMetrics) {
+        self(metrics)
+    }
+}
+
+/// runtime limits for the Datalog engine
+#[derive(Debug, Clone)]
+pub struct AuthorizerLimits {
+    /// maximum number of Datalog facts (memory usage)
+    pub max_facts: u32,
+    /// maximum number of iterations of the rules applications (prevents degenerate rules)
+    pub max_iterations: u32,
+    /// maximum execution time
+    pub max_time: Duration,
+    /// maximum memory used by the facts, in bytes. Unlike `max_facts`,
+    /// this accounts for the size of the terms
+    pub max_memory_bytes: u64,
+    /// maximum number of facts with a predicate name, see
+    /// [AuthorizerLimits::max_facts_for]. This also applies to the facts
+    /// of the token and of the authorizer
+    pub max_facts_per_predicate: HashMap<String, u32>,
+}
+
+impl Default for AuthorizerLimits {
+    fn default() -> Self {
+        AuthorizerLimits {
+            max_facts: 1000,
+            max_iterations: 100,
+            max_time: Duration::from_millis(1),
+            max_memory_bytes: 10 * 1024 * 1024,
+            max_facts_per_predicate: HashMap::default(),
+        }
+    }
+}
+
+impl AuthorizerLimits {
+    /// allows at most `max` facts with the predicate name `predicate`
+    ///
+    /// ```rust
+    /// # use biscuit_auth::AuthorizerLimits;
+    /// let limits = AuthorizerLimits::default()
+    ///     .max_facts_for("time", 1)
+    ///     .max_facts_for("operation", 10);
+    /// ```
+    pub fn max_facts_for(mut self, predicate: &str, max: u32) -> Self {
+        self.max_facts_per_predicate
+            .insert(predicate.to_string(), max);
+        self
+    }
+}
+
+impl core::convert::From<AuthorizerLimits> for crate::datalog::RunLimits {
+    fn from(limits: AuthorizerLimits) -> Self {
+        crate::datalog::RunLimits {
+            max_facts: limits.max_facts,
+            max_iterations: limits.max_iterations,
+            max_time: limits.max_time,
+            max_memory_bytes: limits.max_memory_bytes,
+            max_facts_per_predicate: limits.max_facts_per_predicate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_authorizer() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.add_policy("allow if true").unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+    }
+
+    #[test]
+    fn enumerations() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_enumeration("@operation = {read, write}")
+            .unwrap();
+        authorizer
+            .add_fact("operation(\"read\", \"/file\")")
+            .unwrap();
+        assert!(authorizer
+            .add_policy("allow if operation(\"wirte\", $f)")
+            .is_err());
+        assert!(authorizer
+            .add_code("deny if operation(\"delete\", $f);")
+            .is_err());
+        authorizer
+            .add_code("allow if operation(\"read\", $f);")
+            .unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+    }
+
+    #[test]
+    fn require_policies() {
+        use crate::builder::{rule, var};
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.add_fact("user(\"alice\")").unwrap();
+        authorizer.add_check("check if user($u)").unwrap();
+        assert_eq!(
+            authorizer.clone().authorize(),
+            Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy {
+                checks: vec![]
+            }))
+        );
+
+        authorizer.set_require_policies(true);
+        assert_eq!(
+            authorizer.clone().authorize(),
+            Err(error::Token::FailedLogic(error::Logic::NoPolicies))
+        );
+
+        authorizer
+            .deny_if(rule("denied", &[var("u")], &[pred("admin", &[var("u")])]))
+            .unwrap();
+        authorizer
+            .allow_if(rule("allowed", &[var("u")], &[pred("user", &[var("u")])]))
+            .unwrap();
+        assert_eq!(authorizer.policies[1].to_string(), "allow if user($u)");
+        assert_eq!(authorizer.authorize(), Ok(1));
+    }
+
+    #[test]
+    fn query_authorizer_from_token_tuple() {
+        use crate::Biscuit;
+        use crate::KeyPair;
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder(&keypair);
+        builder
+            .add_authority_fact("user(\"John Doe\", 42)")
+            .unwrap();
+
+        let biscuit = builder.build().unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        let res: Vec<(String, i64)> = authorizer
+            .query("data($name, $id) <- user($name, $id)")
+            .unwrap();
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].0, "John Doe");
+        assert_eq!(res[0].1, 42);
+    }
+
+    #[test]
+    fn revocation_ids() {
+        use crate::Biscuit;
+        use crate::KeyPair;
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder(&keypair);
+        builder.add_authority_fact("user(\"John Doe\")").unwrap();
+
+        let biscuit1 = builder.build().unwrap();
+        let biscuit2 = biscuit1.append(biscuit1.create_block()).unwrap();
+
+        let ids = biscuit2.revocation_identifiers();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids[0], biscuit1.revocation_identifiers()[0]);
+        assert_ne!(ids[0], ids[1]);
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        let res: Vec<(i64, Vec<u8>)> = authorizer
+            .query("data($index, $id) <- revocation_id($index, $id)")
+            .unwrap();
+        assert!(res.is_empty());
+
+        authorizer.add_revocation_id_facts();
+        let res: Vec<(i64, Vec<u8>)> = authorizer
+            .query("data($index, $id) <- revocation_id($index, $id)")
+            .unwrap();
+        assert_eq!(res.len(), 2);
+        assert!(res.contains(&(1, ids[1].clone())));
+
+        authorizer
+            .add_policy(
+                format!(
+                    "deny if revocation_id($index, $id), [hex:{}].contains($id)",
+                    hex::encode(&ids[1])
+                )
+                .as_str(),
+            )
+            .unwrap();
+        authorizer.allow().unwrap();
+        assert!(authorizer.authorize().is_err());
+    }
+
+    #[test]
+    fn revocation_id_facts() {
+        use crate::Biscuit;
+        use crate::KeyPair;
+        let keypair = KeyPair::new();
+        let biscuit1 = Biscuit::builder(&keypair).build().unwrap();
+        let biscuit2 = biscuit1.append(biscuit1.create_block()).unwrap();
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                revocation_id("mine");
+                copy($id) <- revocation_id($id);
+                allow if copy("mine");
+            "#,
+            )
+            .unwrap();
+
+        // the authorizer's own facts with the same name are left alone
+        let mut without = authorizer.clone();
+        without.add_token(&biscuit2).unwrap();
+        let res: Vec<(String,)> = without.query("data($id) <- revocation_id($id)").unwrap();
+        assert_eq!(res, vec![("mine".to_string(),)]);
+        let res: Vec<(i64, Vec<u8>)> = without
+            .query("data($index, $id) <- revocation_id($index, $id)")
+            .unwrap();
+        assert!(res.is_empty());
+
+        // added before the token, and not counted against the limits
+        let mut with = authorizer.clone();
+        with.add_revocation_id_facts();
+        with.add_token(&biscuit2).unwrap();
+        let limits = AuthorizerLimits {
+            max_facts: 3,
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        }
+        .max_facts_for("revocation_id", 1);
+        assert_eq!(with.authorize_with_limits(limits.clone()), Ok(0));
+        let res: Vec<(i64, Vec<u8>)> = with
+            .query_with_limits("data($index, $id) <- revocation_id($index, $id)", limits)
+            .unwrap();
+        assert_eq!(res.len(), 2);
+    }
+
+    #[test]
+    fn external_predicate() {
+        use crate::builder::{int, string};
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.add_external_predicate("age", |arguments: &[Option<Term>]| {
+            match &arguments[0] {
+                Some(Term::Str(name)) if name == "alice" => vec![vec![string("alice"), int(31)]],
+                Some(Term::Str(name)) if name == "bob" => vec![vec![string("bob"), int(17)]],
+                _ => vec![],
+            }
+        });
+        authorizer
+            .add_code(
+                r#"
+                person("alice");
+                person("bob");
+                adult($name) <- person($name), age($name, $age), $age >= 18;
+                allow if true;
+            "#,
+            )
+            .unwrap();
+
+        let res: Vec<(String,)> = authorizer.query("data($name) <- adult($name)").unwrap();
+        assert_eq!(res, vec![("alice".to_string(),)]);
+
+        authorizer.add_check("check if age(\"bob\", 17)").unwrap();
+        authorizer
+            .add_check("check if age(\"carol\", $age)")
+            .unwrap();
+        match authorizer.authorize() {
+            Err(error::Token::FailedLogic(error::Logic::Unauthorized { checks, .. })) => {
+                assert_eq!(checks.len(), 1);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn fact_origin() {
+        use crate::datalog::Origin;
+        use crate::KeyPair;
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"alice\")").unwrap();
+        let token = builder.build().unwrap();
+        let mut block = token.create_block();
+        block.add_fact("operation(\"read\")").unwrap();
+        block
+            .add_rule("can_read($user) <- user($user), operation(\"read\")")
+            .unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.add_fact("resource(\"file1\")").unwrap();
+        authorizer.allow().unwrap();
+        authorizer.authorize().unwrap();
+
+        assert_eq!(
+            authorizer.fact_origin("user(\"alice\")"),
+            Ok(Some(Origin::Authority))
+        );
+        assert_eq!(
+            authorizer.fact_origin("resource(\"file1\")"),
+            Ok(Some(Origin::Authorizer))
+        );
+        assert_eq!(
+            authorizer.fact_origin("operation(\"read\")"),
+            Ok(Some(Origin::Block(1)))
+        );
+        assert_eq!(
+            authorizer.fact_origin("can_read(\"alice\")"),
+            Ok(Some(Origin::Rule(0)))
+        );
+        assert_eq!(authorizer.fact_origin("user(\"bob\")"), Ok(None));
+    }
+
+    #[test]
+    fn analyze_rules() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                parent("alice", "bob");
+                ancestor($a, $b) <- parent($a, $b);
+                ancestor($a, $c) <- parent($a, $b), ancestor($b, $c);
+                manager($a) <- employee($a), reports($b, $a);
+                senior($a) <- manager($a);
+                loop($a) <- parent($a, $b), loop($b);
+            "#,
+            )
+            .unwrap();
+
+        let analysis = authorizer.analyze_rules();
+        assert_eq!(
+            analysis.cycles,
+            vec![vec!["loop($a) <- parent($a, $b), loop($b)".to_string()]]
+        );
+        assert_eq!(
+            analysis.unproductive,
+            vec![
+                (
+                    "manager($a) <- employee($a), reports($b, $a)".to_string(),
+                    "employee".to_string()
+                ),
+                (
+                    "senior($a) <- manager($a)".to_string(),
+                    "manager".to_string()
+                ),
+                (
+                    "loop($a) <- parent($a, $b), loop($b)".to_string(),
+                    "loop".to_string()
+                ),
+            ]
+        );
+
+        authorizer.add_external_predicate("employee", |_: &[Option<Term>]| vec![]);
+        authorizer.add_external_predicate("reports", |_: &[Option<Term>]| vec![]);
+        // `senior` is productive too, since it is generated from `manager`
+        assert_eq!(
+            authorizer.analyze_rules().unproductive,
+            vec![(
+                "loop($a) <- parent($a, $b), loop($b)".to_string(),
+                "loop".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn block_effects() {
+        use crate::KeyPair;
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"alice\")").unwrap();
+        let token = builder.build().unwrap();
+
+        let mut block = token.create_block();
+        block.add_fact("operation(\"read\")").unwrap();
+        block
+            .add_rule("can_read($user) <- user($user), operation(\"read\")")
+            .unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut block = token.create_block();
+        block.add_check("check if user(\"alice\")").unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.allow().unwrap();
+        authorizer.authorize().unwrap();
+        authorizer.authorize().unwrap();
+
+        let effects = authorizer.debug_block_effects();
+        assert_eq!(effects.len(), 2);
+        assert_eq!(effects[0].block_id, 1);
+        assert_eq!(
+            effects[0]
+                .added
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>(),
+            vec!["can_read(\"alice\")", "operation(\"read\")"]
+        );
+        assert!(effects[0].removed.is_empty());
+        assert_eq!(effects[1].block_id, 2);
+        assert!(effects[1].added.is_empty());
+    }
+
+    #[test]
+    fn batch() {
+        use crate::Biscuit;
+        use crate::KeyPair;
+        let root = KeyPair::new();
+
+        let mut serialized = vec![];
+        for user in &["alice", "bob", "charlie"] {
+            let mut builder = Biscuit::builder(&root);
+            builder
+                .add_authority_fact(format!("user(\"{}\")", user).as_str())
+                .unwrap();
+            serialized.push(builder.build().unwrap().to_vec().unwrap());
+        }
+
+        let tokens = Biscuit::from_batch(&serialized, &root.public())
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // an invalid token in the batch does not prevent loading the others
+        let other = KeyPair::new();
+        let mut builder = Biscuit::builder(&other);
+        builder.add_authority_fact("user(\"mallory\")").unwrap();
+        serialized.push(builder.build().unwrap().to_vec().unwrap());
+        let res = Biscuit::from_batch(&serialized, &root.public());
+        assert_eq!(res.len(), 4);
+        assert!(res[..3].iter().all(|r| r.is_ok()));
+        assert!(res[3].is_err());
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_policy("allow if user($user), [\"alice\", \"bob\"].contains($user)")
+            .unwrap();
+        authorizer.deny().unwrap();
+
+        let results = authorizer
+            .authorize_batch(&tokens, AuthorizerLimits::default())
+            .unwrap();
+        assert_eq!(results[0], Ok(0));
+        assert_eq!(results[1], Ok(0));
+        assert!(results[2].is_err());
+    }
+
+    #[test]
+    fn query_authorizer_from_token_string() {
+        use crate::Biscuit;
+        use crate::KeyPair;
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder(&keypair);
+        builder.add_authority_fact("user(\"John Doe\")").unwrap();
+
+        let biscuit = builder.build().unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        let res: Vec<(String,)> = authorizer.query("data($name) <- user($name)").unwrap();
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].0, "John Doe");
+    }
+
+    #[test]
+    fn deterministic_save() {
+        let facts = [
+            "user(\"alice\")",
+            "resource(\"file1\")",
+            "operation(\"read\")",
+            "tags([\"a\", \"b\", \"c\"])",
+        ];
+
+        let build = |facts: &[&str]| {
+            let mut authorizer = Authorizer::new().unwrap();
+            for f in facts {
+                authorizer.add_fact(*f).unwrap();
+            }
+            authorizer
+                .add_rule("allowed($u) <- user($u), resource(\"file1\")")
+                .unwrap();
+            authorizer
+                .add_check("check if operation(\"read\")")
+                .unwrap();
+            authorizer
+                .add_policy("allow if allowed(\"alice\")")
+                .unwrap();
+            authorizer.save().unwrap()
+        };
+
+        let saved = build(&facts);
+        let mut reversed = facts;
+        reversed.reverse();
+        assert_eq!(saved, build(&reversed));
+
+        // loading and saving again does not change the output
+        let loaded = Authorizer::from(&saved).unwrap();
+        assert_eq!(saved, loaded.save().unwrap());
+    }
+
+    #[test]
+    fn format_settings() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.add_fact("user(\"alice\")").unwrap();
+        let saved = authorizer.save().unwrap();
+
+        assert!(Authorizer::from_with_settings(&saved, &FormatSettings::default()).is_ok());
+
+        let settings = FormatSettings {
+            min_version: crate::token::MAX_SCHEMA_VERSION + 1,
+            max_version: crate::token::MAX_SCHEMA_VERSION + 1,
+        };
+        assert_eq!(
+            Authorizer::from_with_settings(&saved, &settings)
+                .err()
+                .unwrap(),
+            error::Token::Format(error::Format::UnsupportedVersion {
+                found: crate::token::MIN_SCHEMA_VERSION,
+                min: crate::token::MAX_SCHEMA_VERSION + 1,
+                max: crate::token::MAX_SCHEMA_VERSION + 1,
+            })
+        );
+
+        // policies trusting keys need a version 4 reader
+        authorizer
+            .add_policy(
+                format!(
+                    "allow if user(\"alice\") trusting {}",
+                    crate::KeyPair::new().public()
+                )
+                .as_str(),
+            )
+            .unwrap();
+        let saved = authorizer.save().unwrap();
+        let settings = FormatSettings {
+            min_version: crate::token::MIN_SCHEMA_VERSION,
+            max_version: crate::token::MIN_SCHEMA_VERSION,
+        };
+        assert_eq!(
+            Authorizer::from_with_settings(&saved, &settings)
+                .err()
+                .unwrap(),
+            error::Token::Format(error::Format::UnsupportedVersion {
+                found: crate::token::THIRD_PARTY_SCHEMA_VERSION,
+                min: crate::token::MIN_SCHEMA_VERSION,
+                max: crate::token::MIN_SCHEMA_VERSION,
+            })
+        );
+    }
+
+    #[test]
+    fn signed_policies() {
+        use crate::KeyPair;
 
-        match (policy_result, errors.is_empty()) {
-            (Some(Ok(i)), true) => Ok(i),
-            (None, _) => Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy {
-                checks: errors,
-            })),
-            (Some(Ok(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
-                policy: error::MatchedPolicy::Allow(i),
-                checks: errors,
-            })),
-            (Some(Err(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
-                policy: error::MatchedPolicy::Deny(i),
-                checks: errors,
-            })),
+        let distribution = KeyPair::new();
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.add_fact("operation(\"read\")").unwrap();
+        authorizer
+            .add_policy("allow if operation(\"read\")")
+            .unwrap();
+        let signed = authorizer.save_signed(&distribution).unwrap();
+
+        let mut loaded = Authorizer::from_signed(&signed, &distribution.public()).unwrap();
+        assert_eq!(loaded.authorize(), Ok(0));
+
+        let other = KeyPair::new();
+        assert!(matches!(
+            Authorizer::from_signed(&signed, &other.public()),
+            Err(error::Token::Format(error::Format::Signature(_)))
+        ));
+
+        let mut tampered = signed.clone();
+        let last = tampered.len() - 70;
+        tampered[last] ^= 1;
+        assert!(Authorizer::from_signed(&tampered, &distribution.public()).is_err());
+
+        struct RejectingVerifier;
+
+        impl Verifier for RejectingVerifier {
+            fn verify(
+                &self,
+                _: &PublicKey,
+                _: &[u8],
+                _: &crate::crypto::Signature,
+            ) -> Result<(), error::Format> {
+                Err(error::Format::Signature(
+                    error::Signature::InvalidSignature("rejected".into()),
+                ))
+            }
         }
+        assert!(
+            Authorizer::from_signed_with(&signed, &distribution.public(), &RejectingVerifier)
+                .is_err()
+        );
     }
 
-    /// prints the content of the authorizer
-    pub fn print_world(&self) -> String {
-        let mut facts = self
-            .world
-            .facts
-            .iter()
-            .map(|f| self.symbols.print_fact(f))
-            .collect::<Vec<_>>();
-        facts.sort();
-
-        let mut rules = self
-            .world
-            .rules
-            .iter()
-            .map(|r| self.symbols.print_rule(r))
-            .collect::<Vec<_>>();
-        rules.sort();
+    #[test]
+    fn failed_check_details() {
+        use crate::KeyPair;
 
-        let mut checks = Vec::new();
-        for (index, check) in self.checks.iter().enumerate() {
-            checks.push(format!("Authorizer[{}]: {}", index, check));
-        }
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        let mut block = token.create_block();
+        block.set_context("service: storage".to_string());
+        block.add_check("check if operation(\"read\")").unwrap();
+        let token = token.append(block).unwrap();
 
-        for (i, block_checks) in self.token_checks.iter().enumerate() {
-            for (j, check) in block_checks.iter().enumerate() {
-                checks.push(format!(
-                    "Block[{}][{}]: {}",
-                    i,
-                    j,
-                    self.symbols.print_check(check)
-                ));
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_code("check if  user(\"alice\");\nallow if true;")
+            .unwrap();
+        authorizer.add_check("check if time($t)").unwrap();
+
+        match authorizer.authorize() {
+            Err(error::Token::FailedLogic(error::Logic::Unauthorized { checks, .. })) => {
+                assert_eq!(
+                    checks,
+                    vec![
+                        error::FailedCheck::Authorizer(error::FailedAuthorizerCheck {
+                            check_id: 0,
+                            rule: "check if user(\"alice\")".to_string(),
+                            source: Some("check if  user(\"alice\")".to_string()),
+                        }),
+                        error::FailedCheck::Authorizer(error::FailedAuthorizerCheck {
+                            check_id: 1,
+                            rule: "check if time($t)".to_string(),
+                            source: None,
+                        }),
+                        error::FailedCheck::Block(error::FailedBlockCheck {
+                            block_id: 1,
+                            check_id: 0,
+                            rule: "check if operation(\"read\")".to_string(),
+                            context: Some("service: storage".to_string()),
+                            revocation_id: Some(hex::encode(&token.revocation_identifiers()[1])),
+                        }),
+                    ]
+                );
             }
+            res => panic!("unexpected result: {:?}", res),
         }
+    }
 
-        let mut policies = Vec::new();
-        for policy in self.policies.iter() {
-            policies.push(policy.to_string());
+    #[test]
+    fn metrics_sink() {
+        use std::sync::Mutex;
+
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let sink = recorded.clone();
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.set_metrics_sink(move |metrics: &AuthorizationMetrics| {
+            sink.lock().unwrap().push((
+                metrics.result.clone(),
+                metrics.facts,
+                metrics.limit_hit().cloned(),
+            ));
+        });
+        authorizer
+            .add_code("user(\"alice\"); allow if user(\"alice\")")
+            .unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // 40 * 40 facts are generated, more than the default limit
+        let mut authorizer = authorizer.clone();
+        for i in 0..40 {
+            authorizer.add_fact(fact("n", &[int(i)])).unwrap();
         }
+        authorizer.add_rule("pair($a, $b) <- n($a), n($b)").unwrap();
+        assert!(authorizer.authorize().is_err());
 
-        format!(
-            "World {{\n  facts: {:#?}\n  rules: {:#?}\n  checks: {:#?}\n  policies: {:#?}\n}}",
-            facts, rules, checks, policies
-        )
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0], (Ok(0), 1, None));
+        assert_eq!(recorded[1].2, Some(error::RunLimit::TooManyFacts));
     }
 
-    /// returns all of the data loaded in the authorizer
-    pub fn dump(&self) -> (Vec<Fact>, Vec<Rule>, Vec<Check>, Vec<Policy>) {
-        let mut checks = self.checks.clone();
-        checks.extend(
-            self.token_checks
-                .iter()
-                .flatten()
-                .map(|c| Check::convert_from(c, &self.symbols)),
+    #[test]
+    fn export_graph() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(
+                r#"user("alice");
+                admin($u) <- user($u);
+                check if admin("alice");
+                allow if user($u);"#,
+            )
+            .unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        assert_eq!(
+            authorizer.export_graph(GraphFormat::Graphviz),
+            r#"digraph authorizer {
+  p0 [label="admin (1 facts)", shape=ellipse];
+  p1 [label="user (1 facts)", shape=ellipse];
+  r0 [label="admin($u) <- user($u)", shape=box];
+  c1 [label="authorizer check 0: check if admin(\"alice\")", shape=hexagon];
+  a2 [label="policy 0: allow if user($u)", shape=octagon];
+  p1 -> r0;
+  p0 -> c1;
+  p1 -> a2;
+  r0 -> p0;
+}"#
         );
 
-        (
-            self.world
-                .facts
-                .iter()
-                .chain(
-                    self.block_worlds
-                        .iter()
-                        .map(|world| world.facts.iter())
-                        .flatten(),
-                )
-                .map(|f| Fact::convert_from(f, &self.symbols))
-                .collect(),
-            self.world
-                .rules
-                .iter()
-                .map(|r| Rule::convert_from(r, &self.symbols))
-                .collect(),
-            checks,
-            self.policies.clone(),
-        )
+        let mermaid = authorizer.export_graph(GraphFormat::Mermaid);
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(
+            mermaid.contains("  c1{{\"authorizer check 0: check if admin(#quot;alice#quot;)\"}}")
+        );
+        assert!(mermaid.contains("  r0 --> p0"));
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct AuthorizerPolicies {
-    pub version: u32,
-    /// list of symbols introduced by this block
-    pub symbols: datalog::SymbolTable,
-    /// list of facts provided by this block
-    pub facts: Vec<datalog::Fact>,
-    /// list of rules provided by blocks
-    pub rules: Vec<datalog::Rule>,
-    /// checks that the token and ambient data must validate
-    pub checks: Vec<datalog::Check>,
-    pub policies: Vec<Policy>,
-}
+    #[test]
+    fn shared_authorizer() {
+        use crate::builder::string;
+        use crate::KeyPair;
 
-/// runtime limits for the Datalog engine
-#[derive(Debug, Clone)]
-pub struct AuthorizerLimits {
-    /// maximum number of Datalog facts (memory usage)
-    pub max_facts: u32,
-    /// maximum number of iterations of the rules applications (prevents degenerate rules)
-    pub max_iterations: u32,
-    /// maximum execution time
-    pub max_time: Duration,
-}
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Authorizer<'static>>();
 
-impl Default for AuthorizerLimits {
-    fn default() -> Self {
-        AuthorizerLimits {
-            max_facts: 1000,
-            max_iterations: 100,
-            max_time: Duration::from_millis(1),
+        let mut authorizer = Authorizer::new().unwrap();
+        for i in 0..100 {
+            authorizer
+                .add_fact(fact("resource", &[string(&format!("file{}", i))]))
+                .unwrap();
         }
+        authorizer
+            .add_code(
+                r#"can_read($f) <- right($f, "read"), resource($f);
+                allow if can_read("file1");"#,
+            )
+            .unwrap();
+        let authorizer = Arc::new(authorizer);
+
+        let root = KeyPair::new();
+        let handles = (0..4)
+            .map(|i| {
+                let authorizer = authorizer.clone();
+                let mut builder = Biscuit::builder(&root);
+                builder
+                    .add_authority_fact(fact(
+                        "right",
+                        &[string(&format!("file{}", i)), string("read")],
+                    ))
+                    .unwrap();
+                let token = builder.build().unwrap();
+
+                std::thread::spawn(move || authorizer.authorize_token(&token).is_ok())
+            })
+            .collect::<Vec<_>>();
+
+        let results = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![false, true, false, false]);
+        // the shared authorizer was not modified
+        assert_eq!(authorizer.world.facts.len(), 100);
     }
-}
 
-impl std::convert::From<AuthorizerLimits> for crate::datalog::RunLimits {
-    fn from(limits: AuthorizerLimits) -> Self {
-        crate::datalog::RunLimits {
-            max_facts: limits.max_facts,
-            max_iterations: limits.max_iterations,
-            max_time: limits.max_time,
-        }
+    #[test]
+    fn token_symbols() {
+        use crate::builder::BlockBuilder;
+        use crate::KeyPair;
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"alice\")").unwrap();
+        builder
+            .add_authority_check("check if operation(\"read\")")
+            .unwrap();
+        let token = builder.build().unwrap();
+        let mut block = BlockBuilder::new();
+        block.add_check("check if resource(\"file1\")").unwrap();
+        let token = token.append(block).unwrap();
+
+        // the authorizer starts with the token's symbols instead of a copy
+        let mut authorizer = token.authorizer().unwrap();
+        assert!(authorizer.symbols.shares_frozen_symbols(&token.symbols));
+
+        authorizer
+            .add_code(
+                r#"operation("read"); resource("file1");
+                allow if user("alice")"#,
+            )
+            .unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+        let users: Vec<(String,)> = authorizer.query("data($u) <- user($u)").unwrap();
+        assert_eq!(users, vec![("alice".to_string(),)]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn client_ip() {
+        use crate::KeyPair;
+        let root = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        let mut block = token.create_block();
+        block.check_client_ip(&["10.0.0.0/8", "2001:db8::/32"]);
+        let token = token.append(block).unwrap();
+
+        let authorize = |ip: &str| {
+            let mut authorizer = Authorizer::new().unwrap();
+            authorizer.set_client_ip(ip.parse().unwrap());
+            authorizer.allow().unwrap();
+            authorizer.authorize_token(&token)
+        };
+
+        assert!(authorize("10.20.30.40").is_ok());
+        assert!(authorize("2001:db8::1").is_ok());
+        assert!(authorize("192.168.1.1").is_err());
+    }
 
     #[test]
-    fn empty_authorizer() {
+    fn pinned_root_key() {
+        use crate::KeyPair;
+        let root = KeyPair::new();
+        let other = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+        let mut block = token.create_block();
+        block.pin_root_keys(&[root.public()]);
+        let token = token.append(block).unwrap();
+
+        let authorize = |key: Option<&KeyPair>| {
+            let mut authorizer = Authorizer::new().unwrap();
+            if let Some(key) = key {
+                authorizer.set_root_key(&key.public());
+            }
+            authorizer.allow().unwrap();
+            authorizer.authorize_token(&token)
+        };
+
+        assert!(authorize(Some(&root)).is_ok());
+        assert!(authorize(Some(&other)).is_err());
+        assert!(authorize(None).is_err());
+    }
+
+    #[test]
+    fn audience() {
+        use crate::KeyPair;
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_audience("payments-api");
+        builder.add_audience("billing-api");
+        let token = builder.build().unwrap();
+
         let mut authorizer = Authorizer::new().unwrap();
-        authorizer.add_policy("allow if true").unwrap();
-        assert_eq!(authorizer.authorize(), Ok(0));
+        authorizer.set_audience("payments-api");
+        authorizer.allow().unwrap();
+        assert_eq!(authorizer.authorize_token(&token), Ok(0));
+
+        authorizer.set_audience("search-api");
+        assert_eq!(
+            authorizer.authorize_token(&token),
+            Err(error::Token::WrongAudience("search-api".to_string()))
+        );
     }
 
     #[test]
-    fn query_authorizer_from_token_tuple() {
-        use crate::Biscuit;
+    fn saved_world() {
         use crate::KeyPair;
-        let keypair = KeyPair::new();
-        let mut builder = Biscuit::builder(&keypair);
-        builder
-            .add_authority_fact("user(\"John Doe\", 42)")
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"alice\")").unwrap();
+        let token = builder.build().unwrap();
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                member("alice", "admins");
+                member("admins", "staff");
+                member($a, $c) <- member($a, $b), member($b, $c);
+                staff($u) <- user($u), member($u, "staff");
+            "#,
+            )
             .unwrap();
+        let world = authorizer.save_world().unwrap();
+
+        let mut loaded = Authorizer::new().unwrap();
+        loaded.load_world(&world).unwrap();
+        loaded.add_policy("allow if staff($u)").unwrap();
+        let staff: Vec<(String,)> = loaded.query("data($u) <- member($u, \"staff\")").unwrap();
+        assert!(staff.contains(&("alice".to_string(),)));
+        assert_eq!(loaded.authorize_token(&token), Ok(0));
+
+        authorizer.add_token(&token).unwrap();
+        assert_eq!(
+            authorizer.save_world(),
+            Err(error::Token::FailedLogic(error::Logic::AuthorizerNotEmpty))
+        );
+    }
 
-        let biscuit = builder.build().unwrap();
+    #[test]
+    fn saved_world_with_limits() {
+        let mut authorizer = Authorizer::new().unwrap();
+        for i in 0..1500 {
+            authorizer.add_fact(fact("r", &[int(i)])).unwrap();
+        }
+        authorizer.add_rule("s($x) <- r($x)").unwrap();
 
-        let mut authorizer = biscuit.authorizer().unwrap();
-        let res: Vec<(String, i64)> = authorizer
-            .query("data($name, $id) <- user($name, $id)")
+        assert_eq!(
+            authorizer.clone().save_world(),
+            Err(error::Token::RunLimit(error::RunLimit::TooManyFacts))
+        );
+
+        let world = authorizer
+            .save_world_with_limits(AuthorizerLimits {
+                max_facts: 5000,
+                max_time: Duration::from_secs(10),
+                ..Default::default()
+            })
             .unwrap();
 
-        assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "John Doe");
-        assert_eq!(res[0].1, 42);
+        let mut loaded = Authorizer::new().unwrap();
+        loaded.load_world(&world).unwrap();
+        let s: Vec<(i64,)> = loaded
+            .query_with_limits(
+                "data($x) <- s($x)",
+                AuthorizerLimits {
+                    max_facts: 5000,
+                    max_time: Duration::from_secs(10),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(s.len(), 1500);
     }
 
     #[test]
-    fn query_authorizer_from_token_string() {
-        use crate::Biscuit;
+    fn predicate_limits() {
         use crate::KeyPair;
-        let keypair = KeyPair::new();
-        let mut builder = Biscuit::builder(&keypair);
-        builder.add_authority_fact("user(\"John Doe\")").unwrap();
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("operation(\"read\")").unwrap();
+        builder.add_authority_fact("operation(\"write\")").unwrap();
+        let token = builder.build().unwrap();
 
-        let biscuit = builder.build().unwrap();
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                n(0); n(1); n(2); n(3); n(4);
+                pair($a, $b) <- n($a), n($b);
+                allow if true;
+            "#,
+            )
+            .unwrap();
+        let limits = AuthorizerLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
 
-        let mut authorizer = biscuit.authorizer().unwrap();
-        let res: Vec<(String,)> = authorizer.query("data($name) <- user($name)").unwrap();
+        assert_eq!(
+            authorizer
+                .authorize_token_with_limits(&token, limits.clone().max_facts_for("pair", 25)),
+            Ok(0)
+        );
+        assert_eq!(
+            authorizer
+                .authorize_token_with_limits(&token, limits.clone().max_facts_for("pair", 10)),
+            Err(error::Token::RunLimit(
+                error::RunLimit::TooManyPredicateFacts {
+                    predicate: "pair".to_string(),
+                    max: 10,
+                }
+            ))
+        );
+        assert_eq!(
+            authorizer.authorize_token_with_limits(&token, limits.max_facts_for("operation", 1)),
+            Err(error::Token::RunLimit(
+                error::RunLimit::TooManyPredicateFacts {
+                    predicate: "operation".to_string(),
+                    max: 1,
+                }
+            ))
+        );
+    }
 
-        assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "John Doe");
+    #[test]
+    fn authorize_limits() {
+        let authorizer = || {
+            let blob = "a".repeat(1000);
+            let mut authorizer = Authorizer::new().unwrap();
+            authorizer
+                .add_code(&format!(
+                    r#"
+                    blob("{0}1"); blob("{0}2"); blob("{0}3");
+                    copy($b) <- blob($b);
+                    allow if true;
+                "#,
+                    blob
+                ))
+                .unwrap();
+            authorizer
+        };
+        let limits = AuthorizerLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        assert_eq!(authorizer().authorize_with_limits(limits.clone()), Ok(0));
+        assert_eq!(
+            authorizer().authorize_with_limits(AuthorizerLimits {
+                max_facts: 2,
+                ..limits.clone()
+            }),
+            Err(error::Token::RunLimit(error::RunLimit::TooManyFacts))
+        );
+        assert_eq!(
+            authorizer().authorize_with_limits(AuthorizerLimits {
+                max_memory_bytes: 100,
+                ..limits
+            }),
+            Err(error::Token::RunLimit(error::RunLimit::TooMuchMemory))
+        );
+    }
+
+    #[test]
+    fn trusted_query_limits() {
+        use crate::token::builder::BlockBuilder;
+        use crate::KeyPair;
+
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+        let token = Biscuit::builder(&root).build().unwrap();
+
+        let mut block = BlockBuilder::new();
+        block
+            .add_code("n(0); n(1); n(2); n(3); n(4); pair($a, $b) <- n($a), n($b);")
+            .unwrap();
+        let request = token.third_party_request().unwrap();
+        let token = token
+            .append_third_party(request.create_block(&external, block).unwrap())
+            .unwrap();
+
+        let authorize = |limits: AuthorizerLimits| {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer
+                .add_check(format!("check if pair(0, 4) trusting {}", external.public()).as_str())
+                .unwrap();
+            authorizer.add_policy("allow if true").unwrap();
+            authorizer.authorize_with_limits(limits)
+        };
+        let limits = AuthorizerLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        assert_eq!(authorize(limits.clone()), Ok(0));
+        // the authorizer's checks run before the block worlds, so the world
+        // of the trusted query is the first to go over the limit
+        assert_eq!(
+            authorize(AuthorizerLimits {
+                max_facts: 20,
+                ..limits
+            }),
+            Err(error::Token::RunLimit(error::RunLimit::TooManyFacts))
+        );
+    }
+
+    #[test]
+    fn query_one_distinct_results() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(r#"user("alice"); role("admin"); role("dev");"#)
+            .unwrap();
+
+        // the two roles generate the same fact
+        let (user,): (String,) = authorizer
+            .query_one("data($u) <- user($u), role($r)")
+            .unwrap();
+        assert_eq!(user, "alice");
+        let res: Option<(String,)> = authorizer
+            .query_opt("data($u) <- user($u), role($r)")
+            .unwrap();
+        assert_eq!(res, Some(("alice".to_string(),)));
+
+        let res: Result<(String,), _> = authorizer.query_one("data($r) <- user($u), role($r)");
+        assert!(matches!(
+            res,
+            Err(error::Token::Query(error::Query::TooManyResults {
+                count: 2,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn invalid_rules() {
+        use crate::builder::{pred, rule, var, BlockBuilder};
+
+        // $b does not appear in the body
+        let invalid = rule("edge", &[var("a"), var("b")], &[pred("node", &[var("a")])]);
+
+        let mut authorizer = Authorizer::new().unwrap();
+        assert!(matches!(
+            authorizer.add_rule(invalid.clone()),
+            Err(error::Token::Language(error::LanguageError::ParseError(_)))
+        ));
+        let mut block = BlockBuilder::new();
+        assert!(matches!(
+            block.add_rule(invalid),
+            Err(error::Token::Language(error::LanguageError::ParseError(_)))
+        ));
     }
 }
@@ -1,19 +1,24 @@
 //! helper functions and structure to create tokens and blocks
 use super::{Biscuit, Block};
-use crate::crypto::KeyPair;
+use crate::collections::HashMap;
+use crate::crypto::{PublicKey, Signer};
 use crate::datalog::{self, SymbolTable};
 use crate::error;
 use crate::parser::parse_block_source;
-use rand_core::{CryptoRng, RngCore};
-use std::{
-    collections::{BTreeSet, HashMap},
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::{
     convert::{TryFrom, TryInto},
     fmt,
-    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use rand_core::{CryptoRng, RngCore};
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // reexport those because the builder uses the same definitions
 pub use crate::datalog::{Binary, Unary};
+pub use crate::parser::Enumeration;
 
 /// creates a Block content to append to an existing token
 #[derive(Clone, Debug, Default)]
@@ -22,6 +27,8 @@ pub struct BlockBuilder {
     pub rules: Vec<Rule>,
     pub checks: Vec<Check>,
     pub context: Option<String>,
+    /// declared with [BlockBuilder::add_enumeration], not stored in the block
+    pub enumerations: Vec<Enumeration>,
 }
 
 impl BlockBuilder {
@@ -35,6 +42,7 @@ impl BlockBuilder {
     {
         let fact = fact.try_into()?;
         fact.validate()?;
+        check_enumerations(&self.enumerations, fact.predicates())?;
 
         self.facts.push(fact);
         Ok(())
@@ -45,7 +53,8 @@ impl BlockBuilder {
         error::Token: From<<R as TryInto<Rule>>::Error>,
     {
         let rule = rule.try_into()?;
-        rule.validate_parameters()?;
+        rule.validate()?;
+        check_enumerations(&self.enumerations, rule.predicates())?;
         self.rules.push(rule);
         Ok(())
     }
@@ -56,12 +65,29 @@ impl BlockBuilder {
     {
         let check = check.try_into()?;
         check.validate_parameters()?;
+        check_enumerations(&self.enumerations, check.predicates())?;
         self.checks.push(check);
         Ok(())
     }
 
+    /// declares the strings allowed in a term of a predicate, like
+    /// `@operation = {read, write}`
+    ///
+    /// the facts, rules and checks added afterwards, including the ones of
+    /// [BlockBuilder::add_code], are rejected if they use another string in
+    /// this term. See [Enumeration]
+    pub fn add_enumeration<E: TryInto<Enumeration>>(
+        &mut self,
+        enumeration: E,
+    ) -> Result<(), error::Token>
+    where
+        error::Token: From<<E as TryInto<Enumeration>>::Error>,
+    {
+        declare_enumeration(&mut self.enumerations, enumeration.try_into()?)
+    }
+
     pub fn add_code<T: AsRef<str>>(&mut self, source: T) -> Result<(), error::Token> {
-        self.add_code_with_params(source, HashMap::new())
+        self.add_code_with_params(source, HashMap::default())
     }
 
     /// Add datalog code to the builder, performing parameter subsitution as required
@@ -73,7 +99,12 @@ impl BlockBuilder {
     ) -> Result<(), error::Token> {
         let input = source.as_ref();
 
-        let source_result = parse_block_source(input)?;
+        let source_result =
+            parse_block_source(input).map_err(|e| error::ParseErrors::located(input, e))?;
+
+        for (_, enumeration) in source_result.enumerations.into_iter() {
+            declare_enumeration(&mut self.enumerations, enumeration)?;
+        }
 
         for (_, mut fact) in source_result.facts.into_iter() {
             for (name, value) in &params {
@@ -87,6 +118,7 @@ impl BlockBuilder {
                 res?;
             }
             fact.validate()?;
+            check_enumerations(&self.enumerations, fact.predicates())?;
             self.facts.push(fact);
         }
 
@@ -102,6 +134,7 @@ impl BlockBuilder {
                 res?;
             }
             rule.validate_parameters()?;
+            check_enumerations(&self.enumerations, rule.predicates())?;
             self.rules.push(rule);
         }
 
@@ -117,6 +150,7 @@ impl BlockBuilder {
                 res?;
             }
             check.validate_parameters()?;
+            check_enumerations(&self.enumerations, check.predicates())?;
             self.checks.push(check);
         }
 
@@ -127,6 +161,12 @@ impl BlockBuilder {
         self.context = Some(context);
     }
 
+    /// adds a `key=value` entry to the context, like the issuer or a ticket
+    /// reference, replacing the previous value of the key. See [context_metadata]
+    pub fn add_context(&mut self, key: &str, value: &str) {
+        add_context_entry(&mut self.context, key, value);
+    }
+
     /// replace a parameter with the term argument
     pub fn set<T: Into<Term>>(&mut self, name: &str, term: T) -> Result<(), String> {
         let term = term.into();
@@ -177,7 +217,9 @@ impl BlockBuilder {
         for check in self.checks {
             checks.push(check.convert(&mut symbols));
         }
+        fold_expressions(&mut rules, &mut checks, &symbols);
         let new_syms = symbols.split_at(symbols_start);
+        let version = super::required_version(&facts, &rules, &checks);
 
         Block {
             symbols: new_syms,
@@ -185,7 +227,8 @@ impl BlockBuilder {
             rules,
             checks,
             context: self.context,
-            version: super::MAX_SCHEMA_VERSION,
+            version,
+            external_key: None,
         }
     }
 
@@ -261,6 +304,41 @@ impl BlockBuilder {
         let _ = self.add_check(check);
     }
 
+    /// restricts the `client_ip` fact to CIDR ranges, like `10.0.0.0/8`,
+    /// see [Authorizer::set_client_ip](crate::Authorizer::set_client_ip)
+    pub fn check_client_ip(&mut self, ranges: &[&str]) {
+        let ranges = ranges.iter().map(|r| string(r)).collect::<BTreeSet<_>>();
+        let check = constrained_rule(
+            "client_ip_check",
+            &[var("ip")],
+            &[pred("client_ip", &[var("ip")])],
+            &[Expression {
+                ops: vec![
+                    Op::Value(set(ranges)),
+                    Op::Value(var("ip")),
+                    Op::Binary(Binary::ContainsIp),
+                ],
+            }],
+        );
+
+        let _ = self.add_check(check);
+    }
+
+    /// pins the token to deployments using one of these root keys, with
+    /// the `root_key` fact from [Authorizer::set_root_key](crate::Authorizer::set_root_key)
+    ///
+    /// an authorizer that does not declare its root key rejects the token
+    pub fn pin_root_keys(&mut self, keys: &[PublicKey]) {
+        let _ = self.add_check(root_key_check(keys));
+    }
+
+    /// restricts the resource to a subtree, with the facts from
+    /// [Authorizer::add_resource_hierarchy](crate::Authorizer::add_resource_hierarchy)
+    pub fn check_resource_prefix(&mut self, prefix: &str) {
+        let _ = self.add_check(crate::hierarchy::check_prefix(prefix));
+    }
+
+    #[cfg(feature = "std")]
     pub fn expiration_date(&mut self, exp: SystemTime) {
         let check = constrained_rule(
             "expiration",
@@ -283,17 +361,31 @@ impl BlockBuilder {
 #[derive(Clone)]
 pub struct BiscuitBuilder<'a> {
     root_key_id: Option<u32>,
-    root: &'a KeyPair,
+    root: Option<&'a dyn Signer>,
     pub symbols_start: usize,
     pub symbols: SymbolTable,
     pub facts: Vec<datalog::Fact>,
     pub rules: Vec<datalog::Rule>,
     pub checks: Vec<datalog::Check>,
     pub context: Option<String>,
+    /// declared with [BiscuitBuilder::add_enumeration], not stored in the token
+    pub enumerations: Vec<Enumeration>,
 }
 
 impl<'a> BiscuitBuilder<'a> {
-    pub fn new(root: &'a KeyPair, base_symbols: SymbolTable) -> BiscuitBuilder<'a> {
+    pub fn new(root: &'a dyn Signer, base_symbols: SymbolTable) -> BiscuitBuilder<'a> {
+        BiscuitBuilder::new_with_signer(Some(root), base_symbols)
+    }
+
+    /// creates a builder without a root signer, for use with [BiscuitBuilder::build_async]
+    pub fn new_unsigned(base_symbols: SymbolTable) -> BiscuitBuilder<'a> {
+        BiscuitBuilder::new_with_signer(None, base_symbols)
+    }
+
+    fn new_with_signer(
+        root: Option<&'a dyn Signer>,
+        base_symbols: SymbolTable,
+    ) -> BiscuitBuilder<'a> {
         BiscuitBuilder {
             root_key_id: None,
             root,
@@ -303,6 +395,7 @@ impl<'a> BiscuitBuilder<'a> {
             rules: vec![],
             checks: vec![],
             context: None,
+            enumerations: vec![],
         }
     }
 
@@ -317,6 +410,7 @@ impl<'a> BiscuitBuilder<'a> {
     {
         let fact = fact.try_into()?;
         fact.validate()?;
+        check_enumerations(&self.enumerations, fact.predicates())?;
 
         let f = fact.convert(&mut self.symbols);
         self.facts.push(f);
@@ -328,7 +422,8 @@ impl<'a> BiscuitBuilder<'a> {
         error::Token: From<<Ru as TryInto<Rule>>::Error>,
     {
         let rule = rule.try_into()?;
-        rule.validate_parameters()?;
+        rule.validate()?;
+        check_enumerations(&self.enumerations, rule.predicates())?;
 
         let r = rule.convert(&mut self.symbols);
         self.rules.push(r);
@@ -340,30 +435,83 @@ impl<'a> BiscuitBuilder<'a> {
         error::Token: From<<C as TryInto<Check>>::Error>,
     {
         let check: Check = rule.try_into()?;
+        check_enumerations(&self.enumerations, check.predicates())?;
         let c = check.convert(&mut self.symbols);
         self.checks.push(c);
         Ok(())
     }
 
+    /// pins the token to deployments using one of these root keys, see
+    /// [BlockBuilder::pin_root_keys]
+    pub fn pin_root_keys(&mut self, keys: &[PublicKey]) {
+        let _ = self.add_authority_check(root_key_check(keys));
+    }
+
+    /// makes a one-time token with the authority fact `nonce(hex:...)`,
+    /// see the [nonce module](crate::nonce)
+    pub fn add_nonce(&mut self, nonce: &[u8]) {
+        let _ = self.add_authority_fact(fact("nonce", &[bytes(nonce)]));
+    }
+
+    /// adds a random nonce, see [BiscuitBuilder::add_nonce]
+    #[cfg(feature = "std")]
+    pub fn add_random_nonce(&mut self) {
+        self.add_nonce(&crate::nonce::generate());
+    }
+
+    /// restricts the token to a service with the authority fact `audience(audience)`,
+    /// see [Authorizer::set_audience](crate::Authorizer::set_audience)
+    ///
+    /// a token can be valid for multiple audiences
+    pub fn add_audience(&mut self, audience: &str) {
+        let _ = self.add_authority_fact(fact("audience", &[string(audience)]));
+    }
+
+    /// binds the token to a tenant with the authority fact `tenant(tenant)`,
+    /// see [Authorizer::set_tenant](crate::Authorizer::set_tenant)
+    pub fn set_tenant(&mut self, tenant: &str) {
+        let _ = self.add_authority_fact(fact("tenant", &[string(tenant)]));
+    }
+
+    /// declares the strings allowed in a term of a predicate, see
+    /// [BlockBuilder::add_enumeration]
+    pub fn add_enumeration<E: TryInto<Enumeration>>(
+        &mut self,
+        enumeration: E,
+    ) -> Result<(), error::Token>
+    where
+        error::Token: From<<E as TryInto<Enumeration>>::Error>,
+    {
+        declare_enumeration(&mut self.enumerations, enumeration.try_into()?)
+    }
+
     pub fn add_code<T: AsRef<str>>(&mut self, source: T) -> Result<(), error::Token> {
         let input = source.as_ref();
 
-        let source_result = parse_block_source(input)?;
+        let source_result =
+            parse_block_source(input).map_err(|e| error::ParseErrors::located(input, e))?;
+
+        for (_, enumeration) in source_result.enumerations.into_iter() {
+            declare_enumeration(&mut self.enumerations, enumeration)?;
+        }
 
         for (_, fact) in source_result.facts.into_iter() {
             fact.validate()?;
+            check_enumerations(&self.enumerations, fact.predicates())?;
             let f = fact.convert(&mut self.symbols);
             self.facts.push(f);
         }
 
         for (_, rule) in source_result.rules.into_iter() {
             rule.validate_parameters()?;
+            check_enumerations(&self.enumerations, rule.predicates())?;
             let r = rule.convert(&mut self.symbols);
             self.rules.push(r);
         }
 
         for (_, check) in source_result.checks.into_iter() {
             check.validate_parameters()?;
+            check_enumerations(&self.enumerations, check.predicates())?;
             let c = check.convert(&mut self.symbols);
             self.checks.push(c);
         }
@@ -380,15 +528,51 @@ impl<'a> BiscuitBuilder<'a> {
         self.context = Some(context);
     }
 
+    /// adds a `key=value` entry to the context, like the issuer or a ticket
+    /// reference, replacing the previous value of the key. See [context_metadata]
+    pub fn add_context(&mut self, key: &str, value: &str) {
+        add_context_entry(&mut self.context, key, value);
+    }
+
+    #[cfg(feature = "std")]
     pub fn build(self) -> Result<Biscuit, error::Token> {
         self.build_with_rng(&mut rand::rngs::OsRng)
     }
 
     pub fn build_with_rng<R: RngCore + CryptoRng>(
-        mut self,
+        self,
         rng: &'a mut R,
     ) -> Result<Biscuit, error::Token> {
+        let root = self.root.ok_or_else(|| {
+            error::Format::Signature(error::Signature::InvalidSignatureGeneration(
+                "no root signer".into(),
+            ))
+        })?;
+        let (root_key_id, symbols, authority_block) = self.into_authority_block();
+
+        Biscuit::new_with_rng(rng, root_key_id, root, symbols, authority_block)
+    }
+
+    /// creates the token, signing the authority block with an async function
+    ///
+    /// the function receives the data to sign with the root private key, so
+    /// that key can stay in an external KMS or HSM. The root signer of the
+    /// builder, if any, is not used
+    #[cfg(feature = "std")]
+    pub async fn build_async<F, Fut>(self, sign: F) -> Result<Biscuit, error::Token>
+    where
+        F: FnOnce(Vec<u8>) -> Fut,
+        Fut: core::future::Future<Output = Result<crate::crypto::Signature, error::Format>>,
+    {
+        let (root_key_id, symbols, authority_block) = self.into_authority_block();
+
+        Biscuit::new_async(root_key_id, sign, symbols, authority_block).await
+    }
+
+    fn into_authority_block(mut self) -> (Option<u32>, SymbolTable, Block) {
+        fold_expressions(&mut self.rules, &mut self.checks, &self.symbols);
         let new_syms = self.symbols.split_at(self.symbols_start);
+        let version = super::required_version(&self.facts, &self.rules, &self.checks);
 
         let authority_block = Block {
             symbols: new_syms,
@@ -396,27 +580,67 @@ impl<'a> BiscuitBuilder<'a> {
             rules: self.rules,
             checks: self.checks,
             context: self.context,
-            version: super::MAX_SCHEMA_VERSION,
+            version,
+            external_key: None,
         };
 
-        Biscuit::new_with_rng(
-            rng,
-            self.root_key_id,
-            self.root,
-            self.symbols,
-            authority_block,
-        )
+        (self.root_key_id, self.symbols, authority_block)
+    }
+}
+
+/// evaluates the constant sub-expressions (like `100 * 60`) of a block's
+/// rules and checks before it is serialized, see [datalog::Expression::fold]
+fn fold_expressions(
+    rules: &mut [datalog::Rule],
+    checks: &mut [datalog::Check],
+    symbols: &SymbolTable,
+) {
+    let queries = checks.iter_mut().flat_map(|check| check.queries.iter_mut());
+    for rule in rules.iter_mut().chain(queries) {
+        for expression in rule.expressions.iter_mut() {
+            *expression = expression.fold(symbols);
+        }
+    }
+}
+
+/// byte arrays of [Term::Bytes], serialized as hex strings
+#[cfg(feature = "serde")]
+mod hex_bytes {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    pub fn serialize<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        use serde::de::Error;
+        use serde::Deserialize;
+
+        let s = String::deserialize(deserializer)?;
+        hex::decode(&s).map_err(D::Error::custom)
     }
 }
 
 /// Builder for a Datalog value
+///
+/// with the `serde` feature, it is represented as `{"type": "integer", "value": 1}`,
+/// with byte arrays encoded as hex strings
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Term {
     Variable(String),
     Integer(i64),
     Str(String),
     Date(u64),
-    Bytes(Vec<u8>),
+    Bytes(#[cfg_attr(feature = "serde", serde(with = "hex_bytes"))] Vec<u8>),
     Bool(bool),
     Set(BTreeSet<Term>),
     Parameter(String),
@@ -479,18 +703,8 @@ impl fmt::Display for Term {
         match self {
             Term::Variable(i) => write!(f, "${}", i),
             Term::Integer(i) => write!(f, "{}", i),
-            Term::Str(s) => write!(f, "\"{}\"", s),
-            Term::Date(d) => {
-                let date = time::OffsetDateTime::from_unix_timestamp(*d as i64)
-                    .ok()
-                    .and_then(|t| {
-                        t.format(&time::format_description::well_known::Rfc3339)
-                            .ok()
-                    })
-                    .unwrap_or_else(|| "<invalid date>".to_string());
-
-                write!(f, "{}", date)
-            }
+            Term::Str(s) => write!(f, "\"{}\"", datalog::escape_string(s)),
+            Term::Date(d) => write!(f, "{}", datalog::print_date(*d)),
             Term::Bytes(s) => write!(f, "hex:{}", hex::encode(s)),
             Term::Bool(b) => {
                 if *b {
@@ -511,6 +725,8 @@ impl fmt::Display for Term {
 }
 
 /// Builder for a Datalog dicate, used in facts and rules
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct Predicate {
     pub name: String,
@@ -572,15 +788,21 @@ impl fmt::Display for Predicate {
 }
 
 /// Builder for a Datalog fact
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Fact {
     pub predicate: Predicate,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub parameters: Option<HashMap<String, Option<Term>>>,
 }
 
 impl Fact {
     pub fn new<T: Into<Vec<Term>>>(name: String, terms: T) -> Fact {
-        let mut parameters = HashMap::new();
+        let mut parameters = HashMap::default();
         let terms: Vec<Term> = terms.into();
 
         for term in &terms {
@@ -625,6 +847,11 @@ impl Fact {
 }
 
 impl Fact {
+    /// the predicate of the fact, see [Rule::predicates]
+    pub(crate) fn predicates(&self) -> impl Iterator<Item = &Predicate> {
+        core::iter::once(&self.predicate)
+    }
+
     pub fn convert(&self, symbols: &mut SymbolTable) -> datalog::Fact {
         let mut fact = self.clone();
         fact.apply_parameters();
@@ -689,6 +916,8 @@ impl fmt::Display for Fact {
 }
 
 /// Builder for a Datalog expression
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Expression {
     pub ops: Vec<Op>,
@@ -729,6 +958,9 @@ impl fmt::Display for Expression {
 }
 
 /// Builder for an expression operation
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Op {
     Value(Term),
@@ -755,17 +987,29 @@ impl Op {
 }
 
 /// Builder for a Datalog rule
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
     pub head: Predicate,
     pub body: Vec<Predicate>,
     pub expressions: Vec<Expression>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub parameters: Option<HashMap<String, Option<Term>>>,
+    /// third party keys whose blocks are visible to this rule, when used in checks and policies
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub trusted_keys: Vec<PublicKey>,
 }
 
 impl Rule {
     pub fn new(head: Predicate, body: Vec<Predicate>, expressions: Vec<Expression>) -> Rule {
-        let mut parameters = HashMap::new();
+        let mut parameters = HashMap::default();
         for term in &head.terms {
             if let Term::Parameter(name) = &term {
                 parameters.insert(name.to_string(), None);
@@ -793,9 +1037,16 @@ impl Rule {
             body,
             expressions,
             parameters: Some(parameters),
+            trusted_keys: Vec::new(),
         }
     }
 
+    /// make facts from third party blocks signed by `key` visible to this rule
+    pub fn trusting(mut self, key: PublicKey) -> Self {
+        self.trusted_keys.push(key);
+        self
+    }
+
     pub fn convert(&self, symbols: &mut SymbolTable) -> datalog::Rule {
         let mut r = self.clone();
         r.apply_parameters();
@@ -816,6 +1067,7 @@ impl Rule {
             head,
             body,
             expressions,
+            trusted_keys: r.trusted_keys.clone(),
         }
     }
 
@@ -833,9 +1085,15 @@ impl Rule {
                 .map(|c| Expression::convert_from(c, symbols))
                 .collect(),
             parameters: None,
+            trusted_keys: r.trusted_keys.clone(),
         }
     }
 
+    /// the head and body predicates, checked against the declared enumerations
+    pub(crate) fn predicates(&self) -> impl Iterator<Item = &Predicate> {
+        core::iter::once(&self.head).chain(self.body.iter())
+    }
+
     pub fn validate_parameters(&self) -> Result<(), error::Token> {
         match &self.parameters {
             None => Ok(()),
@@ -866,7 +1124,7 @@ impl Rule {
     }
 
     pub fn validate_variables(&self) -> Result<(), String> {
-        let mut head_variables: std::collections::HashSet<String> = self
+        let mut head_variables: crate::collections::HashSet<String> = self
             .head
             .terms
             .iter()
@@ -901,6 +1159,22 @@ impl Rule {
         }
     }
 
+    /// checks that the parameters are set and that the head variables
+    /// appear in the body, like rules parsed from Datalog source
+    pub(crate) fn validate(&self) -> Result<(), error::Token> {
+        self.validate_parameters()?;
+        self.validate_variables().map_err(|message| {
+            error::Token::Language(error::LanguageError::ParseError(error::ParseErrors {
+                errors: vec![error::ParseError {
+                    input: self.to_string(),
+                    message: Some(message),
+                    location: None,
+                    expected: None,
+                }],
+            }))
+        })
+    }
+
     /// replace a parameter with the term argument
     pub fn set<T: Into<Term>>(&mut self, name: &str, term: T) -> Result<(), error::Token> {
         if let Some(parameters) = self.parameters.as_mut() {
@@ -970,6 +1244,9 @@ impl Rule {
 }
 
 fn display_rule_body(r: &Rule, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut r = r.clone();
+    r.apply_parameters();
+
     if !r.body.is_empty() {
         write!(f, "{}", r.body[0])?;
 
@@ -994,6 +1271,14 @@ fn display_rule_body(r: &Rule, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         }
     }
 
+    if !r.trusted_keys.is_empty() {
+        write!(f, " trusting {}", r.trusted_keys[0])?;
+
+        for key in r.trusted_keys.iter().skip(1) {
+            write!(f, ", {}", key)?;
+        }
+    }
+
     Ok(())
 }
 
@@ -1009,12 +1294,19 @@ impl fmt::Display for Rule {
 }
 
 /// Builder for a Biscuit check
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Check {
     pub queries: Vec<Rule>,
 }
 
 impl Check {
+    /// the body predicates of the queries, see [Rule::predicates]
+    pub(crate) fn predicates(&self) -> impl Iterator<Item = &Predicate> {
+        self.queries.iter().flat_map(|q| q.body.iter())
+    }
+
     pub fn convert(&self, symbols: &mut SymbolTable) -> datalog::Check {
         let mut queries = vec![];
         for q in self.queries.iter() {
@@ -1104,6 +1396,9 @@ impl fmt::Display for Check {
     }
 }
 
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum PolicyKind {
     Allow,
@@ -1111,6 +1406,8 @@ pub enum PolicyKind {
 }
 
 /// Builder for a Biscuit policy
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Policy {
     pub queries: Vec<Rule>,
@@ -1118,6 +1415,11 @@ pub struct Policy {
 }
 
 impl Policy {
+    /// the body predicates of the queries, see [Rule::predicates]
+    pub(crate) fn predicates(&self) -> impl Iterator<Item = &Predicate> {
+        self.queries.iter().flat_map(|q| q.body.iter())
+    }
+
     /// replace a parameter with the term argument
     pub fn set<T: Into<Term>>(&mut self, name: &str, term: T) -> Result<(), error::Token> {
         let term = term.into();
@@ -1237,6 +1539,7 @@ pub fn string(s: &str) -> Term {
 /// creates a date
 ///
 /// internally the date will be stored as seconds since UNIX_EPOCH
+#[cfg(feature = "std")]
 pub fn date(t: &SystemTime) -> Term {
     let dur = t.duration_since(UNIX_EPOCH).unwrap();
     Term::Date(dur.as_secs())
@@ -1272,6 +1575,129 @@ pub fn parameter(p: &str) -> Term {
     Term::Parameter(p.to_string())
 }
 
+/// `check if root_key($key), [keys].contains($key)`
+fn root_key_check(keys: &[PublicKey]) -> Rule {
+    let keys = keys.iter().map(|k| bytes(&k.to_bytes())).collect();
+    constrained_rule(
+        "root_key_check",
+        &[var("key")],
+        &[pred("root_key", &[var("key")])],
+        &[Expression {
+            ops: vec![
+                Op::Value(set(keys)),
+                Op::Value(var("key")),
+                Op::Binary(Binary::Contains),
+            ],
+        }],
+    )
+}
+
+/// adds an enumeration to the declared ones. Declaring the same
+/// enumeration again is allowed, but not different members for the same term
+pub(crate) fn declare_enumeration(
+    enumerations: &mut Vec<Enumeration>,
+    enumeration: Enumeration,
+) -> Result<(), error::Token> {
+    match enumerations
+        .iter()
+        .find(|e| e.predicate == enumeration.predicate && e.position == enumeration.position)
+    {
+        Some(declared) if *declared == enumeration => Ok(()),
+        Some(_) => Err(error::Token::Language(error::LanguageError::Enumeration(
+            format!("{} is declared twice", enumeration.declared_name()),
+        ))),
+        None => {
+            enumerations.push(enumeration);
+            Ok(())
+        }
+    }
+}
+
+/// returns an error for the first string term that is not a member of
+/// a declared enumeration, see [Enumeration]
+pub(crate) fn check_enumerations<'p>(
+    enumerations: &[Enumeration],
+    predicates: impl IntoIterator<Item = &'p Predicate>,
+) -> Result<(), error::Token> {
+    if enumerations.is_empty() {
+        return Ok(());
+    }
+
+    for predicate in predicates {
+        for enumeration in enumerations {
+            if let Some(s) = enumeration.invalid_members(predicate).first() {
+                return Err(error::Token::Language(error::LanguageError::Enumeration(
+                    enumeration.error_message(s),
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// parses the `key=value` entries added to a context with [BlockBuilder::add_context]
+///
+/// lines without `=` are free form text and are ignored
+pub fn context_metadata(context: &str) -> BTreeMap<String, String> {
+    context
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = split_context_line(line)?;
+            Some((unescape_context(key), unescape_context(value)))
+        })
+        .collect()
+}
+
+fn add_context_entry(context: &mut Option<String>, key: &str, value: &str) {
+    let key = escape_context(key);
+    let mut lines = context
+        .as_deref()
+        .unwrap_or("")
+        .lines()
+        .filter(|line| split_context_line(line).map(|(k, _)| k) != Some(key.as_str()))
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    lines.push(format!("{}={}", key, escape_context(value)));
+
+    *context = Some(lines.join("\n"));
+}
+
+/// splits a line on the first `=` that is not escaped
+fn split_context_line(line: &str) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '=' if !escaped => return Some((&line[..index], &line[index + 1..])),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn escape_context(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('=', "\\=")
+}
+
+fn unescape_context(s: &str) -> String {
+    let mut res = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => res.push('\n'),
+                Some(c) => res.push(c),
+                None => res.push('\\'),
+            },
+            c => res.push(c),
+        }
+    }
+    res
+}
+
 impl From<i64> for Term {
     fn from(i: i64) -> Self {
         Term::Integer(i)
@@ -1360,6 +1786,7 @@ impl TryFrom<Term> for Vec<u8> {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<SystemTime> for Term {
     fn from(t: SystemTime) -> Self {
         let dur = t.duration_since(UNIX_EPOCH).unwrap();
@@ -1367,6 +1794,7 @@ impl From<SystemTime> for Term {
     }
 }
 
+#[cfg(feature = "std")]
 impl TryFrom<Term> for SystemTime {
     type Error = error::Token;
     fn try_from(value: Term) -> Result<Self, Self::Error> {
@@ -1459,6 +1887,20 @@ mod tests {
         assert_eq!(s, "fact($var1, \"hello\") <- f1($var1, $var3), f2(\"hello\", $var3, 1), $var3.starts_with(\"hello\")");
     }
 
+    #[test]
+    fn constant_folding() {
+        let mut builder = BlockBuilder::new();
+        builder
+            .add_check("check if time($time), $time < 2022-01-01T00:00:00Z, 100 * 60 > 1000")
+            .unwrap();
+
+        let block = builder.build(SymbolTable::new());
+        assert_eq!(
+            block.symbols.print_check(&block.checks[0]),
+            "check if time($time), $time < 2022-01-01T00:00:00Z, true"
+        );
+    }
+
     #[test]
     fn set_code_parameters() {
         let mut builder = BlockBuilder::new();
@@ -1518,4 +1960,65 @@ mod tests {
             }))
         )
     }
+
+    #[test]
+    fn enumerations() {
+        let mut block = BlockBuilder::new();
+        block.add_enumeration("@right[1] = {read, write}").unwrap();
+        block.add_fact("right(\"/file\", \"read\")").unwrap();
+        assert_eq!(
+            block.add_fact("right(\"/file\", \"raed\")").unwrap_err(),
+            error::Token::Language(error::LanguageError::Enumeration(
+                "\"raed\" is not a member of @right[1], did you mean \"read\"?".to_string()
+            ))
+        );
+        assert!(block
+            .add_check("check if right($f, [\"read\", \"delete\"])")
+            .is_err());
+
+        // the declarations of add_code apply to the next statements
+        block
+            .add_code("@right[1] = {read, write}; @operation = {read};")
+            .unwrap();
+        assert!(block
+            .add_rule("op($o) <- operation($o), right(\"/f\", \"write\")")
+            .is_ok());
+        assert!(block.add_code("check if operation(\"write\");").is_err());
+        assert!(block.add_code("@operation = {read, write};").is_err());
+
+        let mut builder = BiscuitBuilder::new_unsigned(SymbolTable::new());
+        builder
+            .add_enumeration(Enumeration::new("operation", 0, vec!["read"]))
+            .unwrap();
+        assert!(builder.add_authority_fact("operation(\"read\")").is_ok());
+        assert!(builder
+            .add_authority_check("check if operation(\"raed\")")
+            .is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let term = bytes(&[0xca, 0xfe]);
+        assert_eq!(
+            serde_json::to_string(&term).unwrap(),
+            r#"{"type":"bytes","value":"cafe"}"#
+        );
+
+        let policy = Policy::try_from(
+            r#"allow if resource($r), $r.starts_with("/files/"), $r.length() > 7"#,
+        )
+        .unwrap();
+        let json = serde_json::to_string(&policy).unwrap();
+        assert_eq!(serde_json::from_str::<Policy>(&json).unwrap(), policy);
+
+        let fact: Fact = serde_json::from_str(
+            r#"{"predicate": {"name": "right", "terms": [
+                {"type": "str", "value": "file1"},
+                {"type": "date", "value": 0}
+            ]}}"#,
+        )
+        .unwrap();
+        assert_eq!(fact.to_string(), "right(\"file1\", 1970-01-01T00:00:00Z)");
+    }
 }
@@ -0,0 +1,107 @@
+//! helpers to carry tokens in HTTP Authorization headers
+use super::Biscuit;
+use crate::crypto::PublicKey;
+use crate::error::{self, AuthorizationHeaderError};
+use alloc::string::String;
+
+const BEARER: &str = "bearer";
+
+impl Biscuit {
+    /// deserializes a token from the value of an `Authorization: Bearer <token>` header,
+    /// and validates the signature using the root public key
+    ///
+    /// the scheme is case insensitive, and the token can be encoded with the URL safe
+    /// or the standard base64 alphabet, with or without padding
+    pub fn from_authorization_header<F>(header: &str, f: F) -> Result<Self, error::Token>
+    where
+        F: Fn(Option<u32>) -> PublicKey,
+    {
+        let token = parse_authorization_header(header)?;
+
+        let config = if token.contains(['+', '/']) {
+            base64::STANDARD
+        } else {
+            base64::URL_SAFE
+        };
+        let decoded = base64::decode_config(token, config)?;
+
+        Biscuit::from(&decoded, f)
+    }
+
+    /// serializes the token as the value of an `Authorization` header
+    pub fn to_authorization_header(&self) -> Result<String, error::Token> {
+        Ok(format!("Bearer {}", self.to_base64()?))
+    }
+}
+
+/// extracts the token from the header value
+fn parse_authorization_header(header: &str) -> Result<&str, error::Token> {
+    let header = header.trim();
+
+    let (scheme, token) = match header.find(char::is_whitespace) {
+        Some(index) => header.split_at(index),
+        None => (header, ""),
+    };
+
+    if !scheme.eq_ignore_ascii_case(BEARER) {
+        return Err(error::Token::AuthorizationHeader(
+            AuthorizationHeaderError::MissingBearer,
+        ));
+    }
+
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(error::Token::AuthorizationHeader(
+            AuthorizationHeaderError::EmptyToken,
+        ));
+    }
+
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+
+    #[test]
+    fn header() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(\"1234\")").unwrap();
+        let biscuit = builder.build().unwrap();
+
+        let header = biscuit.to_authorization_header().unwrap();
+        assert!(header.starts_with("Bearer "));
+
+        let token = Biscuit::from_authorization_header(&header, |_| root.public()).unwrap();
+        assert_eq!(token, biscuit);
+
+        let standard = base64::encode_config(biscuit.to_vec().unwrap(), base64::STANDARD);
+        let token =
+            Biscuit::from_authorization_header(&format!("  bearer \t{}  ", standard), |_| {
+                root.public()
+            })
+            .unwrap();
+        assert_eq!(token, biscuit);
+
+        assert_eq!(
+            parse_authorization_header("Basic dXNlcjpwYXNz"),
+            Err(error::Token::AuthorizationHeader(
+                AuthorizationHeaderError::MissingBearer
+            ))
+        );
+        assert_eq!(
+            parse_authorization_header("Bearer  "),
+            Err(error::Token::AuthorizationHeader(
+                AuthorizationHeaderError::EmptyToken
+            ))
+        );
+        assert_eq!(
+            parse_authorization_header("Bearertoken"),
+            Err(error::Token::AuthorizationHeader(
+                AuthorizationHeaderError::MissingBearer
+            ))
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! human readable JSON representation of tokens, with the `json` feature
+//!
+//! this is meant for debugging and support tooling, not as a wire format:
+//! a token cannot be rebuilt from it
+use super::Biscuit;
+use crate::crypto::TokenNext;
+use crate::error;
+use serde::{Deserialize, Serialize};
+
+/// content of a token, with Datalog printed as source code and keys
+/// and signatures in hex
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenJson {
+    pub root_key_id: Option<u32>,
+    pub blocks: Vec<BlockJson>,
+    /// signature closing the token if it is sealed
+    pub seal: Option<String>,
+}
+
+/// content of a block in a [TokenJson]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockJson {
+    pub version: u32,
+    pub context: Option<String>,
+    pub facts: Vec<String>,
+    pub rules: Vec<String>,
+    pub checks: Vec<String>,
+    /// key used to sign the next block
+    pub next_key: String,
+    pub signature: String,
+    /// key of the third party that signed this block
+    pub external_key: Option<String>,
+    pub external_signature: Option<String>,
+}
+
+impl TokenJson {
+    /// reads the output of [Biscuit::to_json]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+impl Biscuit {
+    /// generates a human readable JSON representation of the token
+    pub fn to_json(&self) -> Result<String, error::Token> {
+        serde_json::to_string_pretty(&self.to_json_structure()?)
+            .map_err(|_| error::Token::InternalError)
+    }
+
+    /// returns the structure serialized by [Biscuit::to_json]
+    pub fn to_json_structure(&self) -> Result<TokenJson, error::Token> {
+        let container = self.container.as_ref().ok_or(error::Token::InternalError)?;
+
+        let blocks = self
+            .blocks()
+            .zip(std::iter::once(&container.authority).chain(container.blocks.iter()))
+            .map(|(view, signed)| BlockJson {
+                version: view.version,
+                context: view.context,
+                facts: view.facts.iter().map(|f| f.to_string()).collect(),
+                rules: view.rules.iter().map(|r| r.to_string()).collect(),
+                checks: view.checks.iter().map(|c| c.to_string()).collect(),
+                next_key: hex::encode(signed.next_key.to_bytes()),
+                signature: hex::encode(signed.signature.to_bytes()),
+                external_key: signed
+                    .external_signature
+                    .as_ref()
+                    .map(|e| hex::encode(e.public_key.to_bytes())),
+                external_signature: signed
+                    .external_signature
+                    .as_ref()
+                    .map(|e| hex::encode(e.signature.to_bytes())),
+            })
+            .collect();
+
+        let seal = match &container.proof {
+            TokenNext::Secret(_) => None,
+            TokenNext::Seal(signature) => Some(hex::encode(signature.to_bytes())),
+        };
+
+        Ok(TokenJson {
+            root_key_id: self.root_key_id,
+            blocks,
+            seal,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+
+    #[test]
+    fn json() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(\"file1\", \"read\")")
+            .unwrap();
+        let token = builder.build().unwrap();
+
+        let mut block = token.create_block();
+        block.add_check("check if operation(\"read\")").unwrap();
+        block.set_context("readonly".to_string());
+        let token = token.append(block).unwrap();
+
+        let json = token.to_json().unwrap();
+        let parsed = TokenJson::from_json(&json).unwrap();
+        assert_eq!(parsed, token.to_json_structure().unwrap());
+
+        assert_eq!(parsed.blocks.len(), 2);
+        assert_eq!(parsed.blocks[0].facts, vec!["right(\"file1\", \"read\")"]);
+        assert_eq!(
+            parsed.blocks[1].checks,
+            vec!["check if operation(\"read\")"]
+        );
+        assert_eq!(parsed.blocks[1].context, Some("readonly".to_string()));
+        assert_eq!(
+            parsed.blocks[1].signature,
+            hex::encode(&token.revocation_identifiers()[1])
+        );
+        assert_eq!(parsed.seal, None);
+    }
+}
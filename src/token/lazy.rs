@@ -0,0 +1,199 @@
+//! tokens whose blocks are decoded on access
+use super::{
+    decode_authority_block, decode_block, default_symbol_table, Biscuit, Block, BlockView,
+    RootKeyProvider,
+};
+use crate::{
+    datalog::SymbolTable,
+    error,
+    format::{DeserializationLimits, SerializedBiscuit},
+    sync::lock,
+};
+use std::sync::{Mutex, MutexGuard};
+
+/// A token with a verified signature chain, where only the authority block
+/// was decoded
+///
+/// This is cheaper than [Biscuit::from] for services that only read the
+/// authority block, like issuer or subject facts. The other blocks are
+/// decoded when accessed, and kept for the next accesses.
+/// [LazyBiscuit::into_biscuit] decodes the remaining blocks for authorization
+#[derive(Debug)]
+pub struct LazyBiscuit {
+    authority: Block,
+    /// symbols of the default table and the authority block
+    symbols: SymbolTable,
+    decoded: Mutex<Decoded>,
+    container: SerializedBiscuit,
+}
+
+/// blocks following the authority block, decoded in order
+#[derive(Clone, Debug)]
+struct Decoded {
+    blocks: Vec<Block>,
+    /// symbols of the default table, the authority block and the decoded blocks
+    symbols: SymbolTable,
+}
+
+impl Clone for LazyBiscuit {
+    fn clone(&self) -> Self {
+        LazyBiscuit {
+            authority: self.authority.clone(),
+            symbols: self.symbols.clone(),
+            decoded: Mutex::new(self.decoded().clone()),
+            container: self.container.clone(),
+        }
+    }
+}
+
+impl LazyBiscuit {
+    /// content of the authority block
+    pub fn authority(&self) -> BlockView {
+        BlockView::new(&self.authority, &self.symbols)
+    }
+
+    /// returns the number of blocks (at least 1)
+    pub fn block_count(&self) -> usize {
+        1 + self.container.blocks.len()
+    }
+
+    /// decodes the block at `index`, 0 being the authority block
+    ///
+    /// the symbols of a block depend on the previous blocks, so those are
+    /// decoded as well if they were not accessed yet
+    pub fn block(&self, index: usize) -> Result<Option<BlockView>, error::Token> {
+        if index == 0 {
+            return Ok(Some(self.authority()));
+        }
+        if index >= self.block_count() {
+            return Ok(None);
+        }
+
+        let decoded = self.decode_until(index)?;
+        Ok(Some(BlockView::new(
+            &decoded.blocks[index - 1],
+            &decoded.symbols,
+        )))
+    }
+
+    /// decodes all the blocks to get a [Biscuit]
+    pub fn into_biscuit(self) -> Result<Biscuit, error::Token> {
+        drop(self.decode_until(self.container.blocks.len())?);
+        let Decoded { blocks, symbols } =
+            self.decoded.into_inner().unwrap_or_else(|e| e.into_inner());
+
+        Ok(Biscuit {
+            root_key_id: self.container.root_key_id,
+            authority: self.authority,
+            blocks,
+            symbols,
+            container: Some(self.container),
+        })
+    }
+
+    /// decodes the blocks following the authority block, up to `count` of them
+    fn decode_until(&self, count: usize) -> Result<MutexGuard<'_, Decoded>, error::Token> {
+        let mut decoded = self.decoded();
+
+        while decoded.blocks.len() < count {
+            let block = &self.container.blocks[decoded.blocks.len()];
+            let block = decode_block(
+                &block.data,
+                block.external_signature.as_ref().map(|s| s.public_key),
+                &decoded.symbols,
+                &DeserializationLimits::unlimited(),
+            )?;
+            decoded.symbols.extend(&block.symbols);
+            decoded.blocks.push(block);
+        }
+
+        Ok(decoded)
+    }
+
+    fn decoded(&self) -> MutexGuard<'_, Decoded> {
+        // the blocks are only pushed once decoded, so the state is consistent
+        // if a thread panics
+        lock(&self.decoded)
+    }
+}
+
+impl Biscuit {
+    /// deserializes a token and verifies the whole signature chain, but only
+    /// decodes the authority block. See [LazyBiscuit]
+    pub fn parse_authority_only<T, KP>(
+        slice: T,
+        key_provider: &KP,
+    ) -> Result<LazyBiscuit, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        let container = SerializedBiscuit::from_slice_with_provider(slice.as_ref(), key_provider)
+            .map_err(error::Token::Format)?;
+
+        let authority = decode_authority_block(
+            &container.authority.data,
+            &DeserializationLimits::unlimited(),
+        )?;
+
+        let mut symbols = default_symbol_table();
+        symbols.extend(&authority.symbols);
+
+        Ok(LazyBiscuit {
+            authority,
+            decoded: Mutex::new(Decoded {
+                blocks: Vec::new(),
+                symbols: symbols.clone(),
+            }),
+            symbols,
+            container,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{fact, string};
+    use crate::KeyPair;
+
+    #[test]
+    fn authority_only() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact(fact("user", &[string("alice")]))
+            .unwrap();
+        let mut token = builder.build().unwrap();
+        for i in 0..3 {
+            let mut block = token.create_block();
+            block
+                .add_fact(fact("block", &[string(&format!("block{}", i))]))
+                .unwrap();
+            token = token.append(block).unwrap();
+        }
+        let serialized = token.to_vec().unwrap();
+
+        let lazy = Biscuit::parse_authority_only(&serialized, &root.public()).unwrap();
+        assert_eq!(lazy.block_count(), 4);
+        assert_eq!(lazy.authority().facts[0].to_string(), "user(\"alice\")");
+        assert_eq!(
+            lazy.block(2).unwrap().unwrap().facts[0].to_string(),
+            "block(\"block1\")"
+        );
+        assert!(lazy.block(4).unwrap().is_none());
+
+        // earlier blocks were decoded and kept
+        assert_eq!(lazy.decoded().blocks.len(), 2);
+        assert_eq!(
+            lazy.block(1).unwrap().unwrap().facts[0].to_string(),
+            "block(\"block0\")"
+        );
+        assert_eq!(lazy.decoded().blocks.len(), 2);
+
+        assert_eq!(lazy.into_biscuit().unwrap(), token);
+
+        let other_root = KeyPair::new();
+        assert!(Biscuit::parse_authority_only(&serialized, &other_root.public()).is_err());
+    }
+}
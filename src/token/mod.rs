@@ -1,23 +1,86 @@
 //! main structures to interact with Biscuit tokens
-use super::crypto::{KeyPair, PublicKey};
-use super::datalog::{Check, Fact, Rule, SymbolTable, Term};
+use super::crypto::{KeyPair, PublicKey, Signer};
+use super::datalog::{Binary, Check, Fact, Op, Rule, SymbolTable, Term, Unary};
 use super::error;
-use super::format::SerializedBiscuit;
+use super::format::{DeserializationLimits, SerializedBiscuit};
+use crate::collections::HashMap;
+use alloc::string::String;
+use alloc::vec::Vec;
 use builder::{BiscuitBuilder, BlockBuilder};
-use prost::Message;
+use core::future::Future;
 use rand_core::{CryptoRng, RngCore};
 
-use crate::format::{convert::proto_block_to_token_block, schema};
+use crate::format::convert::proto_block_to_token_block;
 use authorizer::Authorizer;
+use third_party::{ThirdPartyBlock, ThirdPartyRequest};
 
 pub mod authorizer;
 pub mod builder;
+mod http;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod lazy;
+#[cfg(feature = "std")]
+pub mod pool;
+pub mod third_party;
 pub mod unverified;
 
 /// minimum supported version of the serialization format
 pub const MIN_SCHEMA_VERSION: u32 = 3;
 /// maximum supported version of the serialization format
-pub const MAX_SCHEMA_VERSION: u32 = 3;
+pub const MAX_SCHEMA_VERSION: u32 = 5;
+
+/// format version of third party blocks and scoped rules (`trusting`)
+pub(crate) const THIRD_PARTY_SCHEMA_VERSION: u32 = 4;
+/// format version of the expression operations added after version 3
+pub(crate) const EXTENDED_OPS_SCHEMA_VERSION: u32 = 4;
+/// format version of a block wrapping another, compressed, block. See
+/// [compression](crate::format::compression)
+pub(crate) const COMPRESSED_SCHEMA_VERSION: u32 = 5;
+
+/// lowest format version supporting the content of a block
+///
+/// blocks are emitted at this version so they stay readable by older
+/// verifiers when they do not use newer features:
+/// - 3 is the base Datalog format
+/// - 4 adds third party blocks, rules trusting public keys, and the
+///   `contains_ip`, `semver_ge`, `semver_lt`, byte array and SHA-256
+///   operations
+pub(crate) fn required_version(_facts: &[Fact], rules: &[Rule], checks: &[Check]) -> u32 {
+    let queries = checks.iter().flat_map(|check| check.queries.iter());
+
+    rules
+        .iter()
+        .chain(queries)
+        .map(|rule| {
+            let trusting = if rule.trusted_keys.is_empty() {
+                MIN_SCHEMA_VERSION
+            } else {
+                THIRD_PARTY_SCHEMA_VERSION
+            };
+
+            rule.expressions
+                .iter()
+                .flat_map(|expression| expression.ops.iter())
+                .map(op_version)
+                .fold(trusting, core::cmp::max)
+        })
+        .fold(MIN_SCHEMA_VERSION, core::cmp::max)
+}
+
+/// lowest format version supporting an expression operation
+fn op_version(op: &Op) -> u32 {
+    match op {
+        Op::Binary(
+            Binary::ContainsIp | Binary::SemverGe | Binary::SemverLt | Binary::Take | Binary::Skip,
+        ) => EXTENDED_OPS_SCHEMA_VERSION,
+        Op::Unary(
+            Unary::Sha256 | Unary::ToHex | Unary::FromHex | Unary::ToBase64 | Unary::FromBase64,
+        ) => EXTENDED_OPS_SCHEMA_VERSION,
+        _ => MIN_SCHEMA_VERSION,
+    }
+}
 
 /// some symbols are predefined and available in every implementation, to avoid
 /// transmitting them with every token
@@ -73,6 +136,18 @@ impl Biscuit {
         Biscuit::builder_with_symbols(root, default_symbol_table())
     }
 
+    /// creates the first block's builder, with an external signer holding the root key
+    pub fn builder_with_signer(root: &dyn Signer) -> BiscuitBuilder<'_> {
+        BiscuitBuilder::new(root, default_symbol_table())
+    }
+
+    /// creates the first block's builder, without a root signer
+    ///
+    /// the token is then created with [BiscuitBuilder::build_async]
+    pub fn unsigned_builder<'a>() -> BiscuitBuilder<'a> {
+        BiscuitBuilder::new_unsigned(default_symbol_table())
+    }
+
     /// deserializes a token and validates the signature using the root public key
     pub fn from<T, F>(slice: T, f: F) -> Result<Self, error::Token>
     where
@@ -91,6 +166,175 @@ impl Biscuit {
         Biscuit::from_base64_with_symbols(slice, f, default_symbol_table())
     }
 
+    /// deserializes multiple tokens created with the same root key
+    ///
+    /// the signatures of all the tokens are verified in one batch, which is faster
+    /// than verifying them one by one. If the batch fails, each token is verified
+    /// separately, with strict verification, to find the invalid ones
+    pub fn from_batch<T: AsRef<[u8]>>(
+        slices: &[T],
+        root: &PublicKey,
+    ) -> Vec<Result<Self, error::Token>> {
+        let containers = slices
+            .iter()
+            .map(|slice| SerializedBiscuit::deserialize(slice.as_ref()))
+            .collect::<Vec<_>>();
+
+        let valid = containers
+            .iter()
+            .filter_map(|c| c.as_ref().ok())
+            .collect::<Vec<_>>();
+        let batch_verified = SerializedBiscuit::verify_batch(&valid, root).is_ok();
+
+        containers
+            .into_iter()
+            .map(|container| {
+                let container = container?;
+                if !batch_verified {
+                    container.verify(root)?;
+                }
+
+                Biscuit::from_serialized_container(
+                    container,
+                    default_symbol_table(),
+                    &DeserializationLimits::unlimited(),
+                )
+            })
+            .collect()
+    }
+
+    /// deserializes a token with custom deserialization limits, and validates
+    /// the signature using the root public key
+    pub fn from_with_limits<T, F>(
+        slice: T,
+        f: F,
+        limits: &DeserializationLimits,
+    ) -> Result<Self, error::Token>
+    where
+        F: Fn(Option<u32>) -> PublicKey,
+        T: AsRef<[u8]>,
+    {
+        let container = SerializedBiscuit::from_slice_with_limits(slice.as_ref(), f, limits)
+            .map_err(error::Token::Format)?;
+
+        Biscuit::from_serialized_container(container, default_symbol_table(), limits)
+    }
+
+    /// deserializes a token and validates the signature using the root public key
+    /// selected by the key provider from the token's root key id
+    pub fn from_with_provider<T, KP>(slice: T, key_provider: &KP) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        let container = SerializedBiscuit::from_slice_with_provider(slice.as_ref(), key_provider)
+            .map_err(error::Token::Format)?;
+
+        Biscuit::from_serialized_container(
+            container,
+            default_symbol_table(),
+            &DeserializationLimits::unlimited(),
+        )
+    }
+
+    /// deserializes a token and validates the signature with a custom signature verifier,
+    /// using the root public key selected by the key provider
+    pub fn from_with_verifier<T, KP>(
+        slice: T,
+        key_provider: &KP,
+        verifier: &dyn crate::crypto::Verifier,
+    ) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        let container = SerializedBiscuit::deserialize(slice.as_ref())?;
+
+        let root = key_provider.choose(container.root_key_id)?;
+        container.verify_with(&root, verifier)?;
+
+        Biscuit::from_serialized_container(
+            container,
+            default_symbol_table(),
+            &DeserializationLimits::unlimited(),
+        )
+    }
+
+    /// deserializes a token from the [compact](crate::format::compact) encoding and
+    /// validates the signature using the root public key selected by the key provider
+    #[cfg(feature = "compact")]
+    pub fn from_compact<T, KP>(slice: T, key_provider: &KP) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        let container = SerializedBiscuit::from_compact_with_provider(
+            slice.as_ref(),
+            key_provider,
+            &DeserializationLimits::default(),
+        )
+        .map_err(error::Token::Format)?;
+
+        Biscuit::from_serialized_container(
+            container,
+            default_symbol_table(),
+            &DeserializationLimits::default(),
+        )
+    }
+
+    /// deserializes a token from base64 and validates the signature using the root public key
+    /// selected by the key provider from the token's root key id
+    pub fn from_base64_with_provider<T, KP>(
+        slice: T,
+        key_provider: &KP,
+    ) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        let decoded = decode_base64(slice)?;
+        Biscuit::from_with_provider(decoded, key_provider)
+    }
+
+    /// deserializes a token and validates the signature using the root public key
+    /// returned by an async function, called with the token's root key id
+    ///
+    /// this can be used to fetch the root key from a remote service or a database
+    pub async fn from_async<T, F, Fut>(slice: T, f: F) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        F: FnOnce(Option<u32>) -> Fut,
+        Fut: Future<Output = Result<PublicKey, error::Format>>,
+    {
+        let container = SerializedBiscuit::deserialize(slice.as_ref())?;
+
+        let root = f(container.root_key_id).await?;
+        container.verify(&root)?;
+
+        Biscuit::from_serialized_container(
+            container,
+            default_symbol_table(),
+            &DeserializationLimits::unlimited(),
+        )
+    }
+
+    /// deserializes a token from base64 and validates the signature using the root public key
+    /// returned by an async function, called with the token's root key id
+    pub async fn from_base64_async<T, F, Fut>(slice: T, f: F) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        F: FnOnce(Option<u32>) -> Fut,
+        Fut: Future<Output = Result<PublicKey, error::Format>>,
+    {
+        let decoded = decode_base64(slice)?;
+        Biscuit::from_async(decoded, f).await
+    }
+
+    /// returns the root key id, if it was set when creating the token
+    pub fn root_key_id(&self) -> Option<u32> {
+        self.root_key_id
+    }
+
     /// serializes the token
     pub fn to_vec(&self) -> Result<Vec<u8>, error::Token> {
         match self.container.as_ref() {
@@ -99,14 +343,14 @@ impl Biscuit {
         }
     }
 
-    /// serializes the token and encode it to a (URL safe) base64 string
+    /// serializes the token and encode it to a (URL safe, without padding) base64 string
     pub fn to_base64(&self) -> Result<String, error::Token> {
         match self.container.as_ref() {
             None => Err(error::Token::InternalError),
             Some(c) => c
                 .to_vec()
                 .map_err(error::Token::Format)
-                .map(|v| base64::encode_config(v, base64::URL_SAFE)),
+                .map(|v| base64::encode_config(v, base64::URL_SAFE_NO_PAD)),
         }
     }
 
@@ -118,6 +362,15 @@ impl Biscuit {
         }
     }
 
+    /// serializes the token with the [compact](crate::format::compact) encoding
+    #[cfg(feature = "compact")]
+    pub fn to_compact(&self) -> Result<Vec<u8>, error::Token> {
+        match self.container.as_ref() {
+            None => Err(error::Token::InternalError),
+            Some(c) => Ok(c.to_compact()),
+        }
+    }
+
     /// creates a sealed version of the token
     ///
     /// sealed tokens cannot be attenuated
@@ -135,6 +388,14 @@ impl Biscuit {
         }
     }
 
+    /// returns true if the token was sealed and cannot be attenuated anymore
+    pub fn is_sealed(&self) -> bool {
+        self.container
+            .as_ref()
+            .map(|c| c.is_sealed())
+            .unwrap_or(false)
+    }
+
     /// creates a authorizer from this token
     pub fn authorizer(&self) -> Result<Authorizer, error::Token> {
         Authorizer::from_token(self)
@@ -149,11 +410,63 @@ impl Biscuit {
     ///
     /// since the public key is integrated into the token, the keypair can be
     /// discarded right after calling this function
+    #[cfg(feature = "std")]
     pub fn append(&self, block_builder: BlockBuilder) -> Result<Self, error::Token> {
         let keypair = KeyPair::new_with_rng(&mut rand::rngs::OsRng);
         self.append_with_keypair(&keypair, block_builder)
     }
 
+    /// adds a new block to the token, generating the next key with the provided CSPRNG
+    ///
+    /// with a seeded RNG, the same token and block will always produce the same result
+    pub fn append_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        block_builder: BlockBuilder,
+    ) -> Result<Self, error::Token> {
+        let keypair = KeyPair::new_with_rng(rng);
+        self.append_with_keypair(&keypair, block_builder)
+    }
+
+    /// verifies the signatures of the whole chain of blocks with the root public key
+    ///
+    /// this does not run the Datalog checks, which is the role of the [Authorizer]
+    pub fn verify<KP: RootKeyProvider>(&self, key_provider: &KP) -> Result<(), error::Token> {
+        self.verify_with(key_provider, &crate::crypto::DalekVerifier)
+    }
+
+    /// verifies the signatures of the whole chain of blocks with a custom signature verifier
+    pub fn verify_with<KP: RootKeyProvider>(
+        &self,
+        key_provider: &KP,
+        verifier: &dyn crate::crypto::Verifier,
+    ) -> Result<(), error::Token> {
+        match self.container.as_ref() {
+            None => Err(error::Token::InternalError),
+            Some(c) => {
+                let root = key_provider.choose(c.root_key_id)?;
+                c.verify_with(&root, verifier).map_err(error::Token::Format)
+            }
+        }
+    }
+
+    /// attenuates the token with a new block, then verifies the result against the root
+    /// public key
+    ///
+    /// this is meant for holders of the token that do not have the root private key, to
+    /// check the new token before forwarding it
+    #[cfg(feature = "std")]
+    pub fn attenuate<KP: RootKeyProvider>(
+        &self,
+        block_builder: BlockBuilder,
+        key_provider: &KP,
+    ) -> Result<Self, error::Token> {
+        let token = self.append(block_builder)?;
+        token.verify(key_provider)?;
+
+        Ok(token)
+    }
+
     /// returns the list of context elements of each block
     ///
     /// the context is a free form text field in which application specific data
@@ -170,6 +483,12 @@ impl Biscuit {
 
     /// returns a list of revocation identifiers for each block, in order
     ///
+    /// the identifier of a block is the bytes of its signature. Since each
+    /// signature covers the block's content and the key provided by the
+    /// previous block, it is unique to the chain of blocks leading to it.
+    /// They can be added to the authorizer as `revocation_id(block index, id)`
+    /// facts with [Authorizer::add_revocation_id_facts]
+    ///
     /// if a token is generated with the same keys and the same content,
     /// those identifiers will stay the same
     pub fn revocation_identifiers(&self) -> Vec<Vec<u8>> {
@@ -186,6 +505,22 @@ impl Biscuit {
         res
     }
 
+    /// checks the revocation identifiers of the token's blocks in a revocation store
+    ///
+    /// this returns [error::Revocation::Revoked] with the index of the first revoked block
+    pub fn check_revocation<S: crate::revocation::RevocationStore + ?Sized>(
+        &self,
+        store: &S,
+    ) -> Result<(), error::Token> {
+        match store
+            .first_revoked(&self.revocation_identifiers())
+            .map_err(error::Revocation::Lookup)?
+        {
+            Some(index) => Err(error::Revocation::Revoked(index as u32).into()),
+            None => Ok(()),
+        }
+    }
+
     /// pretty printer for this token
     pub fn print(&self) -> String {
         let authority = print_block(&self.symbols, &self.authority);
@@ -228,7 +563,7 @@ impl Biscuit {
     pub(crate) fn new_with_rng<T: RngCore + CryptoRng>(
         rng: &mut T,
         root_key_id: Option<u32>,
-        root: &KeyPair,
+        root: &dyn Signer,
         mut symbols: SymbolTable,
         authority: Block,
     ) -> Result<Biscuit, error::Token> {
@@ -237,6 +572,7 @@ impl Biscuit {
         }
 
         symbols.extend(&authority.symbols);
+        symbols.freeze();
 
         let blocks = vec![];
 
@@ -252,6 +588,38 @@ impl Biscuit {
         })
     }
 
+    /// creates a new token, signing the authority block with an async function
+    #[cfg(feature = "std")]
+    pub(crate) async fn new_async<F, Fut>(
+        root_key_id: Option<u32>,
+        sign: F,
+        mut symbols: SymbolTable,
+        authority: Block,
+    ) -> Result<Biscuit, error::Token>
+    where
+        F: FnOnce(Vec<u8>) -> Fut,
+        Fut: Future<Output = Result<crate::crypto::Signature, error::Format>>,
+    {
+        if !symbols.is_disjoint(&authority.symbols) {
+            return Err(error::Token::SymbolTableOverlap);
+        }
+
+        symbols.extend(&authority.symbols);
+        symbols.freeze();
+
+        let next_keypair = KeyPair::new();
+        let container =
+            SerializedBiscuit::new_async(root_key_id, sign, &next_keypair, &authority).await?;
+
+        Ok(Biscuit {
+            root_key_id,
+            authority,
+            blocks: vec![],
+            symbols,
+            container: Some(container),
+        })
+    }
+
     /// deserializes a token and validates the signature using the root public key, with a custom symbol table
     fn from_with_symbols<F>(slice: &[u8], f: F, symbols: SymbolTable) -> Result<Self, error::Token>
     where
@@ -259,42 +627,43 @@ impl Biscuit {
     {
         let container = SerializedBiscuit::from_slice(slice, f).map_err(error::Token::Format)?;
 
-        Biscuit::from_serialized_container(container, symbols)
+        Biscuit::from_serialized_container(container, symbols, &DeserializationLimits::unlimited())
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(blocks = container.blocks.len() + 1)
+        )
+    )]
     fn from_serialized_container(
         container: SerializedBiscuit,
         mut symbols: SymbolTable,
+        limits: &DeserializationLimits,
     ) -> Result<Self, error::Token> {
-        let authority: Block = schema::Block::decode(&container.authority.data[..])
-            .map_err(|e| {
-                error::Token::Format(error::Format::BlockDeserializationError(format!(
-                    "error deserializing authority block: {:?}",
-                    e
-                )))
-            })
-            .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+        let authority = decode_authority_block(&container.authority.data, limits)?;
+
+        symbols.extend(&authority.symbols);
+        let mut symbols_count = authority.symbols.current_offset();
 
         let mut blocks = vec![];
 
         for block in container.blocks.iter() {
-            let deser: Block = schema::Block::decode(&block.data[..])
-                .map_err(|e| {
-                    error::Token::Format(error::Format::BlockDeserializationError(format!(
-                        "error deserializing block: {:?}",
-                        e
-                    )))
-                })
-                .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+            let deser = decode_block(
+                &block.data,
+                block.external_signature.as_ref().map(|s| s.public_key),
+                &symbols,
+                limits,
+            )?;
+            symbols.extend(&deser.symbols);
+            symbols_count += deser.symbols.current_offset();
+            limits.check_symbols(symbols_count)?;
 
             blocks.push(deser);
         }
-
-        symbols.extend(&authority.symbols);
-
-        for block in blocks.iter() {
-            symbols.extend(&block.symbols);
-        }
+        symbols.freeze();
 
         let root_key_id = container.root_key_id;
         let container = Some(container);
@@ -318,7 +687,7 @@ impl Biscuit {
         F: Fn(Option<u32>) -> PublicKey,
         T: AsRef<[u8]>,
     {
-        let decoded = base64::decode_config(slice, base64::URL_SAFE)?;
+        let decoded = decode_base64(slice)?;
         Biscuit::from_with_symbols(&decoded, f, symbols)
     }
 
@@ -356,6 +725,7 @@ impl Biscuit {
         };
 
         symbols.extend(&block.symbols);
+        symbols.freeze();
         blocks.push(block);
 
         Ok(Biscuit {
@@ -367,6 +737,98 @@ impl Biscuit {
         })
     }
 
+    /// parses a block from Datalog source code and adds it to the token
+    ///
+    /// the source can contain facts, rules and checks
+    pub fn append_block_from_code<T: AsRef<str>>(
+        &self,
+        keypair: &KeyPair,
+        source: T,
+    ) -> Result<Self, error::Token> {
+        let mut block_builder = self.create_block();
+        block_builder.add_code(source)?;
+
+        self.append_with_keypair(keypair, block_builder)
+    }
+
+    /// creates a request for a third party block
+    ///
+    /// the third party will use it to create a block bound to this token
+    /// with [ThirdPartyRequest::create_block]
+    pub fn third_party_request(&self) -> Result<ThirdPartyRequest, error::Token> {
+        match self.container.as_ref() {
+            Some(c) if !c.is_sealed() => Ok(ThirdPartyRequest {
+                previous_signature: c.last_signature(),
+            }),
+            _ => Err(error::Token::AppendOnSealed),
+        }
+    }
+
+    /// adds a block created by a third party to the token
+    #[cfg(feature = "std")]
+    pub fn append_third_party(&self, block: ThirdPartyBlock) -> Result<Self, error::Token> {
+        let keypair = KeyPair::new_with_rng(&mut rand::rngs::OsRng);
+        self.append_third_party_with_keypair(&keypair, block)
+    }
+
+    /// adds a block created by a third party to the token
+    ///
+    /// the external signature must match the current last block of the token
+    pub fn append_third_party_with_keypair(
+        &self,
+        keypair: &KeyPair,
+        block: ThirdPartyBlock,
+    ) -> Result<Self, error::Token> {
+        self.append_third_party_with_verifier(keypair, block, &crate::crypto::DalekVerifier)
+    }
+
+    /// adds a block created by a third party to the token, checking the
+    /// external signature with a custom signature verifier
+    pub fn append_third_party_with_verifier(
+        &self,
+        keypair: &KeyPair,
+        block: ThirdPartyBlock,
+        verifier: &dyn crate::crypto::Verifier,
+    ) -> Result<Self, error::Token> {
+        let c = match self.container.as_ref() {
+            None => return Err(error::Token::AppendOnSealed),
+            Some(c) => c,
+        };
+
+        crate::crypto::verify_external_signature(
+            &block.payload,
+            &c.last_signature(),
+            &block.external_signature,
+            verifier,
+        )?;
+
+        let deser = decode_block(
+            &block.payload,
+            Some(block.external_signature.public_key),
+            &self.symbols,
+            &DeserializationLimits::unlimited(),
+        )?;
+
+        let container =
+            c.append_serialized(keypair, block.payload, Some(block.external_signature))?;
+
+        let authority = self.authority.clone();
+        let mut blocks = self.blocks.clone();
+        let mut symbols = self.symbols.clone();
+
+        symbols.extend(&deser.symbols);
+        symbols.freeze();
+        blocks.push(deser);
+
+        Ok(Biscuit {
+            root_key_id: self.root_key_id,
+            authority,
+            blocks,
+            symbols,
+            container: Some(container),
+        })
+    }
+
     /// gets the list of symbols from a block
     pub fn block_symbols(&self, index: usize) -> Option<Vec<String>> {
         let block = if index == 0 {
@@ -385,6 +847,182 @@ impl Biscuit {
     pub fn block_count(&self) -> usize {
         1 + self.blocks.len()
     }
+
+    /// stable hash of the token's content, covering all blocks and their signatures
+    ///
+    /// see [SerializedBiscuit::content_hash] for the exact derivation
+    pub fn content_hash(&self) -> Result<[u8; 32], error::Token> {
+        match self.container.as_ref() {
+            None => Err(error::Token::InternalError),
+            Some(c) => Ok(c.content_hash()),
+        }
+    }
+
+    /// iterates over the content of each block, starting with the authority block
+    pub fn blocks(&self) -> impl Iterator<Item = BlockView> + '_ {
+        core::iter::once(&self.authority)
+            .chain(self.blocks.iter())
+            .map(move |block| BlockView::new(block, &self.symbols))
+    }
+}
+
+/// tokens are equal if they have the same [content hash](Biscuit::content_hash)
+impl PartialEq for Biscuit {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.content_hash(), other.content_hash()) {
+            (Ok(h1), Ok(h2)) => h1 == h2,
+            _ => false,
+        }
+    }
+}
+
+/// content of a block, with facts, rules and checks converted to builder types
+#[derive(Clone, Debug)]
+pub struct BlockView {
+    pub facts: Vec<builder::Fact>,
+    pub rules: Vec<builder::Rule>,
+    pub checks: Vec<builder::Check>,
+    pub context: Option<String>,
+    pub version: u32,
+    /// key that signed this block, if it was created by a third party
+    pub external_key: Option<PublicKey>,
+}
+
+impl BlockView {
+    fn new(block: &Block, symbols: &SymbolTable) -> Self {
+        BlockView {
+            facts: block
+                .facts
+                .iter()
+                .map(|f| builder::Fact::convert_from(f, symbols))
+                .collect(),
+            rules: block
+                .rules
+                .iter()
+                .map(|r| builder::Rule::convert_from(r, symbols))
+                .collect(),
+            checks: block
+                .checks
+                .iter()
+                .map(|c| builder::Check::convert_from(c, symbols))
+                .collect(),
+            context: block.context.clone(),
+            version: block.version,
+            external_key: block.external_key,
+        }
+    }
+
+    /// `key=value` entries of the context, see [BlockBuilder::add_context]
+    pub fn metadata(&self) -> alloc::collections::BTreeMap<String, String> {
+        self.context
+            .as_deref()
+            .map(builder::context_metadata)
+            .unwrap_or_default()
+    }
+
+    /// prints the content of the block as Datalog source code
+    pub fn print_source(&self) -> String {
+        let mut res = String::new();
+
+        for fact in self.facts.iter() {
+            res.push_str(&format!("{};\n", fact));
+        }
+        for rule in self.rules.iter() {
+            res.push_str(&format!("{};\n", rule));
+        }
+        for check in self.checks.iter() {
+            res.push_str(&format!("{};\n", check));
+        }
+
+        res
+    }
+}
+
+/// serialized as its (URL safe) base64 representation
+///
+/// there is no `Deserialize` implementation since the root public key is
+/// needed to verify the token: deserialize to an [UnverifiedBiscuit](unverified::UnverifiedBiscuit),
+/// then call [UnverifiedBiscuit::check_signature](unverified::UnverifiedBiscuit::check_signature)
+#[cfg(feature = "serde")]
+impl serde::Serialize for Biscuit {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let s = self.to_base64().map_err(S::Error::custom)?;
+        serializer.serialize_str(&s)
+    }
+}
+
+/// selects the root public key used to verify a token
+///
+/// the token can carry a root key id, which lets the provider
+/// pick the right key when root keys are rotated
+pub trait RootKeyProvider {
+    fn choose(&self, key_id: Option<u32>) -> Result<PublicKey, error::Format>;
+}
+
+impl RootKeyProvider for PublicKey {
+    fn choose(&self, _: Option<u32>) -> Result<PublicKey, error::Format> {
+        Ok(*self)
+    }
+}
+
+impl RootKeyProvider for HashMap<u32, PublicKey> {
+    fn choose(&self, key_id: Option<u32>) -> Result<PublicKey, error::Format> {
+        key_id
+            .and_then(|id| self.get(&id))
+            .copied()
+            .ok_or(error::Format::UnknownPublicKey)
+    }
+}
+
+/// deserializes the authority block
+/// decodes a token encoded with the URL safe base64 alphabet, with or without padding
+pub(crate) fn decode_base64<T: AsRef<[u8]>>(slice: T) -> Result<Vec<u8>, error::Format> {
+    base64::decode_config(slice, base64::URL_SAFE).map_err(|e| error::Format::Base64(e.into()))
+}
+
+pub(crate) fn decode_authority_block(
+    data: &[u8],
+    limits: &DeserializationLimits,
+) -> Result<Block, error::Token> {
+    let block = crate::format::decode_block(data, limits)?;
+    let block = proto_block_to_token_block(&block)?;
+    limits.check_symbols(block.symbols.current_offset())?;
+
+    Ok(block)
+}
+
+/// deserializes a block that follows the authority block
+///
+/// third party blocks are serialized with their own symbol table, so their
+/// symbols are moved to the token's symbol table
+pub(crate) fn decode_block(
+    data: &[u8],
+    external_key: Option<PublicKey>,
+    symbols: &SymbolTable,
+    limits: &DeserializationLimits,
+) -> Result<Block, error::Token> {
+    let block = crate::format::decode_block(data, limits)?;
+    let block = proto_block_to_token_block(&block)?;
+
+    match external_key {
+        None => Ok(block),
+        Some(_) if block.version < THIRD_PARTY_SCHEMA_VERSION => Err(error::Token::Format(
+            error::Format::BlockDeserializationError(
+                format!(
+                    "error deserializing block: third party blocks require version {}, found {}",
+                    THIRD_PARTY_SCHEMA_VERSION, block.version
+                )
+                .into(),
+            ),
+        )),
+        Some(key) => {
+            let mut block = block.remap_symbols(symbols);
+            block.external_key = Some(key);
+            Ok(block)
+        }
+    }
 }
 
 fn print_block(symbols: &SymbolTable, block: &Block) -> String {
@@ -448,6 +1086,8 @@ pub struct Block {
     pub context: Option<String>,
     /// format version used to generate this block
     pub version: u32,
+    /// key that signed this block, if it was created by a third party
+    pub external_key: Option<PublicKey>,
 }
 
 impl Block {
@@ -459,8 +1099,42 @@ impl Block {
         self.symbols.insert(s)
     }
 
-    fn print_source(&self, symbols: &SymbolTable) -> String {
-        let facts: Vec<_> = self.facts.iter().map(|f| symbols.print_fact(f)).collect();
+    /// converts a block built with its own symbol table to use `symbols`
+    ///
+    /// the returned block only contains the symbols missing from `symbols`
+    fn remap_symbols(self, symbols: &SymbolTable) -> Block {
+        let mut table = symbols.clone();
+        let start = table.current_offset();
+
+        let facts = self
+            .facts
+            .iter()
+            .map(|f| builder::Fact::convert_from(f, &self.symbols).convert(&mut table))
+            .collect();
+        let rules = self
+            .rules
+            .iter()
+            .map(|r| builder::Rule::convert_from(r, &self.symbols).convert(&mut table))
+            .collect();
+        let checks = self
+            .checks
+            .iter()
+            .map(|c| builder::Check::convert_from(c, &self.symbols).convert(&mut table))
+            .collect();
+
+        Block {
+            symbols: table.split_at(start),
+            facts,
+            rules,
+            checks,
+            context: self.context,
+            version: self.version,
+            external_key: self.external_key,
+        }
+    }
+
+    fn print_source(&self, symbols: &SymbolTable) -> String {
+        let facts: Vec<_> = self.facts.iter().map(|f| symbols.print_fact(f)).collect();
         let rules: Vec<_> = self.rules.iter().map(|r| symbols.print_rule(r)).collect();
         let checks: Vec<_> = self.checks.iter().map(|r| symbols.print_check(r)).collect();
 
@@ -483,10 +1157,12 @@ impl Block {
 
 #[cfg(test)]
 mod tests {
-    use super::builder::{check, fact, pred, rule, string, var};
+    use super::builder::{check, fact, int, pred, rule, string, var};
     use super::*;
     use crate::crypto::KeyPair;
     use crate::error::*;
+    use crate::format::{schema, FormatSettings};
+    use prost::Message;
     use rand::prelude::*;
     use std::time::{Duration, SystemTime};
 
@@ -643,12 +1319,13 @@ mod tests {
 
             let res = authorizer.authorize();
             println!("res2: {:#?}", res);
+            let revocation_ids = final_token.revocation_identifiers();
             assert_eq!(res,
               Err(Token::FailedLogic(Logic::Unauthorized {
                   policy: MatchedPolicy::Allow(0),
                   checks: vec![
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), operation(\"read\"), right($resource, \"read\")") }),
-                FailedCheck::Block(FailedBlockCheck { block_id: 2, check_id: 0, rule: String::from("check if resource(\"file1\")") })
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), operation(\"read\"), right($resource, \"read\")"), context: None, revocation_id: Some(hex::encode(&revocation_ids[1])) }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 2, check_id: 0, rule: String::from("check if resource(\"file1\")"), context: None, revocation_id: Some(hex::encode(&revocation_ids[2])) })
               ]
               })));
         }
@@ -708,7 +1385,9 @@ mod tests {
                         check_id: 0,
                         rule: String::from(
                             "check if resource($resource), $resource.starts_with(\"/folder1/\")"
-                        )
+                        ),
+                        context: None,
+                        revocation_id: Some(hex::encode(&biscuit2.revocation_identifiers()[1])),
                     }),]
                 }))
             );
@@ -721,11 +1400,12 @@ mod tests {
 
             let res = authorizer.authorize();
             println!("res3: {:?}", res);
+            let revocation_id = hex::encode(&biscuit2.revocation_identifiers()[1]);
             assert_eq!(res,
               Err(Token::FailedLogic(Logic::NoMatchingPolicy {
                   checks: vec![
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), $resource.starts_with(\"/folder1/\")") }),
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 1, rule: String::from("check if resource($resource_name), operation(\"read\"), right($resource_name, \"read\")") }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), $resource.starts_with(\"/folder1/\")"), context: None, revocation_id: Some(revocation_id.clone()) }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 1, rule: String::from("check if resource($resource_name), operation(\"read\"), right($resource_name, \"read\")"), context: None, revocation_id: Some(revocation_id) }),
               ]})));
         }
     }
@@ -825,6 +1505,13 @@ mod tests {
         //println!("biscuit2 sealed ({} bytes):\n{}", sealed.len(), sealed.to_hex(16));
 
         let biscuit3 = Biscuit::from(&sealed, |_| root.public()).unwrap();
+        assert!(biscuit3.is_sealed());
+        assert!(!biscuit2.is_sealed());
+        assert_eq!(
+            biscuit3.append(biscuit3.create_block()).unwrap_err(),
+            error::Token::AppendOnSealed
+        );
+        assert_eq!(biscuit3.seal().unwrap_err(), error::Token::AlreadySealed);
 
         {
             let mut authorizer = biscuit3.authorizer().unwrap();
@@ -838,6 +1525,593 @@ mod tests {
         }
     }
 
+    #[test]
+    fn root_key_provider() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root1 = KeyPair::new_with_rng(&mut rng);
+        let root2 = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root2);
+        builder.set_root_key_id(2);
+        builder.add_right("file1", "read");
+        let biscuit = builder.build_with_rng(&mut rng).unwrap();
+        let serialized = biscuit.to_base64().unwrap();
+
+        let mut keys = HashMap::new();
+        keys.insert(1, root1.public());
+        keys.insert(2, root2.public());
+
+        let deser = Biscuit::from_base64_with_provider(&serialized, &keys).unwrap();
+        assert_eq!(deser.root_key_id(), Some(2));
+        Biscuit::from_base64_with_provider(&serialized, &root2.public()).unwrap();
+
+        assert!(Biscuit::from_base64_with_provider(&serialized, &root1.public()).is_err());
+
+        keys.remove(&2);
+        assert_eq!(
+            Biscuit::from_base64_with_provider(&serialized, &keys).unwrap_err(),
+            error::Token::Format(Format::UnknownPublicKey)
+        );
+    }
+
+    #[test]
+    fn append_from_code() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.add_right("file2", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_block_from_code(
+                &keypair2,
+                r#"
+                allowed("file1");
+                check if resource($file), allowed($file);
+                "#,
+            )
+            .unwrap();
+        assert_eq!(
+            biscuit2.print_block_source(1).unwrap(),
+            "allowed(\"file1\");\ncheck if resource($file), allowed($file);\n"
+        );
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer.add_fact("resource(\"file2\")").unwrap();
+        authorizer.allow().unwrap();
+        assert!(authorizer.authorize().is_err());
+
+        assert!(biscuit1
+            .append_block_from_code(&keypair2, "check if resource(")
+            .is_err());
+    }
+
+    #[test]
+    fn deserialization_limits() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.add_right("file2", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        let biscuit2 = biscuit1
+            .append_block_from_code(&root, "check if resource(\"file1\")")
+            .unwrap();
+        let serialized = biscuit2.to_vec().unwrap();
+
+        let limits = DeserializationLimits::default();
+        Biscuit::from_with_limits(&serialized, |_| root.public(), &limits).unwrap();
+
+        // limits are only applied when requested
+        let mut builder = Biscuit::builder(&root);
+        for i in 0..limits.max_facts + 1 {
+            builder
+                .add_authority_fact(fact("f", &[int(i as i64)]))
+                .unwrap();
+        }
+        let large = builder.build_with_rng(&mut rng).unwrap().to_vec().unwrap();
+        Biscuit::from(&large, |_| root.public()).unwrap();
+        assert_eq!(
+            Biscuit::from_with_limits(&large, |_| root.public(), &limits).unwrap_err(),
+            error::Token::Format(Format::Limit(DeserializationLimit::TooManyFacts))
+        );
+
+        let check = |limits: DeserializationLimits, expected: DeserializationLimit| {
+            assert_eq!(
+                Biscuit::from_with_limits(&serialized, |_| root.public(), &limits).unwrap_err(),
+                error::Token::Format(Format::Limit(expected))
+            );
+        };
+
+        check(
+            DeserializationLimits {
+                max_size: serialized.len() - 1,
+                ..Default::default()
+            },
+            DeserializationLimit::TokenSize,
+        );
+        check(
+            DeserializationLimits {
+                max_blocks: 1,
+                ..Default::default()
+            },
+            DeserializationLimit::TooManyBlocks,
+        );
+        check(
+            DeserializationLimits {
+                max_facts: 1,
+                ..Default::default()
+            },
+            DeserializationLimit::TooManyFacts,
+        );
+        check(
+            DeserializationLimits {
+                max_checks: 0,
+                ..Default::default()
+            },
+            DeserializationLimit::TooManyChecks,
+        );
+        check(
+            DeserializationLimits {
+                max_symbols: 1,
+                ..Default::default()
+            },
+            DeserializationLimit::TooManySymbols,
+        );
+
+        let limits = DeserializationLimits {
+            versions: FormatSettings {
+                min_version: MAX_SCHEMA_VERSION + 1,
+                max_version: MAX_SCHEMA_VERSION + 1,
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            Biscuit::from_with_limits(&serialized, |_| root.public(), &limits).unwrap_err(),
+            error::Token::Format(Format::UnsupportedVersion {
+                found: MIN_SCHEMA_VERSION,
+                min: MAX_SCHEMA_VERSION + 1,
+                max: MAX_SCHEMA_VERSION + 1,
+            })
+        );
+    }
+
+    #[test]
+    fn offline_attenuation() {
+        let root = KeyPair::new();
+        let other_root = KeyPair::new();
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(\"file1\", \"read\")")
+            .unwrap();
+        builder
+            .add_authority_fact("right(\"file2\", \"read\")")
+            .unwrap();
+        let biscuit1 = builder.build().unwrap();
+        let serialized = biscuit1.to_vec().unwrap();
+
+        // the holder only knows the root public key
+        let holder = Biscuit::from(&serialized, |_| root.public()).unwrap();
+        let mut block = holder.create_block();
+        block.add_check("check if resource(\"file1\")").unwrap();
+        let biscuit2 = holder.attenuate(block, &root.public()).unwrap();
+        biscuit2.verify(&root.public()).unwrap();
+
+        let biscuit2 = Biscuit::from(&biscuit2.to_vec().unwrap(), |_| root.public()).unwrap();
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer.add_fact("resource(\"file2\")").unwrap();
+        authorizer.allow().unwrap();
+        assert!(authorizer.authorize().is_err());
+
+        // verifying against the wrong root key fails, so the token is not forwarded
+        assert!(holder
+            .attenuate(holder.create_block(), &other_root.public())
+            .is_err());
+        assert!(biscuit2.verify(&other_root.public()).is_err());
+
+        // a sealed token cannot be attenuated
+        let sealed = biscuit2.seal().unwrap();
+        assert_eq!(
+            sealed.attenuate(sealed.create_block(), &root.public()),
+            Err(error::Token::AppendOnSealed)
+        );
+    }
+
+    #[test]
+    fn signature_backend() {
+        use crate::crypto::{DalekVerifier, Verifier};
+        use std::cell::Cell;
+
+        struct CountingVerifier(Cell<usize>);
+
+        impl Verifier for CountingVerifier {
+            fn verify(
+                &self,
+                public_key: &PublicKey,
+                message: &[u8],
+                signature: &crate::crypto::Signature,
+            ) -> Result<(), error::Format> {
+                self.0.set(self.0.get() + 1);
+                DalekVerifier.verify(public_key, message, signature)
+            }
+        }
+
+        struct RejectingVerifier;
+
+        impl Verifier for RejectingVerifier {
+            fn verify(
+                &self,
+                _: &PublicKey,
+                _: &[u8],
+                _: &crate::crypto::Signature,
+            ) -> Result<(), error::Format> {
+                Err(error::Format::Signature(
+                    error::Signature::InvalidSignature("rejected".into()),
+                ))
+            }
+        }
+
+        let root = KeyPair::new();
+        let biscuit1 = Biscuit::builder(&root).build().unwrap();
+        let biscuit2 = biscuit1.append(biscuit1.create_block()).unwrap();
+
+        let verifier = CountingVerifier(Cell::new(0));
+        biscuit2.verify_with(&root.public(), &verifier).unwrap();
+        assert_eq!(verifier.0.get(), 2);
+
+        // the seal signature goes through the verifier too
+        let sealed = biscuit2.seal().unwrap();
+        let verifier = CountingVerifier(Cell::new(0));
+        sealed.verify_with(&root.public(), &verifier).unwrap();
+        assert_eq!(verifier.0.get(), 3);
+
+        assert!(biscuit2
+            .verify_with(&root.public(), &RejectingVerifier)
+            .is_err());
+
+        // deserialization and third party blocks
+        let serialized = biscuit2.to_vec().unwrap();
+        let verifier = CountingVerifier(Cell::new(0));
+        Biscuit::from_with_verifier(&serialized, &root.public(), &verifier).unwrap();
+        assert_eq!(verifier.0.get(), 2);
+        assert!(
+            Biscuit::from_with_verifier(&serialized, &root.public(), &RejectingVerifier).is_err()
+        );
+
+        let external = KeyPair::new();
+        let request = biscuit2.third_party_request().unwrap();
+        let block = request
+            .create_block(&external, BlockBuilder::new())
+            .unwrap();
+        assert!(biscuit2
+            .append_third_party_with_verifier(&KeyPair::new(), block.clone(), &RejectingVerifier)
+            .is_err());
+        let verifier = CountingVerifier(Cell::new(0));
+        biscuit2
+            .append_third_party_with_verifier(&KeyPair::new(), block, &verifier)
+            .unwrap();
+        assert_eq!(verifier.0.get(), 1);
+    }
+
+    #[test]
+    fn many_blocks() {
+        let root = KeyPair::new();
+        let mut biscuit = Biscuit::builder(&root).build().unwrap();
+        for i in 0..10 {
+            let mut block = biscuit.create_block();
+            block
+                .add_check(format!("check if level({})", i).as_str())
+                .unwrap();
+            biscuit = biscuit.append(block).unwrap();
+        }
+
+        let serialized = biscuit.to_vec().unwrap();
+        let biscuit = Biscuit::from(&serialized, |_| root.public()).unwrap();
+
+        let mut container = biscuit.container().unwrap().clone();
+        container.verify(&root.public()).unwrap();
+        assert!(container.verify(&KeyPair::new().public()).is_err());
+
+        container.blocks[3].signature = container.blocks[4].signature;
+        assert!(container.verify(&root.public()).is_err());
+    }
+
+    #[test]
+    fn signature_algorithms() {
+        let root = KeyPair::new();
+        let biscuit = Biscuit::builder(&root).build().unwrap();
+        let serialized = biscuit.to_vec().unwrap();
+
+        let limits = DeserializationLimits {
+            algorithms: vec![],
+            ..Default::default()
+        };
+        assert_eq!(
+            Biscuit::from_with_limits(&serialized, |_| root.public(), &limits).unwrap_err(),
+            error::Token::Format(error::Format::UnsupportedAlgorithm(0))
+        );
+
+        let mut proto = schema::Biscuit::decode(&serialized[..]).unwrap();
+        proto.authority.next_key.algorithm = 2;
+        let mut modified = Vec::new();
+        proto.encode(&mut modified).unwrap();
+        assert_eq!(
+            Biscuit::from(&modified, |_| root.public()).unwrap_err(),
+            error::Token::Format(error::Format::UnsupportedAlgorithm(2))
+        );
+    }
+
+    #[cfg(feature = "p256")]
+    #[test]
+    fn p256_keys() {
+        use crate::crypto::{Algorithm, P256KeyPair};
+
+        let root = P256KeyPair::new();
+        let mut builder = Biscuit::builder_with_signer(&root);
+        builder.add_authority_fact("user(\"1234\")").unwrap();
+        let biscuit1 = builder.build().unwrap();
+
+        // P-256 is only accepted when the verifier allows it
+        let serialized = biscuit1.to_vec().unwrap();
+        assert_eq!(
+            Biscuit::from_with_limits(&serialized, |_| root.public(), &Default::default())
+                .unwrap_err(),
+            error::Token::Format(error::Format::UnsupportedAlgorithm(1))
+        );
+        let limits = DeserializationLimits {
+            algorithms: vec![Algorithm::Ed25519, Algorithm::P256],
+            ..Default::default()
+        };
+        Biscuit::from_with_limits(&serialized, |_| root.public(), &limits).unwrap();
+        assert!(Biscuit::from(&serialized, |_| P256KeyPair::new().public()).is_err());
+
+        let external = P256KeyPair::new();
+        let request = biscuit1.third_party_request().unwrap();
+        let mut block = BlockBuilder::new();
+        block.add_fact("external(\"value\")").unwrap();
+        let third_party_block = request.create_block(&external, block).unwrap();
+        let biscuit2 = biscuit1.append_third_party(third_party_block).unwrap();
+
+        let serialized = biscuit2.to_vec().unwrap();
+        let limits = DeserializationLimits {
+            algorithms: vec![Algorithm::Ed25519],
+            ..Default::default()
+        };
+        assert_eq!(
+            Biscuit::from_with_limits(&serialized, |_| KeyPair::new().public(), &limits)
+                .unwrap_err(),
+            error::Token::Format(error::Format::UnsupportedAlgorithm(1))
+        );
+        let biscuit2 = Biscuit::from(&serialized, |_| root.public()).unwrap();
+        assert_eq!(biscuit2.blocks[0].external_key, Some(external.public()));
+
+        let container = biscuit2.container().unwrap();
+        SerializedBiscuit::verify_batch(&[container, container], &root.public()).unwrap();
+
+        let key = external.public().to_string();
+        assert!(key.starts_with("secp256r1/"));
+        assert_eq!(key.parse::<PublicKey>().unwrap(), external.public());
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer
+            .add_check(format!("check if external(\"value\") trusting {}", key).as_str())
+            .unwrap();
+        authorizer.allow().unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+    }
+
+    #[test]
+    fn block_views() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.set_context("authority".to_string());
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        let biscuit2 = biscuit1
+            .append_block_from_code(
+                &root,
+                "allowed($file) <- right($file, \"read\"); check if resource($file), allowed($file)",
+            )
+            .unwrap();
+
+        let mut block = biscuit2.create_block();
+        block.add_context("issuer", "support");
+        block.add_context("ticket", "T-1");
+        block.add_context("ticket", "T-2\nline=2");
+        let biscuit2 = biscuit2.append(block).unwrap();
+
+        let views = biscuit2.blocks().collect::<Vec<_>>();
+        assert_eq!(views.len(), 3);
+        assert_eq!(views[0].context, Some("authority".to_string()));
+        assert!(views[0].metadata().is_empty());
+        assert_eq!(
+            views[2].metadata().into_iter().collect::<Vec<_>>(),
+            vec![
+                ("issuer".to_string(), "support".to_string()),
+                ("ticket".to_string(), "T-2\nline=2".to_string()),
+            ]
+        );
+        assert_eq!(views[0].facts[0].to_string(), "right(\"file1\", \"read\")");
+        assert_eq!(views[1].version, MIN_SCHEMA_VERSION);
+        assert_eq!(views[1].rules.len(), 1);
+        assert_eq!(views[1].checks.len(), 1);
+
+        for (i, view) in views.iter().enumerate() {
+            assert_eq!(Some(view.print_source()), biscuit2.print_block_source(i));
+        }
+    }
+
+    #[test]
+    fn deterministic_tokens() {
+        let create = |seed: u64| {
+            let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+            let root = KeyPair::new_with_rng(&mut rng);
+
+            let mut builder = Biscuit::builder(&root);
+            builder.add_right("file1", "read");
+            let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+            let mut block = biscuit1.create_block();
+            block.check_resource("file1");
+            let biscuit2 = biscuit1.append_with_rng(&mut rng, block).unwrap();
+
+            biscuit2.to_vec().unwrap()
+        };
+
+        assert_eq!(create(0), create(0));
+        assert_ne!(create(0), create(1));
+    }
+
+    #[test]
+    fn content_hash() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, BlockBuilder::new())
+            .unwrap();
+
+        let deser = Biscuit::from(&biscuit2.to_vec().unwrap(), |_| root.public()).unwrap();
+        assert_eq!(deser.content_hash(), biscuit2.content_hash());
+        assert_eq!(deser, biscuit2);
+        assert!(biscuit1 != biscuit2);
+
+        // the proof is not part of the hash
+        assert_eq!(biscuit2.seal().unwrap(), biscuit2);
+    }
+
+    #[test]
+    fn base64() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit = builder.build_with_rng(&mut rng).unwrap();
+
+        let encoded = biscuit.to_base64().unwrap();
+        assert!(!encoded.contains('='));
+        assert!(!encoded.contains('+') && !encoded.contains('/'));
+        assert_eq!(
+            base64::decode_config(&encoded, base64::URL_SAFE_NO_PAD).unwrap(),
+            biscuit.to_vec().unwrap()
+        );
+
+        // padded tokens are still accepted
+        let padded = base64::encode_config(biscuit.to_vec().unwrap(), base64::URL_SAFE);
+        Biscuit::from_base64(&padded, |_| root.public()).unwrap();
+        Biscuit::from_base64(&encoded, |_| root.public()).unwrap();
+
+        assert_eq!(
+            Biscuit::from_base64("a+b/", |_| root.public()).unwrap_err(),
+            error::Token::Format(error::Format::Base64(Base64Error::InvalidByte(1, b'+')))
+        );
+    }
+
+    // minimal executor, the futures used in tests never return Pending
+    fn block_on<F: Future>(future: F) -> F::Output {
+        use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+        fn noop_raw_waker() -> RawWaker {
+            fn no_op(_: *const ()) {}
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut context = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn async_root_key() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.set_root_key_id(1);
+        builder.add_right("file1", "read");
+        let serialized = builder.build_with_rng(&mut rng).unwrap().to_vec().unwrap();
+
+        let public = root.public();
+        let biscuit = block_on(Biscuit::from_async(&serialized, |id| async move {
+            match id {
+                Some(1) => Ok(public),
+                _ => Err(Format::UnknownPublicKey),
+            }
+        }))
+        .unwrap();
+        assert_eq!(biscuit.root_key_id(), Some(1));
+
+        let res = block_on(Biscuit::from_async(&serialized, |_| async {
+            Err(Format::UnknownPublicKey)
+        }));
+        assert_eq!(
+            res.unwrap_err(),
+            error::Token::Format(Format::UnknownPublicKey)
+        );
+    }
+
+    #[test]
+    fn external_signer() {
+        use crate::crypto::Signer;
+
+        // stands for a key held in a KMS, that only exposes a signing operation
+        struct RemoteKey(KeyPair);
+
+        impl Signer for RemoteKey {
+            fn public_key(&self) -> PublicKey {
+                self.0.public()
+            }
+
+            fn sign(&self, message: &[u8]) -> Result<crate::crypto::Signature, error::Format> {
+                self.0.sign(message)
+            }
+        }
+
+        let remote = RemoteKey(KeyPair::new());
+
+        let mut builder = Biscuit::builder_with_signer(&remote);
+        builder.add_authority_fact("user(\"1234\")").unwrap();
+        let biscuit = builder.build().unwrap();
+        let serialized = biscuit.to_vec().unwrap();
+        Biscuit::from(&serialized, |_| remote.public_key()).unwrap();
+
+        let mut builder = Biscuit::unsigned_builder();
+        builder.add_authority_fact("user(\"1234\")").unwrap();
+        builder.set_root_key_id(1);
+        let remote_ref = &remote;
+        let biscuit =
+            block_on(builder.build_async(|message| async move { remote_ref.sign(&message) }))
+                .unwrap();
+        let serialized = biscuit.to_vec().unwrap();
+        let biscuit = Biscuit::from(&serialized, |_| remote.public_key()).unwrap();
+        assert_eq!(biscuit.root_key_id(), Some(1));
+        biscuit.append(biscuit.create_block()).unwrap();
+
+        // a builder without signer can only be built asynchronously
+        assert!(Biscuit::unsigned_builder().build().is_err());
+    }
+
     #[test]
     fn verif_no_blocks() {
         use crate::token::builder::*;
@@ -877,7 +2151,8 @@ mod tests {
             Err(Token::FailedLogic(Logic::NoMatchingPolicy {
                 checks: vec![FailedCheck::Authorizer(FailedAuthorizerCheck {
                     check_id: 0,
-                    rule: String::from("check if right(\"file2\", \"write\")")
+                    rule: String::from("check if right(\"file2\", \"write\")"),
+                    source: None,
                 }),]
             }))
         );
@@ -1001,6 +2276,8 @@ mod tests {
                         block_id: 0,
                         check_id: 0,
                         rule: String::from("check if resource(\"hello\")"),
+                        context: None,
+                        revocation_id: Some(hex::encode(&biscuit2.revocation_identifiers()[0])),
                     }),]
                 }))
             );
@@ -1170,4 +2447,247 @@ mod tests {
 
         assert!(res.is_err());
     }
+
+    #[test]
+    fn block_versions() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let external = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(\"file1\", \"read\")")
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(biscuit1.authority.version, MIN_SCHEMA_VERSION);
+
+        let biscuit2 = biscuit1
+            .append_block_from_code(
+                &root,
+                format!("check if group(\"admin\") trusting {}", external.public()),
+            )
+            .unwrap();
+        assert_eq!(biscuit2.blocks[0].version, THIRD_PARTY_SCHEMA_VERSION);
+        let serialized = biscuit2.to_vec().unwrap();
+        let biscuit2 = Biscuit::from(&serialized, |_| root.public()).unwrap();
+        assert_eq!(biscuit2.blocks[0].version, THIRD_PARTY_SCHEMA_VERSION);
+
+        let biscuit3 = biscuit2
+            .append_block_from_code(&root, "check if ip($ip), \"10.0.0.0/8\".contains_ip($ip)")
+            .unwrap();
+        assert_eq!(biscuit3.blocks[1].version, EXTENDED_OPS_SCHEMA_VERSION);
+        let biscuit3 = biscuit2
+            .append_block_from_code(&root, "check if version($v), $v.semver_lt(\"2.0.0\")")
+            .unwrap();
+        assert_eq!(biscuit3.blocks[1].version, EXTENDED_OPS_SCHEMA_VERSION);
+        let biscuit3 = biscuit2
+            .append_block_from_code(&root, "check if data($d), $d.sha256().to_hex() == \"ab\"")
+            .unwrap();
+        assert_eq!(biscuit3.blocks[1].version, EXTENDED_OPS_SCHEMA_VERSION);
+
+        // content newer than the declared version is rejected
+        let mut proto = crate::format::convert::token_block_to_proto_block(&biscuit2.blocks[0]);
+        proto.version = Some(MIN_SCHEMA_VERSION);
+        assert!(matches!(
+            crate::format::convert::proto_block_to_token_block(&proto),
+            Err(Format::BlockDeserializationError(_))
+        ));
+
+        // versions without a reader
+        for version in [MIN_SCHEMA_VERSION - 1, MAX_SCHEMA_VERSION + 1] {
+            proto.version = Some(version);
+            assert_eq!(
+                crate::format::convert::proto_block_to_token_block(&proto).unwrap_err(),
+                Format::Version {
+                    minimum: MIN_SCHEMA_VERSION,
+                    maximum: MAX_SCHEMA_VERSION,
+                    actual: version,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn third_party_block() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let external = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_fact("right(\"file1\", \"read\")")
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let request = biscuit1.third_party_request().unwrap();
+        let request = ThirdPartyRequest::from_bytes(&request.to_vec().unwrap()).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact("group(\"admin\")").unwrap();
+        block.add_fact("external(\"value\")").unwrap();
+        let third_party_block = request.create_block(&external, block).unwrap();
+        let third_party_block =
+            ThirdPartyBlock::from_bytes(&third_party_block.to_vec().unwrap()).unwrap();
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_third_party_with_keypair(&keypair2, third_party_block.clone())
+            .unwrap();
+
+        let serialized = biscuit2.to_vec().unwrap();
+        let biscuit2 = Biscuit::from(&serialized, |_| root.public()).unwrap();
+        assert_eq!(biscuit2.blocks[0].external_key, Some(external.public()));
+        assert_eq!(biscuit2.blocks[0].version, THIRD_PARTY_SCHEMA_VERSION);
+        assert_eq!(
+            biscuit2.print_block_source(1).unwrap(),
+            "group(\"admin\");\nexternal(\"value\");\n"
+        );
+
+        // the external signature is bound to the previous block
+        let biscuit3 = biscuit2.append(BlockBuilder::new()).unwrap();
+        assert!(biscuit3.append_third_party(third_party_block).is_err());
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer
+            .add_check(
+                format!(
+                    "check if external(\"value\") trusting {}",
+                    external.public()
+                )
+                .as_str(),
+            )
+            .unwrap();
+        authorizer.allow().unwrap();
+        assert_eq!(authorizer.clone().authorize(), Ok(0));
+
+        // the authorizer's limits also apply to the trusted blocks
+        assert_eq!(
+            authorizer.authorize_with_limits(
+                crate::token::authorizer::AuthorizerLimits::default().max_facts_for("external", 0)
+            ),
+            Err(Token::RunLimit(error::RunLimit::TooManyPredicateFacts {
+                predicate: "external".to_string(),
+                max: 0,
+            }))
+        );
+
+        // facts from third party blocks are not visible without trusting the key
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer
+            .add_check("check if external(\"value\")")
+            .unwrap();
+        authorizer.allow().unwrap();
+        assert!(authorizer.authorize().is_err());
+    }
+
+    #[test]
+    fn third_party_block_async() {
+        use crate::crypto::Signer;
+
+        let root = KeyPair::new();
+        // stands for a non extractable WebCrypto key
+        let external = KeyPair::new();
+        let external_ref = &external;
+
+        let biscuit1 = Biscuit::builder(&root).build().unwrap();
+        let request = biscuit1.third_party_request().unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact("external(\"value\")").unwrap();
+        let third_party_block = block_on(request.create_block_async(
+            external.public(),
+            block,
+            |message| async move { external_ref.sign(&message) },
+        ))
+        .unwrap();
+
+        let biscuit2 = biscuit1.append_third_party(third_party_block).unwrap();
+        let serialized = biscuit2.to_vec().unwrap();
+        let biscuit2 = Biscuit::from(&serialized, |_| root.public()).unwrap();
+        assert_eq!(biscuit2.blocks[0].external_key, Some(external.public()));
+
+        // a signature from another key is rejected when appending
+        let other = KeyPair::new();
+        let other_ref = &other;
+        let third_party_block = block_on(request.create_block_async(
+            external.public(),
+            BlockBuilder::new(),
+            |message| async move { other_ref.sign(&message) },
+        ))
+        .unwrap();
+        assert!(biscuit1.append_third_party(third_party_block).is_err());
+    }
+
+    #[test]
+    fn deterministic_serialization() {
+        let build = || {
+            let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+            let root = KeyPair::new_with_rng(&mut rng);
+
+            let mut builder = Biscuit::builder(&root);
+            builder
+                .add_authority_fact("right(\"file1\", \"read\")")
+                .unwrap();
+            builder
+                .add_authority_rule("allowed($op) <- right(\"file1\", $op)")
+                .unwrap();
+            let token = builder.build_with_rng(&mut rng).unwrap();
+
+            let mut block = token.create_block();
+            block
+                .add_check("check if resource(\"file1\"), [\"read\", \"write\"].contains(\"read\")")
+                .unwrap();
+            token
+                .append_with_rng(&mut rng, block)
+                .unwrap()
+                .to_vec()
+                .unwrap()
+        };
+
+        assert_eq!(build(), build());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_blocks() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        for i in 0..200 {
+            builder
+                .add_authority_fact(fact("allowed_user", &[string(&format!("user{}", i))]))
+                .unwrap();
+        }
+        let token = builder.build().unwrap();
+        let serialized = token.to_vec().unwrap();
+
+        let deserialized = Biscuit::from(&serialized, |_| root.public()).unwrap();
+        assert_eq!(deserialized.blocks().next().unwrap().facts.len(), 200);
+        assert_eq!(deserialized.authority.version, MIN_SCHEMA_VERSION);
+
+        // verifiers that cannot read compressed blocks yet
+        let limits = DeserializationLimits {
+            versions: FormatSettings {
+                min_version: MIN_SCHEMA_VERSION,
+                max_version: COMPRESSED_SCHEMA_VERSION - 1,
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            Biscuit::from_with_limits(&serialized, |_| root.public(), &limits).unwrap_err(),
+            error::Token::Format(Format::UnsupportedVersion {
+                found: COMPRESSED_SCHEMA_VERSION,
+                min: MIN_SCHEMA_VERSION,
+                max: COMPRESSED_SCHEMA_VERSION - 1,
+            })
+        );
+
+        let limits = DeserializationLimits {
+            max_decompressed_size: 100,
+            ..Default::default()
+        };
+        assert_eq!(
+            Biscuit::from_with_limits(&serialized, |_| root.public(), &limits).unwrap_err(),
+            error::Token::Format(Format::Limit(DeserializationLimit::DecompressedSize))
+        );
+    }
 }
@@ -0,0 +1,319 @@
+//! reusable authorizers, to authorize tokens under load
+use super::authorizer::{Authorizer, AuthorizerLimits};
+use super::builder::Fact;
+use super::Biscuit;
+use crate::error;
+use crate::sync::lock;
+use crate::time::Instant;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// keeps authorizers ready to authorize tokens
+///
+/// the rules of the template authorizer are run once on its facts when the
+/// pool is created. Each authorization takes an idle authorizer, or copies
+/// the template, adds the token and runs the checks and policies, then
+/// removes the token's data and puts the authorizer back in the pool
+///
+/// ```rust
+/// # use biscuit_auth::{Authorizer, AuthorizerPool, Biscuit, KeyPair};
+/// let mut authorizer = Authorizer::new().unwrap();
+/// authorizer.add_code(r#"
+///     resource("file1");
+///     allow if user($u), resource($r);
+/// "#).unwrap();
+/// let pool = AuthorizerPool::new(authorizer).unwrap();
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder(&root);
+/// builder.add_authority_fact("user(\"alice\")").unwrap();
+/// let token = builder.build().unwrap();
+///
+/// assert_eq!(pool.authorize(&token), Ok(0));
+/// ```
+pub struct AuthorizerPool {
+    template: Authorizer<'static>,
+    idle: Mutex<Vec<Authorizer<'static>>>,
+    max_idle: usize,
+    limits: AuthorizerLimits,
+    cache: Option<ResultCache>,
+}
+
+/// authorization results, by hash of the token, ambient facts and template
+struct ResultCache {
+    /// hash of the serialized template
+    template: Vec<u8>,
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<Vec<u8>, CacheEntry>>,
+}
+
+/// an authorization result and the time it was stored
+type CacheEntry = (Result<usize, error::Token>, Instant);
+
+impl AuthorizerPool {
+    /// runs the template's rules, the template must not contain a token
+    ///
+    /// the pool keeps up to 16 idle authorizers, see [AuthorizerPool::with_max_idle]
+    pub fn new(authorizer: Authorizer<'static>) -> Result<Self, error::Token> {
+        AuthorizerPool::with_limits(authorizer, AuthorizerLimits::default())
+    }
+
+    /// uses `limits` to run the template's rules and for each authorization
+    pub fn with_limits(
+        mut authorizer: Authorizer<'static>,
+        limits: AuthorizerLimits,
+    ) -> Result<Self, error::Token> {
+        authorizer.prewarm(&limits)?;
+
+        Ok(AuthorizerPool {
+            template: authorizer,
+            idle: Mutex::new(Vec::new()),
+            max_idle: 16,
+            limits,
+            cache: None,
+        })
+    }
+
+    /// sets the maximum number of idle authorizers, the other ones are
+    /// dropped after use
+    pub fn with_max_idle(mut self, max_idle: usize) -> Self {
+        self.max_idle = max_idle;
+        self
+    }
+
+    /// caches the authorization results for `ttl`, for up to `max_entries`
+    /// combinations of token and ambient facts
+    ///
+    /// a result is reused when the same token is authorized with the same
+    /// ambient facts, without running the Datalog engine. Errors from the
+    /// execution limits are not cached. Ambient facts that change on each
+    /// request, like the time, prevent the reuse of results, and changes in
+    /// the attribute sources can take up to `ttl` to be seen
+    ///
+    /// the cache is not used when the template has a replay store, see
+    /// [Authorizer::set_replay_store], so that one-time tokens are rejected
+    /// after their first use
+    pub fn with_cache(mut self, ttl: Duration, max_entries: usize) -> Result<Self, error::Token> {
+        self.cache = Some(ResultCache {
+            template: Sha256::digest(&self.template.template_copy().save()?).to_vec(),
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        });
+        Ok(self)
+    }
+
+    /// number of authorizers waiting for a token
+    pub fn idle(&self) -> usize {
+        self.idle_authorizers().len()
+    }
+
+    /// authorizes a token with an authorizer from the pool
+    pub fn authorize(&self, token: &Biscuit) -> Result<usize, error::Token> {
+        self.authorize_with_facts(token, &[])
+    }
+
+    /// authorizes a token with ambient facts added to the template, like
+    /// the requested resource and operation
+    pub fn authorize_with_facts(
+        &self,
+        token: &Biscuit,
+        facts: &[Fact],
+    ) -> Result<usize, error::Token> {
+        // the nonces of one-time tokens must be recorded on each authorization
+        let cache = self
+            .cache
+            .as_ref()
+            .filter(|_| self.template.replay_store.is_none());
+        let key = cache.map(|cache| cache.key(token, facts));
+        if let (Some(cache), Some(key)) = (cache, key.as_ref()) {
+            if let Some(result) = cache.get(key) {
+                return result;
+            }
+        }
+
+        let authorizer = self.idle_authorizers().pop();
+        let mut authorizer = match authorizer {
+            Some(authorizer) => authorizer,
+            None => self.template.template_copy(),
+        };
+
+        let result = facts
+            .iter()
+            .try_for_each(|fact| authorizer.add_fact(fact.clone()))
+            .and_then(|_| authorizer.add_token(token))
+            .and_then(|_| authorizer.authorize_with_limits(self.limits.clone()));
+
+        let authorizer = authorizer.reset(&self.template);
+        let mut idle = self.idle_authorizers();
+        if idle.len() < self.max_idle {
+            idle.push(authorizer);
+        }
+        drop(idle);
+
+        if let (Some(cache), Some(key)) = (cache, key) {
+            if !matches!(result, Err(error::Token::RunLimit(_))) {
+                cache.insert(key, result.clone());
+            }
+        }
+
+        result
+    }
+
+    fn idle_authorizers(&self) -> std::sync::MutexGuard<'_, Vec<Authorizer<'static>>> {
+        // the idle authorizers were reset before being added
+        lock(&self.idle)
+    }
+}
+
+impl ResultCache {
+    /// the token is identified by its signatures, which cover the content
+    /// of the blocks and the keys
+    fn key(&self, token: &Biscuit, facts: &[Fact]) -> Vec<u8> {
+        let mut facts = facts.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        facts.sort();
+        facts.dedup();
+
+        let mut hasher = Sha256::new();
+        hasher.update(&self.template);
+        for id in token.revocation_identifiers() {
+            hasher.update((id.len() as u64).to_le_bytes());
+            hasher.update(&id);
+        }
+        for fact in facts {
+            hasher.update((fact.len() as u64).to_le_bytes());
+            hasher.update(fact.as_bytes());
+        }
+        hasher.finalize().to_vec()
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Result<usize, error::Token>> {
+        match self.entries().get(key) {
+            Some((result, time)) if time.elapsed() < self.ttl => Some(result.clone()),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, key: Vec<u8>, result: Result<usize, error::Token>) {
+        let mut entries = self.entries();
+        if entries.len() >= self.max_entries {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, time)| time.elapsed() < ttl);
+            if entries.len() >= self.max_entries {
+                entries.clear();
+            }
+        }
+        entries.insert(key, (result, Instant::now()));
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<Vec<u8>, CacheEntry>> {
+        lock(&self.entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+    use std::convert::TryInto;
+
+    #[test]
+    fn reuse() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                resource("file1");
+                owner("alice", "file1");
+                can_read($u, $r) <- owner($u, $r);
+                can_read($u, $r) <- delegated($u, $r);
+                allow if user($u), resource($r), can_read($u, $r);
+                "#,
+            )
+            .unwrap();
+        let pool = AuthorizerPool::new(authorizer).unwrap();
+
+        let root = KeyPair::new();
+        let token = |code: &str| {
+            let mut builder = Biscuit::builder(&root);
+            builder.add_code(code).unwrap();
+            builder.build().unwrap()
+        };
+
+        let alice = token("user(\"alice\");");
+        let bob = token("user(\"bob\"); delegated(\"bob\", \"file1\");");
+        let bob_without_delegation = token("user(\"bob\");");
+
+        assert_eq!(pool.authorize(&alice), Ok(0));
+        assert_eq!(pool.idle(), 1);
+        assert_eq!(pool.authorize(&bob), Ok(0));
+        // the facts derived for bob's token were removed
+        assert!(pool.authorize(&bob_without_delegation).is_err());
+        assert_eq!(pool.idle(), 1);
+
+        let pool = pool.with_max_idle(0);
+        assert_eq!(pool.authorize(&alice), Ok(0));
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[test]
+    fn cache() {
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer
+            .add_code("allow if user($u), operation(\"read\")")
+            .unwrap();
+        let pool = AuthorizerPool::new(authorizer)
+            .unwrap()
+            .with_cache(Duration::from_secs(60), 10)
+            .unwrap();
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_code("user(\"alice\");").unwrap();
+        let token = builder.build().unwrap();
+
+        let read: Fact = "operation(\"read\")".try_into().unwrap();
+        let write: Fact = "operation(\"write\")".try_into().unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(pool.authorize_with_facts(&token, &[read.clone()]), Ok(0));
+            assert!(pool.authorize_with_facts(&token, &[write.clone()]).is_err());
+        }
+        assert_eq!(pool.cache.as_ref().unwrap().entries().len(), 2);
+
+        // another token with the same content has different signatures
+        let mut builder = Biscuit::builder(&root);
+        builder.add_code("user(\"alice\");").unwrap();
+        let other = builder.build().unwrap();
+        assert_eq!(pool.authorize_with_facts(&other, &[read]), Ok(0));
+        assert_eq!(pool.cache.as_ref().unwrap().entries().len(), 3);
+    }
+
+    #[test]
+    fn cache_with_replay_store() {
+        use crate::nonce::MemoryStore;
+
+        let mut authorizer = Authorizer::new().unwrap();
+        authorizer.set_replay_store(MemoryStore::new(Duration::from_secs(60)));
+        authorizer.allow().unwrap();
+        let pool = AuthorizerPool::new(authorizer)
+            .unwrap()
+            .with_cache(Duration::from_secs(60), 10)
+            .unwrap();
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder(&root);
+        builder.add_random_nonce();
+        let token = builder.build().unwrap();
+
+        assert_eq!(pool.authorize(&token), Ok(0));
+        assert_eq!(
+            pool.authorize(&token),
+            Err(error::Token::Replay(error::Replay::Reused))
+        );
+        assert!(pool.cache.as_ref().unwrap().entries().is_empty());
+    }
+}
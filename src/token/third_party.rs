@@ -0,0 +1,194 @@
+//! third party blocks
+//!
+//! a third party block is created by another party than the token holder,
+//! and signed with that party's key. Checks and policies can then trust
+//! the facts it contains with the `trusting` keyword
+use super::builder::BlockBuilder;
+use super::{default_symbol_table, THIRD_PARTY_SCHEMA_VERSION};
+use crate::crypto::{self, ExternalSignature, PublicKey, Signer};
+use crate::error;
+use crate::format::{convert::*, schema};
+use alloc::string::String;
+use alloc::vec::Vec;
+use prost::Message;
+
+/// request sent by the token holder to the third party
+///
+/// it binds the third party block to the token's current last block
+#[derive(Clone, Debug)]
+pub struct ThirdPartyRequest {
+    pub(crate) previous_signature: crypto::Signature,
+}
+
+impl ThirdPartyRequest {
+    /// deserializes a request from raw bytes
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, error::Token> {
+        let data = schema::ThirdPartyBlockRequest::decode(slice).map_err(|e| {
+            error::Format::DeserializationError(error::Cause::with_dependency_source(
+                format!("deserialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+        Ok(ThirdPartyRequest {
+            previous_signature: crypto::Signature::from_bytes(&data.previous_signature)?,
+        })
+    }
+
+    /// deserializes a request from a (URL safe) base64 string
+    pub fn from_base64<T: AsRef<[u8]>>(slice: T) -> Result<Self, error::Token> {
+        let decoded = super::decode_base64(slice)?;
+        ThirdPartyRequest::from_bytes(&decoded)
+    }
+
+    /// serializes the request
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Token> {
+        let request = schema::ThirdPartyBlockRequest {
+            previous_signature: self.previous_signature.to_bytes().to_vec(),
+        };
+
+        let mut v = Vec::new();
+        request.encode(&mut v).map_err(|e| {
+            error::Format::SerializationError(error::Cause::with_dependency_source(
+                format!("serialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+        Ok(v)
+    }
+
+    /// serializes the request and encode it to a (URL safe) base64 string
+    pub fn to_base64(&self) -> Result<String, error::Token> {
+        self.to_vec()
+            .map(|v| base64::encode_config(v, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// creates the third party block and signs it with the third party's key
+    ///
+    /// the block uses its own symbol table, starting from the default symbols.
+    /// The key can be a [KeyPair](crate::KeyPair) or an external [Signer]
+    pub fn create_block(
+        &self,
+        keypair: &dyn Signer,
+        block_builder: BlockBuilder,
+    ) -> Result<ThirdPartyBlock, error::Token> {
+        let payload = serialize_payload(block_builder)?;
+
+        let external_signature =
+            crypto::sign_external(keypair, &payload, &self.previous_signature)?;
+
+        Ok(ThirdPartyBlock {
+            payload,
+            external_signature,
+        })
+    }
+
+    /// creates the third party block and signs it with an async function
+    ///
+    /// the function receives the data to sign with the private key matching `public_key`,
+    /// so the key can stay in a WebCrypto `CryptoKey` or in a remote service
+    pub async fn create_block_async<F, Fut>(
+        &self,
+        public_key: PublicKey,
+        block_builder: BlockBuilder,
+        sign: F,
+    ) -> Result<ThirdPartyBlock, error::Token>
+    where
+        F: FnOnce(Vec<u8>) -> Fut,
+        Fut: core::future::Future<Output = Result<crypto::Signature, error::Format>>,
+    {
+        let payload = serialize_payload(block_builder)?;
+
+        let signature = sign(crypto::external_signature_message(
+            &payload,
+            &self.previous_signature,
+        ))
+        .await?;
+
+        Ok(ThirdPartyBlock {
+            payload,
+            external_signature: ExternalSignature {
+                public_key,
+                signature,
+            },
+        })
+    }
+}
+
+fn serialize_payload(block_builder: BlockBuilder) -> Result<Vec<u8>, error::Token> {
+    let mut block = block_builder.build(default_symbol_table());
+    // verifiers that do not support third party blocks would read it as a
+    // block from the token holder
+    block.version = core::cmp::max(block.version, THIRD_PARTY_SCHEMA_VERSION);
+
+    let mut payload = Vec::new();
+    token_block_to_proto_block(&block)
+        .encode(&mut payload)
+        .map_err(|e| {
+            error::Format::SerializationError(error::Cause::with_dependency_source(
+                format!("serialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+    Ok(payload)
+}
+
+/// block created by a third party, to be added with [Biscuit::append_third_party](super::Biscuit::append_third_party)
+#[derive(Clone, Debug)]
+pub struct ThirdPartyBlock {
+    pub(crate) payload: Vec<u8>,
+    pub(crate) external_signature: ExternalSignature,
+}
+
+impl ThirdPartyBlock {
+    /// deserializes a third party block from raw bytes
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, error::Token> {
+        let data = schema::ThirdPartyBlockContents::decode(slice).map_err(|e| {
+            error::Format::DeserializationError(error::Cause::with_dependency_source(
+                format!("deserialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+        Ok(ThirdPartyBlock {
+            payload: data.payload,
+            external_signature: proto_external_signature_to_external_signature(
+                &data.external_signature,
+            )?,
+        })
+    }
+
+    /// deserializes a third party block from a (URL safe) base64 string
+    pub fn from_base64<T: AsRef<[u8]>>(slice: T) -> Result<Self, error::Token> {
+        let decoded = super::decode_base64(slice)?;
+        ThirdPartyBlock::from_bytes(&decoded)
+    }
+
+    /// serializes the third party block
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Token> {
+        let contents = schema::ThirdPartyBlockContents {
+            payload: self.payload.clone(),
+            external_signature: external_signature_to_proto_external_signature(
+                &self.external_signature,
+            ),
+        };
+
+        let mut v = Vec::new();
+        contents.encode(&mut v).map_err(|e| {
+            error::Format::SerializationError(error::Cause::with_dependency_source(
+                format!("serialization error: {:?}", e),
+                e,
+            ))
+        })?;
+
+        Ok(v)
+    }
+
+    /// serializes the third party block and encode it to a (URL safe) base64 string
+    pub fn to_base64(&self) -> Result<String, error::Token> {
+        self.to_vec()
+            .map(|v| base64::encode_config(v, base64::URL_SAFE_NO_PAD))
+    }
+}
@@ -1,13 +1,16 @@
-use super::{default_symbol_table, Biscuit, Block};
+use super::{
+    decode_authority_block, decode_block, default_symbol_table, Biscuit, Block, RootKeyProvider,
+};
 use crate::{
     builder::BlockBuilder,
     crypto::PublicKey,
     datalog::SymbolTable,
     error,
-    format::{convert::proto_block_to_token_block, schema, SerializedBiscuit},
+    format::{DeserializationLimits, SerializedBiscuit},
     KeyPair,
 };
-use prost::Message;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 /// A token that was parsed without cryptographic signature verification
 ///
@@ -62,11 +65,76 @@ impl UnverifiedBiscuit {
     ///
     /// since the public key is integrated into the token, the keypair can be
     /// discarded right after calling this function
+    #[cfg(feature = "std")]
     pub fn append(&self, block_builder: BlockBuilder) -> Result<Self, error::Token> {
         let keypair = KeyPair::new_with_rng(&mut rand::rngs::OsRng);
         self.append_with_keypair(&keypair, block_builder)
     }
 
+    /// checks the signature of the token with the root key selected by the key provider,
+    /// and convert it to a [Biscuit] for authorization
+    pub fn check_signature_with_provider<KP>(
+        self,
+        key_provider: &KP,
+    ) -> Result<Biscuit, error::Format>
+    where
+        KP: RootKeyProvider,
+    {
+        let root = key_provider.choose(self.container.root_key_id)?;
+        self.check_signature(|_| root)
+    }
+
+    /// checks the signature of the token with a custom signature verifier, and the root
+    /// key selected by the key provider, and convert it to a [Biscuit] for authorization
+    pub fn check_signature_with_verifier<KP>(
+        self,
+        key_provider: &KP,
+        verifier: &dyn crate::crypto::Verifier,
+    ) -> Result<Biscuit, error::Format>
+    where
+        KP: RootKeyProvider,
+    {
+        let root = key_provider.choose(self.container.root_key_id)?;
+        self.container.verify_with(&root, verifier)?;
+
+        Ok(Biscuit {
+            root_key_id: self.container.root_key_id,
+            authority: self.authority,
+            blocks: self.blocks,
+            symbols: self.symbols,
+            container: Some(self.container),
+        })
+    }
+
+    /// returns the root key id, if it was set when creating the token
+    ///
+    /// it can be used to look up the root public key before checking the signature
+    pub fn root_key_id(&self) -> Option<u32> {
+        self.container.root_key_id
+    }
+
+    /// returns the list of context elements of each block
+    pub fn context(&self) -> Vec<Option<String>> {
+        let mut res = vec![self.authority.context.clone()];
+
+        for b in self.blocks.iter() {
+            res.push(b.context.clone());
+        }
+
+        res
+    }
+
+    /// gets the list of symbols from a block
+    pub fn block_symbols(&self, index: usize) -> Option<Vec<String>> {
+        let block = if index == 0 {
+            &self.authority
+        } else {
+            self.blocks.get(index - 1)?
+        };
+
+        Some(block.symbols.strings())
+    }
+
     /// serializes the token
     pub fn to_vec(&self) -> Result<Vec<u8>, error::Token> {
         self.container.to_vec().map_err(error::Token::Format)
@@ -77,7 +145,7 @@ impl UnverifiedBiscuit {
         self.container
             .to_vec()
             .map_err(error::Token::Format)
-            .map(|v| base64::encode_config(v, base64::URL_SAFE))
+            .map(|v| base64::encode_config(v, base64::URL_SAFE_NO_PAD))
     }
 
     /// creates a new block builder
@@ -89,36 +157,27 @@ impl UnverifiedBiscuit {
     pub fn from_with_symbols(slice: &[u8], mut symbols: SymbolTable) -> Result<Self, error::Token> {
         let container = SerializedBiscuit::deserialize(slice)?;
 
-        let authority: Block = schema::Block::decode(&container.authority.data[..])
-            .map_err(|e| {
-                error::Token::Format(error::Format::BlockDeserializationError(format!(
-                    "error deserializing authority block: {:?}",
-                    e
-                )))
-            })
-            .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+        let authority = decode_authority_block(
+            &container.authority.data,
+            &DeserializationLimits::unlimited(),
+        )?;
+
+        symbols.extend(&authority.symbols);
 
         let mut blocks = vec![];
 
         for block in container.blocks.iter() {
-            let deser: Block = schema::Block::decode(&block.data[..])
-                .map_err(|e| {
-                    error::Token::Format(error::Format::BlockDeserializationError(format!(
-                        "error deserializing block: {:?}",
-                        e
-                    )))
-                })
-                .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+            let deser = decode_block(
+                &block.data,
+                block.external_signature.as_ref().map(|s| s.public_key),
+                &symbols,
+                &DeserializationLimits::unlimited(),
+            )?;
+            symbols.extend(&deser.symbols);
 
             blocks.push(deser);
         }
 
-        symbols.extend(&authority.symbols);
-
-        for block in blocks.iter() {
-            symbols.extend(&block.symbols);
-        }
-
         Ok(UnverifiedBiscuit {
             authority,
             blocks,
@@ -132,7 +191,7 @@ impl UnverifiedBiscuit {
     where
         T: AsRef<[u8]>,
     {
-        let decoded = base64::decode_config(slice, base64::URL_SAFE)?;
+        let decoded = super::decode_base64(slice)?;
         Self::from_with_symbols(&decoded, symbols)
     }
 
@@ -211,3 +270,96 @@ impl UnverifiedBiscuit {
         Ok(token)
     }
 }
+
+/// serialized as its (URL safe) base64 representation
+#[cfg(feature = "serde")]
+impl serde::Serialize for UnverifiedBiscuit {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let s = self.to_base64().map_err(S::Error::custom)?;
+        serializer.serialize_str(&s)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for UnverifiedBiscuit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let s = String::deserialize(deserializer)?;
+        UnverifiedBiscuit::from_base64(&s).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn inspect_then_verify() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.set_root_key_id(7);
+        builder.set_context("user 1234".to_string());
+        builder.add_authority_fact("user(\"1234\")").unwrap();
+        let biscuit = builder.build_with_rng(&mut rng).unwrap();
+        let serialized = biscuit.to_vec().unwrap();
+
+        let token = UnverifiedBiscuit::from(&serialized).unwrap();
+        assert_eq!(token.root_key_id(), Some(7));
+        assert_eq!(token.context(), vec![Some("user 1234".to_string())]);
+        assert_eq!(token.block_symbols(0), Some(vec!["1234".to_string()]));
+        assert_eq!(token.block_symbols(1), None);
+        assert_eq!(token.print_block_source(0).unwrap(), "user(\"1234\");\n");
+
+        let other = KeyPair::new_with_rng(&mut rng);
+        assert!(token
+            .clone()
+            .check_signature_with_provider(&other.public())
+            .is_err());
+
+        let biscuit = token.check_signature_with_provider(&root.public()).unwrap();
+        assert_eq!(biscuit.root_key_id(), Some(7));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let json = serde_json::to_string(&root.public()).unwrap();
+        assert!(json.starts_with("\"ed25519/"));
+        let public: PublicKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(public, root.public());
+
+        // hex strings without algorithm from previous versions
+        let legacy = format!("\"{}\"", hex::encode(root.public().to_bytes()));
+        assert_eq!(
+            serde_json::from_str::<PublicKey>(&legacy).unwrap(),
+            root.public()
+        );
+
+        let private: crate::PrivateKey =
+            serde_json::from_str(&serde_json::to_string(&root.private()).unwrap()).unwrap();
+        assert_eq!(private.public(), root.public());
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit = builder.build_with_rng(&mut rng).unwrap();
+
+        let json = serde_json::to_string(&biscuit).unwrap();
+        assert_eq!(json, format!("\"{}\"", biscuit.to_base64().unwrap()));
+
+        let token: UnverifiedBiscuit = serde_json::from_str(&json).unwrap();
+        let biscuit = token.check_signature(|_| root.public()).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            "right(\"file1\", \"read\");\n"
+        );
+    }
+}
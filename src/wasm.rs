@@ -0,0 +1,286 @@
+//! WebAssembly bindings, with the `wasm` feature
+//!
+//! this exposes keys, tokens, builders and authorizers to JavaScript through
+//! wasm-bindgen, so tokens can be attenuated and inspected in the browser.
+//! Errors are thrown as strings starting with the error's [code](crate::error::Token::code)
+use crate::error;
+use wasm_bindgen::prelude::*;
+
+fn to_js(e: error::Token) -> JsValue {
+    JsValue::from_str(&format!("{}: {}", e.code(), e))
+}
+
+#[wasm_bindgen]
+pub struct KeyPair(crate::KeyPair);
+
+#[wasm_bindgen]
+impl KeyPair {
+    /// generates a key pair with the browser's random number generator
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> KeyPair {
+        KeyPair(crate::KeyPair::new())
+    }
+
+    #[wasm_bindgen(js_name = fromPrivateKey)]
+    pub fn from_private_key(data: &[u8]) -> Result<KeyPair, JsValue> {
+        crate::PrivateKey::from_bytes(data)
+            .map(|key| KeyPair(crate::KeyPair::from(key)))
+            .map_err(|e| to_js(e.into()))
+    }
+
+    #[wasm_bindgen(js_name = publicKey)]
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.public())
+    }
+
+    #[wasm_bindgen(js_name = privateKey)]
+    pub fn private_key(&self) -> Vec<u8> {
+        self.0.private().to_bytes().to_vec()
+    }
+}
+
+impl Default for KeyPair {
+    fn default() -> Self {
+        KeyPair::new()
+    }
+}
+
+#[wasm_bindgen]
+pub struct PublicKey(crate::PublicKey);
+
+#[wasm_bindgen]
+impl PublicKey {
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(data: &[u8]) -> Result<PublicKey, JsValue> {
+        crate::PublicKey::from_bytes(data)
+            .map(PublicKey)
+            .map_err(|e| to_js(e.into()))
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = toHex)]
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0.to_bytes())
+    }
+}
+
+/// builds the authority block of a token
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct BiscuitBuilder(crate::builder::BlockBuilder);
+
+#[wasm_bindgen]
+impl BiscuitBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> BiscuitBuilder {
+        BiscuitBuilder::default()
+    }
+
+    /// adds facts, rules and checks from Datalog code
+    #[wasm_bindgen(js_name = addCode)]
+    pub fn add_code(&mut self, source: &str) -> Result<(), JsValue> {
+        self.0.add_code(source).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addFact)]
+    pub fn add_fact(&mut self, fact: &str) -> Result<(), JsValue> {
+        self.0.add_fact(fact).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addRule)]
+    pub fn add_rule(&mut self, rule: &str) -> Result<(), JsValue> {
+        self.0.add_rule(rule).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addCheck)]
+    pub fn add_check(&mut self, check: &str) -> Result<(), JsValue> {
+        self.0.add_check(check).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = setContext)]
+    pub fn set_context(&mut self, context: &str) {
+        self.0.set_context(context.to_string());
+    }
+
+    /// creates a token signed by the root key pair
+    pub fn build(&self, root: &KeyPair) -> Result<Biscuit, JsValue> {
+        let mut builder = crate::Biscuit::builder(&root.0);
+        for fact in self.0.facts.iter().cloned() {
+            builder.add_authority_fact(fact).map_err(to_js)?;
+        }
+        for rule in self.0.rules.iter().cloned() {
+            builder.add_authority_rule(rule).map_err(to_js)?;
+        }
+        for check in self.0.checks.iter().cloned() {
+            builder.add_authority_check(check).map_err(to_js)?;
+        }
+        if let Some(context) = self.0.context.clone() {
+            builder.set_context(context);
+        }
+
+        builder.build().map(Biscuit).map_err(to_js)
+    }
+}
+
+/// builds a block to attenuate a token
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct BlockBuilder(crate::builder::BlockBuilder);
+
+#[wasm_bindgen]
+impl BlockBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> BlockBuilder {
+        BlockBuilder::default()
+    }
+
+    /// adds facts, rules and checks from Datalog code
+    #[wasm_bindgen(js_name = addCode)]
+    pub fn add_code(&mut self, source: &str) -> Result<(), JsValue> {
+        self.0.add_code(source).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addFact)]
+    pub fn add_fact(&mut self, fact: &str) -> Result<(), JsValue> {
+        self.0.add_fact(fact).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addRule)]
+    pub fn add_rule(&mut self, rule: &str) -> Result<(), JsValue> {
+        self.0.add_rule(rule).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addCheck)]
+    pub fn add_check(&mut self, check: &str) -> Result<(), JsValue> {
+        self.0.add_check(check).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = setContext)]
+    pub fn set_context(&mut self, context: &str) {
+        self.0.set_context(context.to_string());
+    }
+}
+
+#[wasm_bindgen]
+pub struct Biscuit(crate::Biscuit);
+
+#[wasm_bindgen]
+impl Biscuit {
+    /// deserializes a token and verifies its signatures with the root public key
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(data: &[u8], root: &PublicKey) -> Result<Biscuit, JsValue> {
+        let root = root.0;
+        crate::Biscuit::from(data, |_| root)
+            .map(Biscuit)
+            .map_err(to_js)
+    }
+
+    /// deserializes a token from URL safe base64 and verifies its signatures
+    /// with the root public key
+    #[wasm_bindgen(js_name = fromBase64)]
+    pub fn from_base64(data: &str, root: &PublicKey) -> Result<Biscuit, JsValue> {
+        let root = root.0;
+        crate::Biscuit::from_base64(data, |_| root)
+            .map(Biscuit)
+            .map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        self.0.to_vec().map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = toBase64)]
+    pub fn to_base64(&self) -> Result<String, JsValue> {
+        self.0.to_base64().map_err(to_js)
+    }
+
+    /// adds a block, signed with a new random key pair
+    pub fn append(&self, block: &BlockBuilder) -> Result<Biscuit, JsValue> {
+        self.0.append(block.0.clone()).map(Biscuit).map_err(to_js)
+    }
+
+    pub fn seal(&self) -> Result<Biscuit, JsValue> {
+        self.0.seal().map(Biscuit).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = blockCount)]
+    pub fn block_count(&self) -> usize {
+        self.0.block_count()
+    }
+
+    /// Datalog code of a block, 0 being the authority block
+    #[wasm_bindgen(js_name = blockSource)]
+    pub fn block_source(&self, index: usize) -> Option<String> {
+        self.0.print_block_source(index)
+    }
+
+    /// revocation ids of the blocks, hex encoded
+    #[wasm_bindgen(js_name = revocationIds)]
+    pub fn revocation_ids(&self) -> Box<[JsValue]> {
+        self.0
+            .revocation_identifiers()
+            .iter()
+            .map(|id| JsValue::from_str(&hex::encode(id)))
+            .collect()
+    }
+
+    pub fn print(&self) -> String {
+        self.0.print()
+    }
+}
+
+/// facts, rules, checks and policies used to authorize tokens
+///
+/// the authorizer is not modified by [Authorizer::authorize], so it can
+/// be used for multiple tokens
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Authorizer(crate::Authorizer<'static>);
+
+#[wasm_bindgen]
+impl Authorizer {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Authorizer, JsValue> {
+        crate::Authorizer::new()
+            .map(Authorizer)
+            .map_err(|e| to_js(e.into()))
+    }
+
+    /// adds facts, rules, checks and policies from Datalog code
+    #[wasm_bindgen(js_name = addCode)]
+    pub fn add_code(&mut self, source: &str) -> Result<(), JsValue> {
+        self.0.add_code(source).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addFact)]
+    pub fn add_fact(&mut self, fact: &str) -> Result<(), JsValue> {
+        self.0.add_fact(fact).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addRule)]
+    pub fn add_rule(&mut self, rule: &str) -> Result<(), JsValue> {
+        self.0.add_rule(rule).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addCheck)]
+    pub fn add_check(&mut self, check: &str) -> Result<(), JsValue> {
+        self.0.add_check(check).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = addPolicy)]
+    pub fn add_policy(&mut self, policy: &str) -> Result<(), JsValue> {
+        self.0.add_policy(policy).map_err(to_js)
+    }
+
+    /// authorizes a token, returning the index of the matching allow policy
+    pub fn authorize(&self, token: &Biscuit) -> Result<usize, JsValue> {
+        let mut authorizer: crate::Authorizer<'_> = self.0.clone();
+        authorizer.add_token(&token.0).map_err(to_js)?;
+        authorizer.authorize().map_err(to_js)
+    }
+}
@@ -113,7 +113,7 @@ failed checks (2):
 authorizer world:
 World {
   facts: [
-    "right(\"file1\", \"read\")",
+    "right(\"file1\", \"read\") // authority",
 ]
   rules: []
   checks: [
@@ -156,4 +156,54 @@ wrote 322 bytes
         .success()
         .stdout("key_pair creation error? (null)\n");
     }
+
+    #[test]
+    fn datalog_code() {
+        (assert_c! {
+            #include <stdio.h>
+            #include <string.h>
+            #include "biscuit_auth.h"
+
+            int main() {
+                char *seed = "abcdefghabcdefghabcdefghabcdefgh";
+
+                KeyPair * root_kp = key_pair_new((const uint8_t *) seed, strlen(seed));
+                BiscuitBuilder* b = biscuit_builder(root_kp);
+                biscuit_builder_add_authority_code(b, "right(\"file1\", \"read\"); check if time($t)");
+                printf("builder add code error? %s\n", error_message());
+                Biscuit * biscuit = biscuit_builder_build(b, (const uint8_t * ) seed, strlen(seed));
+
+                BlockBuilder* bb = biscuit_create_block(biscuit);
+                block_builder_add_code(bb, "check if operation(\"read\")");
+                printf("block builder add code error? %s\n", error_message());
+                Biscuit* b2 = biscuit_append_block(biscuit, bb, root_kp);
+
+                Authorizer * authorizer = biscuit_authorizer(b2);
+                authorizer_add_code(authorizer, "time(2021-12-23T13:00:00Z); operation(\"read\"); allow if right(\"file1\", \"read\")");
+                printf("authorizer add code error? %s\n", error_message());
+                printf("authorizer result: %d\n", authorizer_authorize(authorizer));
+
+                Authorizer * empty = authorizer_new();
+                authorizer_add_code(empty, "deny if true");
+                printf("empty authorizer result: %d\n", authorizer_authorize(empty));
+
+                authorizer_free(empty);
+                authorizer_free(authorizer);
+                block_builder_free(bb);
+                biscuit_free(b2);
+                biscuit_free(biscuit);
+                biscuit_builder_free(b);
+                key_pair_free(root_kp);
+            }
+        })
+        .success()
+        .stdout(
+            r#"builder add code error? (null)
+block builder add code error? (null)
+authorizer add code error? (null)
+authorizer result: 1
+empty authorizer result: 0
+"#,
+        );
+    }
 }